@@ -11,7 +11,7 @@ mod log {
 pub use constants::{ACME_DIR_URL, ACME_REGISTRY_PATH};
 pub use dir_cache::DirCache;
 pub use error::RpxyAcmeError;
-pub use manager::AcmeManager;
+pub use manager::{AcmeEventHook, AcmeManager};
 
 pub mod reexports {
   pub use rustls_acme::is_tls_alpn_challenge;
@@ -6,12 +6,16 @@ use crate::{
 };
 use ahash::HashMap;
 use rustls::ServerConfig;
-use rustls_acme::AcmeConfig;
+use rustls_acme::{AcmeConfig, EventOk};
 use std::{path::PathBuf, sync::Arc};
 use tokio::runtime::Handle;
 use tokio_stream::StreamExt;
 use url::Url;
 
+/// Hook called with the domain and the name of each ACME event like "deployed_new_cert" or "error",
+/// used to record the certificate management events outside this crate, e.g., as metrics.
+pub type AcmeEventHook = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
 #[derive(Debug, Clone)]
 /// ACME settings
 pub struct AcmeManager {
@@ -75,9 +79,11 @@ impl AcmeManager {
 
   /// Start ACME manager to manage certificates for each domain.
   /// Returns a Vec<JoinHandle<()>> as a tasks handles and a map of domain to ServerConfig for challenge.
+  /// If given, `event_hook` is called at each ACME event.
   pub fn spawn_manager_tasks(
     &self,
     cancel_token: tokio_util::sync::CancellationToken,
+    event_hook: Option<AcmeEventHook>,
   ) -> (Vec<tokio::task::JoinHandle<()>>, HashMap<String, Arc<ServerConfig>>) {
     let rustls_client_config = rustls::ClientConfig::builder()
       .dangerous() // The `Verifier` we're using is actually safe
@@ -100,6 +106,7 @@ impl AcmeManager {
         server_configs_for_challenge.insert(domain.to_ascii_lowercase(), state.challenge_rustls_config());
         self.runtime_handle.spawn({
           let cancel_token = cancel_token.clone();
+          let event_hook = event_hook.clone();
           async move {
             info!("rpxy ACME manager task for {domain} started");
             // infinite loop unless the return value is None
@@ -109,9 +116,23 @@ impl AcmeManager {
                   error!("rpxy ACME manager task for {domain} exited");
                   break;
                 };
-                match res {
-                  Ok(ok) => info!("rpxy ACME event: {ok:?}"),
-                  Err(err) => error!("rpxy ACME error: {err:?}"),
+                let event = match res {
+                  Ok(ok) => {
+                    info!("rpxy ACME event: {ok:?}");
+                    match ok {
+                      EventOk::DeployedCachedCert => "deployed_cached_cert",
+                      EventOk::DeployedNewCert => "deployed_new_cert",
+                      EventOk::CertCacheStore => "cert_cache_store",
+                      EventOk::AccountCacheStore => "account_cache_store",
+                    }
+                  }
+                  Err(err) => {
+                    error!("rpxy ACME error: {err:?}");
+                    "error"
+                  }
+                };
+                if let Some(event_hook) = event_hook.as_ref() {
+                  event_hook(&domain, event);
                 }
               }
             };
@@ -0,0 +1,222 @@
+#[cfg(feature = "acme")]
+use super::parse::build_acme_manager;
+use super::{
+  parse::{build_admin_config, build_metrics_config, build_settings, ensure_apps_ports},
+  toml::{ConfigToml, TlsOption, UpstreamTlsOption},
+};
+use crate::error::{anyhow, bail, ensure};
+use ahash::HashSet;
+use rpxy_certs::{CryptoFileSourceBuilder, CryptoSource};
+use rpxy_lib::{AdminListen, ProxyConfig};
+use serde_json::{Value, json};
+
+/// Validate the configuration file fully and return all errors found.
+/// Unlike the startup, unsupported fields are reported as errors, and certificates and keys are read and verified.
+pub async fn check_config(config_file: &str) -> Vec<String> {
+  let (config, unused) = match ConfigToml::new_with_unused_fields(config_file) {
+    Ok(v) => v,
+    Err(e) => return vec![format!("Invalid toml file: {e}")],
+  };
+  let mut errors = unused
+    .iter()
+    .map(|v| format!("Unsupported field (check typos): {v}"))
+    .collect::<Vec<_>>();
+  errors.extend(check_loaded_config(&config).await);
+
+  // secret values must not be printed in error messages
+  errors.iter().map(|e| config.sources.secret_values.redact(e)).collect()
+}
+
+/// Validate the loaded configuration
+async fn check_loaded_config(config: &ConfigToml) -> Vec<String> {
+  let mut errors = Vec::new();
+
+  // global settings
+  let proxy_config = match TryInto::<ProxyConfig>::try_into(config) {
+    Ok(v) => Some(v),
+    Err(e) => {
+      errors.push(e.to_string());
+      None
+    }
+  };
+  if let Err(e) = build_metrics_config(config) {
+    errors.push(format!("metrics: {e}"));
+  }
+  if let Err(e) = build_admin_config(config) {
+    errors.push(format!("admin: {e}"));
+  }
+
+  // backend apps
+  let Some(apps) = config.apps.as_ref().filter(|apps| !apps.0.is_empty()) else {
+    errors.push("Missing application spec".to_string());
+    return errors;
+  };
+  if let Some(Err(e)) = proxy_config.as_ref().map(|v| ensure_apps_ports(v, apps)) {
+    errors.push(e.to_string());
+  }
+  let undefined_default_app = config
+    .default_app
+    .as_ref()
+    .filter(|default_app| !apps.0.keys().any(|v| v.eq_ignore_ascii_case(default_app)));
+  if let Some(default_app) = undefined_default_app {
+    errors.push(format!("default_app '{default_app}' is not defined in apps"));
+  }
+
+  let mut app_names = apps.0.keys().collect::<Vec<_>>();
+  app_names.sort();
+  for app_name in app_names {
+    let app = &apps.0[app_name];
+    if let Err(e) = app.build_app_config(&app_name.to_ascii_lowercase()) {
+      errors.push(format!("app '{app_name}': {e}"));
+    }
+
+    // route conflicts in the app, where those of server names across apps are detected at loading the configuration
+    let mut paths = HashSet::default();
+    for path in app.reverse_proxy.iter().flatten().map(|v| v.path.as_deref().unwrap_or("/")) {
+      if !paths.insert(path.to_ascii_lowercase()) {
+        errors.push(format!("app '{app_name}': reverse_proxy path '{path}' is duplicated"));
+      }
+    }
+
+    // certificates and keys
+    let tls_res = match app.tls.as_ref() {
+      Some(tls) => check_tls(config, tls).await,
+      None => Ok(()),
+    };
+    if let Err(e) = tls_res {
+      errors.push(format!("app '{app_name}': {e}"));
+    }
+    let upstreams = app.reverse_proxy.iter().flatten().flat_map(|rp| rp.upstream.iter());
+    for upstream in upstreams {
+      let Some(tls_options) = upstream.tls_options.as_ref() else {
+        continue;
+      };
+      if let Err(e) = check_upstream_tls(tls_options).await {
+        errors.push(format!("app '{app_name}': upstream '{}': {e}", upstream.location));
+      }
+    }
+  }
+
+  #[cfg(feature = "acme")]
+  if let Err(e) = check_acme(config).await {
+    errors.push(format!("acme: {e}"));
+  }
+
+  errors
+}
+
+/// Check the tls settings of an app, reading certificates and keys and verifying that they match
+async fn check_tls(config: &ConfigToml, tls: &TlsOption) -> Result<(), anyhow::Error> {
+  #[cfg(feature = "acme")]
+  if let Some(true) = tls.acme {
+    ensure!(
+      config.experimental.as_ref().and_then(|v| v.acme.as_ref()).is_some(),
+      "acme = true requires [experimental.acme] settings"
+    );
+    ensure!(
+      tls.tls_cert_path.is_none() && tls.tls_cert_key_path.is_none(),
+      "tls_cert_path and tls_cert_key_path must not be specified with acme = true"
+    );
+    ensure!(config.listen_port_tls.is_some(), "acme = true requires listen_port_tls");
+    return Ok(());
+  }
+  #[cfg(not(feature = "acme"))]
+  let _ = config;
+
+  let (Some(tls_cert_path), Some(tls_cert_key_path)) = (tls.tls_cert_path.as_ref(), tls.tls_cert_key_path.as_ref()) else {
+    bail!("Both tls_cert_path and tls_cert_key_path must be specified");
+  };
+  let crypto_file_source = CryptoFileSourceBuilder::default()
+    .tls_cert_path(tls_cert_path)
+    .tls_cert_key_path(tls_cert_key_path)
+    .client_ca_cert_path(tls.client_ca_cert_path.as_deref())
+    .build()?;
+  let certs_keys = crypto_file_source.read().await?;
+  certs_keys
+    .verify_key_pair()
+    .map_err(|e| anyhow!("tls_cert_key_path does not match tls_cert_path: {e}"))?;
+  if certs_keys.is_mutual_tls() {
+    let trust_anchors = certs_keys.rustls_client_certs_trust_anchors()?;
+    ensure!(
+      !trust_anchors.is_empty(),
+      "No valid CA certificate found in client_ca_cert_path"
+    );
+  }
+  Ok(())
+}
+
+/// Check the tls settings of an upstream, reading certificates and keys and verifying that they match
+async fn check_upstream_tls(tls: &UpstreamTlsOption) -> Result<(), anyhow::Error> {
+  if let (Some(client_cert_path), Some(client_cert_key_path)) = (tls.client_cert_path.as_ref(), tls.client_cert_key_path.as_ref())
+  {
+    let crypto_file_source = CryptoFileSourceBuilder::default()
+      .tls_cert_path(client_cert_path)
+      .tls_cert_key_path(client_cert_key_path)
+      .build()?;
+    crypto_file_source
+      .read()
+      .await?
+      .verify_key_pair()
+      .map_err(|e| anyhow!("client_cert_key_path does not match client_cert_path: {e}"))?;
+  }
+  if let Some(ca_cert_path) = tls.ca_cert_path.as_ref() {
+    let content = std::fs::read_to_string(ca_cert_path).map_err(|e| anyhow!("Failed to read {ca_cert_path}: {e}"))?;
+    ensure!(
+      content.contains("-----BEGIN CERTIFICATE-----"),
+      "No CA certificate found in ca_cert_path"
+    );
+  }
+  Ok(())
+}
+
+#[cfg(feature = "acme")]
+/// Check ACME prerequisites, i.e., contact email, directory url and registry path
+async fn check_acme(config: &ConfigToml) -> Result<(), anyhow::Error> {
+  let Some(acme_option) = config.experimental.as_ref().and_then(|v| v.acme.as_ref()) else {
+    return Ok(());
+  };
+  ensure!(
+    acme_option.email.contains('@'),
+    "Invalid contact email '{}'",
+    acme_option.email
+  );
+  build_acme_manager(config, tokio::runtime::Handle::current()).await?;
+
+  // registry directory must be created or written by rpxy
+  let registry_path = std::path::PathBuf::from(acme_option.registry_path.as_deref().unwrap_or(rpxy_acme::ACME_REGISTRY_PATH));
+  let Some(existing) = registry_path.ancestors().find(|v| v.exists()) else {
+    return Ok(());
+  };
+  ensure!(existing.is_dir(), "Registry path '{}' is not a directory", existing.display());
+  ensure!(
+    !existing.metadata()?.permissions().readonly(),
+    "Registry path '{}' is not writable",
+    existing.display()
+  );
+  Ok(())
+}
+
+/* ----------------------- */
+/// Build the effective configuration in JSON, normalized and filled with default values, where the admin token and secret values are redacted
+pub fn dump_config(config: &ConfigToml) -> Result<Value, anyhow::Error> {
+  let (proxy_config, mut app_config_list) = build_settings(config)?;
+  app_config_list.inner.sort_by(|a, b| a.app_name.cmp(&b.app_name));
+  let metrics_config = build_metrics_config(config)?;
+  let admin_config = build_admin_config(config)?.map(|v| {
+    let listen = match v.listen {
+      AdminListen::Tcp(addr) => addr.to_string(),
+      #[cfg(unix)]
+      AdminListen::Unix(path) => format!("unix:{}", path.display()),
+    };
+    json!({ "listen": listen, "token": "<redacted>" })
+  });
+
+  let mut dump = json!({
+    "proxy": proxy_config,
+    "apps": app_config_list,
+    "metrics": metrics_config,
+    "admin": admin_config,
+  });
+  proxy_config.secret_values.redact_json(&mut dump);
+  Ok(dump)
+}
@@ -0,0 +1,225 @@
+use super::toml::ConfigSources;
+use crate::error::bail;
+use std::{fs, path::Path};
+
+/// Prefix of string values referring to files, whose contents are substituted as secret values
+const FILE_REFERENCE_PREFIX: &str = "file:";
+/// Prefix of environment variables like `${secret:VAR}`, whose values are substituted as secret values
+const SECRET_ENV_PREFIX: &str = "secret:";
+
+/// Substitute environment variables like `${VAR}` and `${VAR:-default}` in all string values of the table, where `$${` is a literal `${`.
+/// Only upper-case names are regarded as environment variables, and others like `${name}` of redirect targets are kept as they are.
+/// Values of variables marked like `${secret:VAR}` are registered as secret values in `sources`, while defaults written in the table are not.
+/// Then, string values like `file:/path/to/secret` are replaced with the contents of the files, which are registered as secret values
+/// in `sources` with the files. Relative paths of the files are resolved from `base_dir`. Returns true if any value is substituted.
+pub(super) fn interpolate_table(
+  table: &mut toml::Table,
+  base_dir: &Path,
+  sources: &mut ConfigSources,
+) -> Result<bool, anyhow::Error> {
+  let mut interpolator = Interpolator {
+    base_dir,
+    sources,
+    substituted: false,
+    errors: Vec::new(),
+  };
+  for (key, value) in table.iter_mut() {
+    interpolator.value(value, key);
+  }
+  if !interpolator.errors.is_empty() {
+    bail!("{}", interpolator.errors.join("\n"));
+  }
+  Ok(interpolator.substituted)
+}
+
+/// Context of the interpolation collecting errors for all values
+struct Interpolator<'a> {
+  base_dir: &'a Path,
+  sources: &'a mut ConfigSources,
+  substituted: bool,
+  errors: Vec<String>,
+}
+
+impl Interpolator<'_> {
+  /// Interpolate the value recursively, where `key_path` like "apps.app1.reverse_proxy[0].path" is used in error messages
+  fn value(&mut self, value: &mut toml::Value, key_path: &str) {
+    match value {
+      toml::Value::String(s) => {
+        if let Some(new) = self.string(s, key_path) {
+          *s = new;
+          self.substituted = true;
+        }
+      }
+      toml::Value::Array(array) => {
+        for (idx, v) in array.iter_mut().enumerate() {
+          self.value(v, &format!("{key_path}[{idx}]"));
+        }
+      }
+      toml::Value::Table(table) => {
+        for (key, v) in table.iter_mut() {
+          self.value(v, &format!("{key_path}.{key}"));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Interpolate the string value, returning None if it has nothing to substitute or fails
+  fn string(&mut self, s: &str, key_path: &str) -> Option<String> {
+    if !s.contains('$') && !s.starts_with(FILE_REFERENCE_PREFIX) {
+      return None;
+    }
+    let expanded = self.expand_env(s, key_path)?;
+    let Some(file_path) = expanded.strip_prefix(FILE_REFERENCE_PREFIX) else {
+      return (expanded != s).then_some(expanded);
+    };
+
+    let file_path = self.base_dir.join(file_path);
+    match fs::read_to_string(&file_path) {
+      Ok(content) => {
+        let content = content.trim_end_matches(['\r', '\n']).to_string();
+        self.sources.secret_values.insert(&content);
+        self.sources.files.push(file_path);
+        Some(content)
+      }
+      Err(e) => {
+        self
+          .errors
+          .push(format!("Failed to read {} referred at {key_path}: {e}", file_path.display()));
+        None
+      }
+    }
+  }
+
+  /// Expand environment variables in the string, where a variable set to an empty string is regarded as unset like shells
+  fn expand_env(&mut self, s: &str, key_path: &str) -> Option<String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    let mut resolved = true;
+    while let Some(pos) = rest.find('$') {
+      expanded.push_str(&rest[..pos]);
+      rest = &rest[pos..];
+      if let Some(r) = rest.strip_prefix("$${") {
+        expanded.push_str("${");
+        rest = r;
+        continue;
+      }
+      let Some(r) = rest.strip_prefix("${") else {
+        expanded.push('$');
+        rest = &rest[1..];
+        continue;
+      };
+      let Some(end) = r.find('}') else {
+        expanded.push('$');
+        rest = &rest[1..];
+        continue;
+      };
+      let (name, default) = match r[..end].split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (&r[..end], None),
+      };
+      let (name, secret) = match name.strip_prefix(SECRET_ENV_PREFIX) {
+        Some(name) => (name, true),
+        None => (name, false),
+      };
+      if !is_env_name(name) {
+        expanded.push_str(&rest[..end + 3]);
+        rest = &r[end + 1..];
+        continue;
+      }
+      match (std::env::var(name).ok().filter(|v| !v.is_empty()), default) {
+        (Some(v), _) => {
+          if secret {
+            self.sources.secret_values.insert(&v);
+          }
+          expanded.push_str(&v);
+        }
+        (None, Some(default)) => expanded.push_str(default),
+        (None, None) => {
+          self
+            .errors
+            .push(format!("Unresolved environment variable '{name}' at {key_path}"));
+          resolved = false;
+        }
+      }
+      rest = &r[end + 1..];
+    }
+    expanded.push_str(rest);
+    resolved.then_some(expanded)
+  }
+}
+
+/// Check if the name is of an environment variable, i.e., upper-case letters, digits and underscores not starting with a digit
+fn is_env_name(name: &str) -> bool {
+  !name.is_empty()
+    && !name.starts_with(|c: char| c.is_ascii_digit())
+    && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn interpolate(value: &str) -> (Result<bool, anyhow::Error>, toml::Table, ConfigSources) {
+    let mut table = toml::Table::new();
+    table.insert("key".to_string(), toml::Value::String(value.to_string()));
+    let mut sources = ConfigSources::default();
+    let res = interpolate_table(&mut table, Path::new("."), &mut sources);
+    (res, table, sources)
+  }
+
+  fn interpolated(value: &str) -> String {
+    let (res, table, _) = interpolate(value);
+    res.unwrap();
+    table["key"].as_str().unwrap().to_string()
+  }
+
+  #[test]
+  fn env_variables_are_expanded() {
+    // SAFETY: variables are only used by this test
+    unsafe {
+      std::env::set_var("RPXY_TEST_INTERPOLATE_SET", "value");
+      std::env::set_var("RPXY_TEST_INTERPOLATE_EMPTY", "");
+    }
+    assert_eq!(interpolated("a-${RPXY_TEST_INTERPOLATE_SET}-b"), "a-value-b");
+    assert_eq!(interpolated("${RPXY_TEST_INTERPOLATE_SET:-default}"), "value");
+    assert_eq!(interpolated("${RPXY_TEST_INTERPOLATE_UNSET:-default}"), "default");
+    assert_eq!(interpolated("${RPXY_TEST_INTERPOLATE_EMPTY:-default}"), "default");
+    assert_eq!(interpolated("$${RPXY_TEST_INTERPOLATE_SET}"), "${RPXY_TEST_INTERPOLATE_SET}");
+    assert_eq!(interpolated("$$1 and $2"), "$$1 and $2");
+    // not environment variables, or unterminated
+    assert_eq!(interpolated("/new/${year}/$1"), "/new/${year}/$1");
+    assert_eq!(interpolated("${RPXY_TEST_INTERPOLATE_SET"), "${RPXY_TEST_INTERPOLATE_SET");
+  }
+
+  #[test]
+  fn unresolved_variables_are_errors() {
+    let (res, _, _) = interpolate("${RPXY_TEST_INTERPOLATE_MISSING1}/${RPXY_TEST_INTERPOLATE_MISSING2}");
+    let e = res.unwrap_err().to_string();
+    assert!(e.contains("RPXY_TEST_INTERPOLATE_MISSING1") && e.contains("RPXY_TEST_INTERPOLATE_MISSING2"));
+    assert!(e.contains("at key"));
+  }
+
+  #[test]
+  fn marked_env_values_are_registered_as_secrets() {
+    // SAFETY: variables are only used by this test
+    unsafe {
+      std::env::set_var("RPXY_TEST_INTERPOLATE_TOKEN", "env-secret-token");
+      std::env::set_var("RPXY_TEST_INTERPOLATE_PORT", "80");
+    }
+    let (res, table, sources) = interpolate("Bearer ${secret:RPXY_TEST_INTERPOLATE_TOKEN}");
+    assert!(res.unwrap());
+    assert_eq!(table["key"].as_str(), Some("Bearer env-secret-token"));
+    assert_eq!(sources.secret_values.redact("Bearer env-secret-token"), "Bearer <redacted>");
+
+    let (_, _, sources) = interpolate("${secret:RPXY_TEST_INTERPOLATE_NO_TOKEN:-default-value}");
+    assert_eq!(sources.secret_values.redact("default-value"), "default-value");
+
+    // values of unmarked variables are not secrets, leaving other values as they are
+    let (_, table, sources) = interpolate("${RPXY_TEST_INTERPOLATE_PORT}");
+    assert_eq!(table["key"].as_str(), Some("80"));
+    let mut dump = serde_json::json!({ "listen_port": "80", "upstream": "backend:8080" });
+    sources.secret_values.redact_json(&mut dump);
+    assert_eq!(dump, serde_json::json!({ "listen_port": "80", "upstream": "backend:8080" }));
+  }
+}
@@ -1,12 +1,19 @@
+mod check;
+mod interpolate;
 mod parse;
 mod service;
 mod toml;
+mod watcher;
 
 pub use {
-  parse::{build_cert_manager, build_settings, Opts, Parser},
+  check::{check_config, dump_config},
+  parse::{build_admin_config, build_cert_manager, build_metrics_config, build_settings, Command, Opts, Parser},
   service::ConfigTomlReloader,
   toml::ConfigToml,
+  watcher::watch_config_files,
 };
 
 #[cfg(feature = "acme")]
 pub use parse::build_acme_manager;
+#[cfg(feature = "otel")]
+pub use toml::OpenTelemetryOption;
@@ -1,10 +1,13 @@
-use super::toml::ConfigToml;
-use crate::error::{anyhow, ensure};
+use super::toml::{Apps, ConfigToml};
+use crate::{
+  error::{anyhow, ensure},
+  log::AccessLogFormat,
+};
 use ahash::HashMap;
-pub use clap::Parser;
+pub use clap::{Parser, Subcommand};
 use hot_reload::{ReloaderReceiver, ReloaderService};
 use rpxy_certs::{CryptoFileSourceBuilder, CryptoReloader, ServerCryptoBase, build_cert_reloader};
-use rpxy_lib::{AppConfig, AppConfigList, ProxyConfig};
+use rpxy_lib::{AdminConfig, AppConfig, AppConfigList, MetricsConfig, ProxyConfig};
 
 #[cfg(feature = "acme")]
 use rpxy_acme::{ACME_DIR_URL, ACME_REGISTRY_PATH, AcmeManager};
@@ -19,6 +22,21 @@ pub struct Opts {
   /// Directory for log files. If not specified, logs are printed to stdout.
   #[arg(short, long = "log-dir", value_name = "LOG_DIR")]
   pub log_dir_path: Option<String>,
+  /// Access log format: default, json, common, combined, or a custom template like "{client_addr} {path} {status}".
+  #[arg(long = "access-log-format", value_name = "FORMAT", default_value = "default")]
+  pub access_log_format: AccessLogFormat,
+  /// Subcommand to inspect the configuration file without starting the proxy
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+/// Subcommands
+#[derive(Subcommand)]
+pub enum Command {
+  /// Validate the configuration fully and list all errors found, exiting with non-zero status on errors
+  Check,
+  /// Print the effective configuration in JSON, normalized and filled with default values
+  Dump,
 }
 
 pub fn build_settings(config: &ConfigToml) -> std::result::Result<(ProxyConfig, AppConfigList), anyhow::Error> {
@@ -30,6 +48,28 @@ pub fn build_settings(config: &ConfigToml) -> std::result::Result<(ProxyConfig,
 
   // assertions for all backend apps
   ensure!(!apps.0.is_empty(), "Wrong application spec.");
+  ensure_apps_ports(&proxy_config, &apps)?;
+
+  // build applications
+  let mut app_config_list_inner = Vec::<AppConfig>::new();
+
+  for (app_name, app) in apps.0.iter() {
+    let _server_name_string = app.server_name.as_ref().ok_or(anyhow!("No server name"))?;
+    let registered_app_name = app_name.to_ascii_lowercase();
+    let app_config = app.build_app_config(&registered_app_name)?;
+    app_config_list_inner.push(app_config);
+  }
+
+  let app_config_list = AppConfigList {
+    inner: app_config_list_inner,
+    default_app: config.default_app.clone().map(|v| v.to_ascii_lowercase()), // default backend application for plaintext http requests
+  };
+
+  Ok((proxy_config, app_config_list))
+}
+
+/// Assertions of tls and https redirection settings of backend apps against listen ports
+pub(super) fn ensure_apps_ports(proxy_config: &ProxyConfig, apps: &Apps) -> std::result::Result<(), anyhow::Error> {
   // if only https_port is specified, tls must be configured for all apps
   if proxy_config.http_port.is_none() {
     ensure!(
@@ -57,23 +97,19 @@ pub fn build_settings(config: &ConfigToml) -> std::result::Result<(ProxyConfig,
       "https_redirection can be specified only when both http_port and https_port are specified"
     );
   }
+  Ok(())
+}
 
-  // build applications
-  let mut app_config_list_inner = Vec::<AppConfig>::new();
-
-  for (app_name, app) in apps.0.iter() {
-    let _server_name_string = app.server_name.as_ref().ok_or(anyhow!("No server name"))?;
-    let registered_app_name = app_name.to_ascii_lowercase();
-    let app_config = app.build_app_config(&registered_app_name)?;
-    app_config_list_inner.push(app_config);
-  }
-
-  let app_config_list = AppConfigList {
-    inner: app_config_list_inner,
-    default_app: config.default_app.clone().map(|v| v.to_ascii_lowercase()), // default backend application for plaintext http requests
-  };
+/* ----------------------- */
+/// Build metrics endpoint config if the metrics listener is configured
+pub fn build_metrics_config(config: &ConfigToml) -> std::result::Result<Option<MetricsConfig>, anyhow::Error> {
+  config.metrics.as_ref().map(|v| v.try_into()).transpose()
+}
 
-  Ok((proxy_config, app_config_list))
+/* ----------------------- */
+/// Build admin API config if the admin listener is configured
+pub fn build_admin_config(config: &ConfigToml) -> std::result::Result<Option<AdminConfig>, anyhow::Error> {
+  config.admin.as_ref().map(|v| v.try_into()).transpose()
 }
 
 /* ----------------------- */
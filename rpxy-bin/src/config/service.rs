@@ -2,6 +2,7 @@ use super::toml::ConfigToml;
 use async_trait::async_trait;
 use hot_reload::{Reload, ReloaderError};
 
+/// Reloader of the configuration file, which reads the included files as well so that their changes are tracked
 #[derive(Clone)]
 pub struct ConfigTomlReloader {
   pub config_path: String,
@@ -1,12 +1,23 @@
+use super::interpolate::interpolate_table;
 use crate::{
   constants::*,
   error::{anyhow, ensure},
   log::warn,
 };
-use ahash::HashMap;
-use rpxy_lib::{AppConfig, ProxyConfig, ReverseProxyConfig, TlsConfig, UpstreamUri, reexports::Uri};
+use ahash::{HashMap, HashSet};
+use rpxy_lib::{
+  AdminConfig, AdminListen, AppConfig, CorsConfig, FaultConfig, HstsConfig, IpCidr, MetricsConfig, MirrorConfig, ProxyConfig,
+  RedirectConfig, ReverseProxyConfig, SecretValues, SecurityHeadersConfig, StaticResponseConfig, TlsConfig,
+  UpstreamDiscoveryConfig, UpstreamDiscoveryMode, UpstreamProtocol, UpstreamTimeoutConfig, UpstreamTlsConfig, UpstreamTlsVersion,
+  UpstreamUri,
+  reexports::{HeaderName, Uri},
+};
 use serde::Deserialize;
-use std::{fs, net::SocketAddr};
+use std::{
+  fs,
+  net::SocketAddr,
+  path::{Path, PathBuf},
+};
 use tokio::time::Duration;
 
 #[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
@@ -15,12 +26,71 @@ pub struct ConfigToml {
   pub listen_port_tls: Option<u16>,
   pub listen_ipv6: Option<bool>,
   pub https_redirection_port: Option<u16>,
+  pub https_redirection_status_code: Option<u16>,
   pub tcp_listen_backlog: Option<u32>,
   pub max_concurrent_streams: Option<u32>,
   pub max_clients: Option<u32>,
+  pub drain_timeout: Option<u64>,
+  pub upstream_pool: Option<UpstreamPoolOption>,
+  pub upstream_dns_servers: Option<Vec<String>>,
+  pub request_id: Option<RequestIdOption>,
+  pub metrics: Option<MetricsOption>,
+  pub admin: Option<AdminOption>,
+  #[cfg(feature = "otel")]
+  pub opentelemetry: Option<OpenTelemetryOption>,
+  pub include: Option<Vec<String>>,
   pub apps: Option<Apps>,
   pub default_app: Option<String>,
   pub experimental: Option<Experimental>,
+  /// sources of the configuration collected at loading, which are not a part of the toml
+  #[serde(skip)]
+  pub sources: ConfigSources,
+}
+
+/// Sources of the configuration other than the values in the toml
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ConfigSources {
+  /// secret values substituted from environment variables and files
+  pub secret_values: SecretValues,
+  /// files read to build the configuration, i.e., the configuration file, included files and files referred by `file:`
+  pub files: Vec<PathBuf>,
+  /// directories searched for included files
+  pub include_dirs: Vec<PathBuf>,
+}
+
+/// Configuration file included from the main one, which only defines apps
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+struct IncludedConfigToml {
+  apps: Option<Apps>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct RequestIdOption {
+  pub header: Option<String>,
+  pub trusted_sources: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct MetricsOption {
+  pub listen_address: String,
+  pub path: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct AdminOption {
+  pub listen_address: Option<String>,
+  pub unix_socket: Option<String>,
+  pub token: String,
+}
+
+#[cfg(feature = "otel")]
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct OpenTelemetryOption {
+  pub endpoint: Option<String>,
+  pub protocol: Option<String>,
+  pub sampler: Option<String>,
+  pub sampler_arg: Option<String>,
+  pub service_name: Option<String>,
 }
 
 #[cfg(any(feature = "http3-quinn", feature = "http3-s2n"))]
@@ -41,6 +111,9 @@ pub struct CacheOption {
   pub max_cache_entry: Option<usize>,
   pub max_cache_each_size: Option<usize>,
   pub max_cache_each_size_on_memory: Option<usize>,
+  pub max_cache_total_size_on_memory: Option<usize>,
+  pub max_cache_total_size_on_file: Option<usize>,
+  pub persistent: Option<bool>,
 }
 
 #[cfg(feature = "acme")]
@@ -73,6 +146,9 @@ pub struct Apps(pub HashMap<String, Application>);
 pub struct Application {
   pub server_name: Option<String>,
   pub reverse_proxy: Option<Vec<ReverseProxyOption>>,
+  pub redirect: Option<Vec<RedirectOption>>,
+  pub static_response: Option<Vec<StaticResponseOption>>,
+  pub security_headers: Option<SecurityHeadersOption>,
   pub tls: Option<TlsOption>,
 }
 
@@ -93,12 +169,117 @@ pub struct ReverseProxyOption {
   pub upstream: Vec<UpstreamParams>,
   pub upstream_options: Option<Vec<String>>,
   pub load_balance: Option<String>,
+  pub cors: Option<CorsOption>,
+  pub timeouts: Option<UpstreamTimeoutsOption>,
+  pub mirror: Option<MirrorOption>,
+  pub faults: Option<Vec<FaultOption>>,
+}
+
+/// Mirroring of requests to a shadow upstream, where the timeout is in seconds
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct MirrorOption {
+  pub upstream: UpstreamParams,
+  pub percentage: Option<u8>,
+  pub max_body_size: Option<usize>,
+  pub timeout: Option<u64>,
+}
+
+/// Timeouts of requests to upstreams in seconds
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct UpstreamTimeoutsOption {
+  pub connect: Option<u64>,
+  pub response_header: Option<u64>,
+  pub request: Option<u64>,
+  pub body_idle: Option<u64>,
+}
+
+/// Connection pools to upstreams, where durations are in seconds
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct UpstreamPoolOption {
+  pub max_idle_per_host: Option<usize>,
+  pub idle_timeout: Option<u64>,
+  pub h2_keepalive_interval: Option<u64>,
+  pub h2_keepalive_timeout: Option<u64>,
+  pub h2_adaptive_window: Option<bool>,
+}
+
+/// Fault injection rule, where delays are in milliseconds
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct FaultOption {
+  pub header: Option<String>,
+  pub percentage: Option<u8>,
+  pub delay_ms: Option<u64>,
+  pub delay_max_ms: Option<u64>,
+  pub abort: Option<u16>,
+  pub reset: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct CorsOption {
+  pub allow_origins: Option<Vec<String>>,
+  pub allow_origin_regex: Option<Vec<String>>,
+  pub allow_methods: Option<Vec<String>>,
+  pub allow_headers: Option<Vec<String>>,
+  pub expose_headers: Option<Vec<String>>,
+  pub allow_credentials: Option<bool>,
+  pub max_age: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct RedirectOption {
+  pub path: Option<String>,
+  pub path_regex: Option<String>,
+  pub target: String,
+  pub status_code: Option<u16>,
+  pub preserve_query: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct StaticResponseOption {
+  pub path: Option<String>,
+  pub path_regex: Option<String>,
+  pub status_code: Option<u16>,
+  pub body: Option<String>,
+  pub content_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct SecurityHeadersOption {
+  pub hsts: Option<HstsOption>,
+  pub content_type_options: Option<String>,
+  pub referrer_policy: Option<String>,
+  pub content_security_policy: Option<String>,
+  pub permissions_policy: Option<String>,
+  pub overwrite: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct HstsOption {
+  pub max_age: Option<u64>,
+  pub include_subdomains: Option<bool>,
+  pub preload: Option<bool>,
 }
 
 #[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
 pub struct UpstreamParams {
   pub location: String,
   pub tls: Option<bool>,
+  pub protocol: Option<String>,
+  pub tls_options: Option<UpstreamTlsOption>,
+  pub discovery: Option<String>,
+  pub discovery_ttl: Option<u64>,
+  pub discovery_source: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+pub struct UpstreamTlsOption {
+  pub ca_cert_path: Option<String>,
+  pub client_cert_path: Option<String>,
+  pub client_cert_key_path: Option<String>,
+  pub server_name: Option<String>,
+  pub verify_hostname: Option<bool>,
+  pub min_version: Option<String>,
+  pub alpn: Option<Vec<String>>,
 }
 
 impl TryInto<ProxyConfig> for &ConfigToml {
@@ -116,6 +297,9 @@ impl TryInto<ProxyConfig> for &ConfigToml {
       },
       ..Default::default()
     };
+    if let Some(code) = self.https_redirection_status_code {
+      proxy_config.https_redirection_status_code = code;
+    }
     ensure!(
       proxy_config.http_port.is_some() || proxy_config.https_port.is_some(),
       anyhow!("Either/Both of http_port or https_port must be specified")
@@ -160,6 +344,58 @@ impl TryInto<ProxyConfig> for &ConfigToml {
       proxy_config.max_concurrent_streams = c;
     }
 
+    // graceful shutdown
+    if let Some(timeout) = self.drain_timeout {
+      proxy_config.drain_timeout = Duration::from_secs(timeout);
+    }
+
+    // connection pools to upstreams
+    if let Some(pool) = &self.upstream_pool {
+      let pool_config = &mut proxy_config.upstream_pool;
+      pool_config.max_idle_per_host = pool.max_idle_per_host;
+      if let Some(timeout) = pool.idle_timeout {
+        pool_config.idle_timeout = Duration::from_secs(timeout);
+      }
+      if let Some(interval) = pool.h2_keepalive_interval {
+        ensure!(interval > 0, "upstream_pool.h2_keepalive_interval must be positive");
+        pool_config.h2_keepalive_interval = Some(Duration::from_secs(interval));
+      }
+      if let Some(timeout) = pool.h2_keepalive_timeout {
+        ensure!(timeout > 0, "upstream_pool.h2_keepalive_timeout must be positive");
+        pool_config.h2_keepalive_timeout = Duration::from_secs(timeout);
+      }
+      pool_config.h2_adaptive_window = pool.h2_adaptive_window.unwrap_or(false);
+    }
+
+    // DNS servers for upstream discovery, where the port defaults to 53
+    if let Some(servers) = &self.upstream_dns_servers {
+      proxy_config.upstream_dns_servers = servers
+        .iter()
+        .map(|v| {
+          v.parse::<SocketAddr>()
+            .or_else(|_| v.parse::<std::net::IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .map_err(|_| anyhow!("Invalid upstream_dns_servers: {v}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    // request id
+    if let Some(request_id) = &self.request_id {
+      if let Some(header) = &request_id.header {
+        proxy_config.request_id_header =
+          HeaderName::from_bytes(header.as_bytes()).map_err(|e| anyhow!("Invalid request_id header: {e}"))?;
+      }
+      proxy_config.request_id_trusted_sources = request_id
+        .trusted_sources
+        .iter()
+        .flatten()
+        .map(|v| {
+          v.parse::<IpCidr>()
+            .map_err(|e| anyhow!("Invalid request_id trusted source: {e}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    }
+
     // experimental
     if let Some(exp) = &self.experimental {
       #[cfg(any(feature = "http3-quinn", feature = "http3-s2n"))]
@@ -219,32 +455,215 @@ impl TryInto<ProxyConfig> for &ConfigToml {
         if let Some(num) = cache_option.max_cache_each_size_on_memory {
           proxy_config.cache_max_each_size_on_memory = num;
         }
+        if let Some(num) = cache_option.max_cache_total_size_on_memory {
+          proxy_config.cache_max_total_size_on_memory = num;
+        }
+        if let Some(num) = cache_option.max_cache_total_size_on_file {
+          proxy_config.cache_max_total_size_on_file = num;
+        }
+        if let Some(persistent) = cache_option.persistent {
+          proxy_config.cache_persistent = persistent;
+        }
       }
     }
 
+    // secret values redacted in the configuration dump
+    proxy_config.secret_values = self.sources.secret_values.clone();
+
+    proxy_config
+      .validate()
+      .map_err(|e| anyhow!("https_redirection_status_code: {e}"))?;
     Ok(proxy_config)
   }
 }
 
-impl ConfigToml {
-  pub fn new(config_file: &str) -> std::result::Result<Self, anyhow::Error> {
-    let config_str = fs::read_to_string(config_file)?;
+impl TryInto<MetricsConfig> for &MetricsOption {
+  type Error = anyhow::Error;
 
-    // Check unused fields during deserialization
-    let t = toml::de::Deserializer::new(&config_str);
-    let mut unused = ahash::HashSet::default();
+  fn try_into(self) -> std::result::Result<MetricsConfig, Self::Error> {
+    let listen_socket = self
+      .listen_address
+      .parse::<SocketAddr>()
+      .map_err(|e| anyhow!("Invalid metrics listen_address: {e}"))?;
+    let path = self.path.clone().unwrap_or_else(|| METRICS_PATH.to_string());
+    ensure!(path.starts_with('/'), anyhow!("Metrics path must start with '/'"));
+    Ok(MetricsConfig { listen_socket, path })
+  }
+}
 
-    let res = serde_ignored::deserialize(t, |path| {
-      unused.insert(path.to_string());
+impl TryInto<AdminConfig> for &AdminOption {
+  type Error = anyhow::Error;
+
+  fn try_into(self) -> std::result::Result<AdminConfig, Self::Error> {
+    ensure!(!self.token.is_empty(), anyhow!("Admin token must not be empty"));
+    let listen = match (self.listen_address.as_ref(), self.unix_socket.as_ref()) {
+      (Some(listen_address), None) => {
+        let listen_socket = listen_address
+          .parse::<SocketAddr>()
+          .map_err(|e| anyhow!("Invalid admin listen_address: {e}"))?;
+        ensure!(
+          listen_socket.ip().is_loopback(),
+          anyhow!("Admin listen_address must be a loopback address")
+        );
+        AdminListen::Tcp(listen_socket)
+      }
+      #[cfg(unix)]
+      (None, Some(unix_socket)) => AdminListen::Unix(unix_socket.into()),
+      _ => return Err(anyhow!("Either one of admin listen_address or unix_socket must be specified")),
+    };
+    Ok(AdminConfig {
+      listen,
+      token: self.token.clone(),
     })
-    .map_err(|e| anyhow!(e));
+  }
+}
+
+impl ConfigToml {
+  pub fn new(config_file: &str) -> std::result::Result<Self, anyhow::Error> {
+    let (config, unused) = Self::new_with_unused_fields(config_file)?;
 
     if !unused.is_empty() {
       let str = unused.iter().fold(String::new(), |acc, x| acc + x + "\n");
       warn!("Configuration file contains unsupported fields. Check typos:\n{}", str);
     }
 
-    res
+    Ok(config)
+  }
+
+  /// Parse the configuration file, returning the unsupported fields (sorted) as well.
+  /// Apps defined in the included files are merged into `apps`.
+  pub fn new_with_unused_fields(config_file: &str) -> std::result::Result<(Self, Vec<String>), anyhow::Error> {
+    let mut unused = Vec::new();
+    let mut sources = ConfigSources::default();
+    let mut config: Self = deserialize_toml_file(Path::new(config_file), &mut sources, |path| unused.push(path))?;
+    config.merge_included_apps(config_file, &mut sources, &mut unused)?;
+    config.sources = sources;
+
+    unused.sort();
+    Ok((config, unused))
+  }
+
+  /// Merge apps defined in the included files, where app names and server names must be unique over all files
+  fn merge_included_apps(
+    &mut self,
+    config_file: &str,
+    sources: &mut ConfigSources,
+    unused: &mut Vec<String>,
+  ) -> std::result::Result<(), anyhow::Error> {
+    let mut registry = AppRegistry::default();
+    for (app_name, app) in self.apps.iter().flat_map(|v| v.0.iter()) {
+      registry.register(app_name, app, config_file)?;
+    }
+    let Some(include) = self.include.as_ref() else {
+      return Ok(());
+    };
+
+    let mut apps = self.apps.take().unwrap_or_default();
+    for file in resolve_include(config_file, include, &mut sources.include_dirs)? {
+      let file_name = file.display().to_string();
+      let included: IncludedConfigToml =
+        deserialize_toml_file(&file, sources, |path| unused.push(format!("{file_name}: {path}")))
+          .map_err(|e| anyhow!("Failed to load included file {file_name}: {e}"))?;
+      for (app_name, app) in included.apps.map(|v| v.0).unwrap_or_default() {
+        registry.register(&app_name, &app, &file_name)?;
+        apps.0.insert(app_name, app);
+      }
+    }
+    self.apps = Some(apps);
+    Ok(())
+  }
+}
+
+/// Deserialize a toml file after the interpolation of environment variables and file references,
+/// passing the paths of unsupported fields to the callback
+fn deserialize_toml_file<T: serde::de::DeserializeOwned>(
+  file: &Path,
+  sources: &mut ConfigSources,
+  mut unused: impl FnMut(String),
+) -> std::result::Result<T, anyhow::Error> {
+  let config_str = fs::read_to_string(file)?;
+  sources.files.push(file.to_path_buf());
+
+  // Interpolate string values, and re-serialize them only if substituted to keep error positions in the original otherwise
+  let mut table = config_str.parse::<toml::Table>().map_err(|e| anyhow!(e))?;
+  let base_dir = file.parent().unwrap_or(Path::new(""));
+  let config_str = match interpolate_table(&mut table, base_dir, sources)? {
+    true => toml::to_string(&table)?,
+    false => config_str,
+  };
+
+  // Check unused fields during deserialization
+  let t = toml::de::Deserializer::new(&config_str);
+  serde_ignored::deserialize(t, |path| unused(path.to_string()))
+    .map_err(|e| anyhow!(sources.secret_values.redact(&e.to_string())))
+}
+
+/// Resolve the files listed in `include`, each of which is a file path, a glob pattern like "apps/*.toml",
+/// or a directory whose "*.toml" files are all included. Relative paths are resolved from the directory of the configuration file.
+/// Directories searched for the files, i.e., the directories and the deepest ones without wildcards of the patterns, are pushed to `include_dirs`.
+fn resolve_include(
+  config_file: &str,
+  include: &[String],
+  include_dirs: &mut Vec<PathBuf>,
+) -> std::result::Result<Vec<PathBuf>, anyhow::Error> {
+  let base_dir = Path::new(config_file).parent().unwrap_or(Path::new(""));
+  let mut files = Vec::new();
+  for entry in include {
+    let path = base_dir.join(entry);
+    let pattern = if path.is_dir() {
+      include_dirs.push(path.clone());
+      path.join("*.toml")
+    } else if entry.contains(['*', '?', '[']) {
+      let dir = path.ancestors().find(|v| !v.to_string_lossy().contains(['*', '?', '[']));
+      include_dirs.extend(dir.map(|v| v.to_path_buf()));
+      path
+    } else {
+      ensure!(path.is_file(), "Included file not found: {}", path.display());
+      files.push(path);
+      continue;
+    };
+    let pattern = pattern.to_str().ok_or_else(|| anyhow!("Invalid include path: {entry}"))?;
+    let mut matched = glob::glob(pattern)
+      .map_err(|e| anyhow!("Invalid include pattern '{entry}': {e}"))?
+      .collect::<Result<Vec<_>, _>>()?;
+    matched.sort();
+    files.extend(matched.into_iter().filter(|v| v.is_file()));
+  }
+
+  // skip the configuration file itself and files included twice
+  let config_file = fs::canonicalize(config_file)?;
+  let mut seen = HashSet::default();
+  files.retain(|v| {
+    let canonical = fs::canonicalize(v).unwrap_or_else(|_| v.clone());
+    canonical != config_file && seen.insert(canonical)
+  });
+  Ok(files)
+}
+
+/// Registry of app names and server names with the files defining them, to detect duplicates over the included files
+#[derive(Default)]
+struct AppRegistry {
+  app_names: HashMap<String, String>,
+  server_names: HashMap<String, (String, String)>,
+}
+
+impl AppRegistry {
+  fn register(&mut self, app_name: &str, app: &Application, file: &str) -> std::result::Result<(), anyhow::Error> {
+    if let Some(other_file) = self.app_names.insert(app_name.to_ascii_lowercase(), file.to_string()) {
+      return Err(anyhow!(
+        "Duplicate app name '{app_name}' in {file}, which is already defined in {other_file}"
+      ));
+    }
+    let Some(server_name) = app.server_name.as_ref() else {
+      return Ok(());
+    };
+    let origin = (app_name.to_string(), file.to_string());
+    if let Some((other_app, other_file)) = self.server_names.insert(server_name.to_ascii_lowercase(), origin) {
+      return Err(anyhow!(
+        "Duplicate server_name '{server_name}' of app '{app_name}' in {file}, which is already used by app '{other_app}' in {other_file}"
+      ));
+    }
+    Ok(())
   }
 }
 
@@ -287,10 +706,65 @@ impl Application {
       None
     };
 
+    // redirect and static response rules
+    let redirect = self
+      .redirect
+      .iter()
+      .flatten()
+      .map(|v| {
+        let redirect = RedirectConfig {
+          path: v.path.clone(),
+          path_regex: v.path_regex.clone(),
+          target: v.target.clone(),
+          status_code: v.status_code.unwrap_or(REDIRECT_RULE_STATUS_CODE),
+          preserve_query: v.preserve_query.unwrap_or(true),
+        };
+        redirect.validate().map_err(|e| anyhow!("[{}] {e}", &server_name_string))?;
+        Ok(redirect)
+      })
+      .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    let static_response = self
+      .static_response
+      .iter()
+      .flatten()
+      .map(|v| StaticResponseConfig {
+        path: v.path.clone(),
+        path_regex: v.path_regex.clone(),
+        status_code: v.status_code.unwrap_or(STATIC_RESPONSE_STATUS_CODE),
+        body: v.body.clone(),
+        content_type: v.content_type.clone(),
+      })
+      .collect::<Vec<_>>();
+
+    // security response headers
+    let security_headers = self.security_headers.as_ref().map(|v| {
+      if v.hsts.is_some() && self.tls.is_none() {
+        warn!(
+          "[{}] HSTS is configured but never emitted since TLS is disabled",
+          &server_name_string
+        );
+      }
+      SecurityHeadersConfig {
+        hsts: v.hsts.as_ref().map(|h| HstsConfig {
+          max_age: h.max_age.unwrap_or(HSTS_MAX_AGE),
+          include_subdomains: h.include_subdomains.unwrap_or(false),
+          preload: h.preload.unwrap_or(false),
+        }),
+        content_type_options: v.content_type_options.clone(),
+        referrer_policy: v.referrer_policy.clone(),
+        content_security_policy: v.content_security_policy.clone(),
+        permissions_policy: v.permissions_policy.clone(),
+        overwrite: v.overwrite.unwrap_or(false),
+      }
+    });
+
     Ok(AppConfig {
       app_name: app_name.to_owned(),
       server_name: server_name_string.to_owned(),
       reverse_proxy: reverse_proxy_config,
+      redirect,
+      static_response,
+      security_headers,
       tls: tls_config,
     })
   }
@@ -301,16 +775,41 @@ impl TryInto<Vec<ReverseProxyConfig>> for &Application {
 
   fn try_into(self) -> std::result::Result<Vec<ReverseProxyConfig>, Self::Error> {
     let _server_name_string = self.server_name.as_ref().ok_or(anyhow!("Missing server_name"))?;
-    let rp_settings = self.reverse_proxy.as_ref().ok_or(anyhow!("Missing reverse_proxy"))?;
+    // reverse_proxy can be omitted only when the app is served by redirect and/or static response rules
+    let has_rules =
+      self.redirect.as_ref().is_some_and(|v| !v.is_empty()) || self.static_response.as_ref().is_some_and(|v| !v.is_empty());
+    let rp_settings = match self.reverse_proxy.as_ref() {
+      Some(rp_settings) => rp_settings,
+      None if has_rules => return Ok(Vec::new()),
+      None => return Err(anyhow!("Missing reverse_proxy")),
+    };
 
     let mut reverse_proxies: Vec<ReverseProxyConfig> = Vec::new();
 
     for rpo in rp_settings.iter() {
-      let upstream_res: Vec<Option<UpstreamUri>> = rpo.upstream.iter().map(|v| v.try_into().ok()).collect();
-      if !upstream_res.iter().all(|v| v.is_some()) {
-        return Err(anyhow!("[{}] Upstream uri is invalid", &_server_name_string));
+      let upstream = rpo
+        .upstream
+        .iter()
+        .map(|v| v.try_into())
+        .collect::<Result<Vec<UpstreamUri>, _>>()
+        .map_err(|e| anyhow!("[{}] Upstream uri is invalid: {e}", &_server_name_string))?;
+      let timeouts = rpo
+        .timeouts
+        .as_ref()
+        .map(|v| v.try_into())
+        .transpose()
+        .map_err(|e| anyhow!("[{}] Upstream timeouts are invalid: {e}", &_server_name_string))?
+        .unwrap_or_default();
+      let mirror = rpo
+        .mirror
+        .as_ref()
+        .map(|v| v.try_into())
+        .transpose()
+        .map_err(|e| anyhow!("[{}] Mirror is invalid: {e}", &_server_name_string))?;
+      let faults = rpo.faults.iter().flatten().map(FaultConfig::from).collect::<Vec<_>>();
+      for fault in faults.iter() {
+        fault.validate().map_err(|e| anyhow!("[{}] {e}", &_server_name_string))?;
       }
-      let upstream = upstream_res.into_iter().map(|v| v.unwrap()).collect();
 
       reverse_proxies.push(ReverseProxyConfig {
         path: rpo.path.clone(),
@@ -318,6 +817,21 @@ impl TryInto<Vec<ReverseProxyConfig>> for &Application {
         upstream,
         upstream_options: rpo.upstream_options.clone(),
         load_balance: rpo.load_balance.clone(),
+        cors: rpo.cors.as_ref().map(|v| CorsConfig {
+          allow_origins: v.allow_origins.clone().unwrap_or_default(),
+          allow_origin_regex: v.allow_origin_regex.clone().unwrap_or_default(),
+          allow_methods: v
+            .allow_methods
+            .clone()
+            .unwrap_or_else(|| CORS_ALLOW_METHODS.iter().map(|m| m.to_string()).collect()),
+          allow_headers: v.allow_headers.clone().unwrap_or_default(),
+          expose_headers: v.expose_headers.clone().unwrap_or_default(),
+          allow_credentials: v.allow_credentials.unwrap_or(false),
+          max_age: v.max_age,
+        }),
+        timeouts,
+        mirror,
+        faults,
       })
     }
 
@@ -325,17 +839,188 @@ impl TryInto<Vec<ReverseProxyConfig>> for &Application {
   }
 }
 
+impl TryInto<UpstreamTimeoutConfig> for &UpstreamTimeoutsOption {
+  type Error = anyhow::Error;
+
+  fn try_into(self) -> std::result::Result<UpstreamTimeoutConfig, Self::Error> {
+    let to_duration = |name: &str, secs: Option<u64>| match secs {
+      Some(0) => Err(anyhow!("{name} timeout must be positive")),
+      Some(secs) => Ok(Some(Duration::from_secs(secs))),
+      None => Ok(None),
+    };
+    Ok(UpstreamTimeoutConfig {
+      connect: to_duration("connect", self.connect)?,
+      response_header: to_duration("response_header", self.response_header)?,
+      request: to_duration("request", self.request)?,
+      body_idle: to_duration("body_idle", self.body_idle)?,
+    })
+  }
+}
+
+impl TryInto<MirrorConfig> for &MirrorOption {
+  type Error = anyhow::Error;
+
+  fn try_into(self) -> std::result::Result<MirrorConfig, Self::Error> {
+    let upstream: UpstreamUri = (&self.upstream).try_into()?;
+    ensure!(upstream.discovery.is_none(), "Shadow upstream does not support discovery");
+    let percentage = self.percentage.unwrap_or(MIRROR_PERCENTAGE);
+    ensure!((1..=100).contains(&percentage), "percentage must be from 1 to 100");
+    let timeout = self.timeout.unwrap_or(MIRROR_TIMEOUT_SEC);
+    ensure!(timeout > 0, "timeout must be positive");
+    Ok(MirrorConfig {
+      upstream,
+      percentage,
+      max_body_size: self.max_body_size.unwrap_or(MIRROR_MAX_BODY_SIZE),
+      timeout: Duration::from_secs(timeout),
+    })
+  }
+}
+
+impl From<&FaultOption> for FaultConfig {
+  fn from(option: &FaultOption) -> Self {
+    FaultConfig {
+      header: option.header.clone(),
+      percentage: option.percentage.unwrap_or(FAULT_PERCENTAGE),
+      delay: option.delay_ms.map(Duration::from_millis),
+      delay_max: option.delay_max_ms.map(Duration::from_millis),
+      abort: option.abort,
+      reset: option.reset.unwrap_or(false),
+    }
+  }
+}
+
 impl TryInto<UpstreamUri> for &UpstreamParams {
   type Error = anyhow::Error;
 
   fn try_into(self) -> std::result::Result<UpstreamUri, Self::Error> {
-    let scheme = match self.tls {
-      Some(true) => "https",
-      _ => "http",
+    let protocol = match self.protocol.as_deref() {
+      Some(v) => UpstreamProtocol::try_from(v).map_err(|e| anyhow!(e))?,
+      None => UpstreamProtocol::Auto,
+    };
+    let tls = self.tls.unwrap_or(false);
+
+    if let Some(path) = self.location.strip_prefix("unix:") {
+      ensure!(!path.is_empty(), "Path of unix domain socket is empty");
+      ensure!(!tls, "tls = true is not supported for unix domain socket upstreams");
+      ensure!(
+        self.tls_options.is_none(),
+        "tls_options is not supported for unix domain socket upstreams"
+      );
+      ensure!(
+        self.discovery.is_none(),
+        "discovery is not supported for unix domain socket upstreams"
+      );
+      ensure!(
+        matches!(protocol, UpstreamProtocol::Auto | UpstreamProtocol::Http1),
+        "HTTP/2 and HTTP/3 are not supported for unix domain socket upstreams"
+      );
+      #[cfg(unix)]
+      return UpstreamUri::unix_socket(path)
+        .map(|v| UpstreamUri { protocol, ..v })
+        .map_err(|e| anyhow!("{}", e));
+      #[cfg(not(unix))]
+      return Err(anyhow!("Unix domain socket upstreams are supported only on unix"));
+    }
+    ensure!(
+      protocol != UpstreamProtocol::H2 || tls,
+      "protocol = \"h2\" requires tls = true, use \"h2c\" for cleartext"
+    );
+    ensure!(
+      protocol != UpstreamProtocol::H2c || !tls,
+      "protocol = \"h2c\" is cleartext and not allowed with tls = true, use \"h2\""
+    );
+    ensure!(
+      protocol != UpstreamProtocol::H3 || tls,
+      "protocol = \"h3\" requires tls = true"
+    );
+
+    let tls_config = match self.tls_options.as_ref() {
+      Some(v) => {
+        ensure!(tls, "tls_options requires tls = true");
+        Some(std::sync::Arc::new(v.try_into()?))
+      }
+      None => None,
     };
+
+    let discovery = match self.discovery.as_deref() {
+      Some(v) => {
+        let ttl = match self.discovery_ttl {
+          Some(0) => return Err(anyhow!("discovery_ttl must be positive")),
+          v => v.map(Duration::from_secs),
+        };
+        // lists of destinations are given by their sources, and records are looked up for the location
+        let mode = match (v, self.discovery_source.as_deref()) {
+          ("file", Some(path)) => UpstreamDiscoveryMode::File(path.into()),
+          ("http", Some(uri)) => {
+            let uri = uri
+              .parse::<Uri>()
+              .map_err(|e| anyhow!("Invalid discovery_source {uri}: {e}"))?;
+            ensure!(
+              matches!(uri.scheme_str(), Some("http" | "https")),
+              "discovery_source must be an http or https url for discovery = \"http\""
+            );
+            UpstreamDiscoveryMode::Http(uri)
+          }
+          ("file" | "http", None) => return Err(anyhow!("discovery = \"{v}\" requires discovery_source")),
+          (_, Some(_)) => return Err(anyhow!("discovery_source is only for discovery = \"file\" or \"http\"")),
+          (_, None) => UpstreamDiscoveryMode::try_from(v).map_err(|e| anyhow!(e))?,
+        };
+        Some(UpstreamDiscoveryConfig { mode, ttl })
+      }
+      None => {
+        ensure!(self.discovery_ttl.is_none(), "discovery_ttl requires discovery");
+        ensure!(self.discovery_source.is_none(), "discovery_source requires discovery");
+        None
+      }
+    };
+
+    let scheme = if tls { "https" } else { "http" };
     let location = format!("{}://{}", scheme, self.location);
+    let inner = location.parse::<Uri>().map_err(|e| anyhow!("{}", e))?;
+    // ports of destinations are given by records or lists other than A/AAAA records
+    if discovery.as_ref().is_some_and(|v| v.mode != UpstreamDiscoveryMode::Dns) {
+      ensure!(
+        inner.port().is_none(),
+        "Port of upstream {} must not be specified for {} discovery",
+        self.location,
+        self.discovery.as_deref().unwrap_or_default()
+      );
+    }
     Ok(UpstreamUri {
-      inner: location.parse::<Uri>().map_err(|e| anyhow!("{}", e))?,
+      inner,
+      protocol,
+      tls_config,
+      discovery,
+    })
+  }
+}
+
+impl TryInto<UpstreamTlsConfig> for &UpstreamTlsOption {
+  type Error = anyhow::Error;
+
+  fn try_into(self) -> std::result::Result<UpstreamTlsConfig, Self::Error> {
+    ensure!(
+      self.client_cert_path.is_some() == self.client_cert_key_path.is_some(),
+      "Both client_cert_path and client_cert_key_path must be specified for client authentication"
+    );
+    let min_version = match self.min_version.as_deref() {
+      Some(v) => UpstreamTlsVersion::try_from(v).map_err(|e| anyhow!(e))?,
+      None => UpstreamTlsVersion::default(),
+    };
+    if let Some(alpn) = self.alpn.as_ref() {
+      ensure!(!alpn.is_empty(), "alpn must not be empty");
+      if let Some(v) = alpn.iter().find(|v| !UPSTREAM_TLS_ALPN_PROTOCOLS.contains(&v.as_str())) {
+        return Err(anyhow!("Unsupported alpn '{v}', which must be either h2 or http/1.1"));
+      }
+    }
+    Ok(UpstreamTlsConfig {
+      ca_cert_path: self.ca_cert_path.as_ref().map(PathBuf::from),
+      client_cert_path: self.client_cert_path.as_ref().map(PathBuf::from),
+      client_cert_key_path: self.client_cert_key_path.as_ref().map(PathBuf::from),
+      server_name: self.server_name.clone(),
+      verify_hostname: self.verify_hostname.unwrap_or(true),
+      min_version,
+      alpn: self.alpn.clone(),
     })
   }
 }
@@ -0,0 +1,180 @@
+use super::toml::ConfigToml;
+use crate::{
+  constants::{CONFIG_WATCH_DEBOUNCE_MAX_MSEC, CONFIG_WATCH_DEBOUNCE_MSEC},
+  log::*,
+};
+use ahash::HashSet;
+use notify::{Event, RecursiveMode, Watcher};
+use std::{
+  fs,
+  hash::{DefaultHasher, Hash, Hasher},
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
+use tokio::{
+  sync::{Notify, mpsc},
+  time::Instant,
+};
+
+/// Watch files referred by the configuration via filesystem notifications, and notify the reload when their contents are changed.
+/// The files are the configuration file, included files, files referred by `file:`, and certificates and keys.
+/// Since editors replace files by renaming and certificate managers swap symbolic links, their parent directories are watched,
+/// and bursts of events are debounced before comparing the contents. The periodic polling remains as a fallback.
+pub async fn watch_config_files(config_file_path: String, reload_notify: Arc<Notify>) {
+  let (tx, mut rx) = mpsc::unbounded_channel();
+  let mut watcher = match notify::recommended_watcher(move |res: notify::Result<Event>| {
+    let _ = tx.send(res);
+  }) {
+    Ok(watcher) => watcher,
+    Err(e) => {
+      warn!("Filesystem notifications are unavailable, and changes of files are tracked only by polling: {e}");
+      return;
+    }
+  };
+
+  let mut snapshot = WatchedSnapshot::new(&config_file_path);
+  let mut watched_dirs = HashSet::default();
+  loop {
+    for dir in snapshot.dirs() {
+      if watched_dirs.contains(&dir) {
+        continue;
+      }
+      match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+        Ok(()) => {
+          debug!("Watch {} for changes of configuration and certificate files", dir.display());
+          watched_dirs.insert(dir);
+        }
+        Err(e) => warn!("Failed to watch {}: {e}", dir.display()),
+      }
+    }
+
+    // wait for an event of the watched files
+    loop {
+      match rx.recv().await {
+        Some(Ok(event)) if snapshot.is_relevant(&event) => break,
+        Some(Ok(_)) => continue,
+        Some(Err(e)) => warn!("Error in filesystem notifications: {e}"),
+        None => return,
+      }
+    }
+    // debounce until events of the watched files calm down, e.g., writing a temporary file and renaming it.
+    // events of other files in the same directories, like logs and caches, don't extend the period, and the whole period is capped.
+    let debounce = Duration::from_millis(CONFIG_WATCH_DEBOUNCE_MSEC);
+    let deadline = Instant::now() + Duration::from_millis(CONFIG_WATCH_DEBOUNCE_MAX_MSEC);
+    let mut calm_at = Instant::now() + debounce;
+    loop {
+      match tokio::time::timeout_at(calm_at.min(deadline), rx.recv()).await {
+        Err(_) => break,
+        Ok(Some(Ok(event))) if snapshot.is_relevant(&event) => calm_at = Instant::now() + debounce,
+        Ok(Some(Ok(_))) => continue,
+        Ok(Some(Err(e))) => warn!("Error in filesystem notifications: {e}"),
+        Ok(None) => return,
+      }
+    }
+
+    let new_snapshot = WatchedSnapshot::new(&config_file_path);
+    if new_snapshot != snapshot {
+      info!("Changes in configuration or certificate files are detected");
+      reload_notify.notify_one();
+    }
+    snapshot = new_snapshot;
+  }
+}
+
+/// Snapshot of the configuration and contents of certificate files to detect their changes
+#[derive(PartialEq, Eq)]
+struct WatchedSnapshot {
+  /// configuration file
+  config_file: PathBuf,
+  /// loaded configuration, None if invalid
+  config: Option<ConfigToml>,
+  /// certificate, key and CA files of apps and upstreams with hashes of their contents
+  cert_files: Vec<(PathBuf, Option<u64>)>,
+}
+
+impl WatchedSnapshot {
+  fn new(config_file_path: &str) -> Self {
+    let config = ConfigToml::new_with_unused_fields(config_file_path)
+      .ok()
+      .map(|(config, _)| config);
+    let apps = config
+      .iter()
+      .flat_map(|config| config.apps.iter().flat_map(|apps| apps.0.values()));
+    let server_cert_files = apps
+      .clone()
+      .filter_map(|app| app.tls.as_ref())
+      .flat_map(|tls| [&tls.tls_cert_path, &tls.tls_cert_key_path, &tls.client_ca_cert_path]);
+    let upstream_cert_files = apps
+      .flat_map(|app| app.reverse_proxy.iter().flatten())
+      .flat_map(|rp| rp.upstream.iter())
+      .filter_map(|upstream| upstream.tls_options.as_ref())
+      .flat_map(|tls| [&tls.ca_cert_path, &tls.client_cert_path, &tls.client_cert_key_path]);
+    let cert_files = server_cert_files
+      .chain(upstream_cert_files)
+      .flatten()
+      .map(|path| (absolute(Path::new(path)), content_hash(Path::new(path))))
+      .collect();
+    Self {
+      config_file: absolute(Path::new(config_file_path)),
+      config,
+      cert_files,
+    }
+  }
+
+  /// Watched files
+  fn files(&self) -> impl Iterator<Item = PathBuf> + '_ {
+    let config_files = self.config.iter().flat_map(|v| v.sources.files.iter()).map(|v| absolute(v));
+    std::iter::once(self.config_file.clone())
+      .chain(config_files)
+      .chain(self.cert_files.iter().map(|(v, _)| v.clone()))
+  }
+
+  /// Directories of watched files and those searched for included files
+  fn dirs(&self) -> Vec<PathBuf> {
+    let include_dirs = self
+      .config
+      .iter()
+      .flat_map(|v| v.sources.include_dirs.iter())
+      .map(|v| absolute(v));
+    let mut dirs = self
+      .files()
+      .filter_map(|v| v.parent().map(|v| v.to_path_buf()))
+      .chain(include_dirs)
+      .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+  }
+
+  /// Check if the event is possibly of watched files, where all events are regarded so if the configuration is invalid
+  fn is_relevant(&self, event: &Event) -> bool {
+    if event.kind.is_access() {
+      return false;
+    }
+    let Some(config) = self.config.as_ref() else {
+      return true;
+    };
+    let files = self.files().collect::<Vec<_>>();
+    let include_dirs = config.sources.include_dirs.iter().map(|v| absolute(v)).collect::<Vec<_>>();
+    event.paths.iter().any(|path| {
+      files.contains(path)
+        || path.parent().is_some_and(|dir| include_dirs.iter().any(|v| v == dir))
+        // symbolic links swapped like "..data" of secrets mounted on kubernetes
+        || path.file_name().is_some_and(|v| v.to_string_lossy().starts_with(".."))
+    })
+  }
+}
+
+/// Absolute path without resolving symbolic links, which is compared with paths of events
+fn absolute(path: &Path) -> PathBuf {
+  std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Hash of the file content, None if unreadable
+fn content_hash(path: &Path) -> Option<u64> {
+  let content = fs::read(path).ok()?;
+  let mut hasher = DefaultHasher::new();
+  content.hash(&mut hasher);
+  Some(hasher.finish())
+}
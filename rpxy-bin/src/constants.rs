@@ -1,6 +1,37 @@
 pub const LISTEN_ADDRESSES_V4: &[&str] = &["0.0.0.0"];
 pub const LISTEN_ADDRESSES_V6: &[&str] = &["[::]"];
+/// Interval of polling the configuration file, which is a fallback of filesystem notifications
 pub const CONFIG_WATCH_DELAY_SECS: u32 = 15;
+/// Period to wait for filesystem notifications to calm down before reloading
+pub const CONFIG_WATCH_DEBOUNCE_MSEC: u64 = 500;
+/// Max period to wait for filesystem notifications to calm down, after which the reload is checked anyway
+pub const CONFIG_WATCH_DEBOUNCE_MAX_MSEC: u64 = 5_000;
+/// Default status code of redirect rules
+pub const REDIRECT_RULE_STATUS_CODE: u16 = 301;
+/// Default status code of static response rules
+pub const STATIC_RESPONSE_STATUS_CODE: u16 = 200;
+/// ALPN protocols allowed in TLS settings of upstreams
+pub const UPSTREAM_TLS_ALPN_PROTOCOLS: &[&str] = &["h2", "http/1.1"];
+/// Default allowed methods of CORS policy
+pub const CORS_ALLOW_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"];
+/// Default percentage of requests mirrored to shadow upstreams
+pub const MIRROR_PERCENTAGE: u8 = 100;
+/// Default max size of request bodies buffered for mirroring (64KiB)
+pub const MIRROR_MAX_BODY_SIZE: usize = 65_536;
+/// Default timeout of mirrored requests in seconds
+pub const MIRROR_TIMEOUT_SEC: u64 = 10;
+/// Default percentage of requests to which fault injection rules apply
+pub const FAULT_PERCENTAGE: u8 = 100;
+/// Default path of the metrics endpoint
+pub const METRICS_PATH: &str = "/metrics";
+/// Default sampler of OpenTelemetry tracing
+#[cfg(feature = "otel")]
+pub const OTEL_SAMPLER: &str = "parentbased_always_on";
+/// Default service name of OpenTelemetry tracing
+#[cfg(feature = "otel")]
+pub const OTEL_SERVICE_NAME: &str = "rpxy";
+/// Default max-age of HSTS (1 year)
+pub const HSTS_MAX_AGE: u64 = 31_536_000;
 
 #[cfg(feature = "cache")]
 // Cache directory
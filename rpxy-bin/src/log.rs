@@ -1,31 +1,39 @@
 use crate::constants::{ACCESS_LOG_FILE, SYSTEM_LOG_FILE};
 use rpxy_lib::log_event_names;
-use std::str::FromStr;
-use tracing_subscriber::{fmt, prelude::*};
+use std::{fmt::Write as _, net::SocketAddr, str::FromStr};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::{
+  fmt::{
+    self,
+    format::{FormatEvent, FormatFields, Writer},
+  },
+  prelude::*,
+  registry::LookupSpan,
+};
 
 #[allow(unused)]
 pub use tracing::{debug, error, info, warn};
 
 /// Initialize the logger with the RUST_LOG environment variable.
-pub fn init_logger(log_dir_path: Option<&str>) {
+pub fn init_logger(log_dir_path: Option<&str>, access_log_format: &AccessLogFormat) {
   let level_string = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
   let level = tracing::Level::from_str(level_string.as_str()).unwrap_or(tracing::Level::INFO);
 
   match log_dir_path {
     None => {
       // log to stdout
-      init_stdio_logger(level);
+      init_stdio_logger(level, access_log_format);
     }
     Some(log_dir_path) => {
       // log to files
       println!("Activate logging to files: {log_dir_path}");
-      init_file_logger(level, log_dir_path);
+      init_file_logger(level, log_dir_path, access_log_format);
     }
   }
 }
 
 /// file logging TODO:
-fn init_file_logger(level: tracing::Level, log_dir_path: &str) {
+fn init_file_logger(level: tracing::Level, log_dir_path: &str, access_log_format: &AccessLogFormat) {
   let log_dir_path = std::path::PathBuf::from(log_dir_path);
   // create the directory if it does not exist
   if !log_dir_path.exists() {
@@ -43,12 +51,7 @@ fn init_file_logger(level: tracing::Level, log_dir_path: &str) {
   let reg = tracing_subscriber::registry();
 
   let access_log_base = fmt::layer()
-    .with_line_number(false)
-    .with_thread_ids(false)
-    .with_thread_names(false)
-    .with_target(false)
-    .with_level(false)
-    .compact()
+    .event_format(AccessLogFormatter::from(access_log_format))
     .with_ansi(false);
   let reg = reg.with(access_log_base.with_writer(access_log).with_filter(AccessLogFilter));
 
@@ -77,9 +80,13 @@ fn init_file_logger(level: tracing::Level, log_dir_path: &str) {
 }
 
 /// stdio logging
-fn init_stdio_logger(level: tracing::Level) {
+fn init_stdio_logger(level: tracing::Level, access_log_format: &AccessLogFormat) {
   // This limits the logger to emits only this crate with any level above RUST_LOG, for included crates it will emit only ERROR (in prod)/INFO (in dev) or above level.
   let stdio_layer = fmt::layer().with_level(true).with_thread_ids(false);
+  // Access logs are emitted by the dedicated layer in the given format
+  let access_log_layer = fmt::layer()
+    .event_format(AccessLogFormatter::from(access_log_format))
+    .with_filter(AccessLogFilter);
   if level <= tracing::Level::INFO {
     // in normal deployment environment
     let stdio_layer = stdio_layer
@@ -89,10 +96,11 @@ fn init_stdio_logger(level: tracing::Level) {
         (metadata
           .target()
           .starts_with(env!("CARGO_PKG_NAME").replace('-', "_").as_str())
+          && metadata.name() != log_event_names::ACCESS_LOG
           && metadata.level() <= &level)
           || metadata.level() <= &tracing::Level::WARN.min(level)
       }));
-    tracing_subscriber::registry().with(stdio_layer).init();
+    tracing_subscriber::registry().with(access_log_layer).with(stdio_layer).init();
   } else {
     // debugging
     let stdio_layer = stdio_layer
@@ -105,13 +113,247 @@ fn init_stdio_logger(level: tracing::Level) {
         (metadata
           .target()
           .starts_with(env!("CARGO_PKG_NAME").replace('-', "_").as_str())
+          && metadata.name() != log_event_names::ACCESS_LOG
           && metadata.level() <= &level)
           || metadata.level() <= &tracing::Level::INFO.min(level)
       }));
-    tracing_subscriber::registry().with(stdio_layer).init();
+    tracing_subscriber::registry().with(access_log_layer).with(stdio_layer).init();
   };
 }
 
+/// Format of access logs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessLogFormat {
+  /// Free-text line of rpxy
+  Default,
+  /// JSON lines with all fields
+  Json,
+  /// Common Log Format
+  Common,
+  /// Combined Log Format, i.e., Common Log Format with referer and user agent
+  Combined,
+  /// Custom template with `{field}` placeholders, e.g., "{client_addr} {method} {path} {status} {duration_ms}"
+  Custom(String),
+}
+
+impl FromStr for AccessLogFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "default" => Ok(Self::Default),
+      "json" => Ok(Self::Json),
+      "common" => Ok(Self::Common),
+      "combined" => Ok(Self::Combined),
+      _ if s.contains('{') => validate_template(s).map(|_| Self::Custom(s.to_string())),
+      _ => Err(format!(
+        "Invalid access log format: {s} (default, json, common, combined, or a template with {{field}} placeholders)"
+      )),
+    }
+  }
+}
+
+/// Fields available in custom templates, i.e., `{timestamp}`, the message and the fields of access log events by rpxy-lib
+const ACCESS_LOG_FIELDS: &[&str] = &[
+  "timestamp",
+  "message",
+  "host",
+  "client_addr",
+  "method",
+  "path",
+  "version",
+  "uri",
+  "status",
+  "user_agent",
+  "referer",
+  "xff",
+  "upstream",
+  "request_id",
+  "duration_ms",
+  "upstream_time_ms",
+  "bytes_in",
+  "bytes_out",
+  "tls_version",
+  "tls_cipher",
+  "sni",
+  "cache",
+];
+
+/// Check that every placeholder of the custom template is closed and names a known field
+fn validate_template(template: &str) -> Result<(), String> {
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    let Some(len) = rest[start..].find('}') else {
+      return Err(format!("Unclosed placeholder in access log format: {template}"));
+    };
+    let name = &rest[start + 1..start + len];
+    if !ACCESS_LOG_FIELDS.contains(&name) {
+      return Err(format!(
+        "Unknown placeholder {{{name}}} in access log format (available: {})",
+        ACCESS_LOG_FIELDS.join(", ")
+      ));
+    }
+    rest = &rest[start + len + 1..];
+  }
+  Ok(())
+}
+
+/// Field value of an access log event
+enum AccessLogValue {
+  Str(String),
+  Int(u64),
+  Float(f64),
+}
+
+impl std::fmt::Display for AccessLogValue {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      AccessLogValue::Str(v) => write!(f, "{v}"),
+      AccessLogValue::Int(v) => write!(f, "{v}"),
+      AccessLogValue::Float(v) => write!(f, "{v:.3}"),
+    }
+  }
+}
+
+#[derive(Default)]
+/// Fields of an access log event in the recorded order
+struct AccessLogFields(Vec<(&'static str, AccessLogValue)>);
+
+impl Visit for AccessLogFields {
+  fn record_f64(&mut self, field: &Field, value: f64) {
+    self.0.push((field.name(), AccessLogValue::Float(value)));
+  }
+  fn record_u64(&mut self, field: &Field, value: u64) {
+    self.0.push((field.name(), AccessLogValue::Int(value)));
+  }
+  fn record_str(&mut self, field: &Field, value: &str) {
+    self.0.push((field.name(), AccessLogValue::Str(value.to_string())));
+  }
+  fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+    self.0.push((field.name(), AccessLogValue::Str(format!("{value:?}"))));
+  }
+}
+
+impl AccessLogFields {
+  /// Get the field value as string, where empty or missing values are rendered as "-"
+  fn get(&self, name: &str) -> String {
+    self
+      .0
+      .iter()
+      .find(|(k, _)| *k == name)
+      .map(|(_, v)| v.to_string())
+      .filter(|v| !v.is_empty())
+      .unwrap_or_else(|| "-".to_string())
+  }
+
+  /// Common Log Format without the trailing fields of Combined Log Format
+  fn common(&self) -> String {
+    let client_addr = self.get("client_addr");
+    let client_ip = client_addr
+      .parse::<SocketAddr>()
+      .map(|v| v.ip().to_string())
+      .unwrap_or(client_addr);
+    format!(
+      "{} - - [{}] \"{} {} {}\" {} {}",
+      client_ip,
+      chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+      self.get("method"),
+      self.get("path"),
+      self.get("version"),
+      self.get("status"),
+      self.get("bytes_out"),
+    )
+  }
+
+  fn json(&self) -> String {
+    let mut line = format!(
+      "{{\"timestamp\":\"{}\"",
+      chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+    );
+    for (name, value) in self.0.iter() {
+      let value = match value {
+        AccessLogValue::Str(v) if v.is_empty() => continue,
+        AccessLogValue::Str(v) => serde_json::Value::from(v.as_str()),
+        AccessLogValue::Int(v) => serde_json::Value::from(*v),
+        AccessLogValue::Float(v) => serde_json::Value::from((v * 1000.0).round() / 1000.0),
+      };
+      let _ = write!(line, ",\"{name}\":{value}");
+    }
+    line.push('}');
+    line
+  }
+
+  /// Render the template by replacing `{field}` placeholders, where `{timestamp}` is also available
+  fn custom(&self, template: &str) -> String {
+    let mut line = String::with_capacity(template.len() * 2);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+      line.push_str(&rest[..start]);
+      let Some(len) = rest[start..].find('}') else {
+        // unclosed brace is kept as is, though such templates are rejected on parsing the format
+        line.push_str(&rest[start..]);
+        rest = "";
+        break;
+      };
+      let name = &rest[start + 1..start + len];
+      match name {
+        "timestamp" => line.push_str(&chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true)),
+        _ => line.push_str(&self.get(name)),
+      }
+      rest = &rest[start + len + 1..];
+    }
+    line.push_str(rest);
+    line
+  }
+}
+
+/// Event formatter for access logs emitted by rpxy-lib with structured fields
+struct AccessLogFormatter {
+  format: AccessLogFormat,
+}
+
+impl From<&AccessLogFormat> for AccessLogFormatter {
+  fn from(format: &AccessLogFormat) -> Self {
+    Self { format: format.clone() }
+  }
+}
+
+impl<S, N> FormatEvent<S, N> for AccessLogFormatter
+where
+  S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+  N: for<'a> FormatFields<'a> + 'static,
+{
+  fn format_event(
+    &self,
+    _ctx: &fmt::FmtContext<'_, S, N>,
+    mut writer: Writer<'_>,
+    event: &tracing::Event<'_>,
+  ) -> std::fmt::Result {
+    let mut fields = AccessLogFields::default();
+    event.record(&mut fields);
+    let line = match &self.format {
+      AccessLogFormat::Default => format!(
+        "{} {}",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        fields.get("message")
+      ),
+      AccessLogFormat::Json => {
+        fields.0.retain(|(k, _)| *k != "message");
+        fields.json()
+      }
+      AccessLogFormat::Common => fields.common(),
+      AccessLogFormat::Combined => format!(
+        "{} \"{}\" \"{}\"",
+        fields.common(),
+        fields.get("referer"),
+        fields.get("user_agent")
+      ),
+      AccessLogFormat::Custom(template) => fields.custom(template),
+    };
+    writeln!(writer, "{line}")
+  }
+}
+
 /// Access log filter
 struct AccessLogFilter;
 impl<S> tracing_subscriber::layer::Filter<S> for AccessLogFilter {
@@ -137,3 +379,38 @@ where
     .open(path)
     .expect("Failed to open the log file")
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn custom_format_rejects_invalid_placeholders() {
+    let format = "{client_addr} \"{method} {path}\" {status}"
+      .parse::<AccessLogFormat>()
+      .unwrap();
+    assert!(matches!(format, AccessLogFormat::Custom(_)));
+    assert!("{timestamp} {request_id}".parse::<AccessLogFormat>().is_ok());
+
+    assert!("{client_addr} {status".parse::<AccessLogFormat>().is_err());
+    assert!("{client_addr} {unknown}".parse::<AccessLogFormat>().is_err());
+    assert!("{}".parse::<AccessLogFormat>().is_err());
+  }
+
+  #[test]
+  fn custom_format_renders_fields() {
+    let fields = AccessLogFields(vec![
+      ("method", AccessLogValue::Str("GET".to_string())),
+      ("status", AccessLogValue::Int(200)),
+      ("duration_ms", AccessLogValue::Float(1.23456)),
+      ("upstream", AccessLogValue::Str(String::new())),
+    ]);
+    assert_eq!(
+      fields.custom("{method} {status} {duration_ms}ms {upstream}"),
+      "GET 200 1.235ms -"
+    );
+    // unclosed brace is kept as is without repeating the preceding text
+    assert_eq!(fields.custom("a {b"), "a {b");
+    assert_eq!(fields.custom("{method} a {b"), "GET a {b");
+  }
+}
@@ -5,23 +5,33 @@ mod config;
 mod constants;
 mod error;
 mod log;
+#[cfg(feature = "otel")]
+mod otel;
 
 #[cfg(feature = "acme")]
 use crate::config::build_acme_manager;
 use crate::{
-  config::{ConfigToml, ConfigTomlReloader, Opts, Parser, build_cert_manager, build_settings},
+  config::{
+    Command, ConfigToml, ConfigTomlReloader, Opts, Parser, build_admin_config, build_cert_manager, build_metrics_config,
+    build_settings, check_config, dump_config, watch_config_files,
+  },
   constants::CONFIG_WATCH_DELAY_SECS,
   error::*,
   log::*,
 };
+use futures_util::{FutureExt, future::BoxFuture};
 use hot_reload::{ReloaderReceiver, ReloaderService};
-use rpxy_lib::{RpxyOptions, RpxyOptionsBuilder, entrypoint};
+use rpxy_lib::{Admin, ConfigUpdater, Metrics, RpxyOptions, RpxyOptionsBuilder, admin_service, entrypoint, metrics_service};
 use std::sync::Arc;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
 fn main() {
   let parsed_opts = Opts::parse();
-  init_logger(parsed_opts.log_dir_path.as_deref());
+  if let Some(command) = parsed_opts.command.as_ref() {
+    run_command(command, &parsed_opts.config_file_path);
+  }
+  init_logger(parsed_opts.log_dir_path.as_deref(), &parsed_opts.access_log_format);
 
   // Validate configuration before starting Tokio runtime
   if let Err(e) = ConfigToml::new(&parsed_opts.config_file_path) {
@@ -49,7 +59,7 @@ fn main() {
           std::process::exit(1);
         }
       }
-      rpxy_res = rpxy_service(config_rx, runtime.handle().clone()) => {
+      rpxy_res = rpxy_service(config_rx, &parsed_opts.config_file_path, runtime.handle().clone()) => {
         if let Err(e) = rpxy_res {
           error!("rpxy service existed: {e}");
           std::process::exit(1);
@@ -60,6 +70,35 @@ fn main() {
   });
 }
 
+/// Run the subcommand inspecting the configuration file without starting the proxy, and exit
+fn run_command(command: &Command, config_file_path: &str) -> ! {
+  match command {
+    Command::Check => {
+      let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+      let errors = runtime.block_on(check_config(config_file_path));
+      if errors.is_empty() {
+        println!("{config_file_path}: configuration is valid");
+        std::process::exit(0);
+      }
+      for e in errors.iter() {
+        eprintln!("error: {e}");
+      }
+      eprintln!("{config_file_path}: {} error(s) found", errors.len());
+      std::process::exit(1);
+    }
+    Command::Dump => match ConfigToml::new(config_file_path).and_then(|config| dump_config(&config)) {
+      Ok(v) => {
+        println!("{}", serde_json::to_string_pretty(&v).unwrap_or_default());
+        std::process::exit(0);
+      }
+      Err(e) => {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+      }
+    },
+  }
+}
+
 /// rpxy service definition
 struct RpxyService {
   runtime_handle: tokio::runtime::Handle,
@@ -67,12 +106,21 @@ struct RpxyService {
   app_conf: rpxy_lib::AppConfigList,
   cert_service: Option<Arc<ReloaderService<rpxy_certs::CryptoReloader, rpxy_certs::ServerCryptoBase>>>,
   cert_rx: Option<ReloaderReceiver<rpxy_certs::ServerCryptoBase>>,
+  metrics: Metrics,
+  admin: Admin,
+  config_updater: ConfigUpdater,
   #[cfg(feature = "acme")]
   acme_manager: Option<rpxy_acme::AcmeManager>,
 }
 
 impl RpxyService {
-  async fn new(config_toml: &ConfigToml, runtime_handle: tokio::runtime::Handle) -> Result<Self, anyhow::Error> {
+  async fn new(
+    config_toml: &ConfigToml,
+    runtime_handle: tokio::runtime::Handle,
+    metrics: Metrics,
+    admin: Admin,
+    config_updater: ConfigUpdater,
+  ) -> Result<Self, anyhow::Error> {
     let (proxy_conf, app_conf) = build_settings(config_toml).map_err(|e| anyhow!("Invalid configuration: {e}"))?;
 
     let (cert_service, cert_rx) = build_cert_manager(config_toml)
@@ -87,147 +135,95 @@ impl RpxyService {
       app_conf,
       cert_service,
       cert_rx,
+      metrics,
+      admin,
+      config_updater,
       #[cfg(feature = "acme")]
       acme_manager: build_acme_manager(config_toml, runtime_handle.clone()).await?,
     })
   }
 
-  async fn start(&self, cancel_token: CancellationToken) -> Result<(), anyhow::Error> {
-    let RpxyService {
-      runtime_handle,
-      proxy_conf,
-      app_conf,
-      cert_service: _,
-      cert_rx,
-      #[cfg(feature = "acme")]
-      acme_manager,
-    } = self;
-
-    #[cfg(feature = "acme")]
-    {
-      let (acme_join_handles, server_config_acme_challenge) = acme_manager
-        .as_ref()
-        .map(|m| m.spawn_manager_tasks(cancel_token.child_token()))
-        .unwrap_or((vec![], Default::default()));
-      let rpxy_opts = RpxyOptionsBuilder::default()
-        .proxy_config(proxy_conf.clone())
-        .app_config_list(app_conf.clone())
-        .cert_rx(cert_rx.clone())
-        .runtime_handle(runtime_handle.clone())
-        .server_configs_acme_challenge(Arc::new(server_config_acme_challenge))
-        .build()?;
-      self
-        .start_inner(rpxy_opts, cancel_token, acme_join_handles)
-        .await
-        .map_err(|e| anyhow!(e))
-    }
-
-    #[cfg(not(feature = "acme"))]
-    {
-      let rpxy_opts = RpxyOptionsBuilder::default()
-        .proxy_config(proxy_conf.clone())
-        .app_config_list(app_conf.clone())
-        .cert_rx(cert_rx.clone())
-        .runtime_handle(runtime_handle.clone())
-        .build()?;
-      self.start_inner(rpxy_opts, cancel_token).await.map_err(|e| anyhow!(e))
-    }
-  }
-
-  /// Wrapper of entry point for rpxy service with certificate management service
-  async fn start_inner(
+  /// Spawn certificate reloader and acme manager tasks for this configuration, and build options of proxy services served with them.
+  /// The returned handle finishes when either of the tasks exits or the cancellation token is cancelled.
+  fn spawn_tasks(
     &self,
-    rpxy_opts: RpxyOptions,
     cancel_token: CancellationToken,
-    #[cfg(feature = "acme")] acme_task_handles: Vec<tokio::task::JoinHandle<()>>,
-  ) -> Result<(), anyhow::Error> {
-    let cancel_token = cancel_token.clone();
-    let runtime_handle = rpxy_opts.runtime_handle.clone();
+  ) -> Result<(RpxyOptions, JoinHandle<Result<(), anyhow::Error>>), anyhow::Error> {
+    let mut tasks: Vec<BoxFuture<'static, Result<(), anyhow::Error>>> = vec![];
+    let mut rpxy_opts = RpxyOptionsBuilder::default();
+    rpxy_opts
+      .proxy_config(self.proxy_conf.clone())
+      .app_config_list(self.app_conf.clone())
+      .cert_rx(self.cert_rx.clone())
+      .runtime_handle(self.runtime_handle.clone())
+      .metrics(self.metrics.clone())
+      .admin(self.admin.clone())
+      .config_updater(self.config_updater.clone());
 
-    // spawn rpxy entrypoint, where cancellation token is possibly contained inside the service
-    let cancel_token_clone = cancel_token.clone();
-    let child_cancel_token = cancel_token.child_token();
-    let rpxy_handle = runtime_handle.spawn(async move {
-      if let Err(e) = entrypoint(&rpxy_opts, child_cancel_token).await {
-        error!("rpxy entrypoint exited on error: {e}");
-        cancel_token_clone.cancel();
-        return Err(anyhow!(e));
-      }
-      Ok(())
-    });
-
-    if self.cert_service.is_none() {
-      return rpxy_handle.await?;
-    }
-
-    // spawn certificate reloader service, where cert service does not have cancellation token inside the service
-    let cert_service = self.cert_service.as_ref().unwrap().clone();
-    let cancel_token_clone = cancel_token.clone();
-    let child_cancel_token = cancel_token.child_token();
-    let cert_handle = runtime_handle.spawn(async move {
-      tokio::select! {
-        cert_res = cert_service.start() => {
-          if let Err(ref e) = cert_res {
-            error!("cert reloader service exited on error: {e}");
+    // certificate reloader service, where cert service does not have cancellation token inside the service
+    if let Some(cert_service) = self.cert_service.clone() {
+      let cancel_token = cancel_token.clone();
+      tasks.push(
+        async move {
+          tokio::select! {
+            cert_res = cert_service.start() => {
+              if let Err(ref e) = cert_res {
+                error!("cert reloader service exited on error: {e}");
+              }
+              cert_res.map_err(|e| anyhow!(e))
+            }
+            _ = cancel_token.cancelled() => {
+              debug!("cert reloader service terminated");
+              Ok(())
+            }
           }
-          cancel_token_clone.cancel();
-          cert_res.map_err(|e| anyhow!(e))
-        }
-        _ = child_cancel_token.cancelled() => {
-          debug!("cert reloader service terminated");
-          Ok(())
         }
-      }
-    });
-
-    #[cfg(not(feature = "acme"))]
-    {
-      let (rpxy_res, cert_res) = tokio::join!(rpxy_handle, cert_handle);
-      let (rpxy_res, cert_res) = (rpxy_res?, cert_res?);
-      match (rpxy_res, cert_res) {
-        (Ok(()), Ok(())) => Ok(()),
-        (Err(e), _) => Err(e),
-        (_, Err(e)) => Err(e),
-      }
+        .boxed(),
+      );
     }
 
+    // acme manager tasks, where cancellation token is possibly contained inside the service
     #[cfg(feature = "acme")]
     {
-      if acme_task_handles.is_empty() {
-        let (rpxy_res, cert_res) = tokio::join!(rpxy_handle, cert_handle);
-        let (rpxy_res, cert_res) = (rpxy_res?, cert_res?);
-        return match (rpxy_res, cert_res) {
-          (Ok(()), Ok(())) => Ok(()),
-          (Err(e), _) => Err(e),
-          (_, Err(e)) => Err(e),
-        };
-      }
-
-      // spawn acme manager tasks, where cancellation token is possibly contained inside the service
-      let select_all = futures_util::future::select_all(acme_task_handles);
-      let cancel_token_clone = cancel_token.clone();
-      let acme_handle = runtime_handle.spawn(async move {
-        let (acme_res, _, _) = select_all.await;
-        if let Err(ref e) = acme_res {
-          error!("acme manager exited on error: {e}");
+      let acme_event_hook: rpxy_acme::AcmeEventHook = {
+        let metrics = self.metrics.clone();
+        Arc::new(move |domain: &str, event: &str| metrics.record_acme_event(domain, event))
+      };
+      let (acme_join_handles, server_config_acme_challenge) = self
+        .acme_manager
+        .as_ref()
+        .map(|m| m.spawn_manager_tasks(cancel_token.child_token(), Some(acme_event_hook)))
+        .unwrap_or((vec![], Default::default()));
+      rpxy_opts.server_configs_acme_challenge(Arc::new(server_config_acme_challenge));
+      tasks.extend(acme_join_handles.into_iter().map(|handle| {
+        async move {
+          let acme_res = handle.await;
+          if let Err(ref e) = acme_res {
+            error!("acme manager exited on error: {e}");
+          }
+          acme_res.map_err(|e| anyhow!(e))
         }
-        cancel_token_clone.cancel();
-        acme_res.map_err(|e| anyhow!(e))
-      });
-      let (rpxy_res, cert_res, acme_res) = tokio::join!(rpxy_handle, cert_handle, acme_handle);
-      let (rpxy_res, cert_res, acme_res) = (rpxy_res?, cert_res?, acme_res?);
-      match (rpxy_res, cert_res, acme_res) {
-        (Ok(()), Ok(()), Ok(())) => Ok(()),
-        (Err(e), _, _) => Err(e),
-        (_, Err(e), _) => Err(e),
-        (_, _, Err(e)) => Err(e),
-      }
+        .boxed()
+      }));
     }
+
+    let tasks_handle = self.runtime_handle.spawn(async move {
+      if tasks.is_empty() {
+        cancel_token.cancelled().await;
+        return Ok(());
+      }
+      let (res, _, _) = futures_util::future::select_all(tasks).await;
+      // cancel other tasks
+      cancel_token.cancel();
+      res
+    });
+    Ok((rpxy_opts.build()?, tasks_handle))
   }
 }
 
 async fn rpxy_service(
   mut config_rx: ReloaderReceiver<ConfigToml, String>,
+  config_file_path: &str,
   runtime_handle: tokio::runtime::Handle,
 ) -> Result<(), anyhow::Error> {
   info!("Start rpxy service with dynamic config reloader");
@@ -237,41 +233,238 @@ async fn rpxy_service(
     .borrow()
     .clone()
     .ok_or(anyhow!("Something wrong in config reloader receiver"))?;
-  let mut service = RpxyService::new(&config_toml, runtime_handle.clone()).await?;
+  // Metrics are kept over the configuration reloads, and the metrics listener is not re-bound at reloads
+  let metrics = Metrics::default();
+  let metrics_config = build_metrics_config(&config_toml).map_err(|e| anyhow!("Invalid metrics configuration: {e}"))?;
+  if let Some(metrics_config) = metrics_config.clone() {
+    let (metrics, runtime_handle_clone) = (metrics.clone(), runtime_handle.clone());
+    runtime_handle.spawn(async move {
+      if let Err(e) = metrics_service(&metrics_config, metrics, runtime_handle_clone).await {
+        error!("metrics service exited on error: {e}");
+      }
+    });
+  }
+  // Admin API is kept over the configuration reloads as well, where reloads requested via the API are notified here
+  // as well as those triggered by SIGHUP and filesystem notifications of configuration and certificate files
+  let reload_notify = Arc::new(tokio::sync::Notify::new());
+  runtime_handle.spawn(watch_config_files(config_file_path.to_string(), reload_notify.clone()));
+  #[cfg(unix)]
+  runtime_handle.spawn(reload_signal(reload_notify.clone()));
+  let admin = {
+    let reload_notify = reload_notify.clone();
+    Admin::new(Arc::new(move || reload_notify.notify_one()))
+  };
+  let admin_config = build_admin_config(&config_toml).map_err(|e| anyhow!("Invalid admin configuration: {e}"))?;
+  if let Some(admin_config) = admin_config.clone() {
+    let (admin, runtime_handle_clone) = (admin.clone(), runtime_handle.clone());
+    runtime_handle.spawn(async move {
+      if let Err(e) = admin_service(&admin_config, admin, runtime_handle_clone).await {
+        error!("admin service exited on error: {e}");
+      }
+    });
+  }
+  // Tracer provider is installed globally only at the initial loading as well as the metrics listener
+  #[cfg(feature = "otel")]
+  let tracer_provider = config_toml
+    .opentelemetry
+    .as_ref()
+    .map(otel::init_tracer_provider)
+    .transpose()
+    .map_err(|e| anyhow!("Invalid OpenTelemetry configuration: {e}"))?;
+  // Configuration is updated in place via the updater while listen sockets are unchanged
+  let config_updater = ConfigUpdater::default();
+  let mut service = RpxyService::new(
+    &config_toml,
+    runtime_handle.clone(),
+    metrics.clone(),
+    admin.clone(),
+    config_updater.clone(),
+  )
+  .await?;
+  // Configuration currently applied, to skip reloads by polling if already applied via notifications
+  let mut applied_config_toml = config_toml.clone();
+
+  // Signals to shut down gracefully, which are watched over the configuration reloads
+  let shutdown_signal = shutdown_signal();
+  tokio::pin!(shutdown_signal);
 
   // Continuous monitoring
   loop {
-    // Notifier for proxy service termination
-    let cancel_token = tokio_util::sync::CancellationToken::new();
+    // Notifier for proxy service termination, where TCP/UDP sockets are re-bound at every iteration
+    let cancel_token = CancellationToken::new();
+    // Notifier for termination of certificate and acme tasks, which are replaced at every configuration update
+    let mut tasks_cancel_token = cancel_token.child_token();
+    let (rpxy_opts, mut tasks_handle) = service.spawn_tasks(tasks_cancel_token.clone())?;
 
-    tokio::select! {
-      /* ---------- */
-      rpxy_res = service.start(cancel_token.clone()) => {
-        if let Err(ref e) = rpxy_res {
-          error!("rpxy service exited on error: {e}");
-        } else {
-          error!("rpxy service exited");
+    // spawn rpxy entrypoint, where cancellation token is possibly contained inside the service
+    let child_cancel_token = cancel_token.child_token();
+    let mut rpxy_handle = runtime_handle.spawn(async move { entrypoint(&rpxy_opts, child_cancel_token).await });
+
+    loop {
+      let new_config_toml = tokio::select! {
+        /* ---------- */
+        rpxy_res = &mut rpxy_handle => {
+          let rpxy_res = rpxy_res.map_err(|e| anyhow!(e)).and_then(|res| res.map_err(|e| anyhow!(e)));
+          if let Err(ref e) = rpxy_res {
+            error!("rpxy service exited on error: {e}");
+          } else {
+            error!("rpxy service exited");
+          }
+          cancel_token.cancel();
+          // flush remaining spans
+          #[cfg(feature = "otel")]
+          if let Some(tracer_provider) = tracer_provider.as_ref() {
+            tracer_provider.shutdown().ok();
+          }
+          return rpxy_res;
         }
-        return rpxy_res.map_err(|e| anyhow!(e));
-      }
-      /* ---------- */
-      _ = config_rx.changed() => {
-        let Some(new_config_toml) = config_rx.borrow().clone() else {
-          error!("Something wrong in config reloader receiver");
-          return Err(anyhow!("Something wrong in config reloader receiver"));
-        };
-        match RpxyService::new(&new_config_toml, runtime_handle.clone()).await {
-          Ok(new_service) => {
-            info!("Configuration updated.");
-            service = new_service;
-          },
-          Err(e) => {
-            error!("rpxy failed to be ready. Configuration does not updated: {e}");
+        /* ---------- */
+        tasks_res = &mut tasks_handle => {
+          let tasks_res = tasks_res.map_err(|e| anyhow!(e)).and_then(|res| res);
+          error!("certificate or acme service exited");
+          cancel_token.cancel();
+          #[cfg(feature = "otel")]
+          if let Some(tracer_provider) = tracer_provider.as_ref() {
+            tracer_provider.shutdown().ok();
           }
-        };
+          return tasks_res;
+        }
+        /* ---------- */
+        signal = &mut shutdown_signal => {
+          info!("{signal} received. Stop accepting and drain active connections");
+          cancel_token.cancel();
+          let rpxy_res = rpxy_handle.await.map_err(|e| anyhow!(e)).and_then(|res| res.map_err(|e| anyhow!(e)));
+          info!("rpxy service terminated");
+          // flush remaining spans
+          #[cfg(feature = "otel")]
+          if let Some(tracer_provider) = tracer_provider.as_ref() {
+            tracer_provider.shutdown().ok();
+          }
+          return rpxy_res;
+        }
+        /* ---------- */
+        _ = config_rx.changed() => {
+          let Some(new_config_toml) = config_rx.borrow().clone() else {
+            error!("Something wrong in config reloader receiver");
+            return Err(anyhow!("Something wrong in config reloader receiver"));
+          };
+          if new_config_toml == applied_config_toml {
+            debug!("Configuration is already applied");
+            continue;
+          }
+          new_config_toml
+        }
+        /* ---------- */
+        _ = reload_notify.notified() => {
+          match ConfigToml::new(config_file_path) {
+            Ok(new_config_toml) => new_config_toml,
+            Err(e) => {
+              error!("rpxy failed to reload. Configuration does not updated: {e}");
+              metrics.record_config_reload(false);
+              continue;
+            }
+          }
+        }
+      };
+      if build_metrics_config(&new_config_toml).ok().flatten() != metrics_config {
+        warn!("Changes in the metrics listener are applied after restart");
+      }
+      if build_admin_config(&new_config_toml).ok().flatten() != admin_config {
+        warn!("Changes in the admin API listener are applied after restart");
+      }
+      #[cfg(feature = "otel")]
+      if new_config_toml.opentelemetry != config_toml.opentelemetry {
+        warn!("Changes in the OpenTelemetry tracing are applied after restart");
+      }
+      let new_service = match RpxyService::new(
+        &new_config_toml,
+        runtime_handle.clone(),
+        metrics.clone(),
+        admin.clone(),
+        config_updater.clone(),
+      )
+      .await
+      {
+        Ok(new_service) => new_service,
+        Err(e) => {
+          error!("rpxy failed to be ready. Configuration does not updated: {e}");
+          metrics.record_config_reload(false);
+          continue;
+        }
+      };
+
+      if service.proxy_conf.requires_rebind(&new_service.proxy_conf) {
+        info!("Configuration updated.");
+        metrics.record_config_reload(true);
         info!("Terminate all spawned services and force to re-bind TCP/UDP sockets");
+        service = new_service;
+        applied_config_toml = new_config_toml;
         cancel_token.cancel();
+        break;
+      }
+
+      // swap the configuration of running proxy services, where in-flight connections are kept served on the previous one
+      let new_tasks_cancel_token = cancel_token.child_token();
+      let (new_rpxy_opts, new_tasks_handle) = new_service.spawn_tasks(new_tasks_cancel_token.clone())?;
+      match config_updater.update(new_rpxy_opts).await {
+        Ok(()) => {
+          info!("Configuration updated.");
+          metrics.record_config_reload(true);
+          // terminate certificate and acme tasks for the previous configuration
+          tasks_cancel_token.cancel();
+          (tasks_cancel_token, tasks_handle) = (new_tasks_cancel_token, new_tasks_handle);
+          service = new_service;
+          applied_config_toml = new_config_toml;
+        }
+        Err(e) => {
+          error!("rpxy failed to apply the configuration. Configuration does not updated: {e}");
+          metrics.record_config_reload(false);
+          new_tasks_cancel_token.cancel();
+        }
       }
     }
   }
 }
+
+/// Wait for the signal to shut down gracefully, i.e., SIGTERM, SIGINT or SIGQUIT
+#[cfg(unix)]
+async fn shutdown_signal() -> &'static str {
+  use tokio::signal::unix::{SignalKind, signal};
+  let (Ok(mut sigterm), Ok(mut sigint), Ok(mut sigquit)) = (
+    signal(SignalKind::terminate()),
+    signal(SignalKind::interrupt()),
+    signal(SignalKind::quit()),
+  ) else {
+    error!("Failed to listen for shutdown signals");
+    return std::future::pending().await;
+  };
+  tokio::select! {
+    _ = sigterm.recv() => "SIGTERM",
+    _ = sigint.recv() => "SIGINT",
+    _ = sigquit.recv() => "SIGQUIT",
+  }
+}
+
+/// Notify the reload of the configuration and certificates at every SIGHUP
+#[cfg(unix)]
+async fn reload_signal(reload_notify: Arc<tokio::sync::Notify>) {
+  use tokio::signal::unix::{SignalKind, signal};
+  let Ok(mut sighup) = signal(SignalKind::hangup()) else {
+    error!("Failed to listen for SIGHUP");
+    return;
+  };
+  while sighup.recv().await.is_some() {
+    info!("SIGHUP received. Reload configuration and certificates");
+    reload_notify.notify_one();
+  }
+}
+
+/// Wait for the signal to shut down gracefully, i.e., Ctrl-C
+#[cfg(not(unix))]
+async fn shutdown_signal() -> &'static str {
+  if tokio::signal::ctrl_c().await.is_err() {
+    error!("Failed to listen for shutdown signals");
+    return std::future::pending().await;
+  }
+  "Ctrl-C"
+}
@@ -0,0 +1,75 @@
+use crate::{
+  config::OpenTelemetryOption,
+  constants::{OTEL_SAMPLER, OTEL_SERVICE_NAME},
+  error::*,
+  log::*,
+};
+use opentelemetry::global;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+  Resource,
+  propagation::TraceContextPropagator,
+  trace::{Sampler, SdkTracerProvider},
+};
+
+/// Build sampler from the name and the argument following `OTEL_TRACES_SAMPLER` and `OTEL_TRACES_SAMPLER_ARG`
+fn build_sampler(name: &str, arg: Option<&str>) -> Result<Sampler, anyhow::Error> {
+  let ratio = || -> Result<f64, anyhow::Error> {
+    let ratio = arg.unwrap_or("1.0").parse::<f64>()?;
+    ensure!((0.0..=1.0).contains(&ratio), "Sampling ratio must be between 0.0 and 1.0");
+    Ok(ratio)
+  };
+  let sampler = match name {
+    "always_on" => Sampler::AlwaysOn,
+    "always_off" => Sampler::AlwaysOff,
+    "traceidratio" => Sampler::TraceIdRatioBased(ratio()?),
+    "parentbased_always_on" => Sampler::ParentBased(Box::new(Sampler::AlwaysOn)),
+    "parentbased_always_off" => Sampler::ParentBased(Box::new(Sampler::AlwaysOff)),
+    "parentbased_traceidratio" => Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio()?))),
+    _ => bail!("Unsupported sampler: {name}"),
+  };
+  Ok(sampler)
+}
+
+/// Build tracer provider exporting spans over OTLP, and install it globally with W3C trace-context propagator.
+/// The returned provider should be shut down at exit to flush remaining spans.
+pub fn init_tracer_provider(option: &OpenTelemetryOption) -> Result<SdkTracerProvider, anyhow::Error> {
+  let protocol = option.protocol.as_deref().unwrap_or("grpc");
+  let exporter = match protocol {
+    "grpc" => {
+      let builder = SpanExporter::builder().with_tonic();
+      match option.endpoint.as_ref() {
+        Some(endpoint) => builder.with_endpoint(endpoint).build(),
+        None => builder.build(),
+      }
+    }
+    "http" => {
+      let builder = SpanExporter::builder().with_http();
+      match option.endpoint.as_ref() {
+        Some(endpoint) => builder.with_endpoint(endpoint).build(),
+        None => builder.build(),
+      }
+    }
+    _ => bail!("Unsupported OTLP protocol: {protocol}"),
+  }?;
+  let sampler = build_sampler(
+    option.sampler.as_deref().unwrap_or(OTEL_SAMPLER),
+    option.sampler_arg.as_deref(),
+  )?;
+  let resource = Resource::builder()
+    .with_service_name(option.service_name.clone().unwrap_or_else(|| OTEL_SERVICE_NAME.to_string()))
+    .build();
+
+  let provider = SdkTracerProvider::builder()
+    .with_batch_exporter(exporter)
+    .with_sampler(sampler)
+    .with_resource(resource)
+    .build();
+  global::set_tracer_provider(provider.clone());
+  global::set_text_map_propagator(TraceContextPropagator::new());
+  info!(
+    "OpenTelemetry tracing is enabled: OTLP over {protocol} to {}",
+    option.endpoint.as_deref().unwrap_or("default endpoint")
+  );
+  Ok(provider)
+}
@@ -14,6 +14,24 @@ type SubjectKeyIdentifier = Vec<u8>;
 /// Client CA trust anchors subject to the subject key identifier
 type TrustAnchors = HashMap<SubjectKeyIdentifier, pki_types::TrustAnchor<'static>>;
 
+/* ------------------------------------------------ */
+/// Summary of the leaf certificate served for a single server name, e.g., to inspect its expiry
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CertificateSummary {
+  /// Subject distinguished name
+  pub subject: String,
+  /// Issuer distinguished name
+  pub issuer: String,
+  /// Serial number in hex
+  pub serial: String,
+  /// Start of the validity period in unix timestamp
+  pub not_before: i64,
+  /// End of the validity period in unix timestamp
+  pub not_after: i64,
+  /// Mutual tls is enabled with client CA certificates
+  pub mutual_tls: bool,
+}
+
 /* ------------------------------------------------ */
 /// Raw certificates and private keys loaded from files for a single server name
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -72,6 +90,28 @@ impl SingleServerCertsKeys {
     Ok(CertifiedKey::new(cert, signing_key))
   }
 
+  /* ------------------------------------------------ */
+  /// Check that the private key matches the public key of the leaf certificate
+  pub fn verify_key_pair(&self) -> Result<(), RpxyCertError> {
+    self.rustls_certified_key()?.keys_match()?;
+    Ok(())
+  }
+
+  /* ------------------------------------------------ */
+  /// Parse the leaf certificate, i.e., the first one of the chain, and return its summary
+  pub fn leaf_certificate_summary(&self) -> Result<CertificateSummary, RpxyCertError> {
+    let leaf = self.certs.first().ok_or(RpxyCertError::InvalidCertificateAndKey)?;
+    let (_, x509_cert) = parse_x509_certificate(leaf).map_err(|_| RpxyCertError::InvalidCertificateAndKey)?;
+    Ok(CertificateSummary {
+      subject: x509_cert.subject().to_string(),
+      issuer: x509_cert.issuer().to_string(),
+      serial: x509_cert.raw_serial_as_string(),
+      not_before: x509_cert.validity().not_before.timestamp(),
+      not_after: x509_cert.validity().not_after.timestamp(),
+      mutual_tls: self.is_mutual_tls(),
+    })
+  }
+
   /* ------------------------------------------------ */
   /// Parse the client CA certificates and return a hashmap of pairs of a subject key identifier (key) and a trust anchor (value)
   pub fn rustls_client_certs_trust_anchors(&self) -> Result<TrustAnchors, RpxyCertError> {
@@ -124,6 +164,19 @@ mod tests {
     let crypto_elem = crypto_elem.unwrap();
     let certificed_key = crypto_elem.rustls_certified_key();
     assert!(certificed_key.is_ok());
+    assert!(crypto_elem.verify_key_pair().is_ok());
+  }
+
+  #[tokio::test]
+  async fn read_mismatched_server_crt_key_files() {
+    let crypto_file_source = CryptoFileSourceBuilder::default()
+      .tls_cert_key_path("../example-certs/client.key")
+      .tls_cert_path("../example-certs/server.crt")
+      .build()
+      .unwrap();
+    let crypto_elem = crypto_file_source.read().await.unwrap();
+    assert!(crypto_elem.rustls_certified_key().is_ok());
+    assert!(crypto_elem.verify_key_pair().is_err());
   }
 
   #[tokio::test]
@@ -153,5 +206,12 @@ mod tests {
 
     let trust_anchors = trust_anchors.unwrap();
     assert_eq!(trust_anchors.len(), 1);
+
+    let summary = crypto_elem.leaf_certificate_summary();
+    assert!(summary.is_ok());
+
+    let summary = summary.unwrap();
+    assert!(summary.mutual_tls);
+    assert!(summary.not_before < summary.not_after);
   }
 }
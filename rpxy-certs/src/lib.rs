@@ -17,7 +17,7 @@ use std::sync::Arc;
 
 /* ------------------------------------------------ */
 pub use crate::{
-  certs::SingleServerCertsKeys,
+  certs::{CertificateSummary, SingleServerCertsKeys},
   crypto_source::{CryptoFileSource, CryptoFileSourceBuilder, CryptoFileSourceBuilderError, CryptoSource},
   reloader_service::CryptoReloader,
   server_crypto::{ServerCrypto, ServerCryptoBase},
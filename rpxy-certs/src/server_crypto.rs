@@ -1,4 +1,8 @@
-use crate::{certs::SingleServerCertsKeys, error::*, log::*};
+use crate::{
+  certs::{CertificateSummary, SingleServerCertsKeys},
+  error::*,
+  log::*,
+};
 use ahash::HashMap;
 use rustls::{
   crypto::CryptoProvider,
@@ -49,6 +53,19 @@ impl TryInto<Arc<ServerCrypto>> for &ServerCryptoBase {
 }
 
 impl ServerCryptoBase {
+  /// Summarize leaf certificates of all server names, skipping ones failed to be parsed
+  pub fn certificate_summaries(&self) -> Vec<(String, CertificateSummary)> {
+    self
+      .inner
+      .iter()
+      .filter_map(|(server_name_bytes, certs_keys)| {
+        let server_name = server_name_bytes_to_string(server_name_bytes).ok()?;
+        let summary = certs_keys.leaf_certificate_summary().ok()?;
+        Some((server_name, summary))
+      })
+      .collect()
+  }
+
   /// Build individual server crypto inner object
   fn build_individual_server_crypto_map(&self) -> Result<ServerNameCryptoMap, RpxyCertError> {
     let mut server_crypto_map: ServerNameCryptoMap = HashMap::default();
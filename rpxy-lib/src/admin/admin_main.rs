@@ -0,0 +1,284 @@
+#[cfg(feature = "cache")]
+use crate::forwarder::RpxyCache;
+use crate::{
+  backend::{BackendAppManager, UpstreamCandidates},
+  globals::{AppConfigList, Globals},
+  log::*,
+};
+use ahash::HashSet;
+use serde_json::{Value, json};
+use std::{
+  sync::{Arc, Mutex, RwLock},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Hook to reload the configuration, which is given by the caller of rpxy-lib managing the configuration
+pub type AdminReloadHook = Arc<dyn Fn() + Send + Sync>;
+
+/// Runtime state of the running proxy service, which is inspected and controlled through the admin API
+pub(crate) struct AdminState {
+  /// Global context including the effective proxy config and the certificate reloader receiver
+  pub(crate) globals: Arc<Globals>,
+  /// Effective configuration of backend applications
+  pub(crate) app_config_list: AppConfigList,
+  /// Registered backend applications with routes and upstreams
+  pub(crate) app_manager: Arc<BackendAppManager>,
+  #[cfg(feature = "cache")]
+  /// Cache of responses if enabled
+  pub(crate) cache: Option<RpxyCache>,
+}
+
+/* ---------------------------------------------- */
+/// Handle of the admin API to inspect and control the running proxy service.
+/// This is shared with the admin service and kept over the configuration reloads by cloning,
+/// where the proxy service registers its runtime state at every start.
+#[derive(Clone, Default)]
+pub struct Admin {
+  inner: Arc<AdminInner>,
+}
+
+#[derive(Default)]
+struct AdminInner {
+  /// Runtime state of the latest proxy service
+  state: RwLock<Option<Arc<AdminState>>>,
+  /// Drained upstreams as pairs of the app name and the upstream uri, which are kept over the configuration reloads
+  drained: Mutex<HashSet<(String, String)>>,
+  /// Hook to reload the configuration
+  reload_hook: Option<AdminReloadHook>,
+}
+
+impl std::fmt::Debug for Admin {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Admin").finish_non_exhaustive()
+  }
+}
+
+impl Admin {
+  /// Create a new admin handle with the hook to reload the configuration
+  pub fn new(reload_hook: AdminReloadHook) -> Self {
+    Self {
+      inner: Arc::new(AdminInner {
+        reload_hook: Some(reload_hook),
+        ..Default::default()
+      }),
+    }
+  }
+
+  /// Register the runtime state of the newly started proxy service, where drained upstreams are kept drained
+  pub(crate) fn register(&self, state: AdminState) {
+    if let Ok(drained) = self.inner.drained.lock() {
+      for (app_name, candidates) in iter_routes(&state.app_manager) {
+        candidates
+          .members()
+          .inner
+          .iter()
+          .filter(|u| drained.contains(&(app_name.to_string(), u.uri.to_string())))
+          .for_each(|u| u.set_draining(true));
+      }
+    }
+    if let Ok(mut lock) = self.inner.state.write() {
+      *lock = Some(Arc::new(state));
+    }
+  }
+
+  /// Get the runtime state of the running proxy service
+  pub(crate) fn state(&self) -> Option<Arc<AdminState>> {
+    self.inner.state.read().ok().and_then(|v| v.clone())
+  }
+
+  /* ---------------------------------------------- */
+  /// Effective configuration of the proxy and backend applications
+  pub(crate) fn config(state: &AdminState) -> Value {
+    let mut config = json!({
+      "proxy": state.globals.proxy_config,
+      "apps": state.app_config_list,
+    });
+    state.globals.proxy_config.secret_values.redact_json(&mut config);
+    config
+  }
+
+  /// Registered backend applications and their routes
+  pub(crate) fn apps(state: &AdminState) -> Value {
+    let mut apps = state
+      .app_manager
+      .apps
+      .values()
+      .map(|app| {
+        let mut routes = app
+          .path_manager
+          .iter()
+          .map(|candidates| {
+            let members = candidates.members();
+            json!({
+              "path": path_string(&candidates.path),
+              "replace_path": candidates.replace_path.as_ref().map(path_string),
+              "upstreams": candidates.sources.iter().map(|u| u.location()).collect::<Vec<_>>(),
+              "load_balance": members.load_balance.name(),
+              "options": candidates.options.iter().map(|v| v.as_str()).collect::<Vec<_>>(),
+              "cors": candidates.cors.is_some(),
+            })
+          })
+          .collect::<Vec<_>>();
+        routes.sort_by_key(|v| v["path"].as_str().unwrap_or_default().to_string());
+        json!({
+          "app_name": app.app_name,
+          "server_name": TryInto::<String>::try_into(&app.server_name).unwrap_or_default(),
+          "tls": app.tls_enabled,
+          "https_redirection": app.https_redirection.unwrap_or(false),
+          "mutual_tls": app.mutual_tls.unwrap_or(false),
+          "routes": routes,
+          "redirect_rules": app.redirect_rules.len(),
+          "static_response_rules": app.static_response_rules.len(),
+          "security_headers": app.security_headers.is_some(),
+        })
+      })
+      .collect::<Vec<_>>();
+    apps.sort_by_key(|v| v["app_name"].as_str().unwrap_or_default().to_string());
+    json!(apps)
+  }
+
+  /// Load balancing state and draining state of upstreams of each route
+  pub(crate) fn upstreams(state: &AdminState) -> Value {
+    let mut routes = iter_routes(&state.app_manager)
+      .map(|(app_name, candidates)| {
+        let members = candidates.members();
+        json!({
+          "app_name": app_name,
+          "path": path_string(&candidates.path),
+          "load_balance": members.load_balance.name(),
+          "next_index": members.load_balance.current_ptr(),
+          "upstreams": members.inner.iter().map(|u| json!({
+            "uri": u.uri.to_string(),
+            "state": if u.is_draining() { "draining" } else { "active" },
+          })).collect::<Vec<_>>(),
+        })
+      })
+      .collect::<Vec<_>>();
+    routes.sort_by_key(|v| format!("{}{}", v["app_name"], v["path"]));
+    json!(routes)
+  }
+
+  /// Loaded server certificates with their expiry
+  pub(crate) fn certificates(state: &AdminState) -> Value {
+    let Some(server_crypto_base) = state.globals.cert_reloader_rx.as_ref().and_then(|rx| rx.borrow().clone()) else {
+      return json!([]);
+    };
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|v| v.as_secs() as i64)
+      .unwrap_or_default();
+    let mut certs = server_crypto_base.certificate_summaries();
+    certs.sort_by(|a, b| a.0.cmp(&b.0));
+    json!(
+      certs
+        .into_iter()
+        .map(|(server_name, summary)| json!({
+          "server_name": server_name,
+          "subject": summary.subject,
+          "issuer": summary.issuer,
+          "serial": summary.serial,
+          "not_before": summary.not_before,
+          "not_after": summary.not_after,
+          "expires_in_secs": summary.not_after - now,
+          "mutual_tls": summary.mutual_tls,
+        }))
+        .collect::<Vec<_>>()
+    )
+  }
+
+  /// Statistics of the response cache
+  pub(crate) async fn cache(state: &AdminState) -> Value {
+    #[cfg(feature = "cache")]
+    if let Some(cache) = state.cache.as_ref() {
+      let (total, on_memory, file) = cache.count().await;
+      let (size_on_memory, size_on_file) = cache.size();
+      let (hits, misses) = state.globals.metrics.cache_hits_misses();
+      return json!({
+        "enabled": true,
+        "entries": total,
+        "entries_on_memory": on_memory,
+        "entries_on_file": file,
+        "max_entries": state.globals.proxy_config.cache_max_entry,
+        "size_on_memory": size_on_memory,
+        "size_on_file": size_on_file,
+        "max_total_size_on_memory": state.globals.proxy_config.cache_max_total_size_on_memory,
+        "max_total_size_on_file": state.globals.proxy_config.cache_max_total_size_on_file,
+        "hits": hits,
+        "misses": misses,
+      });
+    }
+    let _ = state;
+    json!({ "enabled": false })
+  }
+
+  /// Number of active connections from clients
+  pub(crate) fn connections(state: &AdminState) -> Value {
+    let by_transport = state.globals.metrics.active_connections();
+    json!({
+      "active": by_transport.iter().map(|(_, v)| v).sum::<i64>(),
+      "by_transport": by_transport.into_iter().collect::<std::collections::BTreeMap<_, _>>(),
+      "max_clients": state.globals.proxy_config.max_clients,
+    })
+  }
+
+  /* ---------------------------------------------- */
+  /// Trigger the configuration reload, returning false if no hook is given
+  pub(crate) fn reload(&self) -> bool {
+    let Some(hook) = self.inner.reload_hook.as_ref() else {
+      return false;
+    };
+    info!("Configuration reload is requested via admin API");
+    hook();
+    true
+  }
+
+  /// Purge all cache entries, returning the number of purged entries or None if the cache is disabled
+  pub(crate) async fn purge_cache(state: &AdminState) -> Option<usize> {
+    #[cfg(feature = "cache")]
+    if let Some(cache) = state.cache.as_ref() {
+      return Some(cache.purge().await);
+    }
+    let _ = state;
+    None
+  }
+
+  /// Drain or undrain upstreams matched with the uri, optionally only in the given app, returning the number of matched ones
+  pub(crate) fn set_draining(&self, state: &AdminState, uri: &http::Uri, app: Option<&str>, draining: bool) -> usize {
+    let Ok(mut drained) = self.inner.drained.lock() else {
+      return 0;
+    };
+    let mut matched = 0;
+    for (app_name, candidates) in iter_routes(&state.app_manager).filter(|(app_name, _)| app.is_none_or(|v| v == *app_name)) {
+      for upstream in candidates.members().inner.iter().filter(|u| &u.uri == uri) {
+        upstream.set_draining(draining);
+        let key = (app_name.to_string(), upstream.uri.to_string());
+        if draining {
+          drained.insert(key);
+        } else {
+          drained.remove(&key);
+        }
+        matched += 1;
+      }
+    }
+    if matched > 0 {
+      info!(
+        "{} upstream {uri} in {matched} route(s) via admin API",
+        if draining { "Drained" } else { "Undrained" }
+      );
+    }
+    matched
+  }
+}
+
+/// Iterate over routes of all apps as pairs of the app name and upstream candidates
+fn iter_routes(app_manager: &BackendAppManager) -> impl Iterator<Item = (&str, &UpstreamCandidates)> {
+  app_manager
+    .apps
+    .values()
+    .flat_map(|app| app.path_manager.iter().map(|c| (app.app_name.as_str(), c)))
+}
+
+/// Convert path name to string
+fn path_string(path: &crate::name_exp::PathName) -> String {
+  path.try_into().unwrap_or_default()
+}
@@ -0,0 +1,286 @@
+use super::admin_main::Admin;
+use crate::{
+  constants::{ACCEPT_ERROR_BACKOFF_MSEC, TCP_LISTEN_BACKLOG},
+  error::*,
+  forwarder::parse_upstream_location,
+  globals::{AdminConfig, AdminListen},
+  hyper_ext::{
+    body::{BoxBody, full},
+    rt::LocalExecutor,
+  },
+  log::*,
+  proxy::bind_tcp_socket,
+};
+use bytes::Bytes;
+use http::{HeaderValue, Method, Request, Response, StatusCode, header};
+use hyper::{body::Incoming, service::service_fn};
+use hyper_util::{rt::TokioIo, server::conn::auto::Builder as ConnectionBuilder};
+use serde_json::{Value, json};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Paths of the admin API
+mod paths {
+  pub const CONFIG: &str = "/config";
+  pub const APPS: &str = "/apps";
+  pub const UPSTREAMS: &str = "/upstreams";
+  pub const CERTIFICATES: &str = "/certificates";
+  pub const CACHE: &str = "/cache";
+  pub const CONNECTIONS: &str = "/connections";
+  pub const RELOAD: &str = "/reload";
+  pub const CACHE_PURGE: &str = "/cache/purge";
+  pub const UPSTREAMS_DRAIN: &str = "/upstreams/drain";
+  pub const UPSTREAMS_UNDRAIN: &str = "/upstreams/undrain";
+}
+
+/// Serve the admin API on the separate listener given by the config, which requires the bearer token for every request.
+/// This is independent of proxy services, and hence kept running over the configuration reloads.
+pub async fn admin_service(config: &AdminConfig, admin: Admin, runtime_handle: tokio::runtime::Handle) -> RpxyResult<()> {
+  let connection_builder = ConnectionBuilder::new(LocalExecutor::new(runtime_handle.clone()));
+  let token: Arc<str> = Arc::from(config.token.as_str());
+  let serve = |stream, client_addr: String| {
+    let (connection_builder, admin, token) = (connection_builder.clone(), admin.clone(), token.clone());
+    runtime_handle.spawn(serve_connection(stream, client_addr, connection_builder, admin, token));
+  };
+
+  match &config.listen {
+    AdminListen::Tcp(listen_socket) => {
+      let tcp_socket = bind_tcp_socket(listen_socket)?;
+      let tcp_listener = tcp_socket.listen(TCP_LISTEN_BACKLOG)?;
+      info!("Start admin service on {listen_socket}");
+      loop {
+        let (stream, client_addr) = match tcp_listener.accept().await {
+          Ok(v) => v,
+          Err(e) => {
+            back_off_accept_error(e).await;
+            continue;
+          }
+        };
+        serve(Box::new(stream) as Box<dyn Stream>, client_addr.to_string());
+      }
+    }
+    #[cfg(unix)]
+    AdminListen::Unix(path) => {
+      use std::os::unix::fs::PermissionsExt;
+      // remove the stale socket file left by the previous run
+      if tokio::fs::symlink_metadata(path).await.is_ok() {
+        tokio::fs::remove_file(path).await?;
+      }
+      let unix_listener = tokio::net::UnixListener::bind(path)?;
+      tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+      info!("Start admin service on {}", path.display());
+      loop {
+        let (stream, _) = match unix_listener.accept().await {
+          Ok(v) => v,
+          Err(e) => {
+            back_off_accept_error(e).await;
+            continue;
+          }
+        };
+        serve(Box::new(stream) as Box<dyn Stream>, path.display().to_string());
+      }
+    }
+  }
+}
+
+/// Log the error of accepting a connection and back off a while, where the service keeps running since errors like
+/// running out of file descriptors are transient
+async fn back_off_accept_error(e: std::io::Error) {
+  warn!("Failed to accept a connection to admin service: {e}");
+  tokio::time::sleep(Duration::from_millis(ACCEPT_ERROR_BACKOFF_MSEC)).await;
+}
+
+/// Stream of either TCP or unix domain socket
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+/// Serve requests to the admin API over a single connection
+async fn serve_connection(
+  stream: Box<dyn Stream>,
+  client_addr: String,
+  connection_builder: ConnectionBuilder<LocalExecutor>,
+  admin: Admin,
+  token: Arc<str>,
+) {
+  let service = service_fn(move |req: Request<Incoming>| {
+    let (admin, token) = (admin.clone(), token.clone());
+    async move { Ok::<_, Infallible>(admin_response(&req, &admin, &token).await) }
+  });
+  if let Err(e) = connection_builder.serve_connection(TokioIo::new(stream), service).await {
+    debug!("Failed to serve admin API to {client_addr}: {e}");
+  }
+}
+
+/// Build response to the admin API request
+async fn admin_response<B>(req: &Request<B>, admin: &Admin, token: &str) -> Response<BoxBody> {
+  if !is_authorized(req, token) {
+    let mut res = json_response(StatusCode::UNAUTHORIZED, json!({ "error": "Unauthorized" }));
+    res
+      .headers_mut()
+      .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    return res;
+  }
+
+  let path = req.uri().path();
+  let expected_method = match path {
+    paths::CONFIG | paths::APPS | paths::UPSTREAMS | paths::CERTIFICATES | paths::CACHE | paths::CONNECTIONS => Method::GET,
+    paths::RELOAD | paths::CACHE_PURGE | paths::UPSTREAMS_DRAIN | paths::UPSTREAMS_UNDRAIN => Method::POST,
+    _ => return json_response(StatusCode::NOT_FOUND, json!({ "error": "Not found" })),
+  };
+  if req.method() != expected_method {
+    return json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "Method not allowed" }));
+  }
+  if path == paths::RELOAD {
+    return match admin.reload() {
+      true => json_response(StatusCode::ACCEPTED, json!({ "reload": "requested" })),
+      false => json_response(StatusCode::NOT_IMPLEMENTED, json!({ "error": "Reload is not supported" })),
+    };
+  }
+
+  let Some(state) = admin.state() else {
+    return json_response(
+      StatusCode::SERVICE_UNAVAILABLE,
+      json!({ "error": "Proxy service is not running" }),
+    );
+  };
+  match path {
+    paths::CONFIG => json_response(StatusCode::OK, Admin::config(&state)),
+    paths::APPS => json_response(StatusCode::OK, Admin::apps(&state)),
+    paths::UPSTREAMS => json_response(StatusCode::OK, Admin::upstreams(&state)),
+    paths::CERTIFICATES => json_response(StatusCode::OK, Admin::certificates(&state)),
+    paths::CACHE => json_response(StatusCode::OK, Admin::cache(&state).await),
+    paths::CONNECTIONS => json_response(StatusCode::OK, Admin::connections(&state)),
+    paths::CACHE_PURGE => match Admin::purge_cache(&state).await {
+      Some(purged) => json_response(StatusCode::OK, json!({ "purged": purged })),
+      None => json_response(StatusCode::CONFLICT, json!({ "error": "Cache is disabled" })),
+    },
+    _ => {
+      // drain or undrain
+      let Some(uri) = query_param(req, "upstream").and_then(|v| parse_upstream_location(&v)) else {
+        return json_response(
+          StatusCode::BAD_REQUEST,
+          json!({ "error": "Valid `upstream` query parameter is required" }),
+        );
+      };
+      let app = query_param(req, "app");
+      let draining = path == paths::UPSTREAMS_DRAIN;
+      match admin.set_draining(&state, &uri, app.as_deref(), draining) {
+        0 => json_response(StatusCode::NOT_FOUND, json!({ "error": "No matching upstream" })),
+        matched => json_response(StatusCode::OK, json!({ "matched": matched, "draining": draining })),
+      }
+    }
+  }
+}
+
+/// Check the bearer token in the authorization header in constant time
+fn is_authorized<B>(req: &Request<B>, token: &str) -> bool {
+  let Some(given) = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|v| v.as_bytes().strip_prefix(b"Bearer "))
+  else {
+    return false;
+  };
+  given.len() == token.len() && given.iter().zip(token.as_bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Get the percent-decoded value of the query parameter
+fn query_param<B>(req: &Request<B>, key: &str) -> Option<String> {
+  req.uri().query()?.split('&').find_map(|kv| {
+    let (k, v) = kv.split_once('=')?;
+    (k == key).then(|| percent_decode(v))
+  })
+}
+
+/// Decode percent-encoded string, where '+' is also decoded to a space
+fn percent_decode(v: &str) -> String {
+  let hex = |b: &u8| (*b as char).to_digit(16);
+  let bytes = v.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let escaped = (bytes[i] == b'%')
+      .then(|| Some(hex(bytes.get(i + 1)?)? * 16 + hex(bytes.get(i + 2)?)?))
+      .flatten();
+    match (escaped, bytes[i]) {
+      (Some(b), _) => {
+        decoded.push(b as u8);
+        i += 3;
+      }
+      (None, b'+') => {
+        decoded.push(b' ');
+        i += 1;
+      }
+      (None, b) => {
+        decoded.push(b);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Build JSON response
+fn json_response(status: StatusCode, body: Value) -> Response<BoxBody> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(full(Bytes::from(body.to_string())))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use http_body_util::BodyExt;
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  fn request(method: Method, uri: &str, token: Option<&str>) -> Request<()> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+      builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    builder.body(()).unwrap()
+  }
+
+  #[tokio::test]
+  async fn admin_api_requires_token() {
+    let reloaded = Arc::new(AtomicBool::new(false));
+    let admin = {
+      let reloaded = reloaded.clone();
+      Admin::new(Arc::new(move || reloaded.store(true, Ordering::Relaxed)))
+    };
+
+    let res = admin_response(&request(Method::GET, "/config", None), &admin, "secret").await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = admin_response(&request(Method::GET, "/config", Some("secreT")), &admin, "secret").await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = admin_response(&request(Method::POST, "/reload", Some("secret")), &admin, "secret").await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    assert!(reloaded.load(Ordering::Relaxed));
+
+    let res = admin_response(&request(Method::GET, "/reload", Some("secret")), &admin, "secret").await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let res = admin_response(&request(Method::GET, "/unknown", Some("secret")), &admin, "secret").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // no proxy service is registered yet
+    let res = admin_response(&request(Method::GET, "/config", Some("secret")), &admin, "secret").await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert!(serde_json::from_slice::<Value>(&body).unwrap()["error"].is_string());
+  }
+
+  #[test]
+  fn query_param_is_decoded() {
+    let req = request(
+      Method::POST,
+      "/upstreams/drain?app=app1&upstream=http%3A%2F%2F192.168.0.1%3A8080",
+      None,
+    );
+    assert_eq!(query_param(&req, "upstream").unwrap(), "http://192.168.0.1:8080");
+    assert_eq!(query_param(&req, "app").unwrap(), "app1");
+    assert!(query_param(&req, "path").is_none());
+  }
+}
@@ -0,0 +1,6 @@
+mod admin_main;
+mod admin_service;
+
+pub(crate) use admin_main::AdminState;
+pub use admin_main::{Admin, AdminReloadHook};
+pub use admin_service::admin_service;
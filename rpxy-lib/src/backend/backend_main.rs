@@ -8,7 +8,11 @@ use ahash::HashMap;
 use derive_builder::Builder;
 use std::borrow::Cow;
 
-use super::upstream::PathManager;
+use super::{
+  route_rules::{RedirectRule, StaticResponseRule},
+  security_headers::SecurityHeaders,
+  upstream::PathManager,
+};
 
 /// Struct serving information to route incoming connections, like server name to be handled and tls certs/keys settings.
 #[derive(Builder)]
@@ -21,6 +25,18 @@ pub struct BackendApp {
   pub server_name: ServerName,
   /// struct of reverse proxy serving incoming request
   pub path_manager: PathManager,
+  /// redirect rules evaluated before routing to upstreams
+  #[builder(default)]
+  pub redirect_rules: Vec<RedirectRule>,
+  /// static response rules evaluated before redirect rules and routing to upstreams
+  #[builder(default)]
+  pub static_response_rules: Vec<StaticResponseRule>,
+  /// security response header policy applied to forwarded and synthetic responses
+  #[builder(default)]
+  pub security_headers: Option<SecurityHeaders>,
+  /// tls settings: served over TLS with its certificate
+  #[builder(default)]
+  pub tls_enabled: bool,
   /// tls settings: https redirection with 30x
   #[builder(default)]
   pub https_redirection: Option<bool>,
@@ -50,16 +66,35 @@ impl TryFrom<&AppConfig> for BackendApp {
   fn try_from(app_config: &AppConfig) -> Result<Self, Self::Error> {
     let mut backend_builder = BackendAppBuilder::default();
     let path_manager = PathManager::try_from(app_config)?;
+    let redirect_rules = app_config
+      .redirect
+      .iter()
+      .map(RedirectRule::try_from)
+      .collect::<RpxyResult<Vec<_>>>()?;
+    let static_response_rules = app_config
+      .static_response
+      .iter()
+      .map(StaticResponseRule::try_from)
+      .collect::<RpxyResult<Vec<_>>>()?;
+    let security_headers = app_config
+      .security_headers
+      .as_ref()
+      .map(SecurityHeaders::try_from)
+      .transpose()?;
     backend_builder
       .app_name(app_config.app_name.clone())
       .server_name(app_config.server_name.clone())
-      .path_manager(path_manager);
+      .path_manager(path_manager)
+      .redirect_rules(redirect_rules)
+      .static_response_rules(static_response_rules)
+      .security_headers(security_headers);
     // TLS settings and build backend instance
     let backend = if app_config.tls.is_none() {
       backend_builder.build()?
     } else {
       let tls = app_config.tls.as_ref().unwrap();
       backend_builder
+        .tls_enabled(true)
         .https_redirection(Some(tls.https_redirection))
         .mutual_tls(Some(tls.mutual_tls))
         .build()?
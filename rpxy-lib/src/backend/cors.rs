@@ -0,0 +1,330 @@
+use crate::{error::*, globals::CorsConfig};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, header};
+use regex::Regex;
+
+/// Allowed origin of CORS policy
+#[derive(Debug, Clone)]
+enum CorsOrigin {
+  /// Any origin, i.e., "*"
+  Any,
+  /// Exact origin like "https://example.com"
+  Exact(String),
+  /// Wildcard origin like "https://*.example.com", split into the parts before and after "*"
+  Wildcard(String, String),
+  /// Regular expression matched against the whole origin
+  Regex(Regex),
+}
+
+impl CorsOrigin {
+  fn matches(&self, origin: &str) -> bool {
+    match self {
+      CorsOrigin::Any => true,
+      CorsOrigin::Exact(v) => v.eq_ignore_ascii_case(origin),
+      CorsOrigin::Wildcard(prefix, suffix) => {
+        let origin = origin.to_ascii_lowercase();
+        origin.len() > prefix.len() + suffix.len()
+          && origin.starts_with(prefix.as_str())
+          && origin.ends_with(suffix.as_str())
+          && origin[prefix.len()..origin.len() - suffix.len()]
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+      }
+      CorsOrigin::Regex(re) => re.is_match(origin),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+/// CORS policy of a reverse proxy route, handling preflight requests at rpxy and decorating responses
+pub struct CorsPolicy {
+  /// Allowed origins
+  origins: Vec<CorsOrigin>,
+  /// `Access-Control-Allow-Methods`, None means the requested method is echoed back
+  allow_methods: Option<HeaderValue>,
+  /// `Access-Control-Allow-Headers`, None means the requested headers are echoed back
+  allow_headers: Option<HeaderValue>,
+  /// `Access-Control-Expose-Headers`
+  expose_headers: Option<HeaderValue>,
+  /// `Access-Control-Allow-Credentials: true` if enabled
+  allow_credentials: bool,
+  /// `Access-Control-Max-Age`
+  max_age: Option<HeaderValue>,
+}
+
+impl TryFrom<&CorsConfig> for CorsPolicy {
+  type Error = RpxyError;
+
+  fn try_from(config: &CorsConfig) -> Result<Self, Self::Error> {
+    let mut origins = config
+      .allow_origins
+      .iter()
+      .map(|v| {
+        let v = v.to_ascii_lowercase();
+        if v == "*" {
+          return Ok(CorsOrigin::Any);
+        }
+        match v.split_once('*') {
+          None => Ok(CorsOrigin::Exact(v)),
+          Some((prefix, suffix)) if !suffix.contains('*') && suffix.starts_with('.') => {
+            Ok(CorsOrigin::Wildcard(prefix.to_string(), suffix.to_string()))
+          }
+          Some(_) => Err(RpxyError::InvalidCorsConfig(format!("Invalid wildcard origin: {v}"))),
+        }
+      })
+      .collect::<RpxyResult<Vec<_>>>()?;
+    for v in config.allow_origin_regex.iter() {
+      // anchored so that the pattern must match the whole origin
+      let re = Regex::new(&format!("^(?:{v})$")).map_err(|e| RpxyError::InvalidCorsConfig(e.to_string()))?;
+      origins.push(CorsOrigin::Regex(re));
+    }
+    if config.allow_credentials && origins.iter().any(|v| matches!(v, CorsOrigin::Any)) {
+      return Err(RpxyError::InvalidCorsConfig(
+        "Wildcard origin \"*\" cannot be used with allow_credentials".to_string(),
+      ));
+    }
+
+    let join = |values: &[String]| -> RpxyResult<Option<HeaderValue>> {
+      if values.is_empty() {
+        return Ok(None);
+      }
+      HeaderValue::from_str(&values.join(", "))
+        .map(Some)
+        .map_err(|e| RpxyError::InvalidCorsConfig(e.to_string()))
+    };
+    let is_wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+    let allow_methods = match is_wildcard(&config.allow_methods) {
+      true => None,
+      false => {
+        let methods = config
+          .allow_methods
+          .iter()
+          .map(|v| v.to_ascii_uppercase())
+          .collect::<Vec<_>>();
+        Some(join(&methods)?.unwrap_or(HeaderValue::from_static("")))
+      }
+    };
+    let allow_headers = match is_wildcard(&config.allow_headers) {
+      true => None,
+      false => Some(join(&config.allow_headers)?.unwrap_or(HeaderValue::from_static(""))),
+    };
+
+    Ok(Self {
+      origins,
+      allow_methods,
+      allow_headers,
+      expose_headers: join(&config.expose_headers)?,
+      allow_credentials: config.allow_credentials,
+      max_age: config.max_age.map(HeaderValue::from),
+    })
+  }
+}
+
+impl CorsPolicy {
+  /// Check if the request is a CORS preflight request
+  pub fn is_preflight(method: &Method, headers: &HeaderMap) -> bool {
+    method == Method::OPTIONS
+      && headers.contains_key(header::ORIGIN)
+      && headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+  }
+
+  /// Value of `Access-Control-Allow-Origin` for the origin if allowed
+  fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+    let origin_str = origin.to_str().ok()?;
+    let matched = self.origins.iter().find(|v| v.matches(origin_str))?;
+    match matched {
+      CorsOrigin::Any => Some(HeaderValue::from_static("*")),
+      _ => Some(origin.clone()),
+    }
+  }
+
+  /// Common headers for allowed origins, and `Vary: Origin` when the origin is echoed back
+  fn origin_headers(&self, req_headers: &HeaderMap) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if !self.origins.iter().all(|v| matches!(v, CorsOrigin::Any)) {
+      headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+    let allow_origin = req_headers.get(header::ORIGIN).and_then(|v| self.allow_origin(v));
+    let Some(allow_origin) = allow_origin else {
+      return headers;
+    };
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+    if self.allow_credentials {
+      headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+    }
+    headers
+  }
+
+  /// Headers of the response to a preflight request, which is answered at rpxy without reaching upstream.
+  /// No `Access-Control-Allow-*` header is given for disallowed origins, and then browsers block the actual request.
+  pub fn preflight_headers(&self, req_headers: &HeaderMap) -> HeaderMap {
+    let mut headers = self.origin_headers(req_headers);
+    headers.append(header::VARY, HeaderValue::from_static("Access-Control-Request-Method"));
+    headers.append(header::VARY, HeaderValue::from_static("Access-Control-Request-Headers"));
+    if !headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN) {
+      return headers;
+    }
+
+    let echo = |name: HeaderName| req_headers.get(name).cloned();
+    let allow_methods = match &self.allow_methods {
+      Some(v) => Some(v.clone()),
+      None => echo(header::ACCESS_CONTROL_REQUEST_METHOD),
+    };
+    let allow_headers = match &self.allow_headers {
+      Some(v) => Some(v.clone()),
+      None => echo(header::ACCESS_CONTROL_REQUEST_HEADERS),
+    };
+    [
+      (header::ACCESS_CONTROL_ALLOW_METHODS, allow_methods),
+      (header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers),
+      (header::ACCESS_CONTROL_MAX_AGE, self.max_age.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.filter(|v| !v.is_empty()).map(|v| (name, v)))
+    .for_each(|(name, value)| {
+      headers.insert(name, value);
+    });
+    headers
+  }
+
+  /// Headers of the response to an actual (non-preflight) request, computed from the request headers
+  pub fn response_headers(&self, req_headers: &HeaderMap) -> CorsResponseHeaders {
+    let mut headers = self.origin_headers(req_headers);
+    let allowed = headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+    if let Some(expose_headers) = self.expose_headers.as_ref().filter(|_| allowed) {
+      headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose_headers.clone());
+    }
+    CorsResponseHeaders(headers)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+/// CORS headers decided for a request, applied to the response from upstream
+pub struct CorsResponseHeaders(HeaderMap);
+
+impl CorsResponseHeaders {
+  /// Apply CORS headers to the response, replacing `Access-Control-*` headers given by upstream since the policy at rpxy is authoritative
+  pub fn apply(&self, headers: &mut HeaderMap) {
+    let upstream_cors_headers = headers
+      .keys()
+      .filter(|k| k.as_str().starts_with("access-control-"))
+      .cloned()
+      .collect::<Vec<_>>();
+    for name in upstream_cors_headers {
+      headers.remove(name);
+    }
+    for (name, value) in self.0.iter() {
+      if name == header::VARY {
+        headers.append(name, value.clone());
+      } else {
+        headers.insert(name, value.clone());
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config() -> CorsConfig {
+    CorsConfig {
+      allow_origins: vec!["https://example.com".to_string(), "https://*.example.org".to_string()],
+      allow_origin_regex: vec![r"^https://app-\d+\.example\.net$".to_string()],
+      allow_methods: vec!["get".to_string(), "PUT".to_string()],
+      allow_headers: vec!["*".to_string()],
+      expose_headers: vec!["X-Total-Count".to_string()],
+      allow_credentials: true,
+      max_age: Some(600),
+    }
+  }
+
+  fn request_headers(origin: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ORIGIN, HeaderValue::from_str(origin).unwrap());
+    headers.insert(header::ACCESS_CONTROL_REQUEST_METHOD, HeaderValue::from_static("PUT"));
+    headers.insert(
+      header::ACCESS_CONTROL_REQUEST_HEADERS,
+      HeaderValue::from_static("content-type"),
+    );
+    headers
+  }
+
+  #[test]
+  fn origin_matching_works() {
+    let policy = CorsPolicy::try_from(&config()).unwrap();
+    let allowed = |origin: &str| policy.allow_origin(&HeaderValue::from_str(origin).unwrap()).is_some();
+    assert!(allowed("https://example.com"));
+    assert!(allowed("https://a.b.example.org"));
+    assert!(allowed("https://app-12.example.net"));
+    assert!(!allowed("https://example.org"));
+    assert!(!allowed("http://a.example.org"));
+    assert!(!allowed("https://evil.com/.example.org"));
+    assert!(!allowed("https://app-x.example.net"));
+    assert!(!allowed("https://example.com.evil.com"));
+  }
+
+  #[test]
+  fn origin_regex_matches_whole_origin() {
+    let config = CorsConfig {
+      allow_origins: vec![],
+      allow_origin_regex: vec![r"https://example\.com".to_string()],
+      ..config()
+    };
+    let policy = CorsPolicy::try_from(&config).unwrap();
+    let allowed = |origin: &str| policy.allow_origin(&HeaderValue::from_str(origin).unwrap()).is_some();
+    assert!(allowed("https://example.com"));
+    assert!(!allowed("https://example.com.evil.net"));
+    assert!(!allowed("https://evil.net/?https://example.com"));
+  }
+
+  #[test]
+  fn preflight_headers_work() {
+    let policy = CorsPolicy::try_from(&config()).unwrap();
+    let req_headers = request_headers("https://www.example.org");
+    assert!(CorsPolicy::is_preflight(&Method::OPTIONS, &req_headers));
+    let headers = policy.preflight_headers(&req_headers);
+    assert_eq!(
+      headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+      "https://www.example.org"
+    );
+    assert_eq!(headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).unwrap(), "GET, PUT");
+    assert_eq!(headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap(), "content-type");
+    assert_eq!(headers.get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+    assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+
+    let headers = policy.preflight_headers(&request_headers("https://evil.com"));
+    assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    assert!(headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).is_none());
+  }
+
+  #[test]
+  fn response_headers_replace_upstream_ones() {
+    let policy = CorsPolicy::try_from(&config()).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    policy
+      .response_headers(&request_headers("https://example.com"))
+      .apply(&mut headers);
+    assert_eq!(
+      headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+      "https://example.com"
+    );
+    assert_eq!(headers.get(header::ACCESS_CONTROL_EXPOSE_HEADERS).unwrap(), "X-Total-Count");
+    assert_eq!(headers.get_all(header::VARY).iter().count(), 2);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    policy
+      .response_headers(&request_headers("https://evil.com"))
+      .apply(&mut headers);
+    assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+  }
+
+  #[test]
+  fn wildcard_with_credentials_rejected() {
+    let mut config = config();
+    config.allow_origins.push("*".to_string());
+    assert!(CorsPolicy::try_from(&config).is_err());
+  }
+}
@@ -0,0 +1,198 @@
+use crate::{error::*, globals::FaultConfig};
+use http::{HeaderMap, HeaderName, StatusCode};
+use rand::Rng;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Fault ending the request instead of forwarding it
+pub enum FaultAction {
+  /// Respond with the status code
+  Abort(StatusCode),
+  /// Reset the connection without any response
+  Reset,
+}
+
+#[derive(Debug, Clone)]
+/// Fault injection rule of a reverse proxy route
+pub struct FaultRule {
+  /// Header required in requests to which the rule applies
+  header: Option<HeaderName>,
+  /// Percentage of the requests to which the rule applies
+  percentage: u8,
+  /// Range of the delay before forwarding requests
+  delay: Option<(Duration, Duration)>,
+  /// Fault ending requests after the delay
+  action: Option<FaultAction>,
+}
+
+impl TryFrom<&FaultConfig> for FaultRule {
+  type Error = RpxyError;
+
+  fn try_from(config: &FaultConfig) -> Result<Self, Self::Error> {
+    let header = config
+      .header
+      .as_deref()
+      .map(|v| HeaderName::from_bytes(v.as_bytes()))
+      .transpose()
+      .map_err(|e| RpxyError::InvalidFaultRule(format!("Invalid header name: {e}")))?;
+    if !(1..=100).contains(&config.percentage) {
+      return Err(RpxyError::InvalidFaultRule("percentage must be from 1 to 100".to_string()));
+    }
+    let delay = match (config.delay, config.delay_max) {
+      (Some(min), Some(max)) if max < min => {
+        return Err(RpxyError::InvalidFaultRule(
+          "delay_max must not be less than delay".to_string(),
+        ));
+      }
+      (Some(min), max) => Some((min, max.unwrap_or(min))),
+      (None, Some(_)) => return Err(RpxyError::InvalidFaultRule("delay_max requires delay".to_string())),
+      (None, None) => None,
+    };
+    let action = match (config.abort, config.reset) {
+      (Some(_), true) => {
+        return Err(RpxyError::InvalidFaultRule(
+          "Either one of abort or reset can be specified".to_string(),
+        ));
+      }
+      (Some(code), false) => {
+        let status_code = StatusCode::from_u16(code)
+          .ok()
+          .filter(|v| v.is_client_error() || v.is_server_error())
+          .ok_or_else(|| RpxyError::InvalidFaultRule(format!("abort must be a status code of 4xx or 5xx: {code}")))?;
+        Some(FaultAction::Abort(status_code))
+      }
+      (None, true) => Some(FaultAction::Reset),
+      (None, false) => None,
+    };
+    if delay.is_none() && action.is_none() {
+      return Err(RpxyError::InvalidFaultRule(
+        "Any of delay, abort or reset must be specified".to_string(),
+      ));
+    }
+    Ok(Self {
+      header,
+      percentage: config.percentage,
+      delay,
+      action,
+    })
+  }
+}
+
+impl FaultRule {
+  /// Check if the rule applies to the request with the headers, where requests are sampled by the percentage
+  fn applies(&self, headers: &HeaderMap) -> bool {
+    if self.header.as_ref().is_some_and(|v| !headers.contains_key(v)) {
+      return false;
+    }
+    self.percentage >= 100 || rand::rng().random_range(0..100) < self.percentage
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Faults injected to a request
+pub struct InjectedFault {
+  /// Delay before forwarding the request
+  pub delay: Duration,
+  /// Fault ending the request after the delay
+  pub action: Option<FaultAction>,
+}
+
+/// Decide the faults injected to the request by the rules, where each rule applies independently in order.
+/// Delays of applied rules add up, and the first abort or reset ends the request.
+pub fn decide_faults(rules: &[FaultRule], headers: &HeaderMap) -> InjectedFault {
+  let mut fault = InjectedFault::default();
+  for rule in rules.iter().filter(|rule| rule.applies(headers)) {
+    if let Some((min, max)) = rule.delay {
+      fault.delay += if min < max { rand::rng().random_range(min..=max) } else { min };
+    }
+    if fault.action.is_none() {
+      fault.action = rule.action;
+    }
+  }
+  fault
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config() -> FaultConfig {
+    FaultConfig {
+      header: None,
+      percentage: 100,
+      delay: None,
+      delay_max: None,
+      abort: None,
+      reset: false,
+    }
+  }
+
+  #[test]
+  fn fault_rules_are_validated() {
+    assert!(FaultRule::try_from(&config()).is_err());
+    let invalid = [
+      FaultConfig {
+        abort: Some(503),
+        reset: true,
+        ..config()
+      },
+      FaultConfig {
+        abort: Some(302),
+        ..config()
+      },
+      FaultConfig {
+        percentage: 0,
+        reset: true,
+        ..config()
+      },
+      FaultConfig {
+        delay_max: Some(Duration::from_millis(100)),
+        ..config()
+      },
+      FaultConfig {
+        delay: Some(Duration::from_millis(200)),
+        delay_max: Some(Duration::from_millis(100)),
+        ..config()
+      },
+      FaultConfig {
+        header: Some("x fault".to_string()),
+        reset: true,
+        ..config()
+      },
+    ];
+    for v in invalid.iter() {
+      assert!(FaultRule::try_from(v).is_err(), "{v:?}");
+    }
+  }
+
+  #[test]
+  fn faults_are_decided_by_rules() {
+    let rules = [
+      FaultConfig {
+        header: Some("x-fault".to_string()),
+        abort: Some(503),
+        ..config()
+      },
+      FaultConfig {
+        delay: Some(Duration::from_millis(100)),
+        delay_max: Some(Duration::from_millis(200)),
+        ..config()
+      },
+      FaultConfig { reset: true, ..config() },
+    ]
+    .iter()
+    .map(|v| FaultRule::try_from(v).unwrap())
+    .collect::<Vec<_>>();
+
+    let fault = decide_faults(&rules, &HeaderMap::new());
+    assert!((Duration::from_millis(100)..=Duration::from_millis(200)).contains(&fault.delay));
+    assert_eq!(fault.action, Some(FaultAction::Reset));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-fault", "1".parse().unwrap());
+    let fault = decide_faults(&rules, &headers);
+    assert_eq!(fault.action, Some(FaultAction::Abort(StatusCode::SERVICE_UNAVAILABLE)));
+
+    assert_eq!(decide_faults(&rules[..1], &HeaderMap::new()), InjectedFault::default());
+  }
+}
@@ -6,9 +6,12 @@ pub use super::{
 };
 use derive_builder::Builder;
 use rand::Rng;
-use std::sync::{
-  atomic::{AtomicUsize, Ordering},
-  Arc,
+use std::{
+  cmp::Reverse,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
 };
 
 /// Constants to specify a load balance option
@@ -32,6 +35,30 @@ pub(super) trait LoadBalanceWithPointer {
   fn get_ptr(&self, req_info: Option<&LoadBalanceContext>) -> PointerToUpstream;
 }
 
+/// Order of upstream indices served in a cycle of weighted round robin, where each index appears as many times as its weight,
+/// smoothly interleaved with the others. It is empty if all weights are equal, i.e., the simple round robin.
+pub(super) fn weighted_schedule(weights: &[u32]) -> Vec<usize> {
+  if weights.windows(2).all(|w| w[0] == w[1]) {
+    return vec![];
+  }
+  let gcd = weights.iter().fold(0, |a, &b| gcd(a, b)).max(1);
+  let weights = weights.iter().map(|w| (w / gcd) as i64).collect::<Vec<_>>();
+  let total = weights.iter().sum::<i64>();
+  let mut current = vec![0i64; weights.len()];
+  (0..total)
+    .map(|_| {
+      current.iter_mut().zip(weights.iter()).for_each(|(c, w)| *c += w);
+      let (chosen, _) = current.iter().enumerate().max_by_key(|(i, c)| (**c, Reverse(*i))).unwrap();
+      current[chosen] -= total;
+      chosen
+    })
+    .collect()
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+  if b == 0 { a } else { gcd(b, a % b) }
+}
+
 #[derive(Debug, Clone, Builder)]
 /// Round Robin LB object as a pointer to the current serving upstream destination
 pub struct LoadBalanceRoundRobin {
@@ -41,25 +68,39 @@ pub struct LoadBalanceRoundRobin {
   #[builder(setter(custom), default)]
   /// Number of upstream destinations
   num_upstreams: usize,
+  #[builder(setter(custom), default)]
+  /// Order of upstream indices in a cycle if weighted, see [`weighted_schedule`]
+  schedule: Vec<usize>,
 }
 impl LoadBalanceRoundRobinBuilder {
   pub fn num_upstreams(&mut self, v: &usize) -> &mut Self {
     self.num_upstreams = Some(*v);
     self
   }
+  /// Set weights of upstream destinations
+  pub fn weights(&mut self, v: &[u32]) -> &mut Self {
+    self.schedule = Some(weighted_schedule(v));
+    self
+  }
 }
 impl LoadBalanceWithPointer for LoadBalanceRoundRobin {
   /// Increment the count of upstream served up to the max value
   fn get_ptr(&self, _info: Option<&LoadBalanceContext>) -> PointerToUpstream {
     // Get a current count of upstream served
     let current_ptr = self.ptr.load(Ordering::Relaxed);
+    let cycle_len = if self.schedule.is_empty() {
+      self.num_upstreams
+    } else {
+      self.schedule.len()
+    };
 
-    let ptr = if current_ptr < self.num_upstreams - 1 {
+    let ptr = if current_ptr < cycle_len - 1 {
       self.ptr.fetch_add(1, Ordering::Relaxed)
     } else {
       // Clear the counter
       self.ptr.fetch_and(0, Ordering::Relaxed)
     };
+    let ptr = self.schedule.get(ptr).copied().unwrap_or(ptr);
     PointerToUpstream { ptr, context: None }
   }
 }
@@ -70,18 +111,42 @@ pub struct LoadBalanceRandom {
   #[builder(setter(custom), default)]
   /// Number of upstream destinations
   num_upstreams: usize,
+  #[builder(setter(custom), default)]
+  /// Cumulative sums of weights of upstream destinations if weighted, otherwise empty
+  cumulative_weights: Vec<u64>,
 }
 impl LoadBalanceRandomBuilder {
   pub fn num_upstreams(&mut self, v: &usize) -> &mut Self {
     self.num_upstreams = Some(*v);
     self
   }
+  /// Set weights of upstream destinations
+  pub fn weights(&mut self, v: &[u32]) -> &mut Self {
+    let cumulative_weights = match v.windows(2).all(|w| w[0] == w[1]) {
+      true => vec![],
+      false => v
+        .iter()
+        .scan(0u64, |acc, w| {
+          *acc += *w as u64;
+          Some(*acc)
+        })
+        .collect(),
+    };
+    self.cumulative_weights = Some(cumulative_weights);
+    self
+  }
 }
 impl LoadBalanceWithPointer for LoadBalanceRandom {
-  /// Returns the random index within the range
+  /// Returns the random index within the range, where the probability is proportional to the weight if weighted
   fn get_ptr(&self, _info: Option<&LoadBalanceContext>) -> PointerToUpstream {
     let mut rng = rand::rng();
-    let ptr = rng.random_range(0..self.num_upstreams);
+    let ptr = match self.cumulative_weights.last() {
+      Some(total) => {
+        let r = rng.random_range(0..*total);
+        self.cumulative_weights.partition_point(|v| *v <= r)
+      }
+      None => rng.random_range(0..self.num_upstreams),
+    };
     PointerToUpstream { ptr, context: None }
   }
 }
@@ -106,8 +171,69 @@ impl Default for LoadBalance {
 }
 
 impl LoadBalance {
+  /// Get the index of the upstream serving the incoming request, avoiding draining upstreams.
+  /// If all upstreams are draining, the one chosen as usual is returned so that requests are still served.
+  pub fn get_context(
+    &self,
+    context_to_lb: &Option<LoadBalanceContext>,
+    num_upstreams: usize,
+    is_draining: impl Fn(usize) -> bool,
+  ) -> PointerToUpstream {
+    let chosen = self.get_context_inner(context_to_lb);
+    if !is_draining(chosen.ptr) {
+      return chosen;
+    }
+    let alternative = match self {
+      // Fixed and random ones simply take the next available upstream
+      LoadBalance::FixToFirst | LoadBalance::Random(_) => (1..num_upstreams)
+        .map(|i| (chosen.ptr + i) % num_upstreams)
+        .find(|ptr| !is_draining(*ptr))
+        .map(|ptr| PointerToUpstream { ptr, context: None }),
+      // Round robin ones advance the pointer, where the sticky cookie is renewed for the newly chosen upstream
+      _ => (1..self.cycle_len().unwrap_or(num_upstreams))
+        .map(|_| self.get_context_inner(&None))
+        .find(|p| !is_draining(p.ptr)),
+    };
+    alternative.unwrap_or(chosen)
+  }
+
+  /// Name of the load balancing option
+  pub fn name(&self) -> &'static str {
+    match self {
+      LoadBalance::FixToFirst => load_balance_options::FIX_TO_FIRST,
+      LoadBalance::Random(_) => load_balance_options::RANDOM,
+      LoadBalance::RoundRobin(_) => load_balance_options::ROUND_ROBIN,
+      #[cfg(feature = "sticky-cookie")]
+      LoadBalance::StickyRoundRobin(_) => load_balance_options::STICKY_ROUND_ROBIN,
+    }
+  }
+
+  /// Length of a cycle of weighted round robin ones
+  fn cycle_len(&self) -> Option<usize> {
+    let schedule = match self {
+      LoadBalance::RoundRobin(lb) => &lb.schedule,
+      #[cfg(feature = "sticky-cookie")]
+      LoadBalance::StickyRoundRobin(lb) => lb.schedule(),
+      _ => return None,
+    };
+    Some(schedule.len()).filter(|v| *v > 0)
+  }
+
+  /// Pointer of round robin ones to the index of the upstream to be served next
+  pub fn current_ptr(&self) -> Option<usize> {
+    match self {
+      LoadBalance::RoundRobin(lb) => {
+        let ptr = lb.ptr.load(Ordering::Relaxed);
+        Some(lb.schedule.get(ptr).copied().unwrap_or(ptr))
+      }
+      #[cfg(feature = "sticky-cookie")]
+      LoadBalance::StickyRoundRobin(lb) => Some(lb.current_ptr()),
+      _ => None,
+    }
+  }
+
   /// Get the index of the upstream serving the incoming request
-  pub fn get_context(&self, _context_to_lb: &Option<LoadBalanceContext>) -> PointerToUpstream {
+  fn get_context_inner(&self, _context_to_lb: &Option<LoadBalanceContext>) -> PointerToUpstream {
     match self {
       LoadBalance::FixToFirst => PointerToUpstream {
         ptr: 0usize,
@@ -1,5 +1,5 @@
 use super::{
-  load_balance_main::{LoadBalanceContext, LoadBalanceWithPointer, PointerToUpstream},
+  load_balance_main::{LoadBalanceContext, LoadBalanceWithPointer, PointerToUpstream, weighted_schedule},
   sticky_cookie::StickyCookieConfig,
   Upstream,
 };
@@ -23,6 +23,9 @@ pub struct LoadBalanceSticky {
   #[builder(setter(custom), default)]
   /// Number of upstream destinations
   num_upstreams: usize,
+  #[builder(setter(custom), default)]
+  /// Order of upstream indices in a cycle if weighted
+  schedule: Vec<usize>,
   #[builder(setter(custom))]
   /// Information to build the cookie to stick clients to specific backends
   pub sticky_config: StickyCookieConfig,
@@ -45,6 +48,11 @@ impl LoadBalanceStickyBuilder {
     self.num_upstreams = Some(*v);
     self
   }
+  /// Set weights of upstream destinations
+  pub fn weights(&mut self, v: &[u32]) -> &mut Self {
+    self.schedule = Some(weighted_schedule(v));
+    self
+  }
   /// Set the information to build the cookie to stick clients to specific backends
   pub fn sticky_config(&mut self, server_name: &str, path_opt: &Option<String>) -> &mut Self {
     self.sticky_config = Some(StickyCookieConfig {
@@ -59,17 +67,48 @@ impl LoadBalanceStickyBuilder {
     });
     self
   }
-  /// Set the hashmaps: upstream_index_map and upstream_id_map
-  pub fn upstream_maps(&mut self, upstream_vec: &[Upstream]) -> &mut Self {
-    let upstream_index_map: Vec<String> = upstream_vec
+  /// Set the hashmaps: upstream_index_map and upstream_id_map.
+  /// Ids of upstreams served by the previous load balancer are kept so that clients stay with them over membership changes.
+  pub fn upstream_maps(&mut self, upstream_vec: &[Upstream], previous: Option<(&[Upstream], &LoadBalanceSticky)>) -> &mut Self {
+    let previous_ids = previous
+      .map(|(prev_vec, lb)| {
+        prev_vec
+          .iter()
+          .map(|v| &v.uri)
+          .zip(lb.upstream_maps.upstream_index_map.iter())
+          .collect::<HashMap<_, _>>()
+      })
+      .unwrap_or_default();
+    let mut upstream_index_map: Vec<Option<String>> = upstream_vec
       .iter()
-      .enumerate()
-      .map(|(i, v)| v.calculate_id_with_index(i))
+      .map(|v| previous_ids.get(&v.uri).map(|id| id.to_string()))
       .collect();
     let mut upstream_id_map = HashMap::default();
-    for (i, v) in upstream_index_map.iter().enumerate() {
-      upstream_id_map.insert(v.to_string(), i);
+    for (i, id) in upstream_index_map.iter_mut().enumerate() {
+      match id {
+        // duplicated upstreams are given new ids
+        Some(v) if upstream_id_map.contains_key(v.as_str()) => *id = None,
+        Some(v) => {
+          upstream_id_map.insert(v.to_string(), i);
+        }
+        None => (),
+      }
     }
+    // new upstreams are given ids with their indices, avoiding collisions with the kept ones
+    let upstream_index_map = upstream_index_map
+      .into_iter()
+      .enumerate()
+      .map(|(i, id)| {
+        id.unwrap_or_else(|| {
+          let id = (i..)
+            .map(|j| upstream_vec[i].calculate_id_with_index(j))
+            .find(|v| !upstream_id_map.contains_key(v))
+            .unwrap();
+          upstream_id_map.insert(id.clone(), i);
+          id
+        })
+      })
+      .collect();
     self.upstream_maps = Some(UpstreamMap {
       upstream_index_map,
       upstream_id_map,
@@ -78,17 +117,32 @@ impl LoadBalanceStickyBuilder {
   }
 }
 impl<'a> LoadBalanceSticky {
+  /// Get the pointer to the index of the upstream to be served next
+  pub(super) fn current_ptr(&self) -> usize {
+    let ptr = self.ptr.load(Ordering::Relaxed);
+    self.schedule.get(ptr).copied().unwrap_or(ptr)
+  }
+  /// Order of upstream indices in a cycle if weighted
+  pub(super) fn schedule(&self) -> &[usize] {
+    &self.schedule
+  }
   /// Increment the count of upstream served up to the max value
   fn simple_increment_ptr(&self) -> usize {
     // Get a current count of upstream served
     let current_ptr = self.ptr.load(Ordering::Relaxed);
+    let cycle_len = if self.schedule.is_empty() {
+      self.num_upstreams
+    } else {
+      self.schedule.len()
+    };
 
-    if current_ptr < self.num_upstreams - 1 {
+    let ptr = if current_ptr < cycle_len - 1 {
       self.ptr.fetch_add(1, Ordering::Relaxed)
     } else {
       // Clear the counter
       self.ptr.fetch_and(0, Ordering::Relaxed)
-    }
+    };
+    self.schedule.get(ptr).copied().unwrap_or(ptr)
   }
   /// This is always called only internally. So 'unwrap()' is executed.
   fn get_server_id_from_index(&self, index: usize) -> String {
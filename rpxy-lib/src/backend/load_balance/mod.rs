@@ -11,7 +11,7 @@ pub use load_balance_main::{
   load_balance_options, LoadBalance, LoadBalanceContext, LoadBalanceRandomBuilder, LoadBalanceRoundRobinBuilder,
 };
 #[cfg(feature = "sticky-cookie")]
-pub use load_balance_sticky::LoadBalanceStickyBuilder;
+pub use load_balance_sticky::{LoadBalanceSticky, LoadBalanceStickyBuilder};
 #[cfg(feature = "sticky-cookie")]
 pub use sticky_cookie::{StickyCookie, StickyCookieValue};
 
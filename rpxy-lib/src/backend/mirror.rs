@@ -0,0 +1,44 @@
+use super::upstream::Upstream;
+use crate::globals::{MirrorConfig, UpstreamTimeoutConfig};
+use rand::Rng;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone)]
+/// Mirroring policy of a reverse proxy route, copying sampled requests to the shadow upstream
+pub struct MirrorPolicy {
+  /// Shadow upstream, where the protocol is resolved with the options of the route
+  pub upstream: Upstream,
+  /// Percentage of requests mirrored
+  percentage: u8,
+  /// Max size of request bodies buffered for mirroring
+  pub max_body_size: usize,
+  /// Timeouts of mirrored requests
+  pub timeouts: UpstreamTimeoutConfig,
+  /// Limit of mirrored requests in flight, beyond which requests are not mirrored
+  in_flight: Arc<Semaphore>,
+}
+
+impl MirrorPolicy {
+  /// Build the policy with the shadow upstream whose protocol is resolved
+  pub(super) fn new(config: &MirrorConfig, upstream: Upstream, max_in_flight: usize) -> Self {
+    Self {
+      upstream,
+      percentage: config.percentage,
+      max_body_size: config.max_body_size,
+      timeouts: UpstreamTimeoutConfig {
+        request: Some(config.timeout),
+        ..Default::default()
+      },
+      in_flight: Arc::new(Semaphore::new(max_in_flight)),
+    }
+  }
+
+  /// Decide whether the request is mirrored, where the returned permit is held until the mirrored request ends
+  pub fn sample(&self) -> Option<OwnedSemaphorePermit> {
+    if self.percentage < 100 && rand::rng().random_range(0..100) >= self.percentage {
+      return None;
+    }
+    self.in_flight.clone().try_acquire_owned().ok()
+  }
+}
@@ -1,14 +1,27 @@
 mod backend_main;
+mod cors;
+mod fault;
 mod load_balance;
+mod mirror;
+mod route_rules;
+mod security_headers;
 mod upstream;
+mod upstream_discovery;
+mod upstream_list;
 mod upstream_opts;
 
 #[cfg(feature = "sticky-cookie")]
 pub(crate) use self::load_balance::{StickyCookie, StickyCookieValue};
 #[allow(unused)]
 pub(crate) use self::{
+  cors::{CorsPolicy, CorsResponseHeaders},
+  fault::{FaultAction, FaultRule, decide_faults},
   load_balance::{LoadBalance, LoadBalanceContext},
+  mirror::MirrorPolicy,
+  route_rules::{RedirectRule, StaticResponseRule, redirect_status_code},
+  security_headers::SecurityHeaders,
   upstream::{PathManager, Upstream, UpstreamCandidates},
   upstream_opts::UpstreamOption,
 };
 pub(crate) use backend_main::{BackendApp, BackendAppBuilderError, BackendAppManager};
+pub(crate) use upstream_discovery::start_upstream_discovery;
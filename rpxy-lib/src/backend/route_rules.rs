@@ -0,0 +1,423 @@
+use crate::{
+  error::*,
+  globals::{RedirectConfig, StaticResponseConfig},
+  name_exp::{ByteName, PathName},
+};
+use http::{StatusCode, Uri};
+use regex::Regex;
+
+/// Placeholder in static response bodies replaced with the request ID
+const REQUEST_ID_PLACEHOLDER: &str = "{request_id}";
+
+/// Status codes allowed for redirection rules
+const REDIRECT_STATUS_CODES: &[StatusCode] = &[
+  StatusCode::MOVED_PERMANENTLY,
+  StatusCode::FOUND,
+  StatusCode::TEMPORARY_REDIRECT,
+  StatusCode::PERMANENT_REDIRECT,
+];
+
+#[derive(Debug, Clone)]
+/// Matcher of the request path for redirect and static response rules
+pub enum RouteMatcher {
+  /// Path prefix like "/path", matched in the same manner as the reverse proxy path
+  Prefix(PathName),
+  /// Regular expression matched against the whole path
+  Regex(Regex),
+}
+
+/// Captured values of the matched path, used to render the location template
+pub struct RouteMatch<'a> {
+  /// Path following the matched prefix. Empty for regex matches.
+  suffix: &'a str,
+  /// Captures of the regex match
+  captures: Option<regex::Captures<'a>>,
+}
+
+impl RouteMatcher {
+  /// Build matcher from the path prefix or the regex, either one must be given
+  fn try_new(path: &Option<String>, path_regex: &Option<String>) -> RpxyResult<Self> {
+    match (path, path_regex) {
+      (Some(_), Some(_)) => Err(RpxyError::InvalidRouteRule(
+        "Either one of path or path_regex can be specified".to_string(),
+      )),
+      // anchored so that the pattern must match the whole path
+      (None, Some(re)) => Regex::new(&format!("^(?:{re})$"))
+        .map(Self::Regex)
+        .map_err(|e| RpxyError::InvalidRouteRule(format!("Invalid path_regex {re}: {e}"))),
+      (Some(p), None) => Ok(Self::Prefix(p.to_path_name())),
+      (None, None) => Ok(Self::Prefix("/".to_path_name())),
+    }
+  }
+
+  /// Match the given path, and returns captured values if matched
+  pub fn matches<'a>(&self, path: &'a str) -> Option<RouteMatch<'a>> {
+    match self {
+      Self::Prefix(prefix) => {
+        if !path.to_path_name().is_under_route(prefix) {
+          return None;
+        }
+        let suffix = if prefix.len() == 1 { path } else { &path[prefix.len()..] };
+        Some(RouteMatch { suffix, captures: None })
+      }
+      Self::Regex(re) => re.captures(path).map(|captures| RouteMatch {
+        suffix: "",
+        captures: Some(captures),
+      }),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Token of the location template
+enum TemplateToken {
+  /// Literal string
+  Literal(String),
+  /// `$scheme`: "http" or "https" of the incoming request
+  Scheme,
+  /// `$host`: server name of the backend app
+  Host,
+  /// `$path`: path of the incoming request
+  Path,
+  /// `$suffix`: path following the matched prefix
+  Suffix,
+  /// `$query`: query of the incoming request without "?"
+  Query,
+  /// `$request_uri`: path and query of the incoming request
+  RequestUri,
+  /// `$1`, `${name}`: captured value of the regex
+  Capture(String),
+}
+
+#[derive(Debug, Clone)]
+/// Location template of the redirection like "https://www.example.com$request_uri"
+struct LocationTemplate {
+  tokens: Vec<TemplateToken>,
+}
+
+impl TryFrom<&str> for LocationTemplate {
+  type Error = RpxyError;
+
+  /// Parse variables of the form `$name` or `${name}`, where `$$` represents a literal `$`
+  fn try_from(template: &str) -> Result<Self, Self::Error> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+      if c != '$' {
+        literal.push(c);
+        continue;
+      }
+      let name = match chars.peek() {
+        Some('$') => {
+          chars.next();
+          literal.push('$');
+          continue;
+        }
+        Some('{') => {
+          chars.next();
+          let mut name = String::new();
+          loop {
+            match chars.next() {
+              Some('}') => break,
+              Some(c) => name.push(c),
+              None => return Err(RpxyError::InvalidRouteRule(format!("Unclosed variable in {template}"))),
+            }
+          }
+          name
+        }
+        _ => {
+          let mut name = String::new();
+          while let Some(c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+            name.push(*c);
+            chars.next();
+          }
+          name
+        }
+      };
+      let token = match name.as_str() {
+        "" => return Err(RpxyError::InvalidRouteRule(format!("Empty variable in {template}"))),
+        "scheme" => TemplateToken::Scheme,
+        "host" => TemplateToken::Host,
+        "path" => TemplateToken::Path,
+        "suffix" => TemplateToken::Suffix,
+        "query" => TemplateToken::Query,
+        "request_uri" => TemplateToken::RequestUri,
+        v if v.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => TemplateToken::Capture(v.to_string()),
+        v => return Err(RpxyError::InvalidRouteRule(format!("Unknown variable {v} in {template}"))),
+      };
+      if !literal.is_empty() {
+        tokens.push(TemplateToken::Literal(std::mem::take(&mut literal)));
+      }
+      tokens.push(token);
+    }
+    if !literal.is_empty() {
+      tokens.push(TemplateToken::Literal(literal));
+    }
+    Ok(Self { tokens })
+  }
+}
+
+impl LocationTemplate {
+  /// Check if the template refers to the query of the incoming request
+  fn refers_query(&self) -> bool {
+    self
+      .tokens
+      .iter()
+      .any(|t| matches!(t, TemplateToken::Query | TemplateToken::RequestUri))
+  }
+
+  /// Check if all the capture variables exist in the regex
+  fn validate_captures(&self, matcher: &RouteMatcher) -> RpxyResult<()> {
+    for token in self.tokens.iter() {
+      let TemplateToken::Capture(name) = token else {
+        continue;
+      };
+      let exists = match matcher {
+        RouteMatcher::Regex(re) => match name.parse::<usize>() {
+          Ok(idx) => idx < re.captures_len(),
+          Err(_) => re.capture_names().any(|n| n == Some(name.as_str())),
+        },
+        RouteMatcher::Prefix(_) => false,
+      };
+      if !exists {
+        return Err(RpxyError::InvalidRouteRule(format!("No capture group for variable {name}")));
+      }
+    }
+    Ok(())
+  }
+
+  /// Render the template with the given request information
+  fn render(&self, scheme: &str, host: &str, uri: &Uri, route_match: &RouteMatch) -> String {
+    let path = uri.path();
+    let query = uri.query().unwrap_or("");
+    self.tokens.iter().fold(String::new(), |mut acc, token| {
+      match token {
+        TemplateToken::Literal(v) => acc.push_str(v),
+        TemplateToken::Scheme => acc.push_str(scheme),
+        TemplateToken::Host => acc.push_str(host),
+        TemplateToken::Path => acc.push_str(path),
+        TemplateToken::Suffix => acc.push_str(route_match.suffix),
+        TemplateToken::Query => acc.push_str(query),
+        TemplateToken::RequestUri => acc.push_str(uri.path_and_query().map_or(path, |pq| pq.as_str())),
+        TemplateToken::Capture(name) => {
+          let captured = route_match.captures.as_ref().and_then(|c| match name.parse::<usize>() {
+            Ok(idx) => c.get(idx),
+            Err(_) => c.name(name),
+          });
+          acc.push_str(captured.map_or("", |m| m.as_str()));
+        }
+      };
+      acc
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+/// Redirect rule returning a redirection response without any upstream
+pub struct RedirectRule {
+  /// Matcher of the request path
+  pub matcher: RouteMatcher,
+  /// Location template
+  target: LocationTemplate,
+  /// Status code of the redirection response
+  pub status_code: StatusCode,
+  /// Append the original query to the location if the template does not refer to it
+  preserve_query: bool,
+}
+
+/// Status code of redirection responses, which must be one of 301, 302, 307 and 308
+pub(crate) fn redirect_status_code(code: u16) -> RpxyResult<StatusCode> {
+  StatusCode::from_u16(code)
+    .ok()
+    .filter(|c| REDIRECT_STATUS_CODES.contains(c))
+    .ok_or_else(|| {
+      RpxyError::InvalidRouteRule(format!(
+        "Invalid redirect status code {code} (must be one of 301, 302, 307 and 308)"
+      ))
+    })
+}
+
+impl TryFrom<&RedirectConfig> for RedirectRule {
+  type Error = RpxyError;
+
+  fn try_from(config: &RedirectConfig) -> Result<Self, Self::Error> {
+    let matcher = RouteMatcher::try_new(&config.path, &config.path_regex)?;
+    let target = LocationTemplate::try_from(config.target.as_str())?;
+    target.validate_captures(&matcher)?;
+    let status_code = redirect_status_code(config.status_code)?;
+    Ok(Self {
+      matcher,
+      target,
+      status_code,
+      preserve_query: config.preserve_query,
+    })
+  }
+}
+
+impl RedirectRule {
+  /// Returns the location of redirection if the request uri matches the rule
+  pub fn location(&self, scheme: &str, host: &str, uri: &Uri) -> Option<String> {
+    let route_match = self.matcher.matches(uri.path())?;
+    let mut location = self.target.render(scheme, host, uri, &route_match);
+    if let Some(query) = uri
+      .query()
+      .filter(|q| self.preserve_query && !q.is_empty() && !self.target.refers_query())
+    {
+      location.push(if location.contains('?') { '&' } else { '?' });
+      location.push_str(query);
+    }
+    Some(location)
+  }
+}
+
+#[derive(Debug, Clone)]
+/// Static response rule returning a fixed status and body without any upstream
+pub struct StaticResponseRule {
+  /// Matcher of the request path
+  pub matcher: RouteMatcher,
+  /// Status code of the response
+  pub status_code: StatusCode,
+  /// Response body
+  pub body: Option<String>,
+  /// Content-type of the response body
+  pub content_type: Option<String>,
+}
+
+impl StaticResponseRule {
+  /// Render the response body, where `{request_id}` is replaced with the ID of the request
+  pub fn render_body(&self, request_id: &str) -> Option<String> {
+    self.body.as_ref().map(|v| v.replace(REQUEST_ID_PLACEHOLDER, request_id))
+  }
+}
+
+impl TryFrom<&StaticResponseConfig> for StaticResponseRule {
+  type Error = RpxyError;
+
+  fn try_from(config: &StaticResponseConfig) -> Result<Self, Self::Error> {
+    let matcher = RouteMatcher::try_new(&config.path, &config.path_regex)?;
+    let status_code = StatusCode::from_u16(config.status_code)
+      .map_err(|_| RpxyError::InvalidRouteRule(format!("Invalid status code {}", config.status_code)))?;
+    Ok(Self {
+      matcher,
+      status_code,
+      body: config.body.clone(),
+      content_type: config.content_type.clone(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn redirect_config(path: Option<&str>, path_regex: Option<&str>, target: &str) -> RedirectConfig {
+    RedirectConfig {
+      path: path.map(|v| v.to_string()),
+      path_regex: path_regex.map(|v| v.to_string()),
+      target: target.to_string(),
+      status_code: 308,
+      preserve_query: true,
+    }
+  }
+
+  #[test]
+  fn canonical_host_redirect_works() {
+    let rule = RedirectRule::try_from(&redirect_config(None, None, "$scheme://www.example.com$request_uri")).unwrap();
+    let uri = "/path/to?a=b".parse::<Uri>().unwrap();
+    assert_eq!(
+      rule.location("https", "example.com", &uri),
+      Some("https://www.example.com/path/to?a=b".to_string())
+    );
+  }
+
+  #[test]
+  fn prefix_redirect_works() {
+    let rule = RedirectRule::try_from(&redirect_config(Some("/old"), None, "/new$suffix")).unwrap();
+    let uri = "/old/page?a=b".parse::<Uri>().unwrap();
+    assert_eq!(rule.location("https", "example.com", &uri), Some("/new/page?a=b".to_string()));
+    let uri = "/older/page".parse::<Uri>().unwrap();
+    assert_eq!(rule.location("https", "example.com", &uri), None);
+  }
+
+  #[test]
+  fn regex_redirect_works() {
+    let rule = RedirectRule::try_from(&redirect_config(
+      None,
+      Some(r"^/blog/(?<year>\d{4})/(.+)$"),
+      "https://blog.example.com/${year}/$2?from=old",
+    ))
+    .unwrap();
+    let uri = "/blog/2024/post?a=b".parse::<Uri>().unwrap();
+    assert_eq!(
+      rule.location("https", "example.com", &uri),
+      Some("https://blog.example.com/2024/post?from=old&a=b".to_string())
+    );
+    let uri = "/blog/latest".parse::<Uri>().unwrap();
+    assert_eq!(rule.location("https", "example.com", &uri), None);
+  }
+
+  #[test]
+  fn regex_matches_whole_path() {
+    let rule = RedirectRule::try_from(&redirect_config(None, Some(r"/api/(\w+)"), "/v2/$1")).unwrap();
+    let uri = "/api/users".parse::<Uri>().unwrap();
+    assert_eq!(rule.location("https", "example.com", &uri), Some("/v2/users".to_string()));
+    for path in ["/v1/api/users", "/api/users/1"] {
+      let uri = path.parse::<Uri>().unwrap();
+      assert_eq!(rule.location("https", "example.com", &uri), None);
+    }
+  }
+
+  #[test]
+  fn query_not_preserved() {
+    let mut config = redirect_config(Some("/"), None, "https://$host/");
+    config.preserve_query = false;
+    let rule = RedirectRule::try_from(&config).unwrap();
+    let uri = "/any?a=b".parse::<Uri>().unwrap();
+    assert_eq!(
+      rule.location("http", "example.com", &uri),
+      Some("https://example.com/".to_string())
+    );
+  }
+
+  #[test]
+  fn invalid_rules_rejected() {
+    assert!(RedirectRule::try_from(&redirect_config(Some("/"), Some("^/"), "/")).is_err());
+    assert!(RedirectRule::try_from(&redirect_config(Some("/"), None, "/$1")).is_err());
+    assert!(RedirectRule::try_from(&redirect_config(None, Some("^/(a)"), "/$2")).is_err());
+    assert!(RedirectRule::try_from(&redirect_config(Some("/"), None, "/${unclosed")).is_err());
+    assert!(RedirectRule::try_from(&redirect_config(Some("/"), None, "/$unknown-var")).is_err());
+    let mut config = redirect_config(Some("/"), None, "/");
+    config.status_code = 200;
+    assert!(RedirectRule::try_from(&config).is_err());
+    assert!(config.validate().is_err());
+    config.status_code = 307;
+    assert!(config.validate().is_ok());
+  }
+
+  #[test]
+  fn https_redirection_status_code_validated() {
+    let mut config = crate::globals::ProxyConfig::default();
+    assert!(config.validate().is_ok());
+    config.https_redirection_status_code = 303;
+    assert!(config.validate().is_err());
+  }
+
+  #[test]
+  fn static_body_renders_request_id() {
+    let config = StaticResponseConfig {
+      path: Some("/maintenance".to_string()),
+      path_regex: None,
+      status_code: 503,
+      body: Some("Under maintenance. Request ID: {request_id}".to_string()),
+      content_type: None,
+    };
+    let rule = StaticResponseRule::try_from(&config).unwrap();
+    assert_eq!(
+      rule.render_body("0192-abcd").as_deref(),
+      Some("Under maintenance. Request ID: 0192-abcd")
+    );
+    let rule = StaticResponseRule { body: None, ..rule };
+    assert!(rule.render_body("0192-abcd").is_none());
+  }
+}
@@ -0,0 +1,141 @@
+use crate::{
+  error::*,
+  globals::{HstsConfig, SecurityHeadersConfig},
+  log::*,
+};
+use http::{HeaderMap, HeaderName, HeaderValue, header};
+
+/// Minimum max-age of HSTS required for the preload list (1 year)
+const HSTS_PRELOAD_MIN_MAX_AGE: u64 = 31_536_000;
+
+#[derive(Debug, Clone)]
+/// Security response header policy of a backend application, whose header values are validated in advance
+pub struct SecurityHeaders {
+  /// `Strict-Transport-Security` value, only emitted on TLS connections
+  hsts: Option<HeaderValue>,
+  /// Other security headers emitted regardless of TLS
+  headers: Vec<(HeaderName, HeaderValue)>,
+  /// Overwrite headers set by upstream if true, otherwise set them only if absent
+  overwrite: bool,
+}
+
+impl TryFrom<&SecurityHeadersConfig> for SecurityHeaders {
+  type Error = RpxyError;
+
+  fn try_from(config: &SecurityHeadersConfig) -> Result<Self, Self::Error> {
+    let to_header_value = |name: &HeaderName, v: &str| {
+      HeaderValue::from_str(v).map_err(|e| RpxyError::InvalidSecurityHeaders(format!("{name}: {e}")))
+    };
+    let hsts = config
+      .hsts
+      .as_ref()
+      .map(|v| to_header_value(&header::STRICT_TRANSPORT_SECURITY, &hsts_value(v)))
+      .transpose()?;
+    let headers = [
+      (header::X_CONTENT_TYPE_OPTIONS, &config.content_type_options),
+      (header::REFERRER_POLICY, &config.referrer_policy),
+      (header::CONTENT_SECURITY_POLICY, &config.content_security_policy),
+      (HeaderName::from_static("permissions-policy"), &config.permissions_policy),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.as_ref().map(|v| (name, v)))
+    .map(|(name, v)| to_header_value(&name, v).map(|v| (name, v)))
+    .collect::<RpxyResult<Vec<_>>>()?;
+
+    Ok(Self {
+      hsts,
+      headers,
+      overwrite: config.overwrite,
+    })
+  }
+}
+
+/// Build `Strict-Transport-Security` header value
+fn hsts_value(config: &HstsConfig) -> String {
+  if config.preload && (!config.include_subdomains || config.max_age < HSTS_PRELOAD_MIN_MAX_AGE) {
+    warn!("HSTS preload requires includeSubDomains and max-age of at least {HSTS_PRELOAD_MIN_MAX_AGE} seconds");
+  }
+  let mut value = format!("max-age={}", config.max_age);
+  if config.include_subdomains {
+    value.push_str("; includeSubDomains");
+  }
+  if config.preload {
+    value.push_str("; preload");
+  }
+  value
+}
+
+impl SecurityHeaders {
+  /// Apply the policy to response headers. HSTS is only emitted on TLS connections.
+  pub fn apply(&self, headers: &mut HeaderMap, tls_enabled: bool) {
+    let hsts = self
+      .hsts
+      .as_ref()
+      .filter(|_| tls_enabled)
+      .map(|v| (&header::STRICT_TRANSPORT_SECURITY, v));
+    for (name, value) in hsts.into_iter().chain(self.headers.iter().map(|(k, v)| (k, v))) {
+      if self.overwrite {
+        headers.insert(name.clone(), value.clone());
+      } else {
+        headers.entry(name.clone()).or_insert_with(|| value.clone());
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(overwrite: bool) -> SecurityHeadersConfig {
+    SecurityHeadersConfig {
+      hsts: Some(HstsConfig {
+        max_age: 31_536_000,
+        include_subdomains: true,
+        preload: true,
+      }),
+      content_type_options: Some("nosniff".to_string()),
+      referrer_policy: Some("no-referrer".to_string()),
+      content_security_policy: None,
+      permissions_policy: Some("geolocation=()".to_string()),
+      overwrite,
+    }
+  }
+
+  #[test]
+  fn hsts_only_on_tls() {
+    let policy = SecurityHeaders::try_from(&config(false)).unwrap();
+    let mut headers = HeaderMap::new();
+    policy.apply(&mut headers, true);
+    assert_eq!(
+      headers.get(header::STRICT_TRANSPORT_SECURITY).unwrap(),
+      "max-age=31536000; includeSubDomains; preload"
+    );
+    assert_eq!(headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+    assert_eq!(headers.get("permissions-policy").unwrap(), "geolocation=()");
+    assert!(headers.get(header::CONTENT_SECURITY_POLICY).is_none());
+
+    let mut headers = HeaderMap::new();
+    policy.apply(&mut headers, false);
+    assert!(headers.get(header::STRICT_TRANSPORT_SECURITY).is_none());
+    assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer");
+  }
+
+  #[test]
+  fn overwrite_or_set_if_absent() {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("origin"));
+    SecurityHeaders::try_from(&config(false)).unwrap().apply(&mut headers, true);
+    assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "origin");
+
+    SecurityHeaders::try_from(&config(true)).unwrap().apply(&mut headers, true);
+    assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "no-referrer");
+  }
+
+  #[test]
+  fn invalid_header_value_rejected() {
+    let mut config = config(false);
+    config.content_security_policy = Some("default-src\n'self'".to_string());
+    assert!(SecurityHeaders::try_from(&config).is_err());
+  }
+}
@@ -1,23 +1,33 @@
 #[cfg(feature = "sticky-cookie")]
-use super::load_balance::LoadBalanceStickyBuilder;
+use super::load_balance::{LoadBalanceSticky, LoadBalanceStickyBuilder};
 use super::load_balance::{
   load_balance_options as lb_opts, LoadBalance, LoadBalanceContext, LoadBalanceRandomBuilder, LoadBalanceRoundRobinBuilder,
 };
 // use super::{BytesName, LbContext, PathNameBytesExp, UpstreamOption};
-use super::upstream_opts::UpstreamOption;
+use super::{cors::CorsPolicy, fault::FaultRule, mirror::MirrorPolicy, upstream_opts::UpstreamOption};
 use crate::{
+  constants::{MIRROR_MAX_IN_FLIGHT, UNIX_SOCKET_SCHEME},
   error::RpxyError,
-  globals::{AppConfig, UpstreamUri},
+  forwarder::upstream_location,
+  globals::{AppConfig, UpstreamDiscoveryConfig, UpstreamProtocol, UpstreamTimeoutConfig, UpstreamTlsConfig, UpstreamUri},
   log::*,
   name_exp::{ByteName, PathName},
 };
 use ahash::{HashMap, HashSet};
+use arc_swap::ArcSwap;
 #[cfg(feature = "sticky-cookie")]
 use base64::{engine::general_purpose, Engine as _};
 use derive_builder::Builder;
+use http::{Uri, uri::Scheme};
 #[cfg(feature = "sticky-cookie")]
 use sha2::{Digest, Sha256};
-use std::borrow::Cow;
+use std::{
+  borrow::Cow,
+  sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+  },
+};
 
 #[derive(Debug, Clone)]
 /// Handler for given path to route incoming request to path's corresponding upstream server(s).
@@ -32,28 +42,73 @@ impl TryFrom<&AppConfig> for PathManager {
   fn try_from(app_config: &AppConfig) -> Result<Self, Self::Error> {
     let mut inner: HashMap<PathName, UpstreamCandidates> = HashMap::default();
 
-    app_config.reverse_proxy.iter().for_each(|rpc| {
-      let upstream_vec: Vec<Upstream> = rpc.upstream.iter().map(Upstream::from).collect();
-      let elem = UpstreamCandidatesBuilder::default()
+    for rpc in app_config.reverse_proxy.iter() {
+      let mut elem_builder = UpstreamCandidatesBuilder::default();
+      let options = elem_builder
+        .options(&rpc.upstream_options)
+        .options
+        .clone()
+        .unwrap_or_default();
+      let upstream_vec = rpc
+        .upstream
+        .iter()
+        .map(|v| {
+          let mut upstream = Upstream::from(v);
+          upstream.protocol = resolve_protocol(&upstream.uri, upstream.protocol, &options)?;
+          Ok(upstream)
+        })
+        .collect::<Result<Vec<_>, RpxyError>>()?;
+      let cors = rpc.cors.as_ref().map(CorsPolicy::try_from).transpose()?;
+      let mirror = rpc
+        .mirror
+        .as_ref()
+        .map(|v| {
+          let mut upstream = Upstream::from(&v.upstream);
+          upstream.protocol = resolve_protocol(&upstream.uri, upstream.protocol, &options)?;
+          Ok(MirrorPolicy::new(v, upstream, MIRROR_MAX_IN_FLIGHT)) as Result<_, RpxyError>
+        })
+        .transpose()?;
+      let faults = rpc.faults.iter().map(FaultRule::try_from).collect::<Result<Vec<_>, _>>()?;
+      if !faults.is_empty() {
+        warn!(
+          "
+--------------------------------------------------------------------------------------------------
+Fault injection is enabled for route {} of {}!!!
+Requests are delayed, aborted or reset intentionally. Never enable it in production.
+--------------------------------------------------------------------------------------------------",
+          rpc.path.as_deref().unwrap_or("/"),
+          app_config.server_name
+        );
+      }
+      let elem = elem_builder
         .upstream(&upstream_vec)
         .path(&rpc.path)
         .replace_path(&rpc.replace_path)
-        .load_balance(&rpc.load_balance, &upstream_vec, &app_config.server_name, &rpc.path)
-        .options(&rpc.upstream_options)
+        .load_balance(&rpc.load_balance, &app_config.server_name, &rpc.path)
+        .cors(cors)
+        .timeouts(rpc.timeouts)
+        .mirror(mirror)
+        .faults(faults)
         .build()
         .unwrap();
       inner.insert(elem.path.clone(), elem);
-    });
+    }
 
     if app_config.reverse_proxy.iter().filter(|rpc| rpc.path.is_none()).count() >= 2 {
       error!("Multiple default reverse proxy setting");
       return Err(RpxyError::InvalidReverseProxyConfig);
     }
 
-    if !(inner.iter().all(|(_, elem)| {
-      !(elem.options.contains(&UpstreamOption::ForceHttp11Upstream) && elem.options.contains(&UpstreamOption::ForceHttp2Upstream))
-    })) {
-      error!("Either one of force_http11 or force_http2 can be enabled");
+    let force_options = [
+      UpstreamOption::ForceHttp11Upstream,
+      UpstreamOption::ForceHttp2Upstream,
+      UpstreamOption::ForceHttp3Upstream,
+    ];
+    if !(inner
+      .iter()
+      .all(|(_, elem)| force_options.iter().filter(|v| elem.options.contains(v)).count() <= 1))
+    {
+      error!("Either one of force_http11, force_http2 or force_http3 can be enabled");
       return Err(RpxyError::InvalidUpstreamOptionSetting);
     }
 
@@ -61,7 +116,52 @@ impl TryFrom<&AppConfig> for PathManager {
   }
 }
 
+/// Resolve the protocol to the upstream, where `force_httpXX_upstream` options of the route apply only to `auto`,
+/// and unix domain sockets are served over HTTP/1.1.
+fn resolve_protocol(
+  uri: &Uri,
+  protocol: UpstreamProtocol,
+  options: &HashSet<UpstreamOption>,
+) -> Result<UpstreamProtocol, RpxyError> {
+  let is_unix_socket = uri.scheme_str() == Some(UNIX_SOCKET_SCHEME);
+  let is_https = uri.scheme() == Some(&Scheme::HTTPS);
+  let resolved = match protocol {
+    UpstreamProtocol::Auto if options.contains(&UpstreamOption::ForceHttp11Upstream) => UpstreamProtocol::Http1,
+    UpstreamProtocol::Auto if options.contains(&UpstreamOption::ForceHttp2Upstream) && is_https => UpstreamProtocol::H2,
+    UpstreamProtocol::Auto if options.contains(&UpstreamOption::ForceHttp2Upstream) => UpstreamProtocol::H2c,
+    UpstreamProtocol::Auto if options.contains(&UpstreamOption::ForceHttp3Upstream) => UpstreamProtocol::H3,
+    UpstreamProtocol::Auto if is_unix_socket => UpstreamProtocol::Http1,
+    v => v,
+  };
+  let location = upstream_location(uri);
+  match resolved {
+    UpstreamProtocol::H2 | UpstreamProtocol::H2c if is_unix_socket => Err(RpxyError::InvalidUpstreamProtocol(format!(
+      "HTTP/2 is not supported for unix domain socket upstream {location}"
+    ))),
+    UpstreamProtocol::H2 if !is_https => Err(RpxyError::InvalidUpstreamProtocol(format!(
+      "h2 requires TLS for upstream {location}, use h2c for cleartext"
+    ))),
+    UpstreamProtocol::H2c if is_https => Err(RpxyError::InvalidUpstreamProtocol(format!(
+      "h2c requires cleartext for upstream {location}, use h2 for TLS"
+    ))),
+    UpstreamProtocol::H3 if !cfg!(all(feature = "http3-quinn", feature = "rustls-backend")) => {
+      Err(RpxyError::InvalidUpstreamProtocol(format!(
+        "HTTP/3 upstream {location} requires http3-quinn and rustls-backend features"
+      )))
+    }
+    UpstreamProtocol::H3 if !is_https => Err(RpxyError::InvalidUpstreamProtocol(format!(
+      "HTTP/3 requires TLS for upstream {location}"
+    ))),
+    _ => Ok(resolved),
+  }
+}
+
 impl PathManager {
+  /// Iterate over upstream candidates of all routes
+  pub fn iter(&self) -> impl Iterator<Item = &UpstreamCandidates> {
+    self.inner.values()
+  }
+
   /// Get an appropriate upstream destinations for given path string.
   /// trie使ってlongest prefix match させてもいいけどルート記述は少ないと思われるので、
   /// コスト的にこの程度で十分では。
@@ -71,18 +171,7 @@ impl PathManager {
     let matched_upstream = self
       .inner
       .iter()
-      .filter(|(route_bytes, _)| {
-        match path_name.starts_with(route_bytes) {
-          true => {
-            route_bytes.len() == 1 // route = '/', i.e., default
-              || match path_name.get(route_bytes.len()) {
-                None => true, // exact case
-                Some(p) => p == &b'/', // sub-path case
-              }
-          }
-          _ => false,
-        }
-      })
+      .filter(|(route_bytes, _)| path_name.is_under_route(route_bytes))
       .max_by_key(|(route_bytes, _)| route_bytes.len());
     if let Some((path, u)) = matched_upstream {
       debug!(
@@ -101,31 +190,161 @@ impl PathManager {
 pub struct Upstream {
   /// Base uri without specific path
   pub uri: hyper::Uri,
+  /// Protocol to the upstream, resolved at building routes
+  pub protocol: UpstreamProtocol,
+  /// TLS settings to the upstream, where the default ones are used if None
+  pub tls_config: Option<Arc<UpstreamTlsConfig>>,
+  /// Discovery if the upstream in the configuration stands for destinations resolved or listed from it
+  pub discovery: Option<UpstreamDiscoveryConfig>,
+  /// Weight in load balancing, given to destinations listed with weights and 1 otherwise
+  pub weight: u32,
+  /// Uri with the host name of a discovered destination whose `uri` is the resolved address, used for the host header
+  host_uri: Option<hyper::Uri>,
+  /// Draining flag set by operators, where no new request is routed to the upstream if others are available
+  draining: Arc<AtomicBool>,
 }
 impl From<&UpstreamUri> for Upstream {
   fn from(value: &UpstreamUri) -> Self {
     Self {
       uri: value.inner.clone(),
+      protocol: value.protocol,
+      tls_config: value.destination_tls_config(),
+      discovery: value.discovery.clone(),
+      weight: 1,
+      host_uri: None,
+      draining: Default::default(),
     }
   }
 }
 impl Upstream {
+  /// Build a destination discovered from the upstream in the configuration, connected by the resolved address
+  pub(crate) fn discovered(&self, uri: hyper::Uri, host_uri: hyper::Uri) -> Self {
+    Self {
+      uri,
+      protocol: self.protocol,
+      tls_config: self.tls_config.clone(),
+      discovery: None,
+      weight: 1,
+      host_uri: Some(host_uri),
+      draining: Default::default(),
+    }
+  }
+
+  /// Uri whose host and port are used for the host header, i.e., the one with the host name for discovered destinations
+  pub fn host_uri(&self) -> &hyper::Uri {
+    self.host_uri.as_ref().unwrap_or(&self.uri)
+  }
+
+  /// Check if the upstream is draining
+  pub fn is_draining(&self) -> bool {
+    self.draining.load(Ordering::Relaxed)
+  }
+  /// Set or clear the draining flag
+  pub fn set_draining(&self, draining: bool) {
+    self.draining.store(draining, Ordering::Relaxed);
+  }
+
+  /// Location of the upstream, e.g., "http://backend:8080/" or "unix:/run/app.sock"
+  pub fn location(&self) -> String {
+    upstream_location(&self.uri)
+  }
+
   #[cfg(feature = "sticky-cookie")]
   /// Hashing uri with index to avoid collision
   pub fn calculate_id_with_index(&self, index: usize) -> String {
     let mut hasher = Sha256::new();
-    let uri_string = format!("{}&index={}", self.uri.clone(), index);
+    let uri_string = format!("{}&index={}", self.location(), index);
     hasher.update(uri_string.as_bytes());
     let digest = hasher.finalize();
     general_purpose::URL_SAFE_NO_PAD.encode(digest)
   }
 }
+#[derive(Debug, Clone)]
+/// Destinations serving requests of a route with the load balancer over them
+pub struct UpstreamMembers {
+  /// Upstream server(s)
+  pub inner: Vec<Upstream>,
+  /// Load balancing option
+  pub load_balance: LoadBalance,
+}
+
+#[cfg(feature = "sticky-cookie")]
+impl UpstreamMembers {
+  /// Destinations with the sticky load balancer over them
+  fn sticky(&self) -> Option<(&[Upstream], &LoadBalanceSticky)> {
+    match &self.load_balance {
+      LoadBalance::StickyRoundRobin(lb) => Some((&self.inner, lb)),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Load balancing option of a route, from which load balancers are built over the current destinations
+struct LoadBalanceSpec {
+  option: Option<String>,
+  #[cfg(feature = "sticky-cookie")]
+  server_name: String,
+  #[cfg(feature = "sticky-cookie")]
+  path: Option<String>,
+}
+
+impl LoadBalanceSpec {
+  /// Build the load balancer over the given destinations, where the sticky ones inherit ids of destinations from the previous one
+  fn build(&self, upstream_vec: &[Upstream], _previous: Option<&UpstreamMembers>) -> LoadBalance {
+    let upstream_num = &upstream_vec.len();
+    let weights = upstream_vec.iter().map(|v| v.weight).collect::<Vec<_>>();
+    let Some(x) = self.option.as_ref() else {
+      return LoadBalance::default();
+    };
+    match x.as_str() {
+      lb_opts::FIX_TO_FIRST => LoadBalance::FixToFirst,
+      lb_opts::RANDOM => LoadBalance::Random(
+        LoadBalanceRandomBuilder::default()
+          .num_upstreams(upstream_num)
+          .weights(&weights)
+          .build()
+          .unwrap(),
+      ),
+      lb_opts::ROUND_ROBIN => LoadBalance::RoundRobin(
+        LoadBalanceRoundRobinBuilder::default()
+          .num_upstreams(upstream_num)
+          .weights(&weights)
+          .build()
+          .unwrap(),
+      ),
+      #[cfg(feature = "sticky-cookie")]
+      lb_opts::STICKY_ROUND_ROBIN => LoadBalance::StickyRoundRobin(
+        LoadBalanceStickyBuilder::default()
+          .num_upstreams(upstream_num)
+          .weights(&weights)
+          .sticky_config(&self.server_name, &self.path)
+          .upstream_maps(upstream_vec, _previous.and_then(|v| v.sticky())) // TODO:
+          .build()
+          .unwrap(),
+      ),
+      _ => {
+        error!("Specified load balancing option is invalid.");
+        LoadBalance::default()
+      }
+    }
+  }
+}
+
 #[derive(Debug, Clone, Builder)]
 /// Struct serving multiple upstream servers for, e.g., load balancing.
 pub struct UpstreamCandidates {
   #[builder(setter(custom))]
-  /// Upstream server(s)
-  pub inner: Vec<Upstream>,
+  /// Upstream server(s) in the configuration, where discovered ones are expanded to their destinations in `members`
+  pub sources: Vec<Upstream>,
+
+  #[builder(setter(custom))]
+  /// Destinations with the load balancer over them, swapped as a whole when discovered destinations are updated
+  members: Arc<ArcSwap<UpstreamMembers>>,
+
+  #[builder(setter(custom))]
+  /// Load balancing option from which the load balancer is rebuilt at updates of destinations
+  load_balance_spec: LoadBalanceSpec,
 
   #[builder(setter(custom), default)]
   /// Path like "/path" in [[PathName]] associated with the upstream server(s)
@@ -135,19 +354,31 @@ pub struct UpstreamCandidates {
   /// Path in [[PathName]] that will be used to replace the "path" part of incoming url
   pub replace_path: Option<PathName>,
 
-  #[builder(setter(custom), default)]
-  /// Load balancing option
-  pub load_balance: LoadBalance,
-
   #[builder(setter(custom), default)]
   /// Activated upstream options defined in [[UpstreamOption]]
   pub options: HashSet<UpstreamOption>,
+
+  #[builder(default)]
+  /// CORS policy of the route, handling preflight requests at rpxy
+  pub cors: Option<CorsPolicy>,
+
+  #[builder(default)]
+  /// Timeouts of requests to the upstream server(s)
+  pub timeouts: UpstreamTimeoutConfig,
+
+  #[builder(default)]
+  /// Mirroring of requests to a shadow upstream
+  pub mirror: Option<MirrorPolicy>,
+
+  #[builder(default)]
+  /// Fault injection rules applied before forwarding requests
+  pub faults: Vec<FaultRule>,
 }
 
 impl UpstreamCandidatesBuilder {
   /// Set the upstream server(s)
   pub fn upstream(&mut self, upstream_vec: &[Upstream]) -> &mut Self {
-    self.inner = Some(upstream_vec.to_vec());
+    self.sources = Some(upstream_vec.to_vec());
     self
   }
   /// Set the path like "/path" in [[PathName]] associated with the upstream server(s), default is "/"
@@ -164,49 +395,26 @@ impl UpstreamCandidatesBuilder {
     self.replace_path = Some(v.to_owned().as_ref().map_or_else(|| None, |v| Some(v.to_path_name())));
     self
   }
-  /// Set the load balancing option
-  pub fn load_balance(
-    &mut self,
-    v: &Option<String>,
-    // upstream_num: &usize,
-    upstream_vec: &[Upstream],
-    _server_name: &str,
-    _path_opt: &Option<String>,
-  ) -> &mut Self {
-    let upstream_num = &upstream_vec.len();
-    let lb = if let Some(x) = v {
-      match x.as_str() {
-        lb_opts::FIX_TO_FIRST => LoadBalance::FixToFirst,
-        lb_opts::RANDOM => LoadBalance::Random(
-          LoadBalanceRandomBuilder::default()
-            .num_upstreams(upstream_num)
-            .build()
-            .unwrap(),
-        ),
-        lb_opts::ROUND_ROBIN => LoadBalance::RoundRobin(
-          LoadBalanceRoundRobinBuilder::default()
-            .num_upstreams(upstream_num)
-            .build()
-            .unwrap(),
-        ),
-        #[cfg(feature = "sticky-cookie")]
-        lb_opts::STICKY_ROUND_ROBIN => LoadBalance::StickyRoundRobin(
-          LoadBalanceStickyBuilder::default()
-            .num_upstreams(upstream_num)
-            .sticky_config(_server_name, _path_opt)
-            .upstream_maps(upstream_vec) // TODO:
-            .build()
-            .unwrap(),
-        ),
-        _ => {
-          error!("Specified load balancing option is invalid.");
-          LoadBalance::default()
-        }
-      }
-    } else {
-      LoadBalance::default()
+  /// Set the load balancing option, where the initial destinations are the upstream server(s) given by `upstream`
+  /// except for discovered ones, which are added after they are resolved
+  pub fn load_balance(&mut self, v: &Option<String>, _server_name: &str, _path_opt: &Option<String>) -> &mut Self {
+    let spec = LoadBalanceSpec {
+      option: v.clone(),
+      #[cfg(feature = "sticky-cookie")]
+      server_name: _server_name.to_string(),
+      #[cfg(feature = "sticky-cookie")]
+      path: _path_opt.clone(),
     };
-    self.load_balance = Some(lb);
+    let inner = self
+      .sources
+      .iter()
+      .flatten()
+      .filter(|v| v.discovery.is_none())
+      .cloned()
+      .collect::<Vec<_>>();
+    let load_balance = spec.build(&inner, None);
+    self.members = Some(Arc::new(ArcSwap::from_pointee(UpstreamMembers { inner, load_balance })));
+    self.load_balance_spec = Some(spec);
     self
   }
   /// Set the activated upstream options defined in [[UpstreamOption]]
@@ -225,13 +433,56 @@ impl UpstreamCandidatesBuilder {
 }
 
 impl UpstreamCandidates {
-  /// Get an enabled option of load balancing [[LoadBalance]]
-  pub fn get(&self, context_to_lb: &Option<LoadBalanceContext>) -> (Option<&Upstream>, Option<LoadBalanceContext>) {
-    let pointer_to_upstream = self.load_balance.get_context(context_to_lb);
+  /// Current destinations with the load balancer over them
+  pub fn members(&self) -> Arc<UpstreamMembers> {
+    self.members.load_full()
+  }
+
+  /// Get an enabled option of load balancing [[LoadBalance]], where None is returned if no destination is available
+  pub fn get(&self, context_to_lb: &Option<LoadBalanceContext>) -> (Option<Upstream>, Option<LoadBalanceContext>) {
+    let members = self.members.load();
+    if members.inner.is_empty() {
+      return (None, None);
+    }
+    let pointer_to_upstream = members
+      .load_balance
+      .get_context(context_to_lb, members.inner.len(), |i| members.inner[i].is_draining());
     debug!("Upstream of index {} is chosen.", pointer_to_upstream.ptr);
     debug!("Context to LB (Cookie in Request): {:?}", context_to_lb);
     debug!("Context from LB (Set-Cookie in Response): {:?}", pointer_to_upstream.context);
-    (self.inner.get(pointer_to_upstream.ptr), pointer_to_upstream.context)
+    let chosen = members.inner.get(pointer_to_upstream.ptr).cloned();
+    (chosen, pointer_to_upstream.context)
+  }
+
+  /// Update the destinations with the ones discovered from each upstream server in the configuration, given in the same order.
+  /// The current instances of unchanged destinations are kept with their states like draining.
+  /// Returns true if the destinations are changed.
+  pub(crate) fn update_members(&self, mut discovered: Vec<Vec<Upstream>>) -> bool {
+    let current = self.members.load();
+    discovered.resize_with(self.sources.len(), Vec::new);
+    let inner = self
+      .sources
+      .iter()
+      .zip(discovered)
+      .flat_map(|(source, destinations)| match source.discovery {
+        Some(_) => destinations,
+        None => vec![source.clone()],
+      })
+      .map(|u| match current.inner.iter().find(|v| v.uri == u.uri) {
+        Some(v) => Upstream {
+          weight: u.weight,
+          ..v.clone()
+        },
+        None => u,
+      })
+      .collect::<Vec<_>>();
+    let key = |v: &Upstream| (v.uri.clone(), v.weight);
+    if inner.iter().map(key).eq(current.inner.iter().map(key)) {
+      return false;
+    }
+    let load_balance = self.load_balance_spec.build(&inner, Some(&current));
+    self.members.store(Arc::new(UpstreamMembers { inner, load_balance }));
+    true
   }
 }
 
@@ -243,8 +494,13 @@ mod test {
   #[cfg(feature = "sticky-cookie")]
   #[test]
   fn calc_id_works() {
-    let uri = "https://www.rust-lang.org".parse::<hyper::Uri>().unwrap();
-    let upstream = Upstream { uri };
+    let inner = "https://www.rust-lang.org".parse::<hyper::Uri>().unwrap();
+    let upstream = Upstream::from(&UpstreamUri {
+      inner,
+      protocol: Default::default(),
+      tls_config: None,
+      discovery: None,
+    });
     assert_eq!(
       "eGsjoPbactQ1eUJjafYjPT3ekYZQkaqJnHdA_FMSkgM",
       upstream.calculate_id_with_index(0)
@@ -254,4 +510,158 @@ mod test {
       upstream.calculate_id_with_index(1)
     );
   }
+
+  #[cfg(unix)]
+  #[test]
+  fn unix_socket_upstream_works() {
+    let upstream = Upstream::from(&UpstreamUri::unix_socket("/run/app.sock").unwrap());
+    assert_eq!(upstream.location(), "unix:/run/app.sock");
+    assert_eq!(
+      crate::forwarder::parse_upstream_location("unix:/run/app.sock").as_ref(),
+      Some(&upstream.uri)
+    );
+
+    let tcp_upstream = Upstream::from(&UpstreamUri {
+      inner: "http://backend:8080".parse().unwrap(),
+      protocol: Default::default(),
+      tls_config: None,
+      discovery: None,
+    });
+    assert_eq!(tcp_upstream.location(), "http://backend:8080/");
+  }
+
+  #[test]
+  fn upstream_protocol_is_resolved() {
+    let http = "http://backend:8080".parse::<Uri>().unwrap();
+    let https = "https://backend:8443".parse::<Uri>().unwrap();
+    let none = HashSet::default();
+    let force_h1 = HashSet::from_iter([UpstreamOption::ForceHttp11Upstream]);
+    let force_h2 = HashSet::from_iter([UpstreamOption::ForceHttp2Upstream]);
+    let force_h3 = HashSet::from_iter([UpstreamOption::ForceHttp3Upstream]);
+
+    assert_eq!(
+      resolve_protocol(&http, UpstreamProtocol::Auto, &none).unwrap(),
+      UpstreamProtocol::Auto
+    );
+    assert_eq!(
+      resolve_protocol(&http, UpstreamProtocol::Auto, &force_h1).unwrap(),
+      UpstreamProtocol::Http1
+    );
+    assert_eq!(
+      resolve_protocol(&http, UpstreamProtocol::Auto, &force_h2).unwrap(),
+      UpstreamProtocol::H2c
+    );
+    assert_eq!(
+      resolve_protocol(&https, UpstreamProtocol::Auto, &force_h2).unwrap(),
+      UpstreamProtocol::H2
+    );
+    // explicit protocol is prioritized over route options
+    assert_eq!(
+      resolve_protocol(&http, UpstreamProtocol::H2c, &force_h1).unwrap(),
+      UpstreamProtocol::H2c
+    );
+    assert!(resolve_protocol(&http, UpstreamProtocol::H2, &none).is_err());
+    assert!(resolve_protocol(&https, UpstreamProtocol::H2c, &none).is_err());
+    assert!(resolve_protocol(&http, UpstreamProtocol::Auto, &force_h3).is_err());
+    #[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+    assert_eq!(
+      resolve_protocol(&https, UpstreamProtocol::Auto, &force_h3).unwrap(),
+      UpstreamProtocol::H3
+    );
+
+    #[cfg(unix)]
+    {
+      let unix = UpstreamUri::unix_socket("/run/app.sock").unwrap().inner;
+      assert_eq!(
+        resolve_protocol(&unix, UpstreamProtocol::Auto, &none).unwrap(),
+        UpstreamProtocol::Http1
+      );
+      assert!(resolve_protocol(&unix, UpstreamProtocol::Auto, &force_h2).is_err());
+      assert!(resolve_protocol(&unix, UpstreamProtocol::H3, &none).is_err());
+    }
+  }
+
+  #[test]
+  fn draining_upstream_is_skipped() {
+    let upstream_vec = ["http://backend1:8080", "http://backend2:8080", "http://backend3:8080"]
+      .iter()
+      .map(|v| {
+        Upstream::from(&UpstreamUri {
+          inner: v.parse().unwrap(),
+          protocol: Default::default(),
+          tls_config: None,
+          discovery: None,
+        })
+      })
+      .collect::<Vec<_>>();
+    let candidates = UpstreamCandidatesBuilder::default()
+      .upstream(&upstream_vec)
+      .path(&None)
+      .replace_path(&None)
+      .options(&None)
+      .load_balance(&Some("round_robin".to_string()), "example.com", &None)
+      .build()
+      .unwrap();
+
+    candidates.members().inner[1].set_draining(true);
+    let chosen = (0..6)
+      .map(|_| candidates.get(&None).0.unwrap().uri.to_string())
+      .collect::<Vec<_>>();
+    assert!(chosen.iter().all(|v| v != "http://backend2:8080/"));
+    assert!(chosen.iter().any(|v| v == "http://backend3:8080/"));
+
+    // all upstreams are draining, then requests are still served
+    candidates.members().inner.iter().for_each(|v| v.set_draining(true));
+    assert!(candidates.get(&None).0.is_some());
+  }
+
+  #[cfg(feature = "sticky-cookie")]
+  #[test]
+  fn sticky_ids_are_kept_over_membership_changes() {
+    let source = Upstream::from(&UpstreamUri {
+      inner: "http://api.internal".parse().unwrap(),
+      protocol: Default::default(),
+      tls_config: None,
+      discovery: Some(UpstreamDiscoveryConfig {
+        mode: crate::globals::UpstreamDiscoveryMode::File("upstreams.json".into()),
+        ttl: None,
+      }),
+    });
+    let candidates = UpstreamCandidatesBuilder::default()
+      .upstream(std::slice::from_ref(&source))
+      .path(&None)
+      .replace_path(&None)
+      .options(&None)
+      .load_balance(&Some("sticky".to_string()), "example.com", &None)
+      .build()
+      .unwrap();
+    let destinations = |locations: &[&str]| {
+      locations
+        .iter()
+        .map(|v| source.discovered(v.parse().unwrap(), source.uri.clone()))
+        .collect::<Vec<_>>()
+    };
+    // contexts of sticky cookies given to clients for each destination
+    let contexts = || {
+      (0..candidates.members().inner.len())
+        .map(|_| candidates.get(&None))
+        .map(|(upstream, context)| (upstream.unwrap().uri.to_string(), context.unwrap()))
+        .collect::<HashMap<_, _>>()
+    };
+
+    assert!(candidates.update_members(vec![destinations(&["http://10.0.0.1:8080", "http://10.0.0.2:8080"])]));
+    let before = contexts();
+    assert!(candidates.update_members(vec![destinations(&["http://10.0.0.2:8080", "http://10.0.0.3:8080"])]));
+    let after = contexts();
+
+    let id = |contexts: &HashMap<String, LoadBalanceContext>, uri: &str| contexts[uri].sticky_cookie.value.value.clone();
+    assert_eq!(id(&before, "http://10.0.0.2:8080/"), id(&after, "http://10.0.0.2:8080/"));
+    assert_ne!(id(&after, "http://10.0.0.2:8080/"), id(&after, "http://10.0.0.3:8080/"));
+    // clients with the cookie stay with the same destination
+    let context = before["http://10.0.0.2:8080/"].clone();
+    for _ in 0..2 {
+      let chosen = candidates.get(&Some(context.clone())).0.unwrap();
+      assert_eq!(chosen.uri.to_string(), "http://10.0.0.2:8080/");
+    }
+  }
 }
@@ -0,0 +1,707 @@
+use super::{
+  BackendAppManager, Upstream, UpstreamCandidates,
+  upstream_list::{ListClient, ListedUpstream, build_list_client, fetch_list, load_list_file},
+};
+use crate::{
+  constants::{
+    UPSTREAM_DISCOVERY_MAX_INTERVAL_SEC, UPSTREAM_DISCOVERY_MIN_INTERVAL_SEC, UPSTREAM_DISCOVERY_RETRY_INTERVAL_SEC,
+    UPSTREAM_LIST_POLL_INTERVAL_SEC, UPSTREAM_LIST_WATCH_DEBOUNCE_MSEC,
+  },
+  error::{RpxyError, RpxyResult},
+  globals::{Globals, UpstreamDiscoveryConfig, UpstreamDiscoveryMode},
+  log::*,
+  name_exp::{PathName, ServerName},
+};
+use hickory_resolver::{
+  TokioResolver,
+  config::{NameServerConfig, ResolverConfig},
+  name_server::TokioConnectionProvider,
+  proto::xfer::Protocol,
+};
+use http::{
+  Uri,
+  uri::{Authority, Scheme},
+};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+  net::SocketAddr,
+  path::{Path, PathBuf},
+  sync::{Arc, Weak},
+  time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
+
+/// Start discovering destinations of upstreams via DNS or lists for routes having discovered upstreams.
+/// They are resolved here before serving requests, and then re-resolved in background at the intervals of TTLs or configured ones,
+/// or at changes of list files, until the backend applications are dropped at the configuration update.
+pub(crate) async fn start_upstream_discovery(app_manager: &Arc<BackendAppManager>, globals: &Globals) -> RpxyResult<()> {
+  let routes = app_manager
+    .apps
+    .iter()
+    .flat_map(|(server_name, app)| app.path_manager.iter().map(move |c| (server_name, c)))
+    .filter(|(_, candidates)| candidates.sources.iter().any(|v| v.discovery.is_some()))
+    .collect::<Vec<_>>();
+  if routes.is_empty() {
+    return Ok(());
+  }
+  let modes = routes
+    .iter()
+    .flat_map(|(_, candidates)| candidates.sources.iter().filter_map(|v| v.discovery.as_ref()))
+    .map(|v| &v.mode)
+    .collect::<Vec<_>>();
+  let uses_dns = modes
+    .iter()
+    .any(|v| matches!(v, UpstreamDiscoveryMode::Dns | UpstreamDiscoveryMode::Srv));
+  let discoverer = Arc::new(Discoverer {
+    resolver: match uses_dns {
+      true => Some(build_resolver(&globals.proxy_config.upstream_dns_servers)?),
+      false => None,
+    },
+    list_client: match modes.iter().any(|v| matches!(v, UpstreamDiscoveryMode::Http(_))) {
+      true => Some(build_list_client(&globals.runtime_handle)),
+      false => None,
+    },
+  });
+
+  let initial = routes.into_iter().map(|(server_name, candidates)| {
+    let discoverer = discoverer.clone();
+    async move {
+      let mut discovery = RouteDiscovery::new(server_name, candidates);
+      let next = discovery.refresh(&discoverer, candidates).await;
+      (server_name.clone(), candidates.path.clone(), discovery, next)
+    }
+  });
+  for (server_name, path, mut discovery, mut next) in futures::future::join_all(initial).await {
+    let mut list_file_watch = find_route(app_manager, &server_name, &path).and_then(ListFileWatch::new);
+    let app_manager = Arc::downgrade(app_manager);
+    let discoverer = discoverer.clone();
+    globals.runtime_handle.spawn(async move {
+      loop {
+        let changed = async {
+          match list_file_watch.as_mut() {
+            Some(watch) => watch.changed().await,
+            None => std::future::pending().await,
+          }
+        };
+        let list_file_changed = tokio::select! {
+          _ = tokio::time::sleep_until(next) => false,
+          _ = changed => true,
+        };
+        let Some(app_manager) = Weak::upgrade(&app_manager) else {
+          break;
+        };
+        let Some(candidates) = find_route(&app_manager, &server_name, &path) else {
+          break;
+        };
+        if list_file_changed {
+          discovery.expire_list_files(candidates);
+        }
+        next = discovery.refresh(&discoverer, candidates).await;
+      }
+      debug!("Upstream discovery of a route finished");
+    });
+  }
+  Ok(())
+}
+
+/// Find the route of the app
+fn find_route<'a>(
+  app_manager: &'a BackendAppManager,
+  server_name: &ServerName,
+  path: &PathName,
+) -> Option<&'a UpstreamCandidates> {
+  app_manager
+    .apps
+    .get(server_name)?
+    .path_manager
+    .iter()
+    .find(|c| &c.path == path)
+}
+
+/// Watcher of list files of a route via filesystem notifications, in addition to polling them.
+/// Since deploy tooling and kubernetes replace files by renaming and swapping symbolic links, their parent directories are watched.
+struct ListFileWatch {
+  _watcher: RecommendedWatcher,
+  rx: mpsc::UnboundedReceiver<()>,
+}
+
+impl ListFileWatch {
+  /// Start watching list files of the route, where None is returned if the route has none or notifications are unavailable
+  fn new(candidates: &UpstreamCandidates) -> Option<Self> {
+    let files = candidates
+      .sources
+      .iter()
+      .filter_map(|v| match v.discovery.as_ref().map(|v| &v.mode) {
+        Some(UpstreamDiscoveryMode::File(path)) => Some(std::path::absolute(path).unwrap_or_else(|_| path.clone())),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+    if files.is_empty() {
+      return None;
+    }
+    let mut dirs = files
+      .iter()
+      .filter_map(|v| v.parent())
+      .map(Path::to_path_buf)
+      .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let watched = files.clone();
+    let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+      Ok(event) if is_list_file_event(&event, &watched) => {
+        let _ = tx.send(());
+      }
+      Ok(_) => {}
+      Err(e) => warn!("Error in filesystem notifications of upstream lists: {e}"),
+    });
+    let mut watcher = match watcher {
+      Ok(watcher) => watcher,
+      Err(e) => {
+        warn!("Filesystem notifications are unavailable, and upstream lists are tracked only by polling: {e}");
+        return None;
+      }
+    };
+    for dir in dirs {
+      if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+        warn!("Failed to watch {} for upstream lists: {e}", dir.display());
+      }
+    }
+    Some(Self { _watcher: watcher, rx })
+  }
+
+  /// Wait for changes of the list files, debounced until their events calm down, e.g., writing a temporary file and renaming it
+  async fn changed(&mut self) {
+    if self.rx.recv().await.is_none() {
+      return std::future::pending().await;
+    }
+    let debounce = Duration::from_millis(UPSTREAM_LIST_WATCH_DEBOUNCE_MSEC);
+    while let Ok(Some(())) = tokio::time::timeout(debounce, self.rx.recv()).await {}
+  }
+}
+
+/// Check if the event is possibly of the list files
+fn is_list_file_event(event: &Event, files: &[PathBuf]) -> bool {
+  !event.kind.is_access()
+    && event.paths.iter().any(|path| {
+      files.contains(path)
+        // symbolic links swapped like "..data" of config maps mounted on kubernetes
+        || path.file_name().is_some_and(|v| v.to_string_lossy().starts_with(".."))
+    })
+}
+
+/// Resolver of DNS records and client polling lists of destinations, shared among routes and built only if used
+struct Discoverer {
+  resolver: Option<TokioResolver>,
+  list_client: Option<ListClient>,
+}
+
+/// Build the resolver with the given DNS servers, or the system configuration if none.
+/// Records are not cached in the resolver since they are re-resolved at their own intervals.
+fn build_resolver(dns_servers: &[SocketAddr]) -> RpxyResult<TokioResolver> {
+  let mut builder = match dns_servers {
+    [] => TokioResolver::builder_tokio().map_err(|e| RpxyError::FailedToBuildDnsResolver(e.to_string()))?,
+    _ => {
+      let mut config = ResolverConfig::new();
+      for addr in dns_servers {
+        config.add_name_server(NameServerConfig::new(*addr, Protocol::Udp));
+        config.add_name_server(NameServerConfig::new(*addr, Protocol::Tcp));
+      }
+      TokioResolver::builder_with_config(config, TokioConnectionProvider::default())
+    }
+  };
+  builder.options_mut().cache_size = 0;
+  Ok(builder.build())
+}
+
+/// Destinations resolved from a discovered upstream with the time to re-resolve them
+struct Resolved {
+  destinations: Vec<Upstream>,
+  refresh_at: Instant,
+}
+
+/// Discovery state of a route, keeping the last destinations resolved from each upstream in the configuration
+struct RouteDiscovery {
+  /// Route like "example.com/path" for logging
+  route: String,
+  resolved: Vec<Option<Resolved>>,
+}
+
+impl RouteDiscovery {
+  fn new(server_name: &ServerName, candidates: &UpstreamCandidates) -> Self {
+    let server_name: String = server_name.try_into().unwrap_or_default();
+    let path: String = (&candidates.path).try_into().unwrap_or_default();
+    Self {
+      route: format!("{server_name}{path}"),
+      resolved: candidates.sources.iter().map(|_| None).collect(),
+    }
+  }
+
+  /// Make list files of the route due to be reloaded at the next refresh
+  fn expire_list_files(&mut self, candidates: &UpstreamCandidates) {
+    let now = Instant::now();
+    for (source, resolved) in candidates.sources.iter().zip(self.resolved.iter_mut()) {
+      let is_file = matches!(
+        source.discovery.as_ref().map(|v| &v.mode),
+        Some(UpstreamDiscoveryMode::File(_))
+      );
+      if let Some(resolved) = resolved.as_mut().filter(|_| is_file) {
+        resolved.refresh_at = now;
+      }
+    }
+  }
+
+  /// Re-resolve discovered upstreams due to refresh and update destinations of the route, returning the time of the next refresh.
+  /// Destinations are kept on failures except that no record is found.
+  async fn refresh(&mut self, discoverer: &Discoverer, candidates: &UpstreamCandidates) -> Instant {
+    let now = Instant::now();
+    let retry_at = now + Duration::from_secs(UPSTREAM_DISCOVERY_RETRY_INTERVAL_SEC);
+    for (source, resolved) in candidates.sources.iter().zip(self.resolved.iter_mut()) {
+      let Some(discovery) = source.discovery.as_ref() else {
+        continue;
+      };
+      if resolved.as_ref().is_some_and(|v| v.refresh_at > now) {
+        continue;
+      }
+      match resolve(discoverer, source, discovery).await {
+        Ok((destinations, ttl)) => {
+          let interval = discovery.ttl.unwrap_or_else(|| {
+            ttl.clamp(
+              Duration::from_secs(UPSTREAM_DISCOVERY_MIN_INTERVAL_SEC),
+              Duration::from_secs(UPSTREAM_DISCOVERY_MAX_INTERVAL_SEC),
+            )
+          });
+          *resolved = Some(Resolved {
+            destinations,
+            refresh_at: now + interval,
+          });
+        }
+        Err(e) => {
+          warn!("Upstream {}: {e}", source.location());
+          let destinations = match e {
+            DiscoveryError::NoRecords => vec![],
+            DiscoveryError::Other(_) => resolved.take().map(|v| v.destinations).unwrap_or_default(),
+          };
+          *resolved = Some(Resolved {
+            destinations,
+            refresh_at: retry_at,
+          });
+        }
+      }
+    }
+
+    let discovered = self
+      .resolved
+      .iter()
+      .map(|v| v.as_ref().map(|v| v.destinations.clone()).unwrap_or_default())
+      .collect();
+    if candidates.update_members(discovered) {
+      let members = candidates.members();
+      info!(
+        "Destinations of upstreams for {} are updated: [{}]",
+        self.route,
+        members.inner.iter().map(|v| v.location()).collect::<Vec<_>>().join(", ")
+      );
+    }
+    self.resolved.iter().flatten().map(|v| v.refresh_at).min().unwrap_or(retry_at)
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+/// Error on resolving discovered upstreams
+enum DiscoveryError {
+  #[error("No record found")]
+  NoRecords,
+  #[error(transparent)]
+  Other(#[from] RpxyError),
+}
+
+impl From<hickory_resolver::ResolveError> for DiscoveryError {
+  fn from(e: hickory_resolver::ResolveError) -> Self {
+    match e.is_no_records_found() {
+      true => Self::NoRecords,
+      false => Self::Other(RpxyError::FailedToDiscoverUpstream(e.to_string())),
+    }
+  }
+}
+
+/// Resolve destinations of the discovered upstream, with the interval of re-resolution given by the TTL of the records,
+/// where resolved addresses are sorted and listed ones are in the order of the list
+async fn resolve(
+  discoverer: &Discoverer,
+  source: &Upstream,
+  discovery: &UpstreamDiscoveryConfig,
+) -> Result<(Vec<Upstream>, Duration), DiscoveryError> {
+  let scheme = source.uri.scheme().cloned().unwrap_or(Scheme::HTTP);
+  let (listed, ttl) = match &discovery.mode {
+    UpstreamDiscoveryMode::Dns | UpstreamDiscoveryMode::Srv => {
+      let resolver = discoverer
+        .resolver
+        .as_ref()
+        .ok_or_else(|| RpxyError::FailedToDiscoverUpstream("No DNS resolver".to_string()))?;
+      let (addrs, ttl) = lookup(resolver, source, discovery, &scheme).await?;
+      let listed = addrs
+        .into_iter()
+        .map(|addr| {
+          let authority =
+            Authority::try_from(addr.to_string().as_str()).map_err(|e| RpxyError::FailedToDiscoverUpstream(e.to_string()))?;
+          Ok(ListedUpstream { authority, weight: 1 })
+        })
+        .collect::<Result<Vec<_>, RpxyError>>()?;
+      (listed, ttl)
+    }
+    UpstreamDiscoveryMode::File(path) => {
+      let listed = load_list_file(path).await?;
+      (listed, Duration::from_secs(UPSTREAM_LIST_POLL_INTERVAL_SEC))
+    }
+    UpstreamDiscoveryMode::Http(uri) => {
+      let client = discoverer
+        .list_client
+        .as_ref()
+        .ok_or_else(|| RpxyError::FailedToDiscoverUpstream("No client to fetch upstream lists".to_string()))?;
+      let listed = fetch_list(client, uri).await?;
+      (listed, Duration::from_secs(UPSTREAM_LIST_POLL_INTERVAL_SEC))
+    }
+  };
+
+  let server_name = discovery.server_name(&source.uri).unwrap_or_default();
+  let default_port = if scheme == Scheme::HTTPS { 443 } else { 80 };
+  let destinations = listed
+    .into_iter()
+    .map(|ListedUpstream { authority, weight }| {
+      let port = authority.port_u16().unwrap_or(default_port);
+      let uri = Uri::builder()
+        .scheme(scheme.clone())
+        .authority(authority)
+        .path_and_query("/")
+        .build()?;
+      // host of the upstream in the configuration is used for the host header with the port of the destination
+      let host_uri = match discovery.mode {
+        UpstreamDiscoveryMode::Dns => source.uri.clone(),
+        _ => Uri::builder()
+          .scheme(scheme.clone())
+          .authority(format!("{server_name}:{port}"))
+          .path_and_query("/")
+          .build()?,
+      };
+      let mut destination = source.discovered(uri, host_uri);
+      destination.weight = weight;
+      Ok(destination)
+    })
+    .collect::<Result<Vec<_>, http::Error>>()
+    .map_err(|e| RpxyError::FailedToDiscoverUpstream(e.to_string()))?;
+  Ok((destinations, ttl))
+}
+
+/// Look up DNS records of the discovered upstream, returning the sorted addresses with the TTL of the records
+async fn lookup(
+  resolver: &TokioResolver,
+  source: &Upstream,
+  discovery: &UpstreamDiscoveryConfig,
+  scheme: &Scheme,
+) -> Result<(Vec<SocketAddr>, Duration), DiscoveryError> {
+  let host = source
+    .uri
+    .host()
+    .ok_or_else(|| RpxyError::FailedToDiscoverUpstream("No host in upstream uri".to_string()))?;
+  let (mut addrs, valid_until) = match discovery.mode {
+    UpstreamDiscoveryMode::Srv => {
+      let lookup = resolver.srv_lookup(host).await?;
+      let mut valid_until = lookup.as_lookup().valid_until();
+      let priority = lookup.iter().map(|v| v.priority()).min();
+      let mut addrs = vec![];
+      for srv in lookup.iter().filter(|v| Some(v.priority()) == priority) {
+        let target_lookup = resolver.lookup_ip(srv.target().clone()).await?;
+        valid_until = valid_until.min(target_lookup.valid_until());
+        addrs.extend(target_lookup.iter().map(|ip| SocketAddr::new(ip, srv.port())));
+      }
+      (addrs, valid_until)
+    }
+    _ => {
+      let port = source
+        .uri
+        .port_u16()
+        .unwrap_or(if *scheme == Scheme::HTTPS { 443 } else { 80 });
+      let lookup = resolver.lookup_ip(host).await?;
+      let addrs = lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect::<Vec<_>>();
+      (addrs, lookup.valid_until())
+    }
+  };
+  addrs.sort();
+  addrs.dedup();
+  if addrs.is_empty() {
+    return Err(DiscoveryError::NoRecords);
+  }
+  let ttl = valid_until.saturating_duration_since(std::time::Instant::now());
+  Ok((addrs, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{backend::upstream::UpstreamCandidatesBuilder, globals::UpstreamUri, name_exp::ByteName};
+  use hickory_resolver::proto::{
+    op::{Message, MessageType},
+    rr::{
+      Name, RData, Record, RecordType,
+      rdata::{A, SRV},
+    },
+  };
+  use std::{collections::HashMap, net::Ipv4Addr, str::FromStr, sync::Mutex};
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, UdpSocket},
+  };
+
+  type Zone = Arc<Mutex<HashMap<(String, RecordType), Vec<RData>>>>;
+
+  /// Spawn a stub DNS server answering records in the zone with TTL of 30 secs
+  async fn spawn_stub_dns(zone: Zone) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+      let mut buf = [0u8; 1500];
+      while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+        let Ok(req) = Message::from_vec(&buf[..len]) else {
+          continue;
+        };
+        let mut res = Message::new();
+        res
+          .set_id(req.id())
+          .set_message_type(MessageType::Response)
+          .set_op_code(req.op_code())
+          .set_recursion_desired(true)
+          .set_recursion_available(true);
+        for query in req.queries() {
+          let key = (query.name().to_string().trim_end_matches('.').to_string(), query.query_type());
+          let rdata = zone.lock().unwrap().get(&key).cloned().unwrap_or_default();
+          res.add_query(query.clone());
+          res.add_answers(rdata.into_iter().map(|v| Record::from_rdata(query.name().clone(), 30, v)));
+        }
+        let _ = socket.send_to(&res.to_vec().unwrap(), peer).await;
+      }
+    });
+    addr
+  }
+
+  fn set_a(zone: &Zone, name: &str, ips: &[[u8; 4]]) {
+    let rdata = ips.iter().map(|v| RData::A(A(Ipv4Addr::from(*v)))).collect();
+    zone.lock().unwrap().insert((name.to_string(), RecordType::A), rdata);
+  }
+
+  fn candidates(locations: &[(&str, Option<UpstreamDiscoveryMode>)]) -> UpstreamCandidates {
+    let upstream_vec = locations
+      .iter()
+      .map(|(location, mode)| {
+        Upstream::from(&UpstreamUri {
+          inner: location.parse().unwrap(),
+          protocol: Default::default(),
+          tls_config: None,
+          discovery: mode.clone().map(|mode| UpstreamDiscoveryConfig { mode, ttl: None }),
+        })
+      })
+      .collect::<Vec<_>>();
+    UpstreamCandidatesBuilder::default()
+      .upstream(&upstream_vec)
+      .path(&None)
+      .replace_path(&None)
+      .options(&None)
+      .load_balance(&Some("round_robin".to_string()), "example.com", &None)
+      .build()
+      .unwrap()
+  }
+
+  fn member_uris(candidates: &UpstreamCandidates) -> Vec<String> {
+    candidates.members().inner.iter().map(|v| v.uri.to_string()).collect()
+  }
+
+  #[tokio::test]
+  async fn dns_discovery_updates_members() {
+    let zone = Zone::default();
+    set_a(&zone, "api.internal", &[[10, 0, 0, 2], [10, 0, 0, 1]]);
+    let discoverer = Discoverer {
+      resolver: Some(build_resolver(&[spawn_stub_dns(zone.clone()).await]).unwrap()),
+      list_client: None,
+    };
+    let candidates = candidates(&[
+      ("http://api.internal:8080", Some(UpstreamDiscoveryMode::Dns)),
+      ("http://static:80", None),
+    ]);
+    assert_eq!(member_uris(&candidates), ["http://static:80/"]);
+
+    let mut discovery = RouteDiscovery::new(&"example.com".to_server_name(), &candidates);
+    let next = discovery.refresh(&discoverer, &candidates).await;
+    assert!(next > Instant::now() + Duration::from_secs(20));
+    assert_eq!(
+      member_uris(&candidates),
+      ["http://10.0.0.1:8080/", "http://10.0.0.2:8080/", "http://static:80/"]
+    );
+    let members = candidates.members();
+    assert_eq!(members.inner[0].host_uri().to_string(), "http://api.internal:8080/");
+    assert_eq!(members.inner[2].host_uri().to_string(), "http://static:80/");
+
+    // not re-resolved until the TTL expires
+    set_a(&zone, "api.internal", &[[10, 0, 0, 2], [10, 0, 0, 3]]);
+    discovery.refresh(&discoverer, &candidates).await;
+    assert_eq!(candidates.members().inner.len(), 3);
+
+    // unchanged destinations keep their states
+    members.inner[1].set_draining(true);
+    discovery.resolved[0].as_mut().unwrap().refresh_at = Instant::now();
+    discovery.refresh(&discoverer, &candidates).await;
+    assert_eq!(
+      member_uris(&candidates),
+      ["http://10.0.0.2:8080/", "http://10.0.0.3:8080/", "http://static:80/"]
+    );
+    let members = candidates.members();
+    assert!(members.inner[0].is_draining());
+    assert!(!members.inner[1].is_draining());
+    assert_eq!(candidates.get(&None).0.unwrap().uri.to_string(), "http://10.0.0.3:8080/");
+
+    // destinations are removed if no record is found
+    zone.lock().unwrap().clear();
+    discovery.resolved[0].as_mut().unwrap().refresh_at = Instant::now();
+    discovery.refresh(&discoverer, &candidates).await;
+    assert_eq!(member_uris(&candidates), ["http://static:80/"]);
+  }
+
+  #[tokio::test]
+  async fn srv_discovery_uses_highest_priority_targets() {
+    let zone = Zone::default();
+    let srv = [
+      (10, 8081, "a.api.internal."),
+      (10, 8082, "b.api.internal."),
+      (20, 8083, "c.api.internal."),
+    ]
+    .iter()
+    .map(|(priority, port, target)| RData::SRV(SRV::new(*priority, 1, *port, Name::from_str(target).unwrap())))
+    .collect();
+    zone
+      .lock()
+      .unwrap()
+      .insert(("_https._tcp.api.internal".to_string(), RecordType::SRV), srv);
+    set_a(&zone, "a.api.internal", &[[10, 0, 0, 1]]);
+    set_a(&zone, "b.api.internal", &[[10, 0, 0, 2]]);
+    set_a(&zone, "c.api.internal", &[[10, 0, 0, 3]]);
+    let discoverer = Discoverer {
+      resolver: Some(build_resolver(&[spawn_stub_dns(zone.clone()).await]).unwrap()),
+      list_client: None,
+    };
+    let candidates = candidates(&[("https://_https._tcp.api.internal", Some(UpstreamDiscoveryMode::Srv))]);
+    assert!(candidates.get(&None).0.is_none());
+
+    RouteDiscovery::new(&"example.com".to_server_name(), &candidates)
+      .refresh(&discoverer, &candidates)
+      .await;
+    assert_eq!(member_uris(&candidates), ["https://10.0.0.1:8081/", "https://10.0.0.2:8082/"]);
+    let members = candidates.members();
+    assert_eq!(members.inner[1].host_uri().to_string(), "https://api.internal:8082/");
+    let tls_config = members.inner[1].tls_config.as_deref().unwrap();
+    assert_eq!(tls_config.server_name.as_deref(), Some("api.internal"));
+  }
+
+  fn write_list(path: &std::path::Path, entries: &[(&str, u32)]) {
+    let upstream = entries
+      .iter()
+      .map(|(location, weight)| serde_json::json!({ "location": location, "weight": weight }))
+      .collect::<Vec<_>>();
+    std::fs::write(path, serde_json::json!({ "upstream": upstream }).to_string()).unwrap();
+  }
+
+  #[tokio::test]
+  async fn file_discovery_updates_members() {
+    let path = std::env::temp_dir().join(format!("rpxy-upstream-list-{}.json", std::process::id()));
+    write_list(&path, &[("10.0.0.1:8080", 2), ("10.0.0.2:8081", 1)]);
+    let discoverer = Discoverer {
+      resolver: None,
+      list_client: None,
+    };
+    let candidates = candidates(&[("https://api.internal", Some(UpstreamDiscoveryMode::File(path.clone())))]);
+
+    let mut discovery = RouteDiscovery::new(&"example.com".to_server_name(), &candidates);
+    discovery.refresh(&discoverer, &candidates).await;
+    assert_eq!(member_uris(&candidates), ["https://10.0.0.1:8080/", "https://10.0.0.2:8081/"]);
+    let members = candidates.members();
+    assert_eq!(members.inner[0].weight, 2);
+    assert_eq!(members.inner[1].host_uri().to_string(), "https://api.internal:8081/");
+    let chosen = (0..3)
+      .map(|_| candidates.get(&None).0.unwrap().uri.to_string())
+      .filter(|v| v == "https://10.0.0.1:8080/")
+      .count();
+    assert_eq!(chosen, 2);
+
+    // weights are updated, where unchanged destinations keep their states
+    members.inner[1].set_draining(true);
+    write_list(&path, &[("10.0.0.1:8080", 1), ("10.0.0.2:8081", 1), ("10.0.0.3:8082", 1)]);
+    discovery.resolved[0].as_mut().unwrap().refresh_at = Instant::now();
+    discovery.refresh(&discoverer, &candidates).await;
+    let members = candidates.members();
+    assert_eq!(members.inner.iter().map(|v| v.weight).collect::<Vec<_>>(), [1, 1, 1]);
+    assert!(members.inner[1].is_draining());
+    assert_eq!(members.inner[2].uri.to_string(), "https://10.0.0.3:8082/");
+
+    // invalid lists are ignored
+    std::fs::write(&path, "{ broken").unwrap();
+    discovery.resolved[0].as_mut().unwrap().refresh_at = Instant::now();
+    discovery.refresh(&discoverer, &candidates).await;
+    assert_eq!(candidates.members().inner.len(), 3);
+    let _ = std::fs::remove_file(&path);
+  }
+
+  #[tokio::test]
+  async fn list_file_changes_are_watched() {
+    let dir = std::env::temp_dir().join(format!("rpxy-upstream-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("api.json");
+    write_list(&path, &[("10.0.0.1:8080", 1)]);
+    let discoverer = Discoverer {
+      resolver: None,
+      list_client: None,
+    };
+    let candidates = candidates(&[("https://api.internal", Some(UpstreamDiscoveryMode::File(path.clone())))]);
+    let mut discovery = RouteDiscovery::new(&"example.com".to_server_name(), &candidates);
+    discovery.refresh(&discoverer, &candidates).await;
+    let mut watch = ListFileWatch::new(&candidates).unwrap();
+
+    // replaced by renaming, as deploy tooling does, before the polling interval
+    write_list(&dir.join("api.json.tmp"), &[("10.0.0.1:8080", 1), ("10.0.0.2:8080", 1)]);
+    std::fs::rename(dir.join("api.json.tmp"), &path).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), watch.changed()).await.unwrap();
+    discovery.expire_list_files(&candidates);
+    discovery.refresh(&discoverer, &candidates).await;
+    assert_eq!(member_uris(&candidates), ["https://10.0.0.1:8080/", "https://10.0.0.2:8080/"]);
+    let _ = std::fs::remove_dir_all(&dir);
+  }
+
+  #[tokio::test]
+  async fn http_discovery_fetches_list() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf).await;
+        let body = "[[upstream]]\nlocation = '10.0.0.1:8080'\n";
+        let res = format!(
+          "HTTP/1.1 200 OK\r\ncontent-type: application/toml\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+          body.len()
+        );
+        let _ = stream.write_all(res.as_bytes()).await;
+      }
+    });
+    #[cfg(feature = "rustls-backend")]
+    let _ = rustls::crypto::CryptoProvider::install_default(rustls::crypto::aws_lc_rs::default_provider());
+    let discoverer = Discoverer {
+      resolver: None,
+      list_client: Some(build_list_client(&tokio::runtime::Handle::current())),
+    };
+    let list_uri = format!("http://{addr}/upstreams").parse().unwrap();
+    let candidates = candidates(&[("http://api.internal", Some(UpstreamDiscoveryMode::Http(list_uri)))]);
+
+    RouteDiscovery::new(&"example.com".to_server_name(), &candidates)
+      .refresh(&discoverer, &candidates)
+      .await;
+    assert_eq!(member_uris(&candidates), ["http://10.0.0.1:8080/"]);
+  }
+}
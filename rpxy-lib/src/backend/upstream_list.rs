@@ -0,0 +1,168 @@
+use crate::{
+  constants::{UPSTREAM_LIST_FETCH_TIMEOUT_SEC, UPSTREAM_LIST_MAX_SIZE, UPSTREAM_LIST_MAX_WEIGHT},
+  error::{RpxyError, RpxyResult},
+  hyper_ext::rt::LocalExecutor,
+};
+use bytes::Bytes;
+use http::{Uri, header, uri::Authority};
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use serde::Deserialize;
+use std::{path::Path, time::Duration};
+
+#[cfg(feature = "rustls-backend")]
+type ListConnector = hyper_rustls::HttpsConnector<HttpConnector>;
+#[cfg(all(feature = "native-tls-backend", not(feature = "rustls-backend")))]
+type ListConnector = hyper_tls::HttpsConnector<HttpConnector>;
+#[cfg(not(any(feature = "native-tls-backend", feature = "rustls-backend")))]
+type ListConnector = HttpConnector;
+
+/// Client polling lists of upstream destinations from HTTP endpoints
+pub(super) type ListClient = Client<ListConnector, Empty<Bytes>>;
+
+/// Build the client polling lists, where https endpoints are available only with TLS backends
+pub(super) fn build_list_client(runtime_handle: &tokio::runtime::Handle) -> ListClient {
+  let mut http = HttpConnector::new();
+  http.set_connect_timeout(Some(Duration::from_secs(UPSTREAM_LIST_FETCH_TIMEOUT_SEC)));
+
+  #[cfg(feature = "rustls-backend")]
+  let connector = {
+    http.enforce_http(false);
+    #[cfg(feature = "webpki-roots")]
+    let builder = hyper_rustls::HttpsConnectorBuilder::new().with_webpki_roots();
+    #[cfg(not(feature = "webpki-roots"))]
+    let builder = hyper_rustls::HttpsConnectorBuilder::new().with_platform_verifier();
+    builder.https_or_http().enable_http1().wrap_connector(http)
+  };
+  #[cfg(all(feature = "native-tls-backend", not(feature = "rustls-backend")))]
+  let connector = {
+    http.enforce_http(false);
+    hyper_tls::HttpsConnector::new_with_connector(http)
+  };
+  #[cfg(not(any(feature = "native-tls-backend", feature = "rustls-backend")))]
+  let connector = http;
+
+  Client::builder(LocalExecutor::new(runtime_handle.clone())).build(connector)
+}
+
+/// Upstream destination given in a list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ListedUpstream {
+  /// Host and port of the destination
+  pub authority: Authority,
+  /// Weight in load balancing
+  pub weight: u32,
+}
+
+#[derive(Deserialize)]
+/// List of destinations in JSON or TOML, like `{ "upstream": [{ "location": "10.0.0.1:8080", "weight": 2 }] }`
+struct UpstreamList {
+  upstream: Vec<UpstreamListEntry>,
+}
+
+#[derive(Deserialize)]
+struct UpstreamListEntry {
+  location: String,
+  weight: Option<u32>,
+}
+
+/// Load the list from the file, which is in TOML if the extension is "toml" and in JSON otherwise
+pub(super) async fn load_list_file(path: &Path) -> RpxyResult<Vec<ListedUpstream>> {
+  let content = tokio::fs::read(path)
+    .await
+    .map_err(|e| RpxyError::FailedToDiscoverUpstream(format!("Failed to read {}: {e}", path.display())))?;
+  let is_toml = path.extension().is_some_and(|v| v == "toml");
+  parse_list(&content, is_toml)
+}
+
+/// Fetch the list from the HTTP endpoint, which is in TOML if the content type says so or the path has the extension "toml",
+/// and in JSON otherwise
+pub(super) async fn fetch_list(client: &ListClient, uri: &Uri) -> RpxyResult<Vec<ListedUpstream>> {
+  let fetch = async {
+    let res = client
+      .get(uri.clone())
+      .await
+      .map_err(|e| RpxyError::FailedToDiscoverUpstream(format!("Failed to fetch {uri}: {e}")))?;
+    if !res.status().is_success() {
+      return Err(RpxyError::FailedToDiscoverUpstream(format!(
+        "Failed to fetch {uri}: status {}",
+        res.status()
+      )));
+    }
+    let content_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let is_toml = match content_type {
+      Some(v) if v.contains("toml") => true,
+      Some(v) if v.contains("json") => false,
+      _ => uri.path().ends_with(".toml"),
+    };
+    let body = Limited::new(res.into_body(), UPSTREAM_LIST_MAX_SIZE)
+      .collect()
+      .await
+      .map_err(|e| RpxyError::FailedToDiscoverUpstream(format!("Failed to read the response from {uri}: {e}")))?;
+    parse_list(&body.to_bytes(), is_toml)
+  };
+  tokio::time::timeout(Duration::from_secs(UPSTREAM_LIST_FETCH_TIMEOUT_SEC), fetch)
+    .await
+    .map_err(|_| RpxyError::FailedToDiscoverUpstream(format!("Timed out fetching {uri}")))?
+}
+
+/// Parse the list, where the whole list is rejected if any entry is invalid, and duplicated locations are ignored
+fn parse_list(content: &[u8], is_toml: bool) -> RpxyResult<Vec<ListedUpstream>> {
+  let list: UpstreamList = match is_toml {
+    true => std::str::from_utf8(content)
+      .map_err(|e| e.to_string())
+      .and_then(|v| toml::from_str(v).map_err(|e| e.to_string())),
+    false => serde_json::from_slice(content).map_err(|e| e.to_string()),
+  }
+  .map_err(|e| RpxyError::FailedToDiscoverUpstream(format!("Invalid upstream list: {e}")))?;
+
+  let mut listed: Vec<ListedUpstream> = Vec::with_capacity(list.upstream.len());
+  for entry in list.upstream {
+    let authority = entry
+      .location
+      .parse::<Authority>()
+      .map_err(|e| RpxyError::FailedToDiscoverUpstream(format!("Invalid location {} in upstream list: {e}", entry.location)))?;
+    let weight = entry.weight.unwrap_or(1);
+    if !(1..=UPSTREAM_LIST_MAX_WEIGHT).contains(&weight) {
+      return Err(RpxyError::FailedToDiscoverUpstream(format!(
+        "Weight of {} in upstream list must be between 1 and {UPSTREAM_LIST_MAX_WEIGHT}",
+        entry.location
+      )));
+    }
+    if listed.iter().all(|v| v.authority != authority) {
+      listed.push(ListedUpstream { authority, weight });
+    }
+  }
+  Ok(listed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_list_works() {
+    let json = br#"{ "upstream": [{ "location": "10.0.0.1:8080", "weight": 3 }, { "location": "app2:8080" }] }"#;
+    let toml = b"[[upstream]]\nlocation = '10.0.0.1:8080'\nweight = 3\n\n[[upstream]]\nlocation = 'app2:8080'\n";
+    for list in [parse_list(json, false).unwrap(), parse_list(toml, true).unwrap()] {
+      assert_eq!(
+        list,
+        [
+          ListedUpstream {
+            authority: "10.0.0.1:8080".parse().unwrap(),
+            weight: 3
+          },
+          ListedUpstream {
+            authority: "app2:8080".parse().unwrap(),
+            weight: 1
+          },
+        ]
+      );
+    }
+
+    assert!(parse_list(br#"{ "upstream": [] }"#, false).unwrap().is_empty());
+    assert!(parse_list(br#"{ "upstream": [{ "location": "10.0.0.1:8080", "weight": 0 }] }"#, false).is_err());
+    assert!(parse_list(br#"{ "upstream": [{ "location": "10.0.0.1:8080/path" }] }"#, false).is_err());
+    assert!(parse_list(br#"[{ "location": "10.0.0.1:8080" }]"#, false).is_err());
+  }
+}
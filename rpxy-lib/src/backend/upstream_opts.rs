@@ -13,6 +13,10 @@ pub enum UpstreamOption {
   ForceHttp11Upstream,
   /// Force HTTP/2 upstream
   ForceHttp2Upstream,
+  /// Force HTTP/3 upstream
+  ForceHttp3Upstream,
+  /// Allow 0-RTT early data of idempotent requests to HTTP/3 upstreams
+  Allow0RttUpstream,
   // TODO: Adds more options for heder override
 }
 impl TryFrom<&str> for UpstreamOption {
@@ -24,7 +28,23 @@ impl TryFrom<&str> for UpstreamOption {
       "upgrade_insecure_requests" => Ok(Self::UpgradeInsecureRequests),
       "force_http11_upstream" => Ok(Self::ForceHttp11Upstream),
       "force_http2_upstream" => Ok(Self::ForceHttp2Upstream),
+      "force_http3_upstream" => Ok(Self::ForceHttp3Upstream),
+      "allow_0rtt_upstream" => Ok(Self::Allow0RttUpstream),
       _ => Err(RpxyError::UnsupportedUpstreamOption),
     }
   }
 }
+impl UpstreamOption {
+  /// Name of the option given in the configuration
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::KeepOriginalHost => "keep_original_host",
+      Self::SetUpstreamHost => "set_upstream_host",
+      Self::UpgradeInsecureRequests => "upgrade_insecure_requests",
+      Self::ForceHttp11Upstream => "force_http11_upstream",
+      Self::ForceHttp2Upstream => "force_http2_upstream",
+      Self::ForceHttp3Upstream => "force_http3_upstream",
+      Self::Allow0RttUpstream => "allow_0rtt_upstream",
+    }
+  }
+}
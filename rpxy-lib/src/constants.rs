@@ -1,10 +1,42 @@
 pub const RESPONSE_HEADER_SERVER: &str = "rpxy";
 pub const TCP_LISTEN_BACKLOG: u32 = 1024;
+/// Period to back off after failing to accept a connection, e.g., on running out of file descriptors
+pub const ACCEPT_ERROR_BACKOFF_MSEC: u64 = 100;
 pub const PROXY_IDLE_TIMEOUT_SEC: u64 = 20;
 pub const UPSTREAM_IDLE_TIMEOUT_SEC: u64 = 20;
+/// Idle timeout of pooled connections to upstreams, same as the default of hyper
+pub const UPSTREAM_POOL_IDLE_TIMEOUT_SEC: u64 = 90;
+/// Timeout of acknowledgements to HTTP/2 keepalive pings to upstreams, same as the default of hyper
+pub const UPSTREAM_H2_KEEPALIVE_TIMEOUT_SEC: u64 = 20;
 pub const TLS_HANDSHAKE_TIMEOUT_SEC: u64 = 15; // default as with firefox browser
+pub const DRAIN_TIMEOUT_SEC: u64 = 30;
+pub const DRAIN_CHECK_INTERVAL_MSEC: u64 = 100;
 pub const MAX_CLIENTS: usize = 512;
 pub const MAX_CONCURRENT_STREAMS: u32 = 64;
+pub const HTTPS_REDIRECTION_STATUS_CODE: u16 = 301;
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Scheme of upstream uris representing unix domain sockets
+pub const UNIX_SOCKET_SCHEME: &str = "unix";
+/// Bounds of intervals to re-resolve discovered upstreams by TTLs of records, unless the interval is configured
+pub const UPSTREAM_DISCOVERY_MIN_INTERVAL_SEC: u64 = 5;
+pub const UPSTREAM_DISCOVERY_MAX_INTERVAL_SEC: u64 = 300;
+/// Interval to retry resolving discovered upstreams after failures
+pub const UPSTREAM_DISCOVERY_RETRY_INTERVAL_SEC: u64 = 5;
+/// Interval to poll lists of upstream destinations from files and HTTP endpoints, unless the interval is configured.
+/// Files are also watched via filesystem notifications, for which polling is a fallback.
+pub const UPSTREAM_LIST_POLL_INTERVAL_SEC: u64 = 10;
+/// Period to wait for events of a watched list file to calm down before reloading it
+pub const UPSTREAM_LIST_WATCH_DEBOUNCE_MSEC: u64 = 200;
+/// Timeout to fetch lists of upstream destinations from HTTP endpoints
+pub const UPSTREAM_LIST_FETCH_TIMEOUT_SEC: u64 = 10;
+/// Maximum size of lists of upstream destinations
+pub const UPSTREAM_LIST_MAX_SIZE: usize = 1024 * 1024;
+/// Maximum weight of upstream destinations in lists
+pub const UPSTREAM_LIST_MAX_WEIGHT: u32 = 100;
+/// Header added to requests mirrored to shadow upstreams
+pub const MIRROR_MARKER_HEADER: &str = "x-rpxy-mirror";
+/// Maximum number of mirrored requests in flight per route, beyond which requests are not mirrored
+pub const MIRROR_MAX_IN_FLIGHT: usize = 256;
 
 #[allow(non_snake_case)]
 #[cfg(any(feature = "http3-quinn", feature = "http3-s2n"))]
@@ -15,6 +47,8 @@ pub mod H3 {
   pub const MAX_CONCURRENT_BIDISTREAM: u32 = 64;
   pub const MAX_CONCURRENT_UNISTREAM: u32 = 64;
   pub const MAX_IDLE_TIMEOUT: u64 = 10; // secs
+  pub const UPSTREAM_CONNECT_TIMEOUT: u64 = 5; // secs
+  pub const UPSTREAM_FALLBACK_DURATION: u64 = 60; // secs, HTTP/2 is used after QUIC failures during this period
 }
 
 #[cfg(feature = "sticky-cookie")]
@@ -30,8 +64,12 @@ pub const MAX_CACHE_EACH_SIZE: usize = 65_535;
 #[cfg(feature = "cache")]
 // on memory cache if less than or equel to
 pub const MAX_CACHE_EACH_SIZE_ON_MEMORY: usize = 4_096;
-
-// TODO: max cache size in total
+#[cfg(feature = "cache")]
+// max size of all on memory cache objects in bytes
+pub const MAX_CACHE_TOTAL_SIZE_ON_MEMORY: usize = 67_108_864;
+#[cfg(feature = "cache")]
+// max size of all cache files in bytes
+pub const MAX_CACHE_TOTAL_SIZE_ON_FILE: usize = 1_073_741_824;
 
 /// Logging event name TODO: Other separated logs?
 pub mod log_event_names {
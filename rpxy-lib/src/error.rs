@@ -8,6 +8,8 @@ pub enum RpxyError {
   // general errors
   #[error("IO error: {0}")]
   Io(#[from] std::io::Error),
+  #[error("Invalid IP address or CIDR: {0}")]
+  InvalidIpCidr(String),
 
   // TLS errors
   #[error("Failed to build TLS acceptor: {0}")]
@@ -63,6 +65,12 @@ pub enum RpxyError {
   #[error("s2n-quic start error: {0}")]
   S2nQuicStartError(#[from] s2n_quic::provider::StartError),
 
+  // configuration update errors
+  #[error("No running proxy service to update the configuration")]
+  NoRunningProxyService,
+  #[error("Listen sockets need to be re-bound or the cache needs to be rebuilt to apply the configuration")]
+  ListenSocketsChanged,
+
   // certificate reloader errors
   #[error("No certificate reloader when building a proxy for TLS")]
   NoCertificateReloader,
@@ -74,6 +82,20 @@ pub enum RpxyError {
   InvalidReverseProxyConfig,
   #[error("Invalid upstream option setting")]
   InvalidUpstreamOptionSetting,
+  #[error("Invalid upstream protocol: {0}")]
+  InvalidUpstreamProtocol(String),
+  #[error("Invalid redirect or static response rule: {0}")]
+  InvalidRouteRule(String),
+  #[error("Invalid security header setting: {0}")]
+  InvalidSecurityHeaders(String),
+  #[error("Invalid CORS setting: {0}")]
+  InvalidCorsConfig(String),
+  #[error("Invalid fault injection rule: {0}")]
+  InvalidFaultRule(String),
+  #[error("Failed to build DNS resolver for upstream discovery: {0}")]
+  FailedToBuildDnsResolver(String),
+  #[error("Failed to discover upstream destinations: {0}")]
+  FailedToDiscoverUpstream(String),
   #[error("Failed to build backend app: {0}")]
   FailedToBuildBackendApp(#[from] crate::backend::BackendAppBuilderError),
 
@@ -86,12 +108,20 @@ pub enum RpxyError {
   FailedToUpgradeResponse(String),
   #[error("Failed to copy bidirectional for upgraded connections: {0}")]
   FailedToCopyBidirectional(String),
+  #[error("Connection reset by fault injection")]
+  InjectedConnectionReset,
 
   // Forwarder errors
   #[error("Failed to build forwarder: {0}")]
   FailedToBuildForwarder(String),
+  #[error("Failed to connect to upstream: {0}")]
+  FailedToConnectUpstream(String),
   #[error("Failed to fetch from upstream: {0}")]
   FailedToFetchFromUpstream(String),
+  #[error("Upstream timed out: {0}")]
+  UpstreamTimeout(String),
+  #[error("Invalid upstream TLS setting: {0}")]
+  InvalidUpstreamTlsConfig(String),
 
   // Upstream connection setting errors
   #[error("Unsupported upstream option")]
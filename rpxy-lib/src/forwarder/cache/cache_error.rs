@@ -44,4 +44,7 @@ pub enum CacheError {
 
   #[error("Hash mismatched in cache file")]
   HashMismatchedInCacheFile,
+
+  #[error("Failed to write cache index: {0}")]
+  FailedToWriteCacheIndex(String),
 }
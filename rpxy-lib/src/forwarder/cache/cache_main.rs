@@ -1,8 +1,10 @@
 use super::cache_error::*;
 use crate::{
+  error::RpxyError,
   globals::Globals,
   hyper_ext::body::{BoxBody, ResponseBody, UnboundedStreamBody, full},
   log::*,
+  metrics::Metrics,
 };
 use base64::{Engine as _, engine::general_purpose};
 use bytes::{Buf, Bytes, BytesMut};
@@ -10,10 +12,12 @@ use futures::channel::mpsc;
 use http::{Request, Response, Uri};
 use http_body_util::{BodyExt, StreamBody};
 use http_cache_semantics::CachePolicy;
-use hyper::body::{Frame, Incoming};
+use hyper::body::Frame;
 use lru::LruCache;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
+  collections::HashSet,
   path::{Path, PathBuf},
   sync::{
     Arc, Mutex,
@@ -23,10 +27,34 @@ use std::{
 };
 use tokio::{
   fs::{self, File},
-  io::{AsyncReadExt, AsyncWriteExt},
+  io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
   sync::RwLock,
 };
 
+/// Extension of index files written alongside cache files in the persistent mode
+const CACHE_INDEX_EXTENSION: &str = "meta";
+/// Extension of temporary index files being written
+const CACHE_INDEX_TMP_EXTENSION: &str = "meta.tmp";
+
+/* ---------------------------------------------- */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Cache status of a response, attached to the response as an extension for logging
+pub(crate) enum CacheStatus {
+  /// Served from cache
+  Hit,
+  /// Not found in cache and fetched from upstream
+  Miss,
+}
+
+impl CacheStatus {
+  pub(crate) fn as_str(&self) -> &'static str {
+    match self {
+      CacheStatus::Hit => "hit",
+      CacheStatus::Miss => "miss",
+    }
+  }
+}
+
 /* ---------------------------------------------- */
 #[derive(Clone, Debug)]
 /// Cache main manager
@@ -43,6 +71,8 @@ pub(crate) struct RpxyCache {
   max_each_size_on_memory: usize,
   /// Cache directory path
   cache_dir: PathBuf,
+  /// Metrics to record cache hits, misses, evictions and stored bytes
+  metrics: Metrics,
 }
 
 impl RpxyCache {
@@ -53,20 +83,53 @@ impl RpxyCache {
       return None;
     }
     let cache_dir = globals.proxy_config.cache_dir.as_ref().unwrap();
-    let file_store = FileStore::new(&globals.runtime_handle).await;
-    let inner = LruCacheManager::new(globals.proxy_config.cache_max_entry);
+    let persistent = globals.proxy_config.cache_persistent;
+    let file_store = FileStore::new(&globals.runtime_handle, cache_dir, persistent).await;
+    let max_total_size_on_memory = globals.proxy_config.cache_max_total_size_on_memory;
+    let max_total_size_on_file = globals.proxy_config.cache_max_total_size_on_file;
+    let inner = LruCacheManager::new(
+      globals.proxy_config.cache_max_entry,
+      max_total_size_on_memory,
+      max_total_size_on_file,
+      &globals.metrics,
+    );
 
-    let max_each_size = globals.proxy_config.cache_max_each_size;
+    let mut max_each_size = globals.proxy_config.cache_max_each_size;
     let mut max_each_size_on_memory = globals.proxy_config.cache_max_each_size_on_memory;
+    if max_total_size_on_file < max_each_size {
+      warn!("Maximum size of each file cache must be smaller than or equal to the maximum total size of cache files");
+      max_each_size = max_total_size_on_file;
+    }
+    if max_total_size_on_memory < max_each_size_on_memory {
+      warn!("Maximum size of on memory cache per entry must be smaller than or equal to the maximum total size on memory");
+      max_each_size_on_memory = max_total_size_on_memory;
+    }
     if max_each_size < max_each_size_on_memory {
       warn!("Maximum size of on memory cache per entry must be smaller than or equal to the maximum of each file cache");
       max_each_size_on_memory = max_each_size;
     }
 
-    if let Err(e) = fs::remove_dir_all(cache_dir).await {
-      warn!("Failed to clean up the cache dir: {e}");
-    };
+    if !persistent {
+      if let Err(e) = fs::remove_dir_all(cache_dir).await {
+        warn!("Failed to clean up the cache dir: {e}");
+      };
+    }
     fs::create_dir_all(&cache_dir).await.unwrap();
+    if persistent {
+      // restore entries from the index, where ones over the capacity are evicted from the least recently stored ones
+      for (cache_key, cache_object) in file_store.load(max_each_size, max_each_size_on_memory).await {
+        if let Ok(Some((k, v))) = inner.push(&cache_key, &cache_object) {
+          if k != cache_key {
+            file_store.evict(&k, &v.target).await;
+          }
+        }
+        for (k, v) in inner.evict_over_budget(&cache_key) {
+          file_store.evict(&k, &v.target).await;
+        }
+      }
+      let (size_on_memory, size_on_file) = inner.size();
+      info!("Restored cache size: {size_on_memory} bytes on memory, {size_on_file} bytes in files");
+    }
 
     Some(Self {
       file_store,
@@ -75,6 +138,7 @@ impl RpxyCache {
       max_each_size,
       max_each_size_on_memory,
       cache_dir: cache_dir.clone(),
+      metrics: globals.metrics.clone(),
     })
   }
 
@@ -86,8 +150,29 @@ impl RpxyCache {
     (total, on_memory, file)
   }
 
+  /// Total size of cache objects on memory and in files in bytes
+  pub(crate) fn size(&self) -> (usize, usize) {
+    self.inner.size()
+  }
+
+  /// Purge all cache entries including cache files, and return the number of purged entries
+  pub(crate) async fn purge(&self) -> usize {
+    let entries = self.inner.evict_all();
+    for (cache_key, cache_object) in entries.iter() {
+      self.file_store.evict(cache_key, &cache_object.target).await;
+      self.metrics.cache_evicted("purge");
+    }
+    info!("Purged {} cache entries", entries.len());
+    entries.len()
+  }
+
   /// Put response into the cache
-  pub(crate) async fn put(&self, uri: &hyper::Uri, mut body: Incoming, policy: &CachePolicy) -> CacheResult<UnboundedStreamBody> {
+  pub(crate) async fn put(
+    &self,
+    uri: &hyper::Uri,
+    mut body: ResponseBody,
+    policy: &CachePolicy,
+  ) -> CacheResult<UnboundedStreamBody> {
     let cache_manager = self.inner.clone();
     let mut file_store = self.file_store.clone();
     let uri = uri.clone();
@@ -95,8 +180,9 @@ impl RpxyCache {
     let max_each_size = self.max_each_size;
     let max_each_size_on_memory = self.max_each_size_on_memory;
     let cache_dir = self.cache_dir.clone();
+    let metrics = self.metrics.clone();
 
-    let (body_tx, body_rx) = mpsc::unbounded::<Result<Frame<Bytes>, hyper::Error>>();
+    let (body_tx, body_rx) = mpsc::unbounded::<Result<Frame<Bytes>, RpxyError>>();
 
     self.runtime_handle.spawn(async move {
       let mut size = 0usize;
@@ -152,24 +238,42 @@ impl RpxyCache {
 
       // Create cache object
       let cache_key = derive_cache_key_from_uri(&uri);
-      let cache_object = CacheObject {
+      let mut cache_object = CacheObject {
         policy: policy_clone,
         target: CacheFileOrOnMemory::build(&cache_dir, &uri, &buf, max_each_size_on_memory),
         hash: hash_bytes,
+        size,
+        index_size: 0,
+      };
+      // the index entry is counted in the total size of files, which contains the on-memory object as well
+      let index_entry = match file_store.persistent().await {
+        true => Some(cache_object.index_entry(&cache_key)?),
+        false => None,
       };
+      cache_object.index_size = index_entry.as_ref().map(Vec::len).unwrap_or_default();
 
       if let Some((k, v)) = cache_manager.push(&cache_key, &cache_object)? {
         if k != cache_key {
           info!("Over the cache capacity. Evict least recent used entry");
-          if let CacheFileOrOnMemory::File(path) = v.target {
-            file_store.evict(&path).await;
-          }
+          metrics.cache_evicted("capacity");
+          file_store.evict(&k, &v.target).await;
         }
       }
-      // store cache object to file
-      if let CacheFileOrOnMemory::File(_) = cache_object.target {
-        file_store.create(&cache_object, &buf).await?;
+      let evicted = cache_manager.evict_over_budget(&cache_key);
+      if !evicted.is_empty() {
+        info!("Over the total cache size. Evict {} least recent used entries", evicted.len());
       }
+      for (k, v) in evicted {
+        metrics.cache_evicted("size");
+        file_store.evict(&k, &v.target).await;
+      }
+      // store cache object to file, and its index entry if persistent
+      file_store
+        .create(&cache_key, &cache_object, &buf, index_entry.as_deref())
+        .await?;
+      metrics.cache_stored(size);
+      let (size_on_memory, size_on_file) = cache_manager.size();
+      debug!("Cache size: {size_on_memory} bytes on memory, {size_on_file} bytes in files");
 
       Ok(()) as CacheResult<()>
     });
@@ -179,8 +283,19 @@ impl RpxyCache {
     Ok(stream_body)
   }
 
-  /// Get cached response
+  /// Get cached response, recording cache hit or miss
   pub(crate) async fn get<R>(&self, req: &Request<R>) -> Option<Response<ResponseBody>> {
+    let res = self.get_inner(req).await;
+    if res.is_some() {
+      self.metrics.cache_hit();
+    } else {
+      self.metrics.cache_miss();
+    }
+    res
+  }
+
+  /// Get cached response if exists and fresh
+  async fn get_inner<R>(&self, req: &Request<R>) -> Option<Response<ResponseBody>> {
     trace!("Current cache status: (total, on-memory, file) = {:?}", self.count().await);
     let cache_key = derive_cache_key_from_uri(req.uri());
 
@@ -198,22 +313,22 @@ impl RpxyCache {
       // So, we have to evict stale cache entries and cache file objects if found.
       debug!("Stale cache entry: {cache_key}");
       let _evicted_entry = self.inner.evict(&cache_key);
+      self.metrics.cache_evicted("stale");
       // For cache file
-      if let CacheFileOrOnMemory::File(path) = &cached_object.target {
-        self.file_store.evict(&path).await;
-      }
+      self.file_store.evict(&cache_key, &cached_object.target).await;
       return None;
     };
 
     // Finally retrieve the file/on-memory object
-    let response_body = match cached_object.target {
+    let response_body = match &cached_object.target {
       CacheFileOrOnMemory::File(path) => {
         let stream_body = match self.file_store.read(path.clone(), &cached_object.hash).await {
           Ok(s) => s,
           Err(e) => {
             warn!("Failed to read from file cache: {e}");
             let _evicted_entry = self.inner.evict(&cache_key);
-            self.file_store.evict(path).await;
+            self.metrics.cache_evicted("corrupted");
+            self.file_store.evict(&cache_key, &cached_object.target).await;
             return None;
           }
         };
@@ -228,9 +343,11 @@ impl RpxyCache {
         if hash_bytes != cached_object.hash {
           warn!("Hash mismatched. Cache object is corrupted");
           let _evicted_entry = self.inner.evict(&cache_key);
+          self.metrics.cache_evicted("corrupted");
+          self.file_store.evict(&cache_key, &cached_object.target).await;
           return None;
         }
-        ResponseBody::Boxed(BoxBody::new(full(object)))
+        ResponseBody::Boxed(BoxBody::new(full(object.clone())))
       }
     };
     Some(Response::from_parts(res_parts, response_body))
@@ -247,9 +364,9 @@ struct FileStore {
 impl FileStore {
   #[allow(unused)]
   /// Build manager
-  async fn new(runtime_handle: &tokio::runtime::Handle) -> Self {
+  async fn new(runtime_handle: &tokio::runtime::Handle, cache_dir: &Path, persistent: bool) -> Self {
     Self {
-      inner: Arc::new(RwLock::new(FileStoreInner::new(runtime_handle).await)),
+      inner: Arc::new(RwLock::new(FileStoreInner::new(runtime_handle, cache_dir, persistent).await)),
     }
   }
 
@@ -258,18 +375,44 @@ impl FileStore {
     let inner = self.inner.read().await;
     inner.cnt
   }
-  /// Create a temporary file cache
-  async fn create(&mut self, cache_object: &CacheObject, body_bytes: &Bytes) -> CacheResult<()> {
+  /// Whether the index of entries is written alongside cache files
+  async fn persistent(&self) -> bool {
+    self.inner.read().await.persistent
+  }
+  /// Create a temporary file cache for the file target, and the index entry if given in the persistent mode
+  async fn create(
+    &mut self,
+    cache_key: &str,
+    cache_object: &CacheObject,
+    body_bytes: &Bytes,
+    index_entry: Option<&[u8]>,
+  ) -> CacheResult<()> {
     let mut inner = self.inner.write().await;
-    inner.create(cache_object, body_bytes).await
+    if let CacheFileOrOnMemory::File(_) = cache_object.target {
+      inner.create(cache_object, body_bytes).await?;
+    }
+    if let Some(index_entry) = index_entry {
+      inner.write_index(cache_key, index_entry).await?;
+    }
+    Ok(())
   }
-  /// Evict a temporary file cache
-  async fn evict(&self, path: impl AsRef<Path>) {
+  /// Evict a temporary file cache for the file target, and the index entry if persistent
+  async fn evict(&self, cache_key: &str, target: &CacheFileOrOnMemory) {
     // Acquire the write lock
     let mut inner = self.inner.write().await;
-    if let Err(e) = inner.remove(path).await {
-      warn!("Eviction failed during file object removal: {:?}", e);
-    };
+    if let CacheFileOrOnMemory::File(path) = target {
+      if let Err(e) = inner.remove(path).await {
+        warn!("Eviction failed during file object removal: {:?}", e);
+      };
+    }
+    if inner.persistent {
+      inner.remove_index(cache_key).await;
+    }
+  }
+  /// Load entries from the index in the order of storing them, removing corrupted and orphaned files
+  async fn load(&self, max_each_size: usize, max_each_size_on_memory: usize) -> Vec<(String, CacheObject)> {
+    let mut inner = self.inner.write().await;
+    inner.load(max_each_size, max_each_size_on_memory).await
   }
   /// Read a temporary file cache
   async fn read(&self, path: impl AsRef<Path> + Send + Sync + 'static, hash: &Bytes) -> CacheResult<UnboundedStreamBody> {
@@ -285,17 +428,21 @@ struct FileStoreInner {
   cnt: usize,
   /// Async runtime
   runtime_handle: tokio::runtime::Handle,
+  /// Cache directory path
+  cache_dir: PathBuf,
+  /// Whether the index of entries is written alongside cache files to restore the cache after restart
+  persistent: bool,
 }
 
 impl FileStoreInner {
   #[allow(unused)]
   /// Build new cache file manager.
-  /// This first creates cache file dir if not exists, and cleans up the file inside the directory.
-  /// TODO: Persistent cache is really difficult. `sqlite` or something like that is needed.
-  async fn new(runtime_handle: &tokio::runtime::Handle) -> Self {
+  async fn new(runtime_handle: &tokio::runtime::Handle, cache_dir: &Path, persistent: bool) -> Self {
     Self {
       cnt: 0,
       runtime_handle: runtime_handle.clone(),
+      cache_dir: cache_dir.to_path_buf(),
+      persistent,
     }
   }
 
@@ -321,16 +468,33 @@ impl FileStoreInner {
     Ok(())
   }
 
-  /// Retrieve a stored temporary file cache
+  /// Retrieve a stored temporary file cache, which is verified by the hash before sending the first byte.
+  /// The file is read twice on every hit, but never served partially even if it is corrupted after restored.
   async fn read(&self, path: impl AsRef<Path> + Send + Sync + 'static, hash: &Bytes) -> CacheResult<UnboundedStreamBody> {
     let Ok(mut file) = File::open(&path).await else {
       warn!("Cache file object cannot be opened");
       return Err(CacheError::FailedToOpenCacheFile);
     };
+    let mut hasher = Sha256::new();
+    let mut buf = BytesMut::new();
+    loop {
+      match file.read_buf(&mut buf).await {
+        Ok(0) => break,
+        Ok(_) => hasher.update(buf.split().as_ref()),
+        Err(_) => return Err(CacheError::FailedToOpenCacheFile),
+      }
+    }
+    if hasher.finalize().as_slice() != hash.as_ref() {
+      warn!("Hash mismatched. Cache object is corrupted");
+      return Err(CacheError::HashMismatchedInCacheFile);
+    }
+    if file.rewind().await.is_err() {
+      return Err(CacheError::FailedToOpenCacheFile);
+    }
     let hash_clone = hash.clone();
     let mut self_clone = self.clone();
 
-    let (body_tx, body_rx) = mpsc::unbounded::<Result<Frame<Bytes>, hyper::Error>>();
+    let (body_tx, body_rx) = mpsc::unbounded::<Result<Frame<Bytes>, RpxyError>>();
 
     self.runtime_handle.spawn(async move {
       let mut hasher = Sha256::new();
@@ -353,6 +517,8 @@ impl FileStoreInner {
         warn!("Hash mismatched. Cache object is corrupted. Force to remove the cache file.");
         // only file can be evicted
         let _evicted_entry = self_clone.remove(&path).await;
+        // abort the body so that the corrupted object is not taken as a complete response
+        let _ = body_tx.unbounded_send(Err(CacheError::HashMismatchedInCacheFile.into()));
         return Err(CacheError::HashMismatchedInCacheFile);
       }
       Ok(()) as CacheResult<()>
@@ -373,6 +539,220 @@ impl FileStoreInner {
 
     Ok(())
   }
+
+  /// Write the index entry of the cache key, which is replaced at once via a temporary file
+  async fn write_index(&self, cache_key: &str, index_entry: &[u8]) -> CacheResult<()> {
+    let index_path = self.cache_dir.join(derive_index_filename(cache_key));
+    let tmp_path = index_path.with_extension(CACHE_INDEX_TMP_EXTENSION);
+    fs::write(&tmp_path, index_entry)
+      .await
+      .map_err(|e| CacheError::FailedToWriteCacheIndex(e.to_string()))?;
+    fs::rename(&tmp_path, &index_path)
+      .await
+      .map_err(|e| CacheError::FailedToWriteCacheIndex(e.to_string()))
+  }
+
+  /// Remove the index entry of the cache key
+  async fn remove_index(&self, cache_key: &str) {
+    let index_path = self.cache_dir.join(derive_index_filename(cache_key));
+    if let Err(e) = fs::remove_file(&index_path).await {
+      if e.kind() != std::io::ErrorKind::NotFound {
+        warn!("Failed to remove cache index entry at {:?}: {e}", index_path);
+      }
+    }
+  }
+
+  /// Load entries from the index in the order of storing them, where entries are moved between on-memory and files
+  /// according to the current size limit on memory. Corrupted or stale entries and files belonging to no entry are removed.
+  async fn load(&mut self, max_each_size: usize, max_each_size_on_memory: usize) -> Vec<(String, CacheObject)> {
+    let Ok(mut dir) = fs::read_dir(&self.cache_dir).await else {
+      warn!("Failed to read the cache dir: {:?}", self.cache_dir);
+      return vec![];
+    };
+    let mut index_files = vec![];
+    let mut files = HashSet::new();
+    while let Ok(Some(dir_entry)) = dir.next_entry().await {
+      let Ok(metadata) = dir_entry.metadata().await else {
+        continue;
+      };
+      if !metadata.is_file() {
+        continue;
+      }
+      let path = dir_entry.path();
+      if path.extension().is_some_and(|v| v == CACHE_INDEX_EXTENSION) {
+        index_files.push((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), path));
+      } else {
+        files.insert(path);
+      }
+    }
+    index_files.sort();
+
+    let now = SystemTime::now();
+    let mut entries = vec![];
+    for (_, index_path) in index_files {
+      match self
+        .load_entry(&index_path, max_each_size, max_each_size_on_memory, now)
+        .await
+      {
+        Some((cache_key, cache_object)) => {
+          if let CacheFileOrOnMemory::File(path) = &cache_object.target {
+            files.remove(path);
+            self.cnt += 1;
+          }
+          entries.push((cache_key, cache_object));
+        }
+        None => {
+          let _ = fs::remove_file(&index_path).await;
+        }
+      }
+    }
+    // files not referred by valid index entries, e.g., orphaned or corrupted cache files and temporary index files
+    let removed = files.len();
+    for path in files {
+      if let Err(e) = fs::remove_file(&path).await {
+        warn!("Failed to remove an orphaned cache file at {:?}: {e}", path);
+      }
+    }
+    info!(
+      "Loaded {} cache entries from {:?}, and removed {removed} unreferenced files",
+      entries.len(),
+      self.cache_dir
+    );
+    entries
+  }
+
+  /// Load an entry from the index file, where None is returned if it is corrupted or stale.
+  /// The cache file of such an entry is left to be removed as the one belonging to no entry.
+  /// Cache files are only checked for their sizes here to keep the startup fast regardless of the total size of them,
+  /// and their hashes are verified on reading them before sending responses.
+  async fn load_entry(
+    &self,
+    index_path: &Path,
+    max_each_size: usize,
+    max_each_size_on_memory: usize,
+    now: SystemTime,
+  ) -> Option<(String, CacheObject)> {
+    let entry = fs::read(index_path).await.ok().and_then(|v| {
+      serde_json::from_slice::<CacheIndexEntry>(&v)
+        .ok()
+        .map(|entry| (entry, v.len()))
+    });
+    let Some((entry, index_size)) = entry else {
+      warn!("Cache index entry is corrupted: {:?}", index_path);
+      return None;
+    };
+    let file_path = match &entry.target {
+      CacheIndexTarget::File(filename) => Some(self.cache_dir.join(filename)),
+      CacheIndexTarget::OnMemory(_) => None,
+    };
+    // the file name must be the one derived from the key, never pointing to others
+    let valid_names = index_path.file_name() == Some(derive_index_filename(&entry.key).as_ref())
+      && file_path
+        .as_ref()
+        .is_none_or(|v| v.file_name() == Some(derive_filename_from_key(&entry.key).as_ref()));
+    if !valid_names || entry.size > max_each_size {
+      warn!("Cache index entry is invalid: {:?}", index_path);
+      return None;
+    }
+    if entry.policy.is_stale(now) {
+      debug!("Stale cache entry: {}", entry.key);
+      return None;
+    }
+
+    let Some(hash) = general_purpose::STANDARD.decode(&entry.hash).ok().map(Bytes::from) else {
+      warn!("Cache index entry is invalid: {:?}", index_path);
+      return None;
+    };
+
+    // objects to be on memory are loaded and verified here
+    let object = match (&entry.target, &file_path) {
+      (CacheIndexTarget::File(_), Some(path)) if entry.size > max_each_size_on_memory => {
+        let file_size = fs::metadata(path).await.ok().map(|v| v.len());
+        if file_size != Some(entry.size as u64) {
+          warn!("Size mismatched. Cache object is corrupted: {}", entry.key);
+          return None;
+        }
+        None
+      }
+      (CacheIndexTarget::File(_), Some(path)) => fs::read(path).await.ok().map(Bytes::from),
+      (CacheIndexTarget::OnMemory(object), _) => general_purpose::STANDARD.decode(object).ok().map(Bytes::from),
+      (CacheIndexTarget::File(_), None) => return None,
+    };
+    if object
+      .as_ref()
+      .is_some_and(|v| v.len() != entry.size || sha256_bytes(v) != hash)
+    {
+      warn!("Hash mismatched. Cache object is corrupted: {}", entry.key);
+      return None;
+    }
+
+    // move the object between on-memory and file if the size limit on memory is changed
+    let target = match (object, file_path) {
+      (Some(object), _) if object.len() <= max_each_size_on_memory => CacheFileOrOnMemory::OnMemory(object),
+      (Some(object), _) => {
+        let path = self.cache_dir.join(derive_filename_from_key(&entry.key));
+        if let Err(e) = fs::write(&path, &object).await {
+          warn!("Failed to write file cache: {e}");
+          return None;
+        }
+        CacheFileOrOnMemory::File(path)
+      }
+      (None, path) => CacheFileOrOnMemory::File(path?),
+    };
+    let moved = matches!(
+      (&entry.target, &target),
+      (CacheIndexTarget::File(_), CacheFileOrOnMemory::OnMemory(_))
+        | (CacheIndexTarget::OnMemory(_), CacheFileOrOnMemory::File(_))
+    );
+    let mut cache_object = CacheObject {
+      policy: entry.policy,
+      target,
+      hash,
+      size: entry.size,
+      index_size,
+    };
+    if moved {
+      debug!("Cache entry is moved between on-memory and file: {}", entry.key);
+      let res = match cache_object.index_entry(&entry.key) {
+        Ok(index_entry) => {
+          cache_object.index_size = index_entry.len();
+          self.write_index(&entry.key, &index_entry).await
+        }
+        Err(e) => Err(e),
+      };
+      if let Err(e) = res {
+        warn!("Failed to update the cache index entry: {e}");
+        return None;
+      }
+    }
+    Some((entry.key, cache_object))
+  }
+}
+
+/* ---------------------------------------------- */
+#[derive(Serialize, Deserialize)]
+/// Entry of the index written alongside cache files, from which the cache is restored after restart
+struct CacheIndexEntry {
+  /// Cache key
+  key: String,
+  /// Cache policy of the response
+  policy: CachePolicy,
+  /// SHA256 hash of the body in base64
+  hash: String,
+  /// Size of the body in bytes
+  size: usize,
+  /// Target of the body
+  target: CacheIndexTarget,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Target of the body in the index entry
+enum CacheIndexTarget {
+  /// Name of the cache file in the cache directory
+  File(String),
+  /// Body of the on-memory object in base64
+  OnMemory(String),
 }
 
 /* ---------------------------------------------- */
@@ -408,6 +788,30 @@ struct CacheObject {
   target: CacheFileOrOnMemory,
   /// SHA256 hash of target to strongly bind the cache metadata (this object) and file target
   hash: Bytes,
+  /// Size of the body in bytes
+  size: usize,
+  /// Size of the index entry in bytes written in the persistent mode, which is counted in the total size of files
+  index_size: usize,
+}
+
+impl CacheObject {
+  /// Serialize the index entry, where the on-memory object is contained in base64
+  fn index_entry(&self, cache_key: &str) -> CacheResult<Vec<u8>> {
+    let target = match &self.target {
+      CacheFileOrOnMemory::File(path) => {
+        CacheIndexTarget::File(path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+      }
+      CacheFileOrOnMemory::OnMemory(object) => CacheIndexTarget::OnMemory(general_purpose::STANDARD.encode(object)),
+    };
+    let entry = CacheIndexEntry {
+      key: cache_key.to_string(),
+      policy: self.policy.clone(),
+      hash: general_purpose::STANDARD.encode(&self.hash),
+      size: self.size,
+      target,
+    };
+    serde_json::to_vec(&entry).map_err(|e| CacheError::FailedToWriteCacheIndex(e.to_string()))
+  }
 }
 
 /* ---------------------------------------------- */
@@ -418,17 +822,32 @@ struct LruCacheManager {
   inner: Arc<Mutex<LruCache<String, CacheObject>>>, // TODO: keyはstring urlでいいのか疑問。全requestに対してcheckすることになりそう
   /// Counter of current cached object (total)
   cnt: Arc<AtomicUsize>,
+  /// Total size of on-memory cache objects in bytes
+  size_on_memory: Arc<AtomicUsize>,
+  /// Total size of cache files in bytes
+  size_on_file: Arc<AtomicUsize>,
+  /// Maximum total size of on-memory cache objects in bytes
+  max_total_size_on_memory: usize,
+  /// Maximum total size of cache files in bytes
+  max_total_size_on_file: usize,
+  /// Metrics to report the total sizes
+  metrics: Metrics,
 }
 
 impl LruCacheManager {
   #[allow(unused)]
   /// Build LruCache
-  fn new(cache_max_entry: usize) -> Self {
+  fn new(cache_max_entry: usize, max_total_size_on_memory: usize, max_total_size_on_file: usize, metrics: &Metrics) -> Self {
     Self {
       inner: Arc::new(Mutex::new(LruCache::new(
         std::num::NonZeroUsize::new(cache_max_entry).unwrap(),
       ))),
       cnt: Default::default(),
+      size_on_memory: Default::default(),
+      size_on_file: Default::default(),
+      max_total_size_on_memory,
+      max_total_size_on_file,
+      metrics: metrics.clone(),
     }
   }
 
@@ -437,6 +856,38 @@ impl LruCacheManager {
     self.cnt.load(Ordering::Relaxed)
   }
 
+  /// Total size of entries on memory and in files, where the latter includes index entries in the persistent mode
+  fn size(&self) -> (usize, usize) {
+    (
+      self.size_on_memory.load(Ordering::Relaxed),
+      self.size_on_file.load(Ordering::Relaxed),
+    )
+  }
+
+  /// Sizes of the cache object on memory and in files, where the index entry is stored in a file
+  fn tier_sizes(cache_object: &CacheObject) -> (usize, usize) {
+    match cache_object.target {
+      CacheFileOrOnMemory::File(_) => (0, cache_object.size + cache_object.index_size),
+      CacheFileOrOnMemory::OnMemory(_) => (cache_object.size, cache_object.index_size),
+    }
+  }
+
+  /// Update total sizes with added and removed entries, which must be called while the lock is held
+  fn update_size(&self, added: Option<&CacheObject>, removed: Option<&CacheObject>) {
+    if let Some(v) = added {
+      let (on_memory, on_file) = Self::tier_sizes(v);
+      self.size_on_memory.fetch_add(on_memory, Ordering::Relaxed);
+      self.size_on_file.fetch_add(on_file, Ordering::Relaxed);
+    }
+    if let Some(v) = removed {
+      let (on_memory, on_file) = Self::tier_sizes(v);
+      self.size_on_memory.fetch_sub(on_memory, Ordering::Relaxed);
+      self.size_on_file.fetch_sub(on_file, Ordering::Relaxed);
+    }
+    let (size_on_memory, size_on_file) = self.size();
+    self.metrics.cache_size(size_on_memory, size_on_file);
+  }
+
   /// Evict an entry
   fn evict(&self, cache_key: &str) -> Option<(String, CacheObject)> {
     let Ok(mut lock) = self.inner.lock() else {
@@ -444,20 +895,68 @@ impl LruCacheManager {
       return None;
     };
     let res = lock.pop_entry(cache_key);
+    self.update_size(None, res.as_ref().map(|(_, v)| v));
     // This may be inconsistent with the actual number of entries
     self.cnt.store(lock.len(), Ordering::Relaxed);
     res
   }
 
+  /// Evict all entries
+  fn evict_all(&self) -> Vec<(String, CacheObject)> {
+    let Ok(mut lock) = self.inner.lock() else {
+      error!("Mutex can't be locked to evict all cache entries");
+      return vec![];
+    };
+    let res = std::iter::from_fn(|| lock.pop_lru()).collect::<Vec<_>>();
+    self.size_on_memory.store(0, Ordering::Relaxed);
+    self.size_on_file.store(0, Ordering::Relaxed);
+    self.update_size(None, None);
+    self.cnt.store(lock.len(), Ordering::Relaxed);
+    res
+  }
+
   /// Push an entry
   fn push(&self, cache_key: &str, cache_object: &CacheObject) -> CacheResult<Option<(String, CacheObject)>> {
     let Ok(mut lock) = self.inner.lock() else {
       error!("Failed to acquire mutex lock for writing cache entry");
       return Err(CacheError::FailedToAcquiredMutexLockForCache);
     };
-    let res = Ok(lock.push(cache_key.to_string(), cache_object.clone()));
+    let res = lock.push(cache_key.to_string(), cache_object.clone());
+    // replaced or evicted entry
+    self.update_size(Some(cache_object), res.as_ref().map(|(_, v)| v));
     // This may be inconsistent with the actual number of entries
     self.cnt.store(lock.len(), Ordering::Relaxed);
+    Ok(res)
+  }
+
+  /// Evict least recently used entries in tiers over the maximum total size, except for the given entry
+  fn evict_over_budget(&self, cache_key: &str) -> Vec<(String, CacheObject)> {
+    let Ok(mut lock) = self.inner.lock() else {
+      error!("Mutex can't be locked to evict cache entries over the total size");
+      return vec![];
+    };
+    let mut res = vec![];
+    loop {
+      let over_on_memory = self.size_on_memory.load(Ordering::Relaxed) > self.max_total_size_on_memory;
+      let over_on_file = self.size_on_file.load(Ordering::Relaxed) > self.max_total_size_on_file;
+      if !over_on_memory && !over_on_file {
+        break;
+      }
+      let lru_key = lock
+        .iter()
+        .rev()
+        .find(|(k, v)| {
+          let (on_memory, on_file) = Self::tier_sizes(v);
+          k.as_str() != cache_key && ((over_on_memory && on_memory > 0) || (over_on_file && on_file > 0))
+        })
+        .map(|(k, _)| k.clone());
+      let Some(entry) = lru_key.and_then(|k| lock.pop_entry(&k)) else {
+        break;
+      };
+      self.update_size(None, Some(&entry.1));
+      res.push(entry);
+    }
+    self.cnt.store(lock.len(), Ordering::Relaxed);
     res
   }
 
@@ -498,12 +997,191 @@ pub(crate) fn get_policy_if_cacheable<B1, B2>(
 }
 
 fn derive_filename_from_uri(uri: &hyper::Uri) -> String {
+  derive_filename_from_key(&derive_cache_key_from_uri(uri))
+}
+
+fn derive_filename_from_key(cache_key: &str) -> String {
   let mut hasher = Sha256::new();
-  hasher.update(uri.to_string());
+  hasher.update(cache_key);
   let digest = hasher.finalize();
   general_purpose::URL_SAFE_NO_PAD.encode(digest)
 }
 
+fn derive_index_filename(cache_key: &str) -> String {
+  format!("{}.{CACHE_INDEX_EXTENSION}", derive_filename_from_key(cache_key))
+}
+
+fn sha256_bytes(object: &[u8]) -> Bytes {
+  Bytes::copy_from_slice(Sha256::digest(object).as_ref())
+}
+
 fn derive_cache_key_from_uri(uri: &hyper::Uri) -> String {
   uri.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn cache_object(cache_dir: &Path, uri: &str, body: &Bytes) -> (String, CacheObject) {
+    let uri = uri.parse::<Uri>().unwrap();
+    let req = Request::builder().uri(&uri).body(()).unwrap();
+    let res = Response::builder().header("cache-control", "max-age=3600").body(()).unwrap();
+    let cache_object = CacheObject {
+      policy: CachePolicy::new(&req, &res),
+      target: CacheFileOrOnMemory::build(cache_dir, &uri, body, 4),
+      hash: sha256_bytes(body),
+      size: body.len(),
+      index_size: 0,
+    };
+    (derive_cache_key_from_uri(&uri), cache_object)
+  }
+
+  #[tokio::test]
+  async fn persistent_cache_is_loaded_with_integrity_check() {
+    let cache_dir = std::env::temp_dir().join(format!("rpxy-cache-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&cache_dir).await;
+    fs::create_dir_all(&cache_dir).await.unwrap();
+    let runtime_handle = tokio::runtime::Handle::current();
+
+    let mut file_store = FileStore::new(&runtime_handle, &cache_dir, true).await;
+    let bodies = [
+      Bytes::from_static(b"file body"),
+      Bytes::from_static(b"mem"),
+      Bytes::from_static(b"broken"),
+    ];
+    let mut entries = vec![];
+    for (uri, body) in [
+      "http://example.com/file",
+      "http://example.com/mem",
+      "http://example.com/broken",
+    ]
+    .iter()
+    .zip(bodies.iter())
+    {
+      let (cache_key, cache_object) = cache_object(&cache_dir, uri, body);
+      let index_entry = cache_object.index_entry(&cache_key).unwrap();
+      file_store
+        .create(&cache_key, &cache_object, body, Some(&index_entry))
+        .await
+        .unwrap();
+      entries.push((cache_key, cache_object));
+    }
+    // corrupted cache file and orphaned one
+    let CacheFileOrOnMemory::File(broken_path) = &entries[2].1.target else {
+      panic!("must be a file target");
+    };
+    fs::write(broken_path, b"tampered").await.unwrap();
+    fs::write(cache_dir.join("orphan"), b"orphan").await.unwrap();
+
+    let file_store = FileStore::new(&runtime_handle, &cache_dir, true).await;
+    let loaded = file_store.load(1024, 4).await;
+    let keys = loaded.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+    assert_eq!(keys.len(), 2);
+    assert!(keys.contains(&entries[0].0.as_str()) && keys.contains(&entries[1].0.as_str()));
+    assert_eq!(file_store.count().await, 1);
+    assert!(
+      loaded
+        .iter()
+        .any(|(_, v)| matches!(&v.target, CacheFileOrOnMemory::OnMemory(object) if object == &bodies[1]))
+    );
+    assert!(!broken_path.exists());
+    assert!(!cache_dir.join("orphan").exists());
+    assert!(!cache_dir.join(derive_index_filename(&entries[2].0)).exists());
+    for (cache_key, cache_object) in &loaded {
+      let index_size = fs::metadata(cache_dir.join(derive_index_filename(cache_key)))
+        .await
+        .unwrap()
+        .len();
+      assert_eq!(cache_object.index_size as u64, index_size);
+    }
+
+    // entries are moved onto memory or into files as the size limit on memory changes
+    let file_store = FileStore::new(&runtime_handle, &cache_dir, true).await;
+    let loaded = file_store.load(1024, 16).await;
+    assert!(
+      loaded
+        .iter()
+        .all(|(_, v)| matches!(v.target, CacheFileOrOnMemory::OnMemory(_)))
+    );
+    assert_eq!(file_store.count().await, 0);
+    let file_store = FileStore::new(&runtime_handle, &cache_dir, true).await;
+    let loaded = file_store.load(1024, 2).await;
+    assert!(loaded.iter().all(|(_, v)| matches!(v.target, CacheFileOrOnMemory::File(_))));
+    assert_eq!(file_store.count().await, 2);
+
+    // cache files of the same size are verified on reading them before sending any byte
+    let (cache_key, cache_object) = loaded.iter().find(|(k, _)| k == &entries[0].0).unwrap();
+    let CacheFileOrOnMemory::File(path) = &cache_object.target else {
+      panic!("must be a file target");
+    };
+    let body = file_store.read(path.clone(), &cache_object.hash).await.unwrap();
+    assert_eq!(body.collect().await.unwrap().to_bytes(), bodies[0]);
+    fs::write(path, b"FILE BODY").await.unwrap();
+    let file_store = FileStore::new(&runtime_handle, &cache_dir, true).await;
+    assert_eq!(file_store.load(1024, 2).await.len(), 2);
+    assert!(matches!(
+      file_store.read(path.clone(), &cache_object.hash).await,
+      Err(CacheError::HashMismatchedInCacheFile)
+    ));
+    file_store.evict(cache_key, &cache_object.target).await;
+    assert!(!path.exists());
+
+    // eviction removes both the cache file and its index entry
+    let file_store = FileStore::new(&runtime_handle, &cache_dir, true).await;
+    let loaded = file_store.load(1024, 2).await;
+    assert_eq!(loaded.len(), 1);
+    let (cache_key, cache_object) = &loaded[0];
+    file_store.evict(cache_key, &cache_object.target).await;
+    assert!(file_store.load(1024, 2).await.is_empty());
+
+    let _ = fs::remove_dir_all(&cache_dir).await;
+  }
+
+  #[test]
+  fn entries_over_total_size_are_evicted_in_lru_order() {
+    let cache_dir = std::env::temp_dir();
+    let manager = LruCacheManager::new(10, 8, 25, &Metrics::new());
+    let push = |uri: &str, body: &'static [u8]| {
+      let (cache_key, cache_object) = cache_object(&cache_dir, uri, &Bytes::from_static(body));
+      assert!(manager.push(&cache_key, &cache_object).unwrap().is_none());
+      manager
+        .evict_over_budget(&cache_key)
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect::<Vec<_>>()
+    };
+
+    assert!(push("http://example.com/m1", b"abc").is_empty());
+    assert!(push("http://example.com/m2", b"def").is_empty());
+    assert!(push("http://example.com/f1", b"0123456789").is_empty());
+    assert!(push("http://example.com/f2", b"0123456789").is_empty());
+    assert_eq!(manager.size(), (6, 20));
+    // m1 is recently used, and only on-memory entries are evicted for the on-memory budget
+    manager.get("http://example.com/m1").unwrap();
+    assert_eq!(push("http://example.com/m3", b"ghi"), vec!["http://example.com/m2"]);
+    assert_eq!(manager.size(), (6, 20));
+    assert_eq!(
+      push("http://example.com/f3", b"01234567890123456789"),
+      vec!["http://example.com/f1", "http://example.com/f2"]
+    );
+    assert_eq!(manager.size(), (6, 20));
+    assert_eq!(manager.count(), 3);
+
+    manager.evict("http://example.com/m1");
+    assert_eq!(manager.size(), (3, 20));
+    manager.evict_all();
+    assert_eq!(manager.size(), (0, 0));
+
+    // index entries in the persistent mode are counted in the total size of files even for on-memory objects
+    let (cache_key, mut cache_object) = cache_object(&cache_dir, "http://example.com/m4", &Bytes::from_static(b"jkl"));
+    cache_object.index_size = 10;
+    manager.push(&cache_key, &cache_object).unwrap();
+    assert_eq!(manager.size(), (3, 10));
+    assert_eq!(
+      push("http://example.com/f4", b"0123456789012345"),
+      vec!["http://example.com/m4"]
+    );
+    assert_eq!(manager.size(), (0, 16));
+  }
+}
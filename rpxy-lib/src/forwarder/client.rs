@@ -1,21 +1,40 @@
 #[allow(unused)]
 use crate::{
+  constants::UNIX_SOCKET_SCHEME,
   error::{RpxyError, RpxyResult},
-  globals::Globals,
-  hyper_ext::{body::ResponseBody, rt::LocalExecutor},
+  globals::{AppConfigList, Globals, UpstreamProtocol, UpstreamTimeoutConfig, UpstreamTlsConfig},
+  hyper_ext::{
+    body::{ResponseBody, TimeoutBody},
+    rt::{LocalExecutor, TokioTimer},
+  },
   log::*,
 };
+use ahash::{HashMap, HashSet};
 use async_trait::async_trait;
-use http::{Request, Response, Version};
-use hyper::body::{Body, Incoming};
-use hyper_util::client::legacy::{
-  connect::{Connect, HttpConnector},
-  Client,
+use http::{Request, Response, Uri, Version};
+use hyper::body::Body;
+use hyper_util::{
+  client::legacy::{
+    connect::{Connect, Connected, Connection, HttpConnector},
+    Client,
+  },
+  rt::TokioIo,
 };
-use std::sync::Arc;
+use std::{
+  future::Future,
+  pin::Pin,
+  sync::Arc,
+  task::{Context, Poll},
+  time::Duration,
+};
+use tower_service::Service;
 
 #[cfg(feature = "cache")]
-use super::cache::{get_policy_if_cacheable, RpxyCache};
+use super::cache::{get_policy_if_cacheable, CacheStatus, RpxyCache};
+#[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+use super::client_h3::{H3Client, H3RequestError};
+#[cfg(feature = "rustls-backend")]
+use super::upstream_tls::{build_client_config, server_name};
 
 #[async_trait]
 /// Definition of the forwarder that simply forward requests from downstream client to upstream app servers.
@@ -24,12 +43,173 @@ pub trait ForwardRequest<B1, B2> {
   async fn request(&self, req: Request<B1>) -> Result<Response<B2>, Self::Error>;
 }
 
+#[derive(Clone, Copy, Debug)]
+/// Marker in request extensions allowing 0-RTT early data to HTTP/3 upstreams, given by `allow_0rtt_upstream` option
+pub(crate) struct Allow0Rtt;
+
 /// Forwarder http client struct responsible to cache handling
 pub struct Forwarder<C, B> {
   #[cfg(feature = "cache")]
   cache: Option<RpxyCache>,
-  inner: Client<C, B>,
-  inner_h2: Client<C, B>, // `h2c` or http/2-only client is defined separately
+  /// clients with the default connector settings
+  inner: UpstreamClients<C, B>,
+  /// clients with dedicated connection pools per connector settings of upstreams, i.e., TLS settings and connect timeouts
+  inner_dedicated: HashMap<ConnectorSettings, UpstreamClients<C, B>>,
+  #[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+  /// HTTP/3 client for `h3`, falling back to the HTTP/2-only client
+  inner_h3: H3Client,
+}
+
+/// Set of clients per protocol sharing the connector settings
+struct UpstreamClients<C, B> {
+  /// client for `auto` protocol, negotiating HTTP/2 or HTTP/1.1 via ALPN for TLS
+  auto: Client<C, B>,
+  /// HTTP/1.1-only client
+  h1: Client<C, B>,
+  /// HTTP/2-only client for `h2` and `h2c` (prior knowledge)
+  h2: Client<C, B>,
+}
+
+impl<C, B> UpstreamClients<C, B>
+where
+  C: Connect + Clone + Send + Sync + 'static,
+  B: Body + Send + 'static,
+  <B as Body>::Data: Send,
+  <B as Body>::Error: Into<BoxError>,
+{
+  /// Build clients with the connection pool settings from connectors negotiating HTTP/2 or HTTP/1.1, HTTP/1.1 only and HTTP/2 only
+  fn new(globals: &Globals, auto: C, h1: C, h2: C) -> Self {
+    let pool = &globals.proxy_config.upstream_pool;
+    let mut builder = Client::builder(LocalExecutor::new(globals.runtime_handle.clone()));
+    builder
+      .pool_timer(TokioTimer)
+      .pool_idle_timeout(pool.idle_timeout)
+      .pool_max_idle_per_host(pool.max_idle_per_host.unwrap_or(usize::MAX))
+      .http2_adaptive_window(pool.h2_adaptive_window);
+    if let Some(interval) = pool.h2_keepalive_interval {
+      builder
+        .timer(TokioTimer)
+        .http2_keep_alive_interval(interval)
+        .http2_keep_alive_timeout(pool.h2_keepalive_timeout)
+        .http2_keep_alive_while_idle(true);
+    }
+    Self {
+      auto: builder.build(auto),
+      h1: builder.build(h1),
+      h2: builder.clone().http2_only(true).build(h2),
+    }
+  }
+}
+
+impl<C, B> UpstreamClients<C, B> {
+  /// Select the client by the protocol resolved at building routes, where only `auto` depends on the request version
+  fn select<T>(&self, req: &Request<T>) -> &Client<C, B> {
+    match req.extensions().get::<UpstreamProtocol>() {
+      Some(UpstreamProtocol::Http1) => &self.h1,
+      Some(UpstreamProtocol::H2 | UpstreamProtocol::H2c) => &self.h2,
+      _ if req.version() == Version::HTTP_2 => &self.h2,
+      _ => &self.auto,
+    }
+  }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Default)]
+/// Settings of connectors to upstreams, where connections are pooled separately per settings
+struct ConnectorSettings {
+  tls_config: Option<Arc<UpstreamTlsConfig>>,
+  connect_timeout: Option<Duration>,
+}
+
+impl ConnectorSettings {
+  /// Settings given in the request extensions at building the request to the upstream
+  fn of_request<T>(req: &Request<T>) -> Self {
+    Self {
+      tls_config: req.extensions().get::<Arc<UpstreamTlsConfig>>().cloned(),
+      connect_timeout: req.extensions().get::<UpstreamTimeoutConfig>().and_then(|v| v.connect),
+    }
+  }
+}
+
+/// Distinct connector settings of upstreams in the app configurations other than the default one
+fn upstream_connector_settings(app_config_list: &AppConfigList) -> HashSet<ConnectorSettings> {
+  app_config_list
+    .inner
+    .iter()
+    .flat_map(|app| app.reverse_proxy.iter())
+    .flat_map(|rp| {
+      let mirror = rp.mirror.as_ref().map(|mirror| ConnectorSettings {
+        tls_config: mirror.upstream.destination_tls_config(),
+        connect_timeout: None,
+      });
+      rp.upstream
+        .iter()
+        .map(|upstream| ConnectorSettings {
+          tls_config: upstream.destination_tls_config(),
+          connect_timeout: rp.timeouts.connect,
+        })
+        .chain(mirror)
+    })
+    .filter(|settings| *settings != ConnectorSettings::default())
+    .collect()
+}
+
+impl<C, B> Forwarder<C, B> {
+  #[cfg(feature = "cache")]
+  /// Cache of responses if enabled
+  pub(crate) fn cache(&self) -> Option<&RpxyCache> {
+    self.cache.as_ref()
+  }
+
+  #[cfg(feature = "cache")]
+  /// Attach the cache of responses, which is built once and shared by forwarders over configuration updates
+  pub(crate) fn with_cache(mut self, cache: Option<RpxyCache>) -> Self {
+    self.cache = cache;
+    self
+  }
+}
+
+impl<C, B> Forwarder<C, B>
+where
+  C: Connect + Clone + Send + Sync + 'static,
+  B: Body + Send + 'static,
+  <B as Body>::Data: Send,
+  <B as Body>::Error: Into<BoxError>,
+{
+  /// Build forwarder with clients per connector settings of upstreams, given by `build_clients`
+  async fn try_build(
+    _globals: &Arc<Globals>,
+    app_config_list: &AppConfigList,
+    build_clients: impl Fn(&ConnectorSettings) -> RpxyResult<UpstreamClients<C, B>>,
+  ) -> RpxyResult<Self> {
+    let inner = build_clients(&ConnectorSettings::default())?;
+    let inner_dedicated = upstream_connector_settings(app_config_list)
+      .into_iter()
+      .map(|settings| build_clients(&settings).map(|clients| (settings, clients)))
+      .collect::<RpxyResult<HashMap<_, _>>>()?;
+    if !inner_dedicated.is_empty() {
+      info!(
+        "Dedicated clients are built for {} connector settings of upstreams",
+        inner_dedicated.len()
+      );
+    }
+    #[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+    let inner_h3 = {
+      let tls_configs = inner_dedicated
+        .keys()
+        .filter_map(|settings| settings.tls_config.as_deref())
+        .collect::<HashSet<_>>();
+      H3Client::try_new(_globals, &tls_configs)?
+    };
+
+    Ok(Self {
+      inner,
+      inner_dedicated,
+      #[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+      inner_h3,
+      #[cfg(feature = "cache")]
+      cache: None,
+    })
+  }
 }
 
 #[async_trait]
@@ -49,24 +229,28 @@ where
       let mut synth_req = None;
       if self.cache.is_some() {
         // try reading from cache
-        if let Some(cached_response) = self.cache.as_ref().unwrap().get(&req).await {
+        if let Some(mut cached_response) = self.cache.as_ref().unwrap().get(&req).await {
           // if found, return it as response.
           info!("Cache hit - Return from cache");
+          cached_response.extensions_mut().insert(CacheStatus::Hit);
           return Ok(cached_response);
         };
 
         // Synthetic request copy used just for caching (cannot clone request object...)
         synth_req = Some(build_synth_req_for_cache(&req));
       }
-      let res = self.request_directly(req).await;
+      let mut res = self.request_directly(req).await;
 
       if self.cache.is_none() {
-        return res.map(|inner| inner.map(ResponseBody::Incoming));
+        return res;
+      }
+      if let Ok(inner) = res.as_mut() {
+        inner.extensions_mut().insert(CacheStatus::Miss);
       }
 
       // check cacheability and store it if cacheable
       let Ok(Some(cache_policy)) = get_policy_if_cacheable(synth_req.as_ref(), res.as_ref().ok()) else {
-        return res.map(|inner| inner.map(ResponseBody::Incoming));
+        return res;
       };
       let (parts, body) = res.unwrap().into_parts();
 
@@ -87,10 +271,7 @@ where
     // No cache handling
     #[cfg(not(feature = "cache"))]
     {
-      self
-        .request_directly(req)
-        .await
-        .map(|inner| inner.map(ResponseBody::Incoming))
+      self.request_directly(req).await
     }
   }
 }
@@ -102,27 +283,83 @@ where
   <B1 as Body>::Data: Send,
   <B1 as Body>::Error: Into<Box<(dyn std::error::Error + Send + Sync + 'static)>>,
 {
-  async fn request_directly(&self, req: Request<B1>) -> RpxyResult<Response<Incoming>> {
-    // TODO: This 'match' condition is always evaluated at every 'request' invocation. So, it is inefficient.
-    // Needs to be reconsidered. Currently, this is a kind of work around.
-    // This possibly relates to https://github.com/hyperium/hyper/issues/2417.
-    match req.version() {
-      Version::HTTP_2 => self.inner_h2.request(req).await, // handles `h2c` requests
-      _ => self.inner.request(req).await,
+  /// Send the request to the upstream bypassing the cache, e.g., mirrored requests whose responses are discarded
+  pub(crate) async fn request_without_cache(&self, req: Request<B1>) -> RpxyResult<Response<ResponseBody>> {
+    self.request_directly(req).await
+  }
+
+  /// Send the request to the upstream within the timeouts of the route, where the response body is bounded by them as well
+  async fn request_directly(&self, req: Request<B1>) -> RpxyResult<Response<ResponseBody>> {
+    let timeouts = req.extensions().get::<UpstreamTimeoutConfig>().copied().unwrap_or_default();
+    let started_at = tokio::time::Instant::now();
+    let deadline = timeouts.request.map(|v| started_at + v);
+    let header_deadline = [timeouts.response_header.map(|v| started_at + v), deadline]
+      .into_iter()
+      .flatten()
+      .min();
+
+    let res = match header_deadline {
+      Some(header_deadline) => tokio::time::timeout_at(header_deadline, self.request_upstream(req))
+        .await
+        .map_err(|_| RpxyError::UpstreamTimeout("response header is not received in time".to_string()))??,
+      None => self.request_upstream(req).await?,
+    };
+    if deadline.is_none() && timeouts.body_idle.is_none() {
+      return Ok(res);
     }
-    .map_err(|e| RpxyError::FailedToFetchFromUpstream(e.to_string()))
+    Ok(res.map(|body| ResponseBody::Timeout(Box::new(TimeoutBody::new(body, deadline, timeouts.body_idle)))))
+  }
+
+  async fn request_upstream(&self, req: Request<B1>) -> RpxyResult<Response<ResponseBody>> {
+    // HTTP/3 is used unless the request is upgraded over HTTP/1.1, and HTTP/2 is used instead if QUIC connection is unavailable.
+    #[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+    let req = match req.extensions().get::<UpstreamProtocol>() {
+      Some(UpstreamProtocol::H3) if req.version() == Version::HTTP_3 => match self.inner_h3.request(req).await {
+        Ok(res) => return Ok(res),
+        Err(H3RequestError::Failed(e)) => return Err(e),
+        Err(H3RequestError::Unavailable(mut req)) => {
+          *req.version_mut() = Version::HTTP_2;
+          req
+        }
+      },
+      _ => req,
+    };
+
+    let settings = ConnectorSettings::of_request(&req);
+    let clients = match settings == ConnectorSettings::default() {
+      true => &self.inner,
+      false => self.inner_dedicated.get(&settings).ok_or_else(|| {
+        RpxyError::FailedToConnectUpstream("No client is built for the connector settings of the upstream".to_string())
+      })?,
+    };
+    clients
+      .select(&req)
+      .request(req)
+      .await
+      .map(|res| res.map(ResponseBody::Incoming))
+      .map_err(|e| {
+        let connect_timed_out =
+          std::iter::successors(std::error::Error::source(&e), |v| v.source()).any(|v| v.is::<ConnectTimeout>());
+        if connect_timed_out {
+          RpxyError::UpstreamTimeout("connection is not established in time".to_string())
+        } else if e.is_connect() {
+          RpxyError::FailedToConnectUpstream(e.to_string())
+        } else {
+          RpxyError::FailedToFetchFromUpstream(e.to_string())
+        }
+      })
   }
 }
 
 #[cfg(not(any(feature = "native-tls-backend", feature = "rustls-backend")))]
-impl<B> Forwarder<HttpConnector, B>
+impl<B> Forwarder<UpstreamConnector<HttpConnector>, B>
 where
   B: Body + Send + Unpin + 'static,
   <B as Body>::Data: Send,
   <B as Body>::Error: Into<Box<(dyn std::error::Error + Send + Sync + 'static)>>,
 {
   /// Build inner client with http
-  pub async fn try_new(_globals: &Arc<Globals>) -> RpxyResult<Self> {
+  pub async fn try_new(_globals: &Arc<Globals>, app_config_list: &AppConfigList) -> RpxyResult<Self> {
     warn!(
       "
 --------------------------------------------------------------------------------------------------
@@ -131,38 +368,43 @@ We recommend to use this just for testing.
 Please enable native-tls-backend or rustls-backend feature to enable TLS support.
 --------------------------------------------------------------------------------------------------"
     );
-    let executor = LocalExecutor::new(_globals.runtime_handle.clone());
     let mut http = HttpConnector::new();
     http.enforce_http(true);
     http.set_reuse_address(true);
     http.set_keepalive(Some(_globals.proxy_config.upstream_idle_timeout));
-    let inner = Client::builder(executor).build::<_, B>(http);
-    let inner_h2 = inner.clone();
 
-    Ok(Self {
-      inner,
-      inner_h2,
-      #[cfg(feature = "cache")]
-      cache: RpxyCache::new(_globals).await,
-    })
+    let build_clients = |settings: &ConnectorSettings| {
+      if settings.tls_config.is_some() {
+        return Err(RpxyError::InvalidUpstreamTlsConfig(
+          "TLS settings of upstreams require rustls-backend feature".to_string(),
+        ));
+      }
+      let connector = UpstreamConnector::new(http.clone(), settings.connect_timeout);
+      Ok(UpstreamClients::new(
+        _globals,
+        connector.clone(),
+        connector.clone(),
+        connector,
+      ))
+    };
+    Self::try_build(_globals, app_config_list, build_clients).await
   }
 }
 
 #[cfg(all(feature = "native-tls-backend", not(feature = "rustls-backend")))]
 /// Build forwarder with hyper-tls (native-tls)
-impl<B1> Forwarder<hyper_tls::HttpsConnector<HttpConnector>, B1>
+impl<B1> Forwarder<UpstreamConnector<hyper_tls::HttpsConnector<HttpConnector>>, B1>
 where
   B1: Body + Send + Unpin + 'static,
   <B1 as Body>::Data: Send,
   <B1 as Body>::Error: Into<Box<(dyn std::error::Error + Send + Sync + 'static)>>,
 {
   /// Build forwarder
-  pub async fn try_new(_globals: &Arc<Globals>) -> RpxyResult<Self> {
+  pub async fn try_new(_globals: &Arc<Globals>, app_config_list: &AppConfigList) -> RpxyResult<Self> {
     // build hyper client with hyper-tls
     info!("Native TLS support is enabled for the connection to backend applications");
-    let executor = LocalExecutor::new(_globals.runtime_handle.clone());
 
-    let try_build_connector = |alpns: &[&str]| {
+    let try_build_connector = |alpns: &[&str], connect_timeout: Option<Duration>| {
       hyper_tls::native_tls::TlsConnector::builder()
         .request_alpns(alpns)
         .build()
@@ -172,49 +414,45 @@ where
           http.enforce_http(false);
           http.set_reuse_address(true);
           http.set_keepalive(Some(_globals.proxy_config.upstream_idle_timeout));
-          hyper_tls::HttpsConnector::from((http, tls.into()))
+          UpstreamConnector::new(hyper_tls::HttpsConnector::from((http, tls.into())), connect_timeout)
         })
     };
 
-    let connector = try_build_connector(&["h2", "http/1.1"])?;
-    let inner = Client::builder(executor.clone()).build::<_, B1>(connector);
-
-    let connector_h2 = try_build_connector(&["h2"])?;
-    let inner_h2 = Client::builder(executor.clone())
-      .http2_only(true)
-      .build::<_, B1>(connector_h2);
-
-    Ok(Self {
-      inner,
-      inner_h2,
-      #[cfg(feature = "cache")]
-      cache: RpxyCache::new(_globals).await,
-    })
+    let build_clients = |settings: &ConnectorSettings| {
+      if settings.tls_config.is_some() {
+        return Err(RpxyError::InvalidUpstreamTlsConfig(
+          "TLS settings of upstreams require rustls-backend feature".to_string(),
+        ));
+      }
+      Ok(UpstreamClients::new(
+        _globals,
+        try_build_connector(&["h2", "http/1.1"], settings.connect_timeout)?,
+        try_build_connector(&["http/1.1"], settings.connect_timeout)?,
+        try_build_connector(&["h2"], settings.connect_timeout)?,
+      ))
+    };
+    Self::try_build(_globals, app_config_list, build_clients).await
   }
 }
 
 #[cfg(feature = "rustls-backend")]
 /// Build forwarder with hyper-rustls (rustls)
-impl<B1> Forwarder<hyper_rustls::HttpsConnector<HttpConnector>, B1>
+impl<B1> Forwarder<UpstreamConnector<hyper_rustls::HttpsConnector<HttpConnector>>, B1>
 where
   B1: Body + Send + Unpin + 'static,
   <B1 as Body>::Data: Send,
   <B1 as Body>::Error: Into<Box<(dyn std::error::Error + Send + Sync + 'static)>>,
 {
   /// Build forwarder
-  pub async fn try_new(_globals: &Arc<Globals>) -> RpxyResult<Self> {
+  pub async fn try_new(_globals: &Arc<Globals>, app_config_list: &AppConfigList) -> RpxyResult<Self> {
     // build hyper client with rustls and webpki, only https is allowed
     #[cfg(feature = "webpki-roots")]
-    let builder = hyper_rustls::HttpsConnectorBuilder::new().with_webpki_roots();
-    #[cfg(feature = "webpki-roots")]
-    let builder_h2 = hyper_rustls::HttpsConnectorBuilder::new().with_webpki_roots();
+    let builder = || hyper_rustls::HttpsConnectorBuilder::new().with_webpki_roots();
     #[cfg(feature = "webpki-roots")]
     info!("Mozilla WebPKI root certs with rustls is used for the connection to backend applications");
 
     #[cfg(not(feature = "webpki-roots"))]
-    let builder = hyper_rustls::HttpsConnectorBuilder::new().with_platform_verifier();
-    #[cfg(not(feature = "webpki-roots"))]
-    let builder_h2 = hyper_rustls::HttpsConnectorBuilder::new().with_platform_verifier();
+    let builder = || hyper_rustls::HttpsConnectorBuilder::new().with_platform_verifier();
     #[cfg(not(feature = "webpki-roots"))]
     info!("Platform verifier with rustls is used for the connection to backend applications");
 
@@ -223,20 +461,241 @@ where
     http.set_reuse_address(true);
     http.set_keepalive(Some(_globals.proxy_config.upstream_idle_timeout));
 
-    let connector = builder.https_or_http().enable_all_versions().wrap_connector(http.clone());
-    let connector_h2 = builder_h2.https_or_http().enable_http2().wrap_connector(http);
-    let inner = Client::builder(LocalExecutor::new(_globals.runtime_handle.clone())).build::<_, B1>(connector);
-    let inner_h2 = Client::builder(LocalExecutor::new(_globals.runtime_handle.clone()))
-      .http2_only(true)
-      .build::<_, B1>(connector_h2);
+    // dedicated connectors and hence connection pools per TLS settings and connect timeouts of upstreams
+    let build_clients = |settings: &ConnectorSettings| -> RpxyResult<_> {
+      let wrap = |connector| UpstreamConnector::new(connector, settings.connect_timeout);
+      let Some(tls) = settings.tls_config.as_deref() else {
+        return Ok(UpstreamClients::new(
+          _globals,
+          wrap(builder().https_or_http().enable_all_versions().wrap_connector(http.clone())),
+          wrap(builder().https_or_http().enable_http1().wrap_connector(http.clone())),
+          wrap(builder().https_or_http().enable_http2().wrap_connector(http.clone())),
+        ));
+      };
 
-    Ok(Self {
-      inner,
-      inner_h2,
-      #[cfg(feature = "cache")]
-      cache: RpxyCache::new(_globals).await,
-    })
+      let client_config = build_client_config(tls, false)?;
+      let server_name = server_name(tls)?;
+      let builder = || {
+        let builder = hyper_rustls::HttpsConnectorBuilder::new()
+          .with_tls_config(client_config.clone())
+          .https_or_http();
+        match server_name.clone() {
+          Some(name) => builder.with_server_name_resolver(hyper_rustls::FixedServerNameResolver::new(name)),
+          None => builder,
+        }
+      };
+      // ALPN of the `auto` protocol is restricted by the setting, where the others are fixed by their protocols
+      let alpn = tls.alpn.as_deref().unwrap_or_default();
+      let connector = match (alpn.iter().any(|v| v == "h2"), alpn.iter().any(|v| v == "http/1.1")) {
+        (true, false) => builder().enable_http2().wrap_connector(http.clone()),
+        (false, true) => builder().enable_http1().wrap_connector(http.clone()),
+        _ => builder().enable_all_versions().wrap_connector(http.clone()),
+      };
+      Ok(UpstreamClients::new(
+        _globals,
+        wrap(connector),
+        wrap(builder().enable_http1().wrap_connector(http.clone())),
+        wrap(builder().enable_http2().wrap_connector(http.clone())),
+      ))
+    };
+    Self::try_build(_globals, app_config_list, build_clients).await
+  }
+}
+
+/* ---------------------------------------------------------- */
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Clone)]
+/// Connector dispatching connections per request between unix domain sockets and TCP (with TLS) of the inner connector,
+/// where the destination is a unix domain socket if the uri is built by [`unix_socket_uri`].
+pub struct UpstreamConnector<C> {
+  inner: C,
+  /// Timeout to establish a connection including the TLS handshake
+  connect_timeout: Option<Duration>,
+}
+
+impl<C> UpstreamConnector<C> {
+  pub fn new(inner: C, connect_timeout: Option<Duration>) -> Self {
+    Self { inner, connect_timeout }
+  }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Connect timeout expired")]
+/// Error of [`UpstreamConnector`] when the connection is not established within the connect timeout
+struct ConnectTimeout;
+
+impl<C> Service<Uri> for UpstreamConnector<C>
+where
+  C: Service<Uri> + Send + 'static,
+  C::Response: hyper::rt::Read + hyper::rt::Write + Connection + Unpin + Send + 'static,
+  C::Error: Into<BoxError>,
+  C::Future: Send + 'static,
+{
+  type Response = UpstreamStream<C::Response>;
+  type Error = BoxError;
+  type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.inner.poll_ready(cx).map_err(Into::into)
+  }
+
+  fn call(&mut self, dst: Uri) -> Self::Future {
+    let connect_timeout = self.connect_timeout;
+    #[cfg(unix)]
+    if let Some(path) = unix_socket_path(&dst) {
+      return Box::pin(with_connect_timeout(connect_timeout, async move {
+        let stream = tokio::net::UnixStream::connect(&path)
+          .await
+          .map_err(|e| format!("Failed to connect to unix:{}: {e}", path.display()))?;
+        Ok(UpstreamStream::Unix(TokioIo::new(stream)))
+      }));
+    }
+    let connecting = self.inner.call(dst);
+    Box::pin(with_connect_timeout(connect_timeout, async move {
+      connecting.await.map(UpstreamStream::Inner).map_err(Into::into)
+    }))
+  }
+}
+
+/// Fail the connecting future with [`ConnectTimeout`] if it does not complete within the timeout
+async fn with_connect_timeout<T>(
+  connect_timeout: Option<Duration>,
+  connecting: impl Future<Output = Result<T, BoxError>>,
+) -> Result<T, BoxError> {
+  match connect_timeout {
+    Some(connect_timeout) => tokio::time::timeout(connect_timeout, connecting)
+      .await
+      .map_err(|_| Box::new(ConnectTimeout) as BoxError)?,
+    None => connecting.await,
+  }
+}
+
+/// Connection established by [`UpstreamConnector`]
+pub enum UpstreamStream<T> {
+  /// TCP (with TLS) connection of the inner connector
+  Inner(T),
+  #[cfg(unix)]
+  /// Unix domain socket connection
+  Unix(TokioIo<tokio::net::UnixStream>),
+}
+
+impl<T: Connection> Connection for UpstreamStream<T> {
+  fn connected(&self) -> Connected {
+    match self {
+      Self::Inner(stream) => stream.connected(),
+      #[cfg(unix)]
+      Self::Unix(_) => Connected::new(),
+    }
+  }
+}
+
+impl<T: hyper::rt::Read + Unpin> hyper::rt::Read for UpstreamStream<T> {
+  fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: hyper::rt::ReadBufCursor<'_>) -> Poll<std::io::Result<()>> {
+    match self.get_mut() {
+      Self::Inner(stream) => Pin::new(stream).poll_read(cx, buf),
+      #[cfg(unix)]
+      Self::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+    }
+  }
+}
+
+impl<T: hyper::rt::Write + Unpin> hyper::rt::Write for UpstreamStream<T> {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+    match self.get_mut() {
+      Self::Inner(stream) => Pin::new(stream).poll_write(cx, buf),
+      #[cfg(unix)]
+      Self::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+    }
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+    match self.get_mut() {
+      Self::Inner(stream) => Pin::new(stream).poll_flush(cx),
+      #[cfg(unix)]
+      Self::Unix(stream) => Pin::new(stream).poll_flush(cx),
+    }
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+    match self.get_mut() {
+      Self::Inner(stream) => Pin::new(stream).poll_shutdown(cx),
+      #[cfg(unix)]
+      Self::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+    }
+  }
+
+  fn is_write_vectored(&self) -> bool {
+    match self {
+      Self::Inner(stream) => stream.is_write_vectored(),
+      #[cfg(unix)]
+      Self::Unix(stream) => stream.is_write_vectored(),
+    }
+  }
+
+  fn poll_write_vectored(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    bufs: &[std::io::IoSlice<'_>],
+  ) -> Poll<std::io::Result<usize>> {
+    match self.get_mut() {
+      Self::Inner(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+      #[cfg(unix)]
+      Self::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+    }
+  }
+}
+
+#[cfg(unix)]
+/// Build the uri of a unix domain socket, where the path is hex-encoded in the host with the scheme "unix".
+/// Then connections are pooled per socket, and the connector recovers the path from the uri.
+pub(crate) fn unix_socket_uri(path: &std::path::Path) -> Result<Uri, http::Error> {
+  use std::{fmt::Write, os::unix::ffi::OsStrExt};
+  let host = path.as_os_str().as_bytes().iter().fold(String::new(), |mut acc, b| {
+    let _ = write!(acc, "{b:02x}");
+    acc
+  });
+  Uri::builder()
+    .scheme(UNIX_SOCKET_SCHEME)
+    .authority(host)
+    .path_and_query("/")
+    .build()
+}
+
+#[cfg(unix)]
+/// Get the path of the unix domain socket if the uri is built by [`unix_socket_uri`]
+pub(crate) fn unix_socket_path(uri: &Uri) -> Option<std::path::PathBuf> {
+  use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+  if uri.scheme_str() != Some(UNIX_SOCKET_SCHEME) {
+    return None;
+  }
+  let host = uri.host()?.as_bytes();
+  if host.is_empty() || host.len() % 2 != 0 {
+    return None;
+  }
+  let path = host
+    .chunks(2)
+    .map(|v| std::str::from_utf8(v).ok().and_then(|v| u8::from_str_radix(v, 16).ok()))
+    .collect::<Option<Vec<u8>>>()?;
+  Some(OsStr::from_bytes(&path).into())
+}
+
+/// Location of the upstream shown in logs and metrics, which is "unix:/path/to/socket" for unix domain sockets
+pub(crate) fn upstream_location(uri: &Uri) -> String {
+  #[cfg(unix)]
+  if let Some(path) = unix_socket_path(uri) {
+    return format!("{UNIX_SOCKET_SCHEME}:{}", path.display());
+  }
+  uri.to_string()
+}
+
+/// Parse the location of the upstream given by [`upstream_location`] into its uri
+pub(crate) fn parse_upstream_location(location: &str) -> Option<Uri> {
+  #[cfg(unix)]
+  if let Some(path) = location.strip_prefix(UNIX_SOCKET_SCHEME).and_then(|v| v.strip_prefix(':')) {
+    return unix_socket_uri(std::path::Path::new(path)).ok();
   }
+  location.parse().ok()
 }
 
 #[cfg(feature = "cache")]
@@ -0,0 +1,588 @@
+use super::{
+  client::Allow0Rtt,
+  upstream_tls::{build_client_config, server_name},
+};
+use crate::{
+  constants::H3,
+  error::{RpxyError, RpxyResult},
+  globals::{Globals, UpstreamTimeoutConfig, UpstreamTlsConfig},
+  hyper_ext::body::{IncomingLike, ResponseBody},
+  log::*,
+};
+use ahash::{HashMap, HashSet};
+use bytes::{Buf, Bytes};
+use http::{Method, Request, Response, Uri, Version, header, uri::Authority};
+use http_body_util::BodyExt;
+use hyper::body::Body;
+use hyper_rustls::ConfigBuilderExt;
+use quinn::{Endpoint, TransportConfig, crypto::rustls::QuicClientConfig};
+use std::{
+  net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+  sync::{Arc, Mutex, OnceLock},
+  time::{Duration, Instant},
+};
+
+type SendRequest = h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>;
+type RequestStream<S> = h3::client::RequestStream<S, Bytes>;
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+/// Key of pooled connections: the authority, the TLS settings and whether 0-RTT is used
+type PoolKey = (Authority, Option<Arc<UpstreamTlsConfig>>, bool);
+
+/// Error of a request over HTTP/3
+pub(crate) enum H3RequestError<B> {
+  /// QUIC connection is unavailable, and the request is returned back untouched to be sent over HTTP/2
+  Unavailable(Request<B>),
+  /// Failed after the request is sent
+  Failed(RpxyError),
+}
+
+/// HTTP/3 client to upstream servers, where a QUIC connection is established and reused per upstream authority.
+/// Connections allowing 0-RTT early data carry only idempotent requests without body, and are pooled separately.
+pub(crate) struct H3Client {
+  /// UDP endpoint bound at the first connection, so that no socket is held unless an upstream is served over HTTP/3
+  endpoint: OnceLock<Endpoint>,
+  /// client config with the root certificates, used by default
+  client_config: quinn::ClientConfig,
+  runtime_handle: tokio::runtime::Handle,
+  /// client configs and server names overriding SNI per TLS settings of upstreams
+  tls_configs: HashMap<UpstreamTlsConfig, (quinn::ClientConfig, Option<String>)>,
+  /// pooled connections
+  connections: Mutex<HashMap<PoolKey, PooledConnection>>,
+  /// authorities whose QUIC connections failed, to which HTTP/2 is used until the instant
+  fallback: Mutex<HashMap<Authority, Instant>>,
+}
+
+#[derive(Clone)]
+struct PooledConnection {
+  quic: quinn::Connection,
+  send_request: SendRequest,
+}
+
+impl H3Client {
+  /// Build the client with the root certificates same as the TCP clients, and client configs per TLS settings of upstreams
+  pub(crate) fn try_new(globals: &Globals, upstream_tls_configs: &HashSet<&UpstreamTlsConfig>) -> RpxyResult<Self> {
+    let provider = rustls::crypto::CryptoProvider::get_default().ok_or(RpxyError::NoDefaultCryptoProvider)?;
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+      .with_protocol_versions(&[&rustls::version::TLS13])
+      .map_err(|e| RpxyError::FailedToBuildForwarder(format!("TLS 1.3 client config failed: {e}")))?;
+    #[cfg(feature = "webpki-roots")]
+    let builder = builder.with_webpki_roots();
+    #[cfg(not(feature = "webpki-roots"))]
+    let builder = builder
+      .try_with_platform_verifier()
+      .map_err(|e| RpxyError::FailedToBuildForwarder(format!("Platform verifier failed: {e}")))?;
+    let client_config = quic_client_config(builder.with_no_client_auth(), globals)?;
+    let tls_configs = upstream_tls_configs
+      .iter()
+      .map(|tls| {
+        let client_config = quic_client_config(build_client_config(tls, true)?, globals)?;
+        let server_name = server_name(tls)?.map(|v| v.to_str().into_owned());
+        Ok(((*tls).clone(), (client_config, server_name)))
+      })
+      .collect::<RpxyResult<HashMap<_, _>>>()?;
+
+    Ok(Self {
+      endpoint: OnceLock::new(),
+      client_config,
+      runtime_handle: globals.runtime_handle.clone(),
+      tls_configs,
+      connections: Mutex::new(HashMap::default()),
+      fallback: Mutex::new(HashMap::default()),
+    })
+  }
+
+  /// Send the request over HTTP/3.
+  /// The request is returned back if QUIC connection is unavailable before sending it, and then it should be sent over HTTP/2.
+  pub(crate) async fn request<B>(&self, req: Request<B>) -> Result<Response<ResponseBody>, H3RequestError<B>>
+  where
+    B: Body + Send + Unpin + 'static,
+    <B as Body>::Data: Send,
+    <B as Body>::Error: Into<BoxError>,
+  {
+    let Some(authority) = req.uri().authority().cloned() else {
+      return Err(H3RequestError::Failed(RpxyError::FailedToFetchFromUpstream(
+        "Upstream uri has no authority".to_string(),
+      )));
+    };
+    if self.is_falling_back(&authority) {
+      return Err(H3RequestError::Unavailable(req));
+    }
+    let head = match build_request_head(&req) {
+      Ok(head) => head,
+      Err(e) => return Err(H3RequestError::Failed(e)),
+    };
+    let tls_config = req.extensions().get::<Arc<UpstreamTlsConfig>>().cloned();
+    let connect_timeout = req
+      .extensions()
+      .get::<UpstreamTimeoutConfig>()
+      .and_then(|v| v.connect)
+      .unwrap_or(Duration::from_secs(H3::UPSTREAM_CONNECT_TIMEOUT));
+
+    // 0-RTT early data may be replayed, so it is used only for idempotent requests without body, which can be retried safely
+    let early_data = req.extensions().get::<Allow0Rtt>().is_some()
+      && matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+      && req.body().is_end_stream();
+    if early_data {
+      match self
+        .send_request((authority.clone(), tls_config.clone(), true), head.clone(), connect_timeout)
+        .await
+      {
+        Ok(stream) => {
+          let (mut send_stream, recv_stream) = stream.split();
+          if send_stream.finish().await.is_ok() {
+            if let Ok(res) = self.recv_response(recv_stream).await {
+              return Ok(res);
+            }
+          }
+          debug!("HTTP/3 request with 0-RTT failed, and retry without 0-RTT: {authority}");
+        }
+        Err(e) => debug!("HTTP/3 request with 0-RTT failed, and retry without 0-RTT: {e}"),
+      }
+    }
+
+    let stream = match self
+      .send_request((authority.clone(), tls_config, false), head, connect_timeout)
+      .await
+    {
+      Ok(stream) => stream,
+      Err(e) => {
+        warn!("HTTP/3 to upstream {authority} is unavailable, falling back to HTTP/2: {e}");
+        if let Ok(mut fallback) = self.fallback.lock() {
+          let until = Instant::now() + Duration::from_secs(H3::UPSTREAM_FALLBACK_DURATION);
+          fallback.insert(authority, until);
+        }
+        return Err(H3RequestError::Unavailable(req));
+      }
+    };
+    let (mut send_stream, recv_stream) = stream.split();
+    let body = req.into_body();
+    self.runtime_handle.spawn(async move {
+      if let Err(e) = send_body(&mut send_stream, body).await {
+        warn!("HTTP/3 error on sending request body to upstream: {e}");
+        send_stream.stop_stream(h3::error::Code::H3_REQUEST_CANCELLED);
+      }
+    });
+    self.recv_response(recv_stream).await.map_err(H3RequestError::Failed)
+  }
+
+  /// Check if HTTP/2 is used instead for the authority due to recent QUIC failures
+  fn is_falling_back(&self, authority: &Authority) -> bool {
+    let Ok(mut fallback) = self.fallback.lock() else {
+      return false;
+    };
+    match fallback.get(authority) {
+      Some(until) if *until > Instant::now() => true,
+      Some(_) => {
+        fallback.remove(authority);
+        false
+      }
+      None => false,
+    }
+  }
+
+  /// Send the request head over a pooled connection, where a new connection is established within the connect timeout
+  /// if none or the pooled one is closed
+  async fn send_request(
+    &self,
+    key: PoolKey,
+    head: Request<()>,
+    connect_timeout: Duration,
+  ) -> RpxyResult<RequestStream<h3_quinn::BidiStream<Bytes>>> {
+    let authority = &key.0;
+    let pooled = self
+      .connections
+      .lock()
+      .ok()
+      .and_then(|connections| connections.get(&key).cloned())
+      .filter(|v| v.quic.close_reason().is_none());
+    if let Some(mut pooled) = pooled {
+      match pooled.send_request.send_request(head.clone()).await {
+        Ok(stream) => return Ok(stream),
+        Err(e) => debug!("Pooled HTTP/3 connection to {authority} is unusable, and reconnect: {e}"),
+      }
+    }
+
+    let mut connection = tokio::time::timeout(connect_timeout, self.connect(&key))
+      .await
+      .map_err(|_| RpxyError::FailedToConnectUpstream(format!("QUIC connection to {authority} timed out")))??;
+    let stream = connection.send_request.send_request(head).await?;
+    if let Ok(mut connections) = self.connections.lock() {
+      connections.insert(key, connection);
+    }
+    Ok(stream)
+  }
+
+  /// Get the endpoint, binding its UDP socket at the first call
+  fn endpoint(&self) -> RpxyResult<&Endpoint> {
+    if let Some(endpoint) = self.endpoint.get() {
+      return Ok(endpoint);
+    }
+    // dual-stack socket if available
+    let mut endpoint = Endpoint::client(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)))
+      .or_else(|_| Endpoint::client(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))))
+      .map_err(|e| RpxyError::FailedToConnectUpstream(format!("Failed to bind UDP socket for HTTP/3: {e}")))?;
+    endpoint.set_default_client_config(self.client_config.clone());
+    // another endpoint bound concurrently is dropped here
+    Ok(self.endpoint.get_or_init(|| endpoint))
+  }
+
+  /// Establish a new QUIC connection and HTTP/3 session on it
+  async fn connect(&self, (authority, tls_config, early_data): &PoolKey) -> RpxyResult<PooledConnection> {
+    let host = authority.host().trim_start_matches('[').trim_end_matches(']');
+    let port = authority.port_u16().unwrap_or(443);
+    let addr = tokio::net::lookup_host((host, port))
+      .await
+      .ok()
+      .and_then(|mut v| v.next())
+      .ok_or_else(|| RpxyError::FailedToConnectUpstream(format!("Failed to resolve {authority}")))?;
+    let endpoint = self.endpoint()?;
+    let connecting = match tls_config {
+      Some(tls_config) => {
+        let (client_config, server_name) = self.tls_configs.get(tls_config.as_ref()).ok_or_else(|| {
+          RpxyError::FailedToConnectUpstream("No client config is built for the TLS settings of the upstream".to_string())
+        })?;
+        let server_name = server_name.as_deref().unwrap_or(host);
+        endpoint.connect_with(client_config.clone(), addr, server_name)
+      }
+      None => endpoint.connect(addr, host),
+    }
+    .map_err(|e| RpxyError::FailedToConnectUpstream(e.to_string()))?;
+
+    let quic = match *early_data {
+      true => match connecting.into_0rtt() {
+        Ok((quic, accepted)) => {
+          // streams sent in rejected early data are lost including those of HTTP/3 session, so the connection is discarded
+          let quic_clone = quic.clone();
+          self.runtime_handle.spawn(async move {
+            if !accepted.await {
+              quic_clone.close(0u32.into(), b"0-RTT rejected");
+            }
+          });
+          quic
+        }
+        Err(connecting) => connecting.await?,
+      },
+      false => connecting.await?,
+    };
+    debug!("QUIC connection established to upstream {authority} ({addr})");
+
+    let (mut driver, send_request) = h3::client::new(h3_quinn::Connection::new(quic.clone())).await?;
+    let authority = authority.clone();
+    self.runtime_handle.spawn(async move {
+      let res = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+      debug!("HTTP/3 connection to upstream {authority} closed: {res:?}");
+    });
+    Ok(PooledConnection { quic, send_request })
+  }
+
+  /// Receive the response head, and stream its body through a channel
+  async fn recv_response(&self, mut recv_stream: RequestStream<h3_quinn::RecvStream>) -> RpxyResult<Response<ResponseBody>> {
+    let mut res = recv_stream.recv_response().await?;
+    // hyper serving HTTP/1.1 downstream does not accept the version of HTTP/3, like the responses from HTTP/2 upstreams
+    *res.version_mut() = Version::HTTP_2;
+    let (mut sender, body) = IncomingLike::channel();
+    self.runtime_handle.spawn(async move {
+      let res = async {
+        while let Some(mut data) = recv_stream.recv_data().await? {
+          sender.send_data(data.copy_to_bytes(data.remaining())).await?;
+        }
+        if let Some(trailers) = recv_stream.recv_trailers().await? {
+          sender.send_trailers(trailers).await?;
+        }
+        Ok(()) as RpxyResult<()>
+      }
+      .await;
+      if let Err(e) = res {
+        debug!("HTTP/3 error on receiving response body from upstream: {e}");
+        sender.send_error(e);
+      }
+    });
+    Ok(res.map(|_| ResponseBody::IncomingLike(body)))
+  }
+}
+
+/// Build the QUIC client config from the TLS client config, where ALPN is set to h3
+fn quic_client_config(mut tls_config: rustls::ClientConfig, globals: &Globals) -> RpxyResult<quinn::ClientConfig> {
+  tls_config.alpn_protocols = vec![b"h3".to_vec()];
+  // early data is sent only if the connection is explicitly established with 0-RTT
+  tls_config.enable_early_data = true;
+
+  let quic_config = QuicClientConfig::try_from(tls_config)
+    .map_err(|e| RpxyError::FailedToBuildForwarder(format!("QUIC client config failed: {e}")))?;
+  let mut transport_config = TransportConfig::default();
+  transport_config.max_idle_timeout(quinn::IdleTimeout::try_from(globals.proxy_config.upstream_idle_timeout).ok());
+  let mut client_config = quinn::ClientConfig::new(Arc::new(quic_config));
+  client_config.transport_config(Arc::new(transport_config));
+  Ok(client_config)
+}
+
+/// Build the request head for HTTP/3, where `:authority` is taken from the host header
+fn build_request_head<B>(req: &Request<B>) -> RpxyResult<Request<()>> {
+  let authority = match req.headers().get(header::HOST) {
+    Some(host) => host
+      .to_str()
+      .map_err(|e| RpxyError::FailedToFetchFromUpstream(format!("Invalid host header: {e}")))?,
+    None => req.uri().authority().map(|v| v.as_str()).unwrap_or_default(),
+  };
+  let uri = Uri::builder()
+    .scheme("https")
+    .authority(authority)
+    .path_and_query(req.uri().path_and_query().map(|v| v.as_str()).unwrap_or("/"))
+    .build()
+    .map_err(|e| RpxyError::FailedToFetchFromUpstream(e.to_string()))?;
+  let mut head = Request::builder()
+    .method(req.method())
+    .uri(uri)
+    .version(Version::HTTP_3)
+    .body(())
+    .map_err(|e| RpxyError::FailedToFetchFromUpstream(e.to_string()))?;
+  *head.headers_mut() = req.headers().clone();
+  head.headers_mut().remove(header::HOST);
+  Ok(head)
+}
+
+/// Send the request body and trailers, and finish the stream
+async fn send_body<B>(send_stream: &mut RequestStream<h3_quinn::SendStream<Bytes>>, mut body: B) -> RpxyResult<()>
+where
+  B: Body + Unpin,
+  <B as Body>::Error: Into<BoxError>,
+{
+  loop {
+    let frame = match body.frame().await {
+      Some(Ok(frame)) => frame,
+      Some(Err(e)) => return Err(RpxyError::HyperBodyManipulationError(e.into().to_string())),
+      None => break,
+    };
+    match frame.into_data() {
+      Ok(mut data) => send_stream.send_data(data.copy_to_bytes(data.remaining())).await?,
+      Err(frame) => {
+        if let Ok(trailers) = frame.into_trailers() {
+          send_stream.send_trailers(trailers).await?;
+        }
+      }
+    }
+  }
+  send_stream.finish().await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{count::RequestCount, globals::ProxyConfig, metrics::Metrics};
+  use http::HeaderMap;
+  use http_body_util::{Full, StreamBody};
+  use hyper::body::Frame;
+  use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  fn globals() -> Globals {
+    let _ = rustls::crypto::CryptoProvider::install_default(rustls::crypto::aws_lc_rs::default_provider());
+    Globals {
+      proxy_config: ProxyConfig::default(),
+      request_count: RequestCount::default(),
+      metrics: Metrics::new(),
+      runtime_handle: tokio::runtime::Handle::current(),
+      cert_reloader_rx: None,
+      #[cfg(feature = "acme")]
+      server_configs_acme_challenge: Default::default(),
+    }
+  }
+
+  fn tls_config() -> UpstreamTlsConfig {
+    UpstreamTlsConfig {
+      ca_cert_path: Some("../example-certs/server.crt".into()),
+      server_name: Some("localhost".to_string()),
+      ..Default::default()
+    }
+  }
+
+  /// Spawn an HTTP/3 server echoing the authority, body and trailers of requests, and count its accepted connections
+  fn spawn_echo_server() -> (SocketAddr, Arc<AtomicUsize>) {
+    let certs = CertificateDer::pem_file_iter("../example-certs/server.crt")
+      .unwrap()
+      .collect::<Result<Vec<_>, _>>()
+      .unwrap();
+    let key = PrivateKeyDer::from_pem_file("../example-certs/server.key").unwrap();
+    let mut tls_config = rustls::ServerConfig::builder()
+      .with_no_client_auth()
+      .with_single_cert(certs, key)
+      .unwrap();
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+    let quic_config = quinn::crypto::rustls::QuicServerConfig::try_from(tls_config).unwrap();
+    let server_config = quinn::ServerConfig::with_crypto(Arc::new(quic_config));
+    let endpoint = Endpoint::server(server_config, SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let accepted_clone = accepted.clone();
+    tokio::spawn(async move {
+      while let Some(incoming) = endpoint.accept().await {
+        accepted_clone.fetch_add(1, Ordering::Relaxed);
+        tokio::spawn(async move {
+          let quic = incoming.await.unwrap();
+          let mut conn = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(quic))
+            .await
+            .unwrap();
+          while let Ok(Some((req, mut stream))) = conn.accept().await {
+            let mut body = Vec::new();
+            while let Some(mut data) = stream.recv_data().await.unwrap() {
+              body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+            }
+            let trailers = stream.recv_trailers().await.unwrap();
+            let res = Response::builder()
+              .header("x-authority", req.uri().authority().unwrap().as_str())
+              .header("x-has-host", req.headers().contains_key(header::HOST).to_string())
+              .body(())
+              .unwrap();
+            stream.send_response(res).await.unwrap();
+            stream.send_data(Bytes::from(body)).await.unwrap();
+            if let Some(trailers) = trailers {
+              stream.send_trailers(trailers).await.unwrap();
+            }
+            stream.finish().await.unwrap();
+          }
+        });
+      }
+    });
+    (addr, accepted)
+  }
+
+  #[test]
+  fn request_head_takes_authority_from_host() {
+    let req = Request::builder()
+      .uri("http://127.0.0.1:8443/path?query")
+      .header(header::HOST, "example.com")
+      .header("x-custom", "value")
+      .body(())
+      .unwrap();
+    let head = build_request_head(&req).unwrap();
+    assert_eq!(head.uri().to_string(), "https://example.com/path?query");
+    assert_eq!(head.version(), Version::HTTP_3);
+    assert!(!head.headers().contains_key(header::HOST));
+    assert_eq!(head.headers().get("x-custom").unwrap(), "value");
+
+    // the authority of the uri is used without host header
+    let req = Request::builder().uri("http://127.0.0.1:8443").body(()).unwrap();
+    let head = build_request_head(&req).unwrap();
+    assert_eq!(head.uri().to_string(), "https://127.0.0.1:8443/");
+  }
+
+  #[tokio::test]
+  async fn falling_back_expires() {
+    let client = H3Client::try_new(&globals(), &HashSet::default()).unwrap();
+    let authority = Authority::from_static("localhost:8443");
+    assert!(!client.is_falling_back(&authority));
+
+    let now = Instant::now();
+    client
+      .fallback
+      .lock()
+      .unwrap()
+      .insert(authority.clone(), now + Duration::from_secs(60));
+    assert!(client.is_falling_back(&authority));
+
+    client
+      .fallback
+      .lock()
+      .unwrap()
+      .insert(authority.clone(), now - Duration::from_secs(1));
+    assert!(!client.is_falling_back(&authority));
+    assert!(client.fallback.lock().unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn request_to_loopback_server_works() {
+    let tls = tls_config();
+    let client = H3Client::try_new(&globals(), &HashSet::from_iter([&tls])).unwrap();
+    let (addr, accepted) = spawn_echo_server();
+    let tls = Arc::new(tls);
+
+    // body and trailers are streamed both ways
+    let mut trailers = HeaderMap::new();
+    trailers.insert("x-trailer", "done".parse().unwrap());
+    let frames = vec![
+      Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from_static(b"hello "))),
+      Ok(Frame::data(Bytes::from_static(b"world"))),
+      Ok(Frame::trailers(trailers)),
+    ];
+    let mut req = Request::builder()
+      .method(Method::POST)
+      .uri(format!("https://{addr}/echo"))
+      .header(header::HOST, format!("localhost:{}", addr.port()))
+      .body(StreamBody::new(futures_util::stream::iter(frames)))
+      .unwrap();
+    req.extensions_mut().insert(tls.clone());
+    let Ok(res) = client.request(req).await else {
+      panic!("HTTP/3 request failed");
+    };
+    assert_eq!(res.status(), http::StatusCode::OK);
+    assert_eq!(
+      res.headers().get("x-authority").unwrap(),
+      format!("localhost:{}", addr.port()).as_str()
+    );
+    assert_eq!(res.headers().get("x-has-host").unwrap(), "false");
+    let collected = res.into_body().collect().await.unwrap();
+    assert_eq!(collected.trailers().unwrap().get("x-trailer").unwrap(), "done");
+    assert_eq!(collected.to_bytes(), Bytes::from_static(b"hello world"));
+
+    // the pooled connection is reused
+    let mut req = Request::builder()
+      .uri(format!("https://{addr}/"))
+      .body(Full::new(Bytes::from_static(b"again")))
+      .unwrap();
+    req.extensions_mut().insert(tls.clone());
+    let Ok(res) = client.request(req).await else {
+      panic!("HTTP/3 request failed");
+    };
+    assert_eq!(
+      res.into_body().collect().await.unwrap().to_bytes(),
+      Bytes::from_static(b"again")
+    );
+    assert_eq!(accepted.load(Ordering::Relaxed), 1);
+
+    // idempotent request without body allowing 0-RTT is sent over another connection pooled separately
+    let mut req = Request::builder()
+      .uri(format!("https://{addr}/"))
+      .body(Full::new(Bytes::new()))
+      .unwrap();
+    req.extensions_mut().insert(tls.clone());
+    req.extensions_mut().insert(Allow0Rtt);
+    let Ok(res) = client.request(req).await else {
+      panic!("HTTP/3 request failed");
+    };
+    assert_eq!(res.status(), http::StatusCode::OK);
+    assert_eq!(accepted.load(Ordering::Relaxed), 2);
+    let connections = client.connections.lock().unwrap();
+    assert_eq!(connections.len(), 2);
+    assert!(connections.keys().any(|(_, _, early_data)| *early_data));
+  }
+
+  #[tokio::test]
+  async fn unreachable_upstream_falls_back() {
+    let client = H3Client::try_new(&globals(), &HashSet::default()).unwrap();
+    // UDP socket never answering QUIC handshakes
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let authority = Authority::try_from(format!("127.0.0.1:{}", socket.local_addr().unwrap().port())).unwrap();
+
+    let mut req = Request::builder()
+      .uri(format!("https://{authority}/"))
+      .body(Full::new(Bytes::new()))
+      .unwrap();
+    req.extensions_mut().insert(UpstreamTimeoutConfig {
+      connect: Some(Duration::from_millis(200)),
+      ..Default::default()
+    });
+    let Err(H3RequestError::Unavailable(req)) = client.request(req).await else {
+      panic!("request should be returned back to be sent over HTTP/2");
+    };
+    assert!(client.is_falling_back(&authority));
+    let until = *client.fallback.lock().unwrap().get(&authority).unwrap();
+    assert!(until > Instant::now() + Duration::from_secs(H3::UPSTREAM_FALLBACK_DURATION - 1));
+
+    // returned back immediately within the window without connecting
+    let started = Instant::now();
+    assert!(matches!(client.request(req).await, Err(H3RequestError::Unavailable(_))));
+    assert!(started.elapsed() < Duration::from_millis(100));
+  }
+}
@@ -1,11 +1,17 @@
 #[cfg(feature = "cache")]
 mod cache;
 mod client;
+#[cfg(all(feature = "http3-quinn", feature = "rustls-backend"))]
+mod client_h3;
+#[cfg(feature = "rustls-backend")]
+mod upstream_tls;
 
 use crate::hyper_ext::body::RequestBody;
 
 pub(crate) type Forwarder<C> = client::Forwarder<C, RequestBody>;
-pub(crate) use client::ForwardRequest;
+#[cfg(unix)]
+pub(crate) use client::unix_socket_uri;
+pub(crate) use client::{Allow0Rtt, ForwardRequest, parse_upstream_location, upstream_location};
 
 #[cfg(feature = "cache")]
-pub(crate) use cache::CacheError;
+pub(crate) use cache::{CacheError, CacheStatus, RpxyCache};
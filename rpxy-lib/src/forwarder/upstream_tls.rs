@@ -0,0 +1,205 @@
+use crate::{
+  error::{RpxyError, RpxyResult},
+  globals::{UpstreamTlsConfig, UpstreamTlsVersion},
+};
+use rustls::{
+  ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+  client::{
+    WebPkiServerVerifier,
+    danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+  },
+  crypto::CryptoProvider,
+  pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+};
+use std::{path::Path, sync::Arc};
+
+/// Build the rustls client config for upstream destinations with the TLS settings.
+/// ALPN protocols are left empty, which are set by connectors, and `tls13_only` is given for QUIC.
+pub(super) fn build_client_config(tls: &UpstreamTlsConfig, tls13_only: bool) -> RpxyResult<ClientConfig> {
+  let provider = CryptoProvider::get_default()
+    .ok_or(RpxyError::NoDefaultCryptoProvider)?
+    .clone();
+  let versions: &[&rustls::SupportedProtocolVersion] = match (tls13_only, tls.min_version) {
+    (true, _) | (_, UpstreamTlsVersion::Tls13) => &[&rustls::version::TLS13],
+    (false, UpstreamTlsVersion::Tls12) => &[&rustls::version::TLS13, &rustls::version::TLS12],
+  };
+
+  let verifier = build_verifier(tls, provider.clone())?;
+  let verifier: Arc<dyn ServerCertVerifier> = match tls.verify_hostname {
+    true => verifier,
+    false => Arc::new(NoHostnameVerification(verifier)),
+  };
+  let builder = ClientConfig::builder_with_provider(provider)
+    .with_protocol_versions(versions)
+    .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(e.to_string()))?
+    .dangerous()
+    .with_custom_certificate_verifier(verifier);
+
+  match (tls.client_cert_path.as_ref(), tls.client_cert_key_path.as_ref()) {
+    (Some(cert_path), Some(key_path)) => {
+      let certs = read_certs(cert_path)?;
+      let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(format!("Failed to read {}: {e}", key_path.display())))?;
+      builder
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(format!("Invalid client certificate or key: {e}")))
+    }
+    (None, None) => Ok(builder.with_no_client_auth()),
+    _ => Err(RpxyError::InvalidUpstreamTlsConfig(
+      "Both client certificate and key must be given".to_string(),
+    )),
+  }
+}
+
+/// Server name sent in SNI if overridden
+pub(super) fn server_name(tls: &UpstreamTlsConfig) -> RpxyResult<Option<ServerName<'static>>> {
+  tls
+    .server_name
+    .as_ref()
+    .map(|v| {
+      ServerName::try_from(v.to_owned())
+        .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(format!("Invalid server name '{v}': {e}")))
+    })
+    .transpose()
+}
+
+/// Verifier of server certificates with the given CA certificates, or the default roots same as the default clients
+fn build_verifier(tls: &UpstreamTlsConfig, provider: Arc<CryptoProvider>) -> RpxyResult<Arc<dyn ServerCertVerifier>> {
+  let roots = match tls.ca_cert_path.as_ref() {
+    Some(ca_cert_path) => {
+      let mut roots = RootCertStore::empty();
+      let (added, _) = roots.add_parsable_certificates(read_certs(ca_cert_path)?);
+      if added == 0 {
+        return Err(RpxyError::InvalidUpstreamTlsConfig(format!(
+          "No valid CA certificate found in {}",
+          ca_cert_path.display()
+        )));
+      }
+      roots
+    }
+    #[cfg(feature = "webpki-roots")]
+    None => RootCertStore {
+      roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    },
+    #[cfg(not(feature = "webpki-roots"))]
+    None => {
+      let verifier = rustls_platform_verifier::Verifier::new(provider)
+        .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(format!("Platform verifier failed: {e}")))?;
+      return Ok(Arc::new(verifier));
+    }
+  };
+  let verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+    .build()
+    .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(e.to_string()))?;
+  Ok(verifier)
+}
+
+/// Read certificates in PEM
+fn read_certs(path: &Path) -> RpxyResult<Vec<CertificateDer<'static>>> {
+  let certs = CertificateDer::pem_file_iter(path)
+    .and_then(|v| v.collect::<Result<Vec<_>, _>>())
+    .map_err(|e| RpxyError::InvalidUpstreamTlsConfig(format!("Failed to read {}: {e}", path.display())))?;
+  if certs.is_empty() {
+    return Err(RpxyError::InvalidUpstreamTlsConfig(format!(
+      "No certificate found in {}",
+      path.display()
+    )));
+  }
+  Ok(certs)
+}
+
+#[derive(Debug)]
+/// Verifier accepting server certificates not valid for the server name, where their chains are still verified by the inner one
+struct NoHostnameVerification(Arc<dyn ServerCertVerifier>);
+
+impl ServerCertVerifier for NoHostnameVerification {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    server_name: &ServerName<'_>,
+    ocsp_response: &[u8],
+    now: UnixTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    match self
+      .0
+      .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    {
+      Err(rustls::Error::InvalidCertificate(
+        rustls::CertificateError::NotValidForName | rustls::CertificateError::NotValidForNameContext { .. },
+      )) => Ok(ServerCertVerified::assertion()),
+      res => res,
+    }
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    self.0.verify_tls12_signature(message, cert, dss)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    self.0.verify_tls13_signature(message, cert, dss)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.0.supported_verify_schemes()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn install_provider() {
+    let _ = CryptoProvider::install_default(rustls::crypto::aws_lc_rs::default_provider());
+  }
+
+  #[test]
+  fn client_config_with_mutual_tls_works() {
+    install_provider();
+    let tls = UpstreamTlsConfig {
+      ca_cert_path: Some("../example-certs/server.crt".into()),
+      client_cert_path: Some("../example-certs/client.crt".into()),
+      client_cert_key_path: Some("../example-certs/client.key".into()),
+      server_name: Some("localhost".to_string()),
+      verify_hostname: false,
+      min_version: UpstreamTlsVersion::Tls13,
+      alpn: None,
+    };
+    let config = build_client_config(&tls, false).unwrap();
+    assert!(config.client_auth_cert_resolver.has_certs());
+    assert!(config.alpn_protocols.is_empty());
+    assert!(server_name(&tls).unwrap().is_some());
+  }
+
+  #[test]
+  fn client_config_with_invalid_files_fails() {
+    install_provider();
+    let missing_ca = UpstreamTlsConfig {
+      ca_cert_path: Some("../example-certs/not-found.crt".into()),
+      ..Default::default()
+    };
+    assert!(build_client_config(&missing_ca, false).is_err());
+
+    let missing_key = UpstreamTlsConfig {
+      client_cert_path: Some("../example-certs/client.crt".into()),
+      ..Default::default()
+    };
+    assert!(build_client_config(&missing_key, false).is_err());
+
+    let invalid_name = UpstreamTlsConfig {
+      server_name: Some("invalid name".to_string()),
+      ..Default::default()
+    };
+    assert!(server_name(&invalid_name).is_err());
+  }
+}
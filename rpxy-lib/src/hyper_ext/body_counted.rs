@@ -0,0 +1,120 @@
+use crate::error::RpxyError;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::{
+  pin::Pin,
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+  },
+  task::{Context, Poll},
+};
+
+/// Callback called once with the number of data bytes passed through the body
+type OnEnd = Box<dyn FnOnce(u64) + Send + Sync>;
+
+/// Body passing frames of the inner body through while counting its data bytes in the given counter,
+/// where the callback is called when the inner body ends, fails or is dropped halfway.
+pub struct CountedBody<B> {
+  inner: B,
+  counter: Arc<AtomicU64>,
+  on_end: Option<OnEnd>,
+}
+
+impl<B> CountedBody<B> {
+  /// Wrap the body, counting its data bytes in the counter
+  pub(crate) fn new(inner: B, counter: Arc<AtomicU64>) -> Self {
+    Self {
+      inner,
+      counter,
+      on_end: None,
+    }
+  }
+
+  /// Set the callback called at the end of the body
+  pub(crate) fn on_end(mut self, on_end: impl FnOnce(u64) + Send + Sync + 'static) -> Self {
+    self.on_end = Some(Box::new(on_end));
+    self
+  }
+
+  fn finish(&mut self) {
+    if let Some(on_end) = self.on_end.take() {
+      on_end(self.counter.load(Ordering::Relaxed));
+    }
+  }
+}
+
+impl<B> Drop for CountedBody<B> {
+  fn drop(&mut self) {
+    self.finish();
+  }
+}
+
+impl<B> Body for CountedBody<B>
+where
+  B: Body<Data = Bytes, Error = RpxyError> + Unpin,
+{
+  type Data = Bytes;
+  type Error = RpxyError;
+
+  fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+    let this = self.get_mut();
+    let frame = match Pin::new(&mut this.inner).poll_frame(cx) {
+      Poll::Ready(frame) => frame,
+      Poll::Pending => return Poll::Pending,
+    };
+    match &frame {
+      Some(Ok(frame)) => {
+        if let Some(data) = frame.data_ref() {
+          this.counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        // senders may stop polling once the body tells its end
+        if this.inner.is_end_stream() {
+          this.finish();
+        }
+      }
+      Some(Err(_)) | None => this.finish(),
+    }
+    Poll::Ready(frame)
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    self.inner.size_hint()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use http_body_util::{BodyExt, StreamBody};
+  use std::sync::Mutex;
+
+  fn stream_body(chunks: Vec<&'static [u8]>) -> impl Body<Data = Bytes, Error = RpxyError> + Unpin {
+    let frames = chunks.into_iter().map(|v| Ok(Frame::data(Bytes::from_static(v))));
+    StreamBody::new(futures_util::stream::iter(frames))
+  }
+
+  #[tokio::test]
+  async fn counted_body_reports_bytes_at_end_or_drop() {
+    let ended = Arc::new(Mutex::new(Vec::new()));
+    let counted = |chunks| {
+      let ended = ended.clone();
+      CountedBody::new(stream_body(chunks), Arc::default()).on_end(move |v| ended.lock().unwrap().push(v))
+    };
+
+    // streamed body without known length, reported once at the end
+    let body = counted(vec![b"hello ", b"world"]);
+    assert!(body.size_hint().exact().is_none());
+    assert_eq!(body.collect().await.unwrap().to_bytes(), Bytes::from_static(b"hello world"));
+    assert_eq!(*ended.lock().unwrap(), vec![11]);
+
+    // dropped halfway, reported with the bytes passed through so far
+    let mut body = counted(vec![b"hello ", b"world"]);
+    body.frame().await.unwrap().unwrap();
+    drop(body);
+    assert_eq!(*ended.lock().unwrap(), vec![11, 6]);
+  }
+}
@@ -0,0 +1,121 @@
+use crate::error::RpxyError;
+use bytes::BytesMut;
+use futures_channel::oneshot;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::{
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+/// Body passing frames of the inner body through while copying its data up to the limit,
+/// where the copy is sent when the inner body ends and discarded if it exceeds the limit, fails or is dropped halfway.
+pub struct TeeBody<B> {
+  inner: B,
+  copied: BytesMut,
+  limit: usize,
+  tx: Option<oneshot::Sender<Bytes>>,
+}
+
+impl<B> TeeBody<B> {
+  /// Wrap the body, returning the receiver of the copy
+  pub(crate) fn new(inner: B, limit: usize) -> (Self, oneshot::Receiver<Bytes>) {
+    let (tx, rx) = oneshot::channel();
+    let body = Self {
+      inner,
+      copied: BytesMut::new(),
+      limit,
+      tx: Some(tx),
+    };
+    (body, rx)
+  }
+
+  fn finish(&mut self) {
+    if let Some(tx) = self.tx.take() {
+      let _ = tx.send(self.copied.split().freeze());
+    }
+  }
+
+  fn discard(&mut self) {
+    self.tx = None;
+    self.copied = BytesMut::new();
+  }
+}
+
+impl<B> Body for TeeBody<B>
+where
+  B: Body<Data = Bytes, Error = RpxyError> + Unpin,
+{
+  type Data = Bytes;
+  type Error = RpxyError;
+
+  fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+    let this = self.get_mut();
+    let frame = match Pin::new(&mut this.inner).poll_frame(cx) {
+      Poll::Ready(frame) => frame,
+      Poll::Pending => return Poll::Pending,
+    };
+    match &frame {
+      Some(Ok(frame)) => {
+        if let Some(data) = frame.data_ref().filter(|_| this.tx.is_some()) {
+          if this.copied.len() + data.len() > this.limit {
+            this.discard();
+          } else {
+            this.copied.extend_from_slice(data);
+          }
+        }
+        // senders may stop polling once the body tells its end
+        if this.inner.is_end_stream() {
+          this.finish();
+        }
+      }
+      Some(Err(_)) => this.discard(),
+      None => this.finish(),
+    }
+    Poll::Ready(frame)
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    self.inner.size_hint()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use http_body_util::{BodyExt, StreamBody};
+
+  fn stream_body(chunks: Vec<Result<&'static [u8], RpxyError>>) -> impl Body<Data = Bytes, Error = RpxyError> + Unpin {
+    let frames = chunks.into_iter().map(|v| v.map(|v| Frame::data(Bytes::from_static(v))));
+    StreamBody::new(futures_util::stream::iter(frames))
+  }
+
+  #[tokio::test]
+  async fn tee_body_copies_data_within_limit() {
+    let (body, rx) = TeeBody::new(stream_body(vec![Ok(b"hello "), Ok(b"world")]), 11);
+    let passed = body.collect().await.unwrap().to_bytes();
+    assert_eq!(passed, Bytes::from_static(b"hello world"));
+    assert_eq!(rx.await.unwrap(), Bytes::from_static(b"hello world"));
+
+    // exceeding the limit, passed through but not copied
+    let (body, rx) = TeeBody::new(stream_body(vec![Ok(b"hello "), Ok(b"world")]), 10);
+    let passed = body.collect().await.unwrap().to_bytes();
+    assert_eq!(passed, Bytes::from_static(b"hello world"));
+    assert!(rx.await.is_err());
+
+    // failed halfway
+    let failed = Err(RpxyError::HyperBodyManipulationError("broken".to_string()));
+    let (body, rx) = TeeBody::new(stream_body(vec![Ok(b"hello "), failed]), 100);
+    assert!(body.collect().await.is_err());
+    assert!(rx.await.is_err());
+
+    // dropped halfway
+    let (mut body, rx) = TeeBody::new(stream_body(vec![Ok(b"hello "), Ok(b"world")]), 100);
+    body.frame().await.unwrap().unwrap();
+    drop(body);
+    assert!(rx.await.is_err());
+  }
+}
@@ -0,0 +1,113 @@
+use crate::error::RpxyError;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::{
+  future::Future,
+  pin::Pin,
+  task::{Context, Poll},
+  time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+/// Body aborted with an error if the deadline passes or no frame arrives for the idle timeout
+pub struct TimeoutBody<B> {
+  inner: B,
+  deadline: Option<Pin<Box<Sleep>>>,
+  idle_timeout: Option<Duration>,
+  idle: Option<Pin<Box<Sleep>>>,
+  timed_out: bool,
+}
+
+impl<B> TimeoutBody<B> {
+  /// Wrap the body, where the idle timer starts now
+  pub(crate) fn new(inner: B, deadline: Option<Instant>, idle_timeout: Option<Duration>) -> Self {
+    Self {
+      inner,
+      deadline: deadline.map(|v| Box::pin(tokio::time::sleep_until(v))),
+      idle_timeout,
+      idle: idle_timeout.map(|v| Box::pin(tokio::time::sleep(v))),
+      timed_out: false,
+    }
+  }
+}
+
+impl<B> Body for TimeoutBody<B>
+where
+  B: Body<Data = Bytes, Error = RpxyError> + Unpin,
+{
+  type Data = Bytes;
+  type Error = RpxyError;
+
+  fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+    let this = self.get_mut();
+    if this.timed_out {
+      return Poll::Ready(None);
+    }
+    if let Poll::Ready(frame) = Pin::new(&mut this.inner).poll_frame(cx) {
+      if let (Some(idle), Some(idle_timeout)) = (this.idle.as_mut(), this.idle_timeout) {
+        idle.as_mut().reset(Instant::now() + idle_timeout);
+      }
+      return Poll::Ready(frame);
+    }
+
+    let expired = if this.deadline.as_mut().is_some_and(|v| v.as_mut().poll(cx).is_ready()) {
+      "request timeout"
+    } else if this.idle.as_mut().is_some_and(|v| v.as_mut().poll(cx).is_ready()) {
+      "body idle timeout"
+    } else {
+      return Poll::Pending;
+    };
+    this.timed_out = true;
+    Poll::Ready(Some(Err(RpxyError::UpstreamTimeout(format!(
+      "{expired} expired while receiving response body"
+    )))))
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.timed_out || self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    self.inner.size_hint()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hyper_ext::body::{IncomingLike, ResponseBody};
+  use http_body_util::BodyExt;
+
+  #[tokio::test]
+  async fn idle_body_times_out() {
+    let (mut sender, body) = IncomingLike::channel();
+    let idle_timeout = Some(Duration::from_millis(100));
+    let mut body = TimeoutBody::new(ResponseBody::IncomingLike(body), None, idle_timeout);
+
+    // idle timer is reset by each frame, and expires without further frames
+    for _ in 0..2 {
+      tokio::time::sleep(Duration::from_millis(60)).await;
+      sender.send_data(Bytes::from_static(b"chunk")).await.unwrap();
+      let frame = body.frame().await.unwrap().unwrap();
+      assert_eq!(frame.into_data().unwrap(), Bytes::from_static(b"chunk"));
+    }
+    assert!(matches!(body.frame().await, Some(Err(RpxyError::UpstreamTimeout(_)))));
+    assert!(body.frame().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn body_times_out_at_deadline() {
+    let (mut sender, body) = IncomingLike::channel();
+    let deadline = Instant::now() + Duration::from_millis(150);
+    let idle_timeout = Some(Duration::from_millis(100));
+    let mut body = TimeoutBody::new(ResponseBody::IncomingLike(body), Some(deadline), idle_timeout);
+
+    for _ in 0..2 {
+      tokio::time::sleep(Duration::from_millis(60)).await;
+      sender.send_data(Bytes::from_static(b"chunk")).await.unwrap();
+      assert!(body.frame().await.unwrap().is_ok());
+    }
+    let started = Instant::now();
+    assert!(matches!(body.frame().await, Some(Err(RpxyError::UpstreamTimeout(_)))));
+    assert!(started.elapsed() < Duration::from_millis(90));
+  }
+}
@@ -1,8 +1,8 @@
-use super::body::IncomingLike;
+use super::body::{CountedBody, IncomingLike, TeeBody, TimeoutBody};
 use crate::error::RpxyError;
 use futures::channel::mpsc::UnboundedReceiver;
 use http_body_util::{combinators, BodyExt, Empty, Full, StreamBody};
-use hyper::body::{Body, Bytes, Frame, Incoming};
+use hyper::body::{Body, Bytes, Frame, Incoming, SizeHint};
 use std::pin::Pin;
 
 /// Type for synthetic boxed body
@@ -23,9 +23,15 @@ pub(crate) fn full(body: Bytes) -> BoxBody {
 /// Request body used in this project
 /// - Incoming: just a type that only forwards the downstream request body to upstream.
 /// - IncomingLike: a Incoming-like type in which channel is used
+/// - Full: a body on memory, e.g., copy of a request body mirrored to a shadow upstream
+/// - Tee: another type wrapped to copy its data for mirroring
+/// - Counted: another type wrapped to count its data bytes for logging
 pub enum RequestBody {
   Incoming(Incoming),
   IncomingLike(IncomingLike),
+  Full(Full<Bytes>),
+  Tee(Box<TeeBody<RequestBody>>),
+  Counted(Box<CountedBody<RequestBody>>),
 }
 
 impl Body for RequestBody {
@@ -39,22 +45,51 @@ impl Body for RequestBody {
     match self.get_mut() {
       RequestBody::Incoming(incoming) => Pin::new(incoming).poll_frame(cx).map_err(RpxyError::HyperBodyError),
       RequestBody::IncomingLike(incoming_like) => Pin::new(incoming_like).poll_frame(cx),
+      RequestBody::Full(full) => Pin::new(full).poll_frame(cx).map_err(|never| match never {}),
+      RequestBody::Tee(tee) => Pin::new(tee.as_mut()).poll_frame(cx),
+      RequestBody::Counted(counted) => Pin::new(counted.as_mut()).poll_frame(cx),
+    }
+  }
+
+  fn is_end_stream(&self) -> bool {
+    match self {
+      RequestBody::Incoming(incoming) => incoming.is_end_stream(),
+      RequestBody::IncomingLike(incoming_like) => incoming_like.is_end_stream(),
+      RequestBody::Full(full) => full.is_end_stream(),
+      RequestBody::Tee(tee) => tee.is_end_stream(),
+      RequestBody::Counted(counted) => counted.is_end_stream(),
+    }
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    match self {
+      RequestBody::Incoming(incoming) => incoming.size_hint(),
+      RequestBody::IncomingLike(incoming_like) => incoming_like.size_hint(),
+      RequestBody::Full(full) => full.size_hint(),
+      RequestBody::Tee(tee) => tee.size_hint(),
+      RequestBody::Counted(counted) => counted.size_hint(),
     }
   }
 }
 
 /* ------------------------------------ */
-pub type UnboundedStreamBody = StreamBody<UnboundedReceiver<Result<Frame<bytes::Bytes>, hyper::Error>>>;
+pub type UnboundedStreamBody = StreamBody<UnboundedReceiver<Result<Frame<bytes::Bytes>, RpxyError>>>;
 
 #[allow(unused)]
 /// Response body use in this project
 /// - Incoming: just a type that only forwards the upstream response body to downstream.
 /// - Boxed: a type that is generated from cache or synthetic response body, e.g.,, small byte object.
 /// - Streamed: another type that is generated from stream, e.g., large byte object.
+/// - IncomingLike: a Incoming-like type in which channel is used, e.g., response from HTTP/3 upstream.
+/// - Timeout: another type wrapped with timeouts of the upstream route.
+/// - Counted: another type wrapped to count its data bytes for logging at its end.
 pub enum ResponseBody {
   Incoming(Incoming),
   Boxed(BoxBody),
   Streamed(UnboundedStreamBody),
+  IncomingLike(IncomingLike),
+  Timeout(Box<TimeoutBody<ResponseBody>>),
+  Counted(Box<CountedBody<ResponseBody>>),
 }
 
 impl Body for ResponseBody {
@@ -66,10 +101,34 @@ impl Body for ResponseBody {
     cx: &mut std::task::Context<'_>,
   ) -> std::task::Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
     match self.get_mut() {
-      ResponseBody::Incoming(incoming) => Pin::new(incoming).poll_frame(cx),
-      ResponseBody::Boxed(boxed) => Pin::new(boxed).poll_frame(cx),
+      ResponseBody::Incoming(incoming) => Pin::new(incoming).poll_frame(cx).map_err(RpxyError::HyperBodyError),
+      ResponseBody::Boxed(boxed) => Pin::new(boxed).poll_frame(cx).map_err(RpxyError::HyperBodyError),
       ResponseBody::Streamed(streamed) => Pin::new(streamed).poll_frame(cx),
+      ResponseBody::IncomingLike(incoming_like) => Pin::new(incoming_like).poll_frame(cx),
+      ResponseBody::Timeout(timeout) => Pin::new(timeout.as_mut()).poll_frame(cx),
+      ResponseBody::Counted(counted) => Pin::new(counted.as_mut()).poll_frame(cx),
+    }
+  }
+
+  fn is_end_stream(&self) -> bool {
+    match self {
+      ResponseBody::Incoming(incoming) => incoming.is_end_stream(),
+      ResponseBody::Boxed(boxed) => boxed.is_end_stream(),
+      ResponseBody::Streamed(streamed) => streamed.is_end_stream(),
+      ResponseBody::IncomingLike(incoming_like) => incoming_like.is_end_stream(),
+      ResponseBody::Timeout(timeout) => timeout.is_end_stream(),
+      ResponseBody::Counted(counted) => counted.is_end_stream(),
+    }
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    match self {
+      ResponseBody::Incoming(incoming) => incoming.size_hint(),
+      ResponseBody::Boxed(boxed) => boxed.size_hint(),
+      ResponseBody::Streamed(streamed) => streamed.size_hint(),
+      ResponseBody::IncomingLike(incoming_like) => incoming_like.size_hint(),
+      ResponseBody::Timeout(timeout) => timeout.size_hint(),
+      ResponseBody::Counted(counted) => counted.size_hint(),
     }
-    .map_err(RpxyError::HyperBodyError)
   }
 }
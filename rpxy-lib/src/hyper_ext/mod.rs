@@ -1,4 +1,7 @@
+mod body_counted;
 mod body_incoming_like;
+mod body_tee;
+mod body_timeout;
 mod body_type;
 mod executor;
 mod tokio_timer;
@@ -11,6 +14,9 @@ pub(crate) mod rt {
 }
 #[allow(unused)]
 pub(crate) mod body {
+  pub(crate) use super::body_counted::CountedBody;
   pub(crate) use super::body_incoming_like::IncomingLike;
+  pub(crate) use super::body_tee::TeeBody;
+  pub(crate) use super::body_timeout::TimeoutBody;
   pub(crate) use super::body_type::{empty, full, BoxBody, RequestBody, ResponseBody, UnboundedStreamBody};
 }
@@ -0,0 +1,86 @@
+use crate::error::*;
+use std::{net::IpAddr, str::FromStr};
+
+/// IP address block in CIDR notation like "10.0.0.0/8", or a single address like "127.0.0.1"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpCidr {
+  addr: IpAddr,
+  prefix_len: u8,
+}
+
+impl FromStr for IpCidr {
+  type Err = RpxyError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || RpxyError::InvalidIpCidr(s.to_string());
+    let (addr, prefix_len) = match s.trim().split_once('/') {
+      Some((addr, len)) => (
+        addr.parse::<IpAddr>().map_err(|_| invalid())?,
+        Some(len.parse::<u8>().map_err(|_| invalid())?),
+      ),
+      None => (s.trim().parse::<IpAddr>().map_err(|_| invalid())?, None),
+    };
+    let max_len = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix_len = prefix_len.unwrap_or(max_len);
+    if prefix_len > max_len {
+      return Err(invalid());
+    }
+    Ok(Self { addr, prefix_len })
+  }
+}
+
+impl std::fmt::Display for IpCidr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}/{}", self.addr, self.prefix_len)
+  }
+}
+
+impl serde::Serialize for IpCidr {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl IpCidr {
+  /// Check if the address is contained in the block. IPv4-mapped IPv6 addresses are handled as IPv4.
+  pub fn contains(&self, addr: &IpAddr) -> bool {
+    let addr = match addr {
+      IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*addr),
+      IpAddr::V4(_) => *addr,
+    };
+    match (self.addr, addr) {
+      (IpAddr::V4(net), IpAddr::V4(addr)) => {
+        let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+        u32::from(net) & mask == u32::from(addr) & mask
+      }
+      (IpAddr::V6(net), IpAddr::V6(addr)) => {
+        let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+        u128::from(net) & mask == u128::from(addr) & mask
+      }
+      _ => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn contains_works() {
+    let cidr = "10.1.0.0/16".parse::<IpCidr>().unwrap();
+    assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+    assert!(cidr.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+    assert!(!cidr.contains(&"10.2.0.1".parse().unwrap()));
+
+    let cidr = "::1".parse::<IpCidr>().unwrap();
+    assert!(cidr.contains(&"::1".parse().unwrap()));
+    assert!(!cidr.contains(&"127.0.0.1".parse().unwrap()));
+
+    let cidr = "0.0.0.0/0".parse::<IpCidr>().unwrap();
+    assert!(cidr.contains(&"192.168.0.1".parse().unwrap()));
+
+    assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+    assert!("localhost".parse::<IpCidr>().is_err());
+  }
+}
@@ -1,3 +1,4 @@
+mod admin;
 mod backend;
 mod constants;
 mod count;
@@ -5,32 +6,48 @@ mod error;
 mod forwarder;
 mod globals;
 mod hyper_ext;
+mod ip_cidr;
 mod log;
 mod message_handler;
+mod metrics;
 mod name_exp;
 mod proxy;
 /* ------------------------------------------------ */
+#[cfg(feature = "cache")]
+use crate::forwarder::RpxyCache;
 use crate::{
+  admin::AdminState,
+  constants::DRAIN_CHECK_INTERVAL_MSEC,
+  count::RequestCount,
   // crypto::build_cert_reloader,
   error::*,
   forwarder::Forwarder,
   globals::Globals,
   log::*,
   message_handler::HttpMessageHandlerBuilder,
-  proxy::Proxy,
+  proxy::{Proxy, ProxyContext, ProxyContextSwap},
 };
 use futures::future::join_all;
 use hot_reload::ReloaderReceiver;
 use rpxy_certs::ServerCryptoBase;
 use rustls::crypto::CryptoProvider;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio_util::sync::CancellationToken;
 
 /* ------------------------------------------------ */
+pub use crate::admin::{Admin, AdminReloadHook, admin_service};
 pub use crate::constants::log_event_names;
-pub use crate::globals::{AppConfig, AppConfigList, ProxyConfig, ReverseProxyConfig, TlsConfig, UpstreamUri};
+pub use crate::globals::{
+  AdminConfig, AdminListen, AppConfig, AppConfigList, CorsConfig, FaultConfig, HstsConfig, MetricsConfig, MirrorConfig,
+  ProxyConfig, RedirectConfig, ReverseProxyConfig, SecretValues, SecurityHeadersConfig, StaticResponseConfig, TlsConfig,
+  UpstreamDiscoveryConfig, UpstreamDiscoveryMode, UpstreamPoolConfig, UpstreamProtocol, UpstreamTimeoutConfig, UpstreamTlsConfig,
+  UpstreamTlsVersion, UpstreamUri,
+};
+pub use crate::ip_cidr::IpCidr;
+pub use crate::metrics::{Metrics, metrics_service};
+pub use crate::proxy::ConfigUpdater;
 pub mod reexports {
-  pub use hyper::Uri;
+  pub use hyper::{Uri, header::HeaderName};
 }
 
 #[derive(derive_builder::Builder)]
@@ -44,6 +61,15 @@ pub struct RpxyOptions {
   pub cert_rx: Option<ReloaderReceiver<ServerCryptoBase>>, // TODO:
   /// Async task runtime handler
   pub runtime_handle: tokio::runtime::Handle,
+  /// Metrics shared with the metrics service, kept over the configuration reloads
+  #[builder(default)]
+  pub metrics: Metrics,
+  /// Admin API handle to which the runtime state is registered, kept over the configuration reloads
+  #[builder(default)]
+  pub admin: Admin,
+  /// Handle to update the configuration of the running proxy services in place, kept over the configuration reloads
+  #[builder(default)]
+  pub config_updater: ConfigUpdater,
 
   #[cfg(feature = "acme")]
   /// ServerConfig used for only ACME challenge for ACME domains
@@ -51,17 +77,15 @@ pub struct RpxyOptions {
 }
 
 /// Entrypoint that creates and spawns tasks of reverse proxy services
-pub async fn entrypoint(
-  RpxyOptions {
+pub async fn entrypoint(opts: &RpxyOptions, cancel_token: CancellationToken) -> RpxyResult<()> {
+  let RpxyOptions {
     proxy_config,
-    app_config_list,
-    cert_rx, // TODO:
     runtime_handle,
-    #[cfg(feature = "acme")]
-    server_configs_acme_challenge,
-  }: &RpxyOptions,
-  cancel_token: CancellationToken,
-) -> RpxyResult<()> {
+    metrics,
+    admin,
+    config_updater,
+    ..
+  } = opts;
   #[cfg(all(feature = "http3-quinn", feature = "http3-s2n"))]
   warn!("Both \"http3-quinn\" and \"http3-s2n\" features are enabled. \"http3-quinn\" will be used");
 
@@ -108,35 +132,70 @@ pub async fn entrypoint(
   #[cfg(feature = "post-quantum")]
   info!("Post-quantum crypto provider is installed");
 
-  // 1. build backends, and make it contained in Arc
-  let app_manager = Arc::new(backend::BackendAppManager::try_from(app_config_list)?);
+  // Context of proxy services is built from the options at the start and at every update of the configuration.
+  // Counter for serving requests is shared among the contexts to limit the number of clients over the updates.
+  let request_count = RequestCount::default();
+  // The cache is built at the start and carried over the updates, where its settings are unchanged unless rebinding.
+  let build_context = async |opts: &RpxyOptions, current: Option<Arc<ProxyContext<_>>>| -> RpxyResult<_> {
+    // 1. build backends, and make it contained in Arc
+    let app_manager = Arc::new(backend::BackendAppManager::try_from(&opts.app_config_list)?);
 
-  // 2. build global shared context
-  let globals = Arc::new(Globals {
-    proxy_config: proxy_config.clone(),
-    request_count: Default::default(),
-    runtime_handle: runtime_handle.clone(),
-    cert_reloader_rx: cert_rx.clone(),
+    // 2. build global shared context
+    let globals = Arc::new(Globals {
+      proxy_config: opts.proxy_config.clone(),
+      request_count: request_count.clone(),
+      metrics: metrics.clone(),
+      runtime_handle: runtime_handle.clone(),
+      cert_reloader_rx: opts.cert_rx.clone(),
 
-    #[cfg(feature = "acme")]
-    server_configs_acme_challenge: server_configs_acme_challenge.clone(),
-  });
+      #[cfg(feature = "acme")]
+      server_configs_acme_challenge: opts.server_configs_acme_challenge.clone(),
+    });
+
+    // resolve discovered upstreams before serving, and keep them re-resolved in background while the backends are in use
+    backend::start_upstream_discovery(&app_manager, &globals).await?;
+
+    // 3. build message handler containing Arc-ed http_client and backends, and make it contained in Arc as well
+    let forwarder = Forwarder::try_new(&globals, &opts.app_config_list).await?;
+    #[cfg(feature = "cache")]
+    let forwarder = match current.as_ref() {
+      Some(current) => forwarder.with_cache(current.message_handler.cache().cloned()),
+      None => forwarder.with_cache(RpxyCache::new(&globals).await),
+    };
+    #[cfg(not(feature = "cache"))]
+    let _ = current;
+    let forwarder = Arc::new(forwarder);
+    // runtime state for the admin API, registered only after the context goes live
+    let admin_state = AdminState {
+      globals: globals.clone(),
+      app_config_list: opts.app_config_list.clone(),
+      app_manager: app_manager.clone(),
+      #[cfg(feature = "cache")]
+      cache: forwarder.cache().cloned(),
+    };
+    let message_handler = Arc::new(
+      HttpMessageHandlerBuilder::default()
+        .globals(globals.clone())
+        .app_manager(app_manager)
+        .forwarder(forwarder)
+        .build()?,
+    );
+
+    // build hyper connection builder shared with proxy instances
+    let connection_builder = proxy::connection_builder(&globals);
+    let context = ProxyContext {
+      globals,
+      connection_builder,
+      message_handler,
+    };
+    Ok((context, admin_state))
+  };
+  let (context, admin_state) = build_context(opts, None).await?;
+  let globals = context.globals.clone();
+  let context = Arc::new(ProxyContextSwap::new(context));
+  admin.register(admin_state);
 
-  // 3. build message handler containing Arc-ed http_client and backends, and make it contained in Arc as well
-  let forwarder = Arc::new(Forwarder::try_new(&globals).await?);
-  let message_handler = Arc::new(
-    HttpMessageHandlerBuilder::default()
-      .globals(globals.clone())
-      .app_manager(app_manager.clone())
-      .forwarder(forwarder)
-      .build()?,
-  );
-
-  // 4. spawn each proxy for a given socket with copied Arc-ed message_handler.
-  // build hyper connection builder shared with proxy instances
-  let connection_builder = proxy::connection_builder(&globals);
-
-  // spawn each proxy for a given socket with copied Arc-ed backend, message_handler and connection builder.
+  // 4. spawn each proxy for a given socket with the swappable context shared among them.
   let addresses = globals.proxy_config.listen_sockets.clone();
   let join_handles = addresses.into_iter().map(|listening_on| {
     let mut tls_enabled = false;
@@ -147,8 +206,8 @@ pub async fn entrypoint(
       globals: globals.clone(),
       listening_on,
       tls_enabled,
-      connection_builder: connection_builder.clone(),
-      message_handler: message_handler.clone(),
+      context: context.clone(),
+      shutdown_token: cancel_token.clone(),
     };
 
     let cancel_token = cancel_token.clone();
@@ -170,7 +229,48 @@ pub async fn entrypoint(
     })
   });
 
-  let join_res = join_all(join_handles).await;
+  // 5. serve requests to update the configuration, where the context is swapped while listeners keep running
+  let mut update_rx = config_updater.register();
+  let update_service = async {
+    loop {
+      let (new_opts, res_tx) = tokio::select! {
+        _ = cancel_token.cancelled() => break,
+        req = update_rx.recv() => match req {
+          Some(req) => req,
+          None => break,
+        },
+      };
+      let res = if globals.proxy_config.requires_rebind(&new_opts.proxy_config) {
+        Err(RpxyError::ListenSocketsChanged)
+      } else {
+        build_context(&new_opts, Some(context.load()))
+          .await
+          .map(|(new_context, admin_state)| {
+            context.store(new_context);
+            admin.register(admin_state);
+          })
+      };
+      if let Err(e) = &res {
+        error!("Failed to update the configuration: {e}");
+      }
+      res_tx.send(res).ok();
+    }
+  };
+
+  let (join_res, _) = tokio::join!(join_all(join_handles), update_service);
+
+  // 6. drain active connections, where listeners are already closed and established connections are gracefully closed
+  let drain_started = tokio::time::Instant::now();
+  if request_count.current() > 0 {
+    info!("Wait for {} active connections to finish", request_count.current());
+  }
+  while request_count.current() > 0 {
+    if drain_started.elapsed() >= globals.proxy_config.drain_timeout {
+      warn!("Drain timeout: {} active connections remain", request_count.current());
+      break;
+    }
+    tokio::time::sleep(Duration::from_millis(DRAIN_CHECK_INTERVAL_MSEC)).await;
+  }
   let mut errs = join_res.into_iter().filter_map(|res| {
     if let Ok(Err(e)) = res {
       error!("Some proxy services are down: {}", e);
@@ -1,33 +1,61 @@
 use super::{
   http_log::HttpMessageLog,
   http_result::{HttpError, HttpResult},
-  synthetic_response::{secure_redirection_response, synthetic_error_response},
+  request_id::assign_request_id,
+  synthetic_response::{
+    preflight_response, redirection_response, secure_redirection_response, static_response, synthetic_error_response,
+  },
+  trace_context::{end_client_span, end_server_span, start_client_span, start_server_span},
   utils_headers::*,
   utils_request::InspectParseHost,
 };
+#[cfg(feature = "cache")]
+use crate::forwarder::{CacheStatus, RpxyCache};
 use crate::{
-  backend::{BackendAppManager, LoadBalanceContext},
+  backend::{BackendApp, BackendAppManager, CorsPolicy, CorsResponseHeaders, FaultAction, LoadBalanceContext, decide_faults},
+  constants::UNIX_SOCKET_SCHEME,
   error::*,
-  forwarder::{ForwardRequest, Forwarder},
+  forwarder::{ForwardRequest, Forwarder, upstream_location},
   globals::Globals,
-  hyper_ext::body::{RequestBody, ResponseBody},
+  hyper_ext::body::{CountedBody, RequestBody, ResponseBody},
   log::*,
   name_exp::ServerName,
 };
 use derive_builder::Builder;
 use http::{Request, Response, StatusCode};
 use hyper_util::{client::legacy::connect::Connect, rt::TokioIo};
-use std::{net::SocketAddr, sync::Arc};
+use opentelemetry::trace::FutureExt;
+use std::{
+  net::SocketAddr,
+  sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+  },
+  time::Instant,
+};
 use tokio::io::copy_bidirectional;
 
 #[allow(dead_code)]
 #[derive(Debug)]
-/// Context object to handle sticky cookies at HTTP message handler
+/// Context object to handle sticky cookies and CORS headers at HTTP message handler
 pub(super) struct HandlerContext {
   #[cfg(feature = "sticky-cookie")]
   pub(super) context_lb: Option<LoadBalanceContext>,
   #[cfg(not(feature = "sticky-cookie"))]
   pub(super) context_lb: Option<()>,
+  /// CORS headers decided from the request, applied to the response
+  pub(super) cors_headers: Option<CorsResponseHeaders>,
+}
+
+#[derive(Debug, Clone)]
+/// TLS information of the connection from a client, used for the SNI consistency check and logging
+pub struct TlsInfo {
+  /// Server name indicated in ClientHello
+  pub server_name: ServerName,
+  /// Negotiated TLS protocol version like "TLSv1_3"
+  pub version: &'static str,
+  /// Negotiated cipher suite like "TLS13_AES_128_GCM_SHA256"
+  pub cipher: &'static str,
 }
 
 #[derive(Clone, Builder)]
@@ -37,7 +65,7 @@ pub struct HttpMessageHandler<C>
 where
   C: Send + Sync + Connect + Clone + 'static,
 {
-  forwarder: Arc<Forwarder<C>>,
+  pub(super) forwarder: Arc<Forwarder<C>>,
   pub(super) globals: Arc<Globals>,
   app_manager: Arc<BackendAppManager>,
 }
@@ -46,50 +74,112 @@ impl<C> HttpMessageHandler<C>
 where
   C: Send + Sync + Connect + Clone + 'static,
 {
+  #[cfg(feature = "cache")]
+  /// Cache of responses used by the forwarder if enabled
+  pub(crate) fn cache(&self) -> Option<&RpxyCache> {
+    self.forwarder.cache()
+  }
+
   /// Handle incoming request message from a client.
   /// Responsible to passthrough responses from backend applications or generate synthetic error responses.
   pub async fn handle_request(
     &self,
-    req: Request<RequestBody>,
+    mut req: Request<RequestBody>,
     client_addr: SocketAddr, // For access control
     listen_addr: SocketAddr,
     tls_enabled: bool,
-    tls_server_name: Option<ServerName>,
+    tls_info: Option<TlsInfo>,
   ) -> RpxyResult<Response<ResponseBody>> {
+    // Assign request ID, which is forwarded to upstream and returned to the client
+    let request_id = assign_request_id(&mut req, &client_addr, &self.globals.proxy_config);
+    let request_id_header = &self.globals.proxy_config.request_id_header;
+
     // preparing log data
     let mut log_data = HttpMessageLog::from(&req);
-    log_data.client_addr(&client_addr);
+    log_data.client_addr(&client_addr).tls_info(&tls_info).request_id(&request_id);
+    // count the bytes of the request body for logging, as many bodies are streamed without their length
+    let bytes_in = Arc::new(AtomicU64::default());
+    let req = req.map(|body| RequestBody::Counted(Box::new(CountedBody::new(body, bytes_in.clone()))));
+
+    // Server span continuing the trace context of the incoming request if any
+    let trace_cx = start_server_span(&req);
 
-    let http_result = self
-      .handle_request_inner(&mut log_data, req, client_addr, listen_addr, tls_enabled, tls_server_name)
-      .await;
+    // Find backend application for given server_name first, to which synthetic error responses are also applied.
+    let tls_server_name = tls_info.map(|v| v.server_name);
+    let (backend_app, http_result) = match self.find_backend_app(&req, tls_enabled, tls_server_name) {
+      Ok(backend_app) => {
+        let res = self
+          .handle_request_inner(&mut log_data, req, backend_app, client_addr, listen_addr, tls_enabled)
+          .with_context(trace_cx.clone())
+          .await;
+        (Some(backend_app), res)
+      }
+      Err(e) => (None, Err(e)),
+    };
 
     // passthrough or synthetic response
-    match http_result {
-      Ok(v) => {
-        log_data.status_code(&v.status()).output();
-        Ok(v)
+    let res = match http_result {
+      Ok(mut v) => {
+        v.headers_mut().insert(request_id_header.clone(), request_id);
+        v
+      }
+      Err(HttpError::InjectedReset) => {
+        // no response is returned so that the connection or the stream is reset
+        info!("{}: {log_data}", HttpError::InjectedReset);
+        // the access log and the request metric are output here with status 0, since no response body is to end
+        let app_name = backend_app.map(|v| v.app_name.as_str()).unwrap_or_default();
+        log_data.bytes(bytes_in.load(Ordering::Relaxed), 0).output();
+        end_server_span(&trace_cx, app_name, &log_data);
+        self.globals.metrics.observe_request(
+          app_name,
+          &log_data.route,
+          None,
+          log_data.version,
+          log_data.started_at.elapsed(),
+        );
+        return Err(RpxyError::InjectedConnectionReset);
       }
       Err(e) => {
-        error!("{e}: {log_data}");
+        match e {
+          HttpError::InjectedAbort(_) => info!("{e}: {log_data}"),
+          _ => error!("{e}: {log_data}"),
+        }
         let code = StatusCode::from(e);
-        log_data.status_code(&code).output();
-        synthetic_error_response(code)
+        let mut res = synthetic_error_response(code)?;
+        res.headers_mut().insert(request_id_header.clone(), request_id);
+        if let Some(backend_app) = backend_app {
+          self.generate_response_synthetic(&mut res, backend_app, tls_enabled);
+        }
+        res
       }
-    }
+    };
+    log_data.status_code(&res.status());
+
+    // The access log, the server span and the request metrics are output when the response body ends or is dropped,
+    // so that they carry the actual bytes and duration of streamed bodies.
+    let app_name = backend_app.map(|v| v.app_name.clone()).unwrap_or_default();
+    let globals = self.globals.clone();
+    let on_end = move |bytes_out| {
+      log_data.bytes(bytes_in.load(Ordering::Relaxed), bytes_out).output();
+      end_server_span(&trace_cx, &app_name, &log_data);
+      globals.metrics.observe_request(
+        &app_name,
+        &log_data.route,
+        log_data.status,
+        log_data.version,
+        log_data.started_at.elapsed(),
+      );
+    };
+    Ok(res.map(|body| ResponseBody::Counted(Box::new(CountedBody::new(body, Arc::default()).on_end(on_end)))))
   }
 
-  /// Handle inner with no synthetic error response.
-  /// Synthetic response is generated by caller.
-  async fn handle_request_inner(
+  /// Find backend application for the given request, checking the consistency between TLS SNI and Host.
+  fn find_backend_app<B>(
     &self,
-    log_data: &mut HttpMessageLog,
-    mut req: Request<RequestBody>,
-    client_addr: SocketAddr, // For access control
-    listen_addr: SocketAddr,
+    req: &Request<B>,
     tls_enabled: bool,
     tls_server_name: Option<ServerName>,
-  ) -> HttpResult<Response<ResponseBody>> {
+  ) -> HttpResult<&BackendApp> {
     // Here we start to inspect and parse with server_name
     let server_name = req
       .inspect_parse_host()
@@ -104,28 +194,73 @@ where
       }
     }
     // Find backend application for given server_name, and drop if incoming request is invalid as request.
-    let backend_app = match self.app_manager.apps.get(&server_name) {
-      Some(backend_app) => backend_app,
+    match self.app_manager.apps.get(&server_name) {
+      Some(backend_app) => Ok(backend_app),
       None => {
         let Some(default_server_name) = &self.app_manager.default_server_name else {
           return Err(HttpError::NoMatchingBackendApp);
         };
         debug!("Serving by default app");
-        self.app_manager.apps.get(default_server_name).unwrap()
+        Ok(self.app_manager.apps.get(default_server_name).unwrap())
       }
-    };
+    }
+  }
 
+  /// Handle inner with no synthetic error response.
+  /// Synthetic response is generated by caller.
+  async fn handle_request_inner(
+    &self,
+    log_data: &mut HttpMessageLog,
+    mut req: Request<RequestBody>,
+    backend_app: &BackendApp,
+    client_addr: SocketAddr, // For access control
+    listen_addr: SocketAddr,
+    tls_enabled: bool,
+  ) -> HttpResult<Response<ResponseBody>> {
     // Redirect to https if !tls_enabled and redirect_to_https is true
     if !tls_enabled && backend_app.https_redirection.unwrap_or(false) {
       debug!(
         "Redirect to secure connection: {}",
         <&ServerName as TryInto<String>>::try_into(&backend_app.server_name).unwrap_or_default()
       );
-      return secure_redirection_response(
+      let mut res = secure_redirection_response(
         &backend_app.server_name,
         self.globals.proxy_config.https_redirection_port,
+        self.globals.proxy_config.https_redirection_status_code,
         &req,
-      );
+      )?;
+      self.generate_response_synthetic(&mut res, backend_app, tls_enabled);
+      return Ok(res);
+    }
+
+    // Static response rules and redirect rules are served without any upstream
+    let path = req.uri().path();
+    if let Some(rule) = backend_app
+      .static_response_rules
+      .iter()
+      .find(|rule| rule.matcher.matches(path).is_some())
+    {
+      debug!("Serve static response: {}", rule.status_code);
+      let request_id = req
+        .headers()
+        .get(&self.globals.proxy_config.request_id_header)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+      let mut res = static_response(rule, request_id)?;
+      self.generate_response_synthetic(&mut res, backend_app, tls_enabled);
+      return Ok(res);
+    }
+    let scheme = if tls_enabled { "https" } else { "http" };
+    let host: String = (&backend_app.server_name).try_into().unwrap_or_default();
+    if let Some((status_code, location)) = backend_app
+      .redirect_rules
+      .iter()
+      .find_map(|rule| rule.location(scheme, &host, req.uri()).map(|v| (rule.status_code, v)))
+    {
+      debug!("Redirect by rule: {status_code} {location}");
+      let mut res = redirection_response(status_code, &location)?;
+      self.generate_response_synthetic(&mut res, backend_app, tls_enabled);
+      return Ok(res);
     }
 
     // Find reverse proxy for given path and choose one of upstream host
@@ -134,6 +269,33 @@ where
     let Some(upstream_candidates) = backend_app.path_manager.get(path) else {
       return Err(HttpError::NoUpstreamCandidates);
     };
+    log_data.route(&upstream_candidates.path);
+
+    // Answer CORS preflight requests without reaching upstream if the route has CORS policy
+    let preflight = CorsPolicy::is_preflight(req.method(), req.headers());
+    if let Some(cors) = upstream_candidates.cors.as_ref().filter(|_| preflight) {
+      debug!("Answer CORS preflight request");
+      let mut res = preflight_response(cors.preflight_headers(req.headers()))?;
+      self.generate_response_synthetic(&mut res, backend_app, tls_enabled);
+      return Ok(res);
+    }
+    // Inject faults for resilience testing before forwarding
+    if !upstream_candidates.faults.is_empty() {
+      let fault = decide_faults(&upstream_candidates.faults, req.headers());
+      if !fault.delay.is_zero() {
+        debug!("Delay request by fault injection: {:?}", fault.delay);
+        tokio::time::sleep(fault.delay).await;
+      }
+      match fault.action {
+        Some(FaultAction::Abort(status_code)) => return Err(HttpError::InjectedAbort(status_code)),
+        Some(FaultAction::Reset) => return Err(HttpError::InjectedReset),
+        None => (),
+      }
+    }
+    // No destination has been discovered yet or the last discovery found no record
+    if upstream_candidates.members().inner.is_empty() {
+      return Err(HttpError::NoAvailableUpstream);
+    }
 
     // Upgrade in request header
     let upgrade_in_request = extract_upgrade(req.headers());
@@ -147,7 +309,7 @@ where
     let req_on_upgrade = hyper::upgrade::on(&mut req);
 
     // Build request from destination information
-    let _context = match self.generate_request_forwarded(
+    let context = match self.generate_request_forwarded(
       &client_addr,
       &listen_addr,
       &mut req,
@@ -169,29 +331,56 @@ where
     );
     log_data.xff(&req.headers().get("x-forwarded-for"));
     log_data.upstream(req.uri());
+    // Copy the request to the shadow upstream in background if mirrored, except for upgraded connections
+    if upgrade_in_request.is_none() {
+      self.mirror_request(&mut req, upstream_candidates);
+    }
     //////
 
     //////////////
     // Forward request to a chosen backend
-    let mut res_backend = match self.forwarder.request(req).await {
+    let upstream = match req.uri().scheme_str() {
+      Some(UNIX_SOCKET_SCHEME) => upstream_location(req.uri()),
+      _ => req.uri().authority().map(|v| v.to_string()).unwrap_or_default(),
+    };
+    let client_trace_cx = start_client_span(&mut req);
+    let upstream_started_at = Instant::now();
+    let mut res_backend = match self.forwarder.request(req).with_context(client_trace_cx.clone()).await {
       Ok(v) => v,
       Err(e) => {
-        return Err(HttpError::FailedToGetResponseFromBackend(e.to_string()));
+        end_client_span(&client_trace_cx, Err(&e));
+        self.globals.metrics.upstream_error(&backend_app.app_name, &upstream, &e);
+        return Err(match e {
+          RpxyError::UpstreamTimeout(_) => HttpError::TimeoutOfResponseFromBackend(e.to_string()),
+          _ => HttpError::FailedToGetResponseFromBackend(e.to_string()),
+        });
       }
     };
+    let upstream_time = upstream_started_at.elapsed();
+    log_data.upstream_time(upstream_time);
+    self
+      .globals
+      .metrics
+      .observe_upstream(&backend_app.app_name, &upstream, upstream_time);
+    #[cfg(feature = "cache")]
+    if let Some(cache_status) = res_backend.extensions().get::<CacheStatus>() {
+      log_data.cache_status(cache_status.as_str());
+    }
+    let cache_status = Some(log_data.cache_status.as_str()).filter(|v| !v.is_empty());
+    end_client_span(&client_trace_cx, Ok((res_backend.status(), cache_status)));
     //////////////
     // Process reverse proxy context generated during the forwarding request generation.
     #[cfg(feature = "sticky-cookie")]
-    if let Some(context_from_lb) = _context.context_lb {
+    if let Some(context_from_lb) = &context.context_lb {
       let res_headers = res_backend.headers_mut();
-      if let Err(e) = set_sticky_cookie_lb_context(res_headers, &context_from_lb) {
+      if let Err(e) = set_sticky_cookie_lb_context(res_headers, context_from_lb) {
         return Err(HttpError::FailedToAddSetCookeInResponse(e.to_string()));
       }
     }
 
     if res_backend.status() != StatusCode::SWITCHING_PROTOCOLS {
       // Generate response to client
-      if let Err(e) = self.generate_response_forwarded(&mut res_backend, backend_app) {
+      if let Err(e) = self.generate_response_forwarded(&mut res_backend, backend_app, &context, tls_enabled) {
         return Err(HttpError::FailedToGenerateDownstreamResponse(e.to_string()));
       }
       return Ok(res_backend);
@@ -218,6 +407,7 @@ where
     //   return Err(HttpError::NoUpgradeExtensionInResponse);
     // };
     let res_on_upgrade = hyper::upgrade::on(&mut res_backend);
+    let request_count = self.globals.request_count.clone();
 
     self.globals.runtime_handle.spawn(async move {
       let mut response_upgraded = TokioIo::new(res_on_upgrade.await.map_err(|e| {
@@ -228,12 +418,14 @@ where
         error!("Failed to upgrade request: {}", e);
         RpxyError::FailedToUpgradeRequest(e.to_string())
       })?);
-      copy_bidirectional(&mut response_upgraded, &mut request_upgraded)
-        .await
-        .map_err(|e| {
-          error!("Coping between upgraded connections failed: {}", e);
-          RpxyError::FailedToCopyBidirectional(e.to_string())
-        })?;
+      // upgraded connection is counted as active until it is closed, which is waited for at the graceful shutdown
+      request_count.increment();
+      let copied = copy_bidirectional(&mut response_upgraded, &mut request_upgraded).await;
+      request_count.decrement();
+      copied.map_err(|e| {
+        error!("Coping between upgraded connections failed: {}", e);
+        RpxyError::FailedToCopyBidirectional(e.to_string())
+      })?;
       Ok(()) as RpxyResult<()>
     });
 
@@ -1,7 +1,8 @@
 use super::{HttpMessageHandler, handler_main::HandlerContext, utils_headers::*, utils_request::update_request_line};
 use crate::{
-  backend::{BackendApp, UpstreamCandidates},
+  backend::{BackendApp, UpstreamCandidates, UpstreamOption},
   constants::RESPONSE_HEADER_SERVER,
+  forwarder::Allow0Rtt,
   log::*,
 };
 use anyhow::{Result, anyhow, ensure};
@@ -19,12 +20,28 @@ where
 
   #[allow(unused_variables)]
   /// Manipulate a response message sent from a backend application to forward downstream to a client.
-  pub(super) fn generate_response_forwarded<B>(&self, response: &mut Response<B>, backend_app: &BackendApp) -> Result<()> {
+  pub(super) fn generate_response_forwarded<B>(
+    &self,
+    response: &mut Response<B>,
+    backend_app: &BackendApp,
+    context: &HandlerContext,
+    tls_enabled: bool,
+  ) -> Result<()> {
     let headers = response.headers_mut();
     remove_connection_header(headers);
     remove_hop_header(headers);
     add_header_entry_overwrite_if_exist(headers, "server", RESPONSE_HEADER_SERVER)?;
 
+    // CORS headers of the route replacing ones given by upstream
+    if let Some(cors_headers) = &context.cors_headers {
+      cors_headers.apply(headers);
+    }
+
+    // Security response headers like HSTS
+    if let Some(security_headers) = &backend_app.security_headers {
+      security_headers.apply(headers, tls_enabled);
+    }
+
     #[cfg(any(feature = "http3-quinn", feature = "http3-s2n"))]
     {
       // Manipulate ALT_SVC allowing h3 in response message only when mutual TLS is not enabled
@@ -55,6 +72,14 @@ where
     Ok(())
   }
 
+  /// Manipulate a synthetic response message generated at rpxy, e.g., redirection, static and error responses, for a backend application
+  pub(super) fn generate_response_synthetic<B>(&self, response: &mut Response<B>, backend_app: &BackendApp, tls_enabled: bool) {
+    // Security response headers like HSTS
+    if let Some(security_headers) = &backend_app.security_headers {
+      security_headers.apply(response.headers_mut(), tls_enabled);
+    }
+  }
+
   #[allow(clippy::too_many_arguments)]
   /// Manipulate a request message sent from a client to forward upstream to a backend application
   pub(super) fn generate_request_forwarded<B>(
@@ -79,6 +104,12 @@ where
       }
     };
 
+    // Decide CORS headers of the response from the request headers before manipulation
+    let cors_headers = upstream_candidates
+      .cors
+      .as_ref()
+      .map(|cors| cors.response_headers(req.headers()));
+
     let original_uri = req.uri().to_string();
     let headers = req.headers_mut();
     // delete headers specified in header.connection
@@ -103,7 +134,8 @@ where
     // Fix unique upstream destination since there could be multiple ones.
     #[cfg(feature = "sticky-cookie")]
     let (upstream_chosen_opt, context_from_lb) = {
-      let context_to_lb = if let crate::backend::LoadBalance::StickyRoundRobin(lb) = &upstream_candidates.load_balance {
+      let members = upstream_candidates.members();
+      let context_to_lb = if let crate::backend::LoadBalance::StickyRoundRobin(lb) = &members.load_balance {
         takeout_sticky_cookie_lb_context(req.headers_mut(), &lb.sticky_config.name)?
       } else {
         None
@@ -119,13 +151,14 @@ where
       context_lb: context_from_lb,
       #[cfg(not(feature = "sticky-cookie"))]
       context_lb: None,
+      cors_headers,
     };
     /////////////////////////////////////////////
 
     // apply upstream-specific headers given in upstream_option
     let headers = req.headers_mut();
     // apply upstream options to header
-    apply_upstream_options_to_header(headers, &upstream_chosen.uri, upstream_candidates)?;
+    apply_upstream_options_to_header(headers, upstream_chosen.host_uri(), upstream_candidates)?;
 
     // update uri in request
     ensure!(
@@ -158,6 +191,15 @@ where
       None => org_pq,
     };
     *req.uri_mut() = new_uri.path_and_query(new_pq).build()?;
+    // protocol, TLS settings and timeouts to the upstream, which select the client in the forwarder
+    req.extensions_mut().insert(upstream_chosen.protocol);
+    if let Some(tls_config) = upstream_chosen.tls_config.as_ref() {
+      req.extensions_mut().insert(tls_config.clone());
+    }
+    req.extensions_mut().insert(upstream_candidates.timeouts);
+    if upstream_candidates.options.contains(&UpstreamOption::Allow0RttUpstream) {
+      req.extensions_mut().insert(Allow0Rtt);
+    }
 
     // upgrade
     if let Some(v) = upgrade {
@@ -168,7 +210,7 @@ where
     }
     if upgrade.is_none() {
       // can update request line i.e., http version, only if not upgrade (http 1.1)
-      update_request_line(req, upstream_chosen, upstream_candidates)?;
+      update_request_line(req, &upstream_chosen, upstream_candidates)?;
     }
 
     Ok(context)
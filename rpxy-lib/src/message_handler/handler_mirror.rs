@@ -0,0 +1,128 @@
+use super::{HttpMessageHandler, utils_headers::apply_upstream_options_to_header, utils_request::update_request_line};
+use crate::{
+  backend::{MirrorPolicy, UpstreamCandidates},
+  constants::MIRROR_MARKER_HEADER,
+  forwarder::upstream_location,
+  hyper_ext::body::{RequestBody, TeeBody},
+  log::*,
+};
+use anyhow::{Result, anyhow};
+use http::{HeaderValue, Request, Uri};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Body;
+use hyper_util::client::legacy::connect::Connect;
+use std::time::Instant;
+
+impl<C> HttpMessageHandler<C>
+where
+  C: Send + Sync + Connect + Clone + 'static,
+{
+  /// Mirror the request forwarded to the upstream if sampled by the mirroring policy of the route.
+  /// The mirrored request is sent in background after the whole body is forwarded to the upstream,
+  /// where the body of the request is replaced with one copying its data for the mirrored request.
+  pub(super) fn mirror_request(&self, req: &mut Request<RequestBody>, upstream_candidates: &UpstreamCandidates) {
+    let Some(mirror) = upstream_candidates.mirror.as_ref() else {
+      return;
+    };
+    if req.body().size_hint().lower() > mirror.max_body_size as u64 {
+      debug!("Request is not mirrored since the body exceeds the limit");
+      return;
+    }
+    let Some(permit) = mirror.sample() else {
+      return;
+    };
+    let mut mirror_req = match build_mirror_request(req, mirror, upstream_candidates) {
+      Ok(v) => v,
+      Err(e) => {
+        warn!("Failed to build mirrored request: {e}");
+        return;
+      }
+    };
+
+    let body_rx = if req.body().is_end_stream() {
+      None
+    } else {
+      let body = std::mem::replace(req.body_mut(), RequestBody::Full(Full::default()));
+      let (body, rx) = TeeBody::new(body, mirror.max_body_size);
+      *req.body_mut() = RequestBody::Tee(Box::new(body));
+      Some(rx)
+    };
+    let request_id = req
+      .headers()
+      .get(&self.globals.proxy_config.request_id_header)
+      .and_then(|v| v.to_str().ok())
+      .unwrap_or_default()
+      .to_owned();
+    let upstream = upstream_location(&mirror.upstream.uri);
+    let forwarder = self.forwarder.clone();
+
+    self.globals.runtime_handle.spawn(async move {
+      let _permit = permit;
+      if let Some(rx) = body_rx {
+        // the body is not copied if it exceeds the limit or is not forwarded to the end
+        let Ok(body) = rx.await else {
+          debug!("Request {request_id} is not mirrored since the body is not fully buffered");
+          return;
+        };
+        *mirror_req.body_mut() = RequestBody::Full(Full::new(body));
+      }
+      let started_at = Instant::now();
+      let res = forwarder.request_without_cache(mirror_req).await;
+      let duration_ms = started_at.elapsed().as_secs_f64() * 1000.0;
+      match res {
+        Ok(res) => {
+          let status = res.status().as_u16();
+          // responses are read to the end and discarded, keeping the connections reusable
+          if let Err(e) = res.into_body().collect().await {
+            debug!("Failed to read response of mirrored request {request_id}: {e}");
+          }
+          info!(
+            request_id,
+            upstream, status, duration_ms, "Mirrored request {request_id} to {upstream}: {status} in {duration_ms:.3}ms"
+          );
+        }
+        Err(e) => info!(
+          request_id,
+          upstream, duration_ms, "Mirrored request {request_id} to {upstream} failed in {duration_ms:.3}ms: {e}"
+        ),
+      }
+    });
+  }
+}
+
+/// Build the request to the shadow upstream from the request forwarded to the upstream, except for the body
+fn build_mirror_request(
+  req: &Request<RequestBody>,
+  mirror: &MirrorPolicy,
+  upstream_candidates: &UpstreamCandidates,
+) -> Result<Request<RequestBody>> {
+  let shadow = &mirror.upstream;
+  let (Some(scheme), Some(authority)) = (shadow.uri.scheme(), shadow.uri.authority()) else {
+    return Err(anyhow!("Shadow upstream uri `scheme` and `authority` is broken"));
+  };
+  let uri = Uri::builder()
+    .scheme(scheme.as_str())
+    .authority(authority.as_str())
+    .path_and_query(req.uri().path_and_query().map(|v| v.as_str()).unwrap_or("/"))
+    .build()?;
+
+  let mut mirror_req = Request::builder()
+    .method(req.method().clone())
+    .uri(uri)
+    .version(req.version())
+    .body(RequestBody::Full(Full::default()))?;
+  let headers = mirror_req.headers_mut();
+  *headers = req.headers().clone();
+  apply_upstream_options_to_header(headers, shadow.host_uri(), upstream_candidates)?;
+  headers.insert(MIRROR_MARKER_HEADER, HeaderValue::from_static("1"));
+
+  // protocol, TLS settings and timeouts to the shadow upstream, which select the client in the forwarder
+  mirror_req.extensions_mut().insert(shadow.protocol);
+  if let Some(tls_config) = shadow.tls_config.as_ref() {
+    mirror_req.extensions_mut().insert(tls_config.clone());
+  }
+  mirror_req.extensions_mut().insert(mirror.timeouts);
+  update_request_line(&mut mirror_req, shadow, upstream_candidates)?;
+
+  Ok(mirror_req)
+}
@@ -1,12 +1,18 @@
-use super::canonical_address::ToCanonical;
-use crate::log::*;
+use super::{canonical_address::ToCanonical, handler_main::TlsInfo};
+use crate::{forwarder::upstream_location, log::*, name_exp::PathName};
 use http::header;
-use std::net::SocketAddr;
+use hyper::body::Body;
+use std::{
+  net::SocketAddr,
+  time::{Duration, Instant},
+};
 
 /// Struct to log HTTP messages
 #[derive(Debug, Clone)]
 pub struct HttpMessageLog {
-  // pub tls_server_name: String,
+  pub tls_server_name: String,
+  pub tls_version: String,
+  pub tls_cipher: String,
   pub client_addr: String,
   pub method: String,
   pub host: String,
@@ -15,12 +21,27 @@ pub struct HttpMessageLog {
   pub uri_scheme: String,
   pub uri_host: String,
   pub ua: String,
+  pub referer: String,
   pub xff: String,
-  pub status: String,
+  pub status: Option<http::StatusCode>,
   pub upstream: String,
+  /// Matched route (path prefix) in the backend app, which is not logged but used for metrics
+  pub route: String,
+  pub request_id: String,
+  /// Bytes of the request and response bodies, counted until the response body ends
+  pub bytes_in: u64,
+  pub bytes_out: u64,
+  pub cache_status: String,
+  /// Time when the request is received, from which the request duration is measured
+  pub started_at: Instant,
+  /// Time elapsed until the response header is received from upstream
+  pub upstream_time: Option<Duration>,
 }
 
-impl<T> From<&http::Request<T>> for HttpMessageLog {
+impl<T> From<&http::Request<T>> for HttpMessageLog
+where
+  T: Body,
+{
   fn from(req: &http::Request<T>) -> Self {
     let header_mapper = |v: header::HeaderName| {
       req
@@ -30,7 +51,9 @@ impl<T> From<&http::Request<T>> for HttpMessageLog {
         .to_string()
     };
     Self {
-      // tls_server_name: "".to_string(),
+      tls_server_name: "".to_string(),
+      tls_version: "".to_string(),
+      tls_cipher: "".to_string(),
       client_addr: "".to_string(),
       method: req.method().to_string(),
       host: header_mapper(header::HOST),
@@ -39,9 +62,17 @@ impl<T> From<&http::Request<T>> for HttpMessageLog {
       uri_scheme: req.uri().scheme_str().unwrap_or("").to_string(),
       uri_host: req.uri().host().unwrap_or("").to_string(),
       ua: header_mapper(header::USER_AGENT),
+      referer: header_mapper(header::REFERER),
       xff: header_mapper(header::HeaderName::from_static("x-forwarded-for")),
-      status: "".to_string(),
+      status: None,
       upstream: "".to_string(),
+      route: "".to_string(),
+      request_id: "".to_string(),
+      bytes_in: 0,
+      bytes_out: 0,
+      cache_status: "".to_string(),
+      started_at: Instant::now(),
+      upstream_time: None,
     }
   }
 }
@@ -50,25 +81,18 @@ impl std::fmt::Display for HttpMessageLog {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     write!(
       f,
-      "{} <- {} -- {} {} {:?} -- {} -- {} \"{}\", \"{}\" \"{}\"",
-      if !self.host.is_empty() {
-        self.host.as_str()
-      } else {
-        self.uri_host.as_str()
-      },
+      "{} <- {} -- {} {} {:?} -- {} -- {} \"{}\", \"{}\" \"{}\" -- {}",
+      self.host_str(),
       self.client_addr,
       self.method,
       self.p_and_q,
       self.version,
-      self.status,
-      if !self.uri_scheme.is_empty() && !self.uri_host.is_empty() {
-        format!("{}://{}", self.uri_scheme, self.uri_host)
-      } else {
-        "".to_string()
-      },
+      self.status.map(|v| v.to_string()).unwrap_or_default(),
+      self.uri_str(),
       self.ua,
       self.xff,
-      self.upstream
+      self.upstream,
+      self.request_id
     )
   }
 }
@@ -78,12 +102,16 @@ impl HttpMessageLog {
     self.client_addr = client_addr.to_canonical().to_string();
     self
   }
-  // pub fn tls_server_name(&mut self, tls_server_name: &str) -> &mut Self {
-  //   self.tls_server_name = tls_server_name.to_string();
-  //   self
-  // }
+  pub fn tls_info(&mut self, tls_info: &Option<TlsInfo>) -> &mut Self {
+    if let Some(tls_info) = tls_info {
+      self.tls_server_name = (&tls_info.server_name).try_into().unwrap_or_default();
+      self.tls_version = tls_info.version.to_string();
+      self.tls_cipher = tls_info.cipher.to_string();
+    }
+    self
+  }
   pub fn status_code(&mut self, status_code: &http::StatusCode) -> &mut Self {
-    self.status = status_code.to_string();
+    self.status = Some(*status_code);
     self
   }
   pub fn xff(&mut self, xff: &Option<&header::HeaderValue>) -> &mut Self {
@@ -91,13 +119,73 @@ impl HttpMessageLog {
     self
   }
   pub fn upstream(&mut self, upstream: &http::Uri) -> &mut Self {
-    self.upstream = upstream.to_string();
+    self.upstream = upstream_location(upstream);
+    self
+  }
+  pub fn route(&mut self, route: &PathName) -> &mut Self {
+    self.route = route.try_into().unwrap_or_default();
     self
   }
+  pub fn request_id(&mut self, request_id: &header::HeaderValue) -> &mut Self {
+    self.request_id = request_id.to_str().unwrap_or("").to_string();
+    self
+  }
+  pub fn upstream_time(&mut self, upstream_time: Duration) -> &mut Self {
+    self.upstream_time = Some(upstream_time);
+    self
+  }
+  pub fn cache_status(&mut self, cache_status: &str) -> &mut Self {
+    self.cache_status = cache_status.to_string();
+    self
+  }
+  pub fn bytes(&mut self, bytes_in: u64, bytes_out: u64) -> &mut Self {
+    self.bytes_in = bytes_in;
+    self.bytes_out = bytes_out;
+    self
+  }
+
+  fn host_str(&self) -> &str {
+    if !self.host.is_empty() {
+      self.host.as_str()
+    } else {
+      self.uri_host.as_str()
+    }
+  }
+
+  fn uri_str(&self) -> String {
+    if !self.uri_scheme.is_empty() && !self.uri_host.is_empty() {
+      format!("{}://{}", self.uri_scheme, self.uri_host)
+    } else {
+      "".to_string()
+    }
+  }
 
+  /// Output the access log as an event with structured fields, which are formatted by the subscriber
   pub fn output(&self) {
+    let as_millis = |v: Duration| v.as_secs_f64() * 1000.0;
     info!(
       name: crate::constants::log_event_names::ACCESS_LOG,
+      host = self.host_str(),
+      client_addr = self.client_addr,
+      method = self.method,
+      path = self.p_and_q,
+      version = ?self.version,
+      uri = self.uri_str(),
+      // 0 if no response is returned, e.g., the connection is reset by fault injection
+      status = self.status.map_or(0, |v| v.as_u16()),
+      user_agent = self.ua,
+      referer = self.referer,
+      xff = self.xff,
+      upstream = self.upstream,
+      request_id = self.request_id,
+      duration_ms = as_millis(self.started_at.elapsed()),
+      upstream_time_ms = self.upstream_time.map(as_millis),
+      bytes_in = self.bytes_in,
+      bytes_out = self.bytes_out,
+      tls_version = self.tls_version,
+      tls_cipher = self.tls_cipher,
+      sni = self.tls_server_name,
+      cache = self.cache_status,
       "{}", self
     );
   }
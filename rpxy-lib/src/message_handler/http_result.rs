@@ -18,12 +18,18 @@ pub enum HttpError {
   NoMatchingBackendApp,
   #[error("Failed to redirect: {0}")]
   FailedToRedirect(String),
+  #[error("Failed to generate static response: {0}")]
+  FailedToGenerateStaticResponse(String),
   #[error("No upstream candidates")]
   NoUpstreamCandidates,
+  #[error("No available upstream destination")]
+  NoAvailableUpstream,
   #[error("Failed to generate upstream request for backend application: {0}")]
   FailedToGenerateUpstreamRequest(String),
   #[error("Failed to get response from backend: {0}")]
   FailedToGetResponseFromBackend(String),
+  #[error("Timed out waiting for response from backend: {0}")]
+  TimeoutOfResponseFromBackend(String),
 
   #[error("Failed to add set-cookie header in response {0}")]
   FailedToAddSetCookeInResponse(String),
@@ -32,6 +38,11 @@ pub enum HttpError {
 
   #[error("Failed to upgrade connection: {0}")]
   FailedToUpgrade(String),
+
+  #[error("Aborted by fault injection with {0}")]
+  InjectedAbort(StatusCode),
+  #[error("Reset by fault injection")]
+  InjectedReset,
   // #[error("Request does not have an upgrade extension")]
   // NoUpgradeExtensionInRequest,
   // #[error("Response does not have an upgrade extension")]
@@ -48,12 +59,16 @@ impl From<HttpError> for StatusCode {
       HttpError::SniHostInconsistency => StatusCode::MISDIRECTED_REQUEST,
       HttpError::NoMatchingBackendApp => StatusCode::SERVICE_UNAVAILABLE,
       HttpError::FailedToRedirect(_) => StatusCode::INTERNAL_SERVER_ERROR,
+      HttpError::FailedToGenerateStaticResponse(_) => StatusCode::INTERNAL_SERVER_ERROR,
       HttpError::NoUpstreamCandidates => StatusCode::NOT_FOUND,
+      HttpError::NoAvailableUpstream => StatusCode::SERVICE_UNAVAILABLE,
       HttpError::FailedToGenerateUpstreamRequest(_) => StatusCode::INTERNAL_SERVER_ERROR,
       HttpError::FailedToAddSetCookeInResponse(_) => StatusCode::INTERNAL_SERVER_ERROR,
       HttpError::FailedToGenerateDownstreamResponse(_) => StatusCode::INTERNAL_SERVER_ERROR,
       HttpError::FailedToUpgrade(_) => StatusCode::INTERNAL_SERVER_ERROR,
       HttpError::FailedToGetResponseFromBackend(_) => StatusCode::BAD_GATEWAY,
+      HttpError::TimeoutOfResponseFromBackend(_) => StatusCode::GATEWAY_TIMEOUT,
+      HttpError::InjectedAbort(code) => code,
       // HttpError::NoUpgradeExtensionInRequest => StatusCode::BAD_REQUEST,
       // HttpError::NoUpgradeExtensionInResponse => StatusCode::BAD_GATEWAY,
       _ => StatusCode::INTERNAL_SERVER_ERROR,
@@ -1,11 +1,14 @@
 mod canonical_address;
 mod handler_main;
 mod handler_manipulate_messages;
+mod handler_mirror;
 mod http_log;
 mod http_result;
+mod request_id;
 mod synthetic_response;
+mod trace_context;
 mod utils_headers;
 mod utils_request;
 
 pub use handler_main::HttpMessageHandlerBuilderError;
-pub(crate) use handler_main::{HttpMessageHandler, HttpMessageHandlerBuilder};
+pub(crate) use handler_main::{HttpMessageHandler, HttpMessageHandlerBuilder, TlsInfo};
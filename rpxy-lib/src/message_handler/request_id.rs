@@ -0,0 +1,90 @@
+use crate::globals::ProxyConfig;
+use http::{HeaderValue, Request};
+use rand::Rng;
+use std::{
+  net::SocketAddr,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Max length of the request ID trusted from an incoming request
+const MAX_INCOMING_REQUEST_ID_LEN: usize = 128;
+
+/// Generate a request ID in the form of UUIDv7, i.e., 48 bits of unix time in milliseconds followed by random bits.
+pub(super) fn generate_request_id() -> String {
+  let unix_ms = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|v| v.as_millis() as u64)
+    .unwrap_or_default();
+  let rand: u128 = rand::rng().random();
+  let mut v = ((unix_ms as u128) << 80) | (rand & 0xFFFF_FFFF_FFFF_FFFF_FFFF);
+  // version 7 and variant 0b10
+  v = (v & !(0xF << 76)) | (0x7 << 76);
+  v = (v & !(0x3 << 62)) | (0x2 << 62);
+  let hex = format!("{v:032x}");
+  format!(
+    "{}-{}-{}-{}-{}",
+    &hex[0..8],
+    &hex[8..12],
+    &hex[12..16],
+    &hex[16..20],
+    &hex[20..32]
+  )
+}
+
+/// Take the request ID from the request if given by a trusted source, otherwise generate a new one.
+/// The request ID is set to the request so that it is forwarded to upstream.
+pub(super) fn assign_request_id<B>(req: &mut Request<B>, client_addr: &SocketAddr, proxy_config: &ProxyConfig) -> HeaderValue {
+  let header_name = &proxy_config.request_id_header;
+  let trusted = proxy_config
+    .request_id_trusted_sources
+    .iter()
+    .any(|cidr| cidr.contains(&client_addr.ip()));
+  let incoming = req
+    .headers()
+    .get(header_name)
+    .filter(|_| trusted)
+    .filter(|v| !v.is_empty() && v.len() <= MAX_INCOMING_REQUEST_ID_LEN && v.to_str().is_ok())
+    .cloned();
+  let request_id = incoming.unwrap_or_else(|| HeaderValue::from_str(&generate_request_id()).unwrap());
+  req.headers_mut().insert(header_name.clone(), request_id.clone());
+  request_id
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ip_cidr::IpCidr;
+
+  #[test]
+  fn generated_request_id_is_uuid_v7() {
+    let id = generate_request_id();
+    assert_eq!(id.len(), 36);
+    assert_eq!(id.split('-').map(|v| v.len()).collect::<Vec<_>>(), vec![8, 4, 4, 4, 12]);
+    assert_eq!(&id[14..15], "7");
+    assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+    assert_ne!(id, generate_request_id());
+  }
+
+  #[test]
+  fn incoming_request_id_trusted_only_from_trusted_sources() {
+    let proxy_config = ProxyConfig {
+      request_id_trusted_sources: vec!["10.0.0.0/8".parse::<IpCidr>().unwrap()],
+      ..Default::default()
+    };
+    let request = || {
+      Request::builder()
+        .header(&proxy_config.request_id_header, "incoming-id")
+        .body(())
+        .unwrap()
+    };
+
+    let mut req = request();
+    let id = assign_request_id(&mut req, &"10.1.1.1:443".parse().unwrap(), &proxy_config);
+    assert_eq!(id, "incoming-id");
+
+    let mut req = request();
+    let id = assign_request_id(&mut req, &"192.168.1.1:443".parse().unwrap(), &proxy_config);
+    assert_ne!(id, "incoming-id");
+    assert_eq!(req.headers().get(&proxy_config.request_id_header).unwrap(), &id);
+  }
+}
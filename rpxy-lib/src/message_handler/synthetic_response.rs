@@ -1,10 +1,12 @@
 use super::http_result::{HttpError, HttpResult};
 use crate::{
+  backend::StaticResponseRule,
   error::*,
-  hyper_ext::body::{empty, ResponseBody},
+  hyper_ext::body::{empty, full, ResponseBody},
   name_exp::ServerName,
 };
-use http::{Request, Response, StatusCode, Uri};
+use bytes::Bytes;
+use http::{header, HeaderMap, Request, Response, StatusCode, Uri};
 
 /// build http response with status code of 4xx and 5xx
 pub(crate) fn synthetic_error_response(status_code: StatusCode) -> RpxyResult<Response<ResponseBody>> {
@@ -15,10 +17,11 @@ pub(crate) fn synthetic_error_response(status_code: StatusCode) -> RpxyResult<Re
   Ok(res)
 }
 
-/// Generate synthetic response message of a redirection to https host with 301
+/// Generate synthetic response message of a redirection to https host with 301 or the configured status code
 pub(super) fn secure_redirection_response<B>(
   server_name: &ServerName,
   tls_port: Option<u16>,
+  status_code: u16,
   req: &Request<B>,
 ) -> HttpResult<Response<ResponseBody>> {
   let server_name: String = server_name.try_into().unwrap_or_default();
@@ -33,10 +36,44 @@ pub(super) fn secure_redirection_response<B>(
   }
   .build()
   .map_err(|e| HttpError::FailedToRedirect(e.to_string()))?;
+  let status_code = StatusCode::from_u16(status_code).unwrap_or(StatusCode::MOVED_PERMANENTLY);
+  redirection_response(status_code, &dest_uri.to_string())
+}
+
+/// Generate synthetic response message of a redirection to the given location
+pub(super) fn redirection_response(status_code: StatusCode, location: &str) -> HttpResult<Response<ResponseBody>> {
   let response = Response::builder()
-    .status(StatusCode::MOVED_PERMANENTLY)
-    .header("Location", dest_uri.to_string())
+    .status(status_code)
+    .header(header::LOCATION, location)
     .body(ResponseBody::Boxed(empty()))
     .map_err(|e| HttpError::FailedToRedirect(e.to_string()))?;
   Ok(response)
 }
+
+/// Generate synthetic response message with a fixed status and body given by the static response rule,
+/// where the body is rendered with the request ID
+pub(super) fn static_response(rule: &StaticResponseRule, request_id: &str) -> HttpResult<Response<ResponseBody>> {
+  let mut builder = Response::builder().status(rule.status_code);
+  let body = match rule.render_body(request_id) {
+    Some(body) => {
+      let content_type = rule.content_type.as_deref().unwrap_or("text/plain; charset=utf-8");
+      builder = builder.header(header::CONTENT_TYPE, content_type);
+      full(Bytes::from(body))
+    }
+    None => empty(),
+  };
+  let response = builder
+    .body(ResponseBody::Boxed(body))
+    .map_err(|e| HttpError::FailedToGenerateStaticResponse(e.to_string()))?;
+  Ok(response)
+}
+
+/// Generate synthetic response message to a CORS preflight request with the given headers
+pub(super) fn preflight_response(headers: HeaderMap) -> HttpResult<Response<ResponseBody>> {
+  let mut response = Response::builder()
+    .status(StatusCode::NO_CONTENT)
+    .body(ResponseBody::Boxed(empty()))
+    .map_err(|e| HttpError::FailedToGenerateStaticResponse(e.to_string()))?;
+  *response.headers_mut() = headers;
+  Ok(response)
+}
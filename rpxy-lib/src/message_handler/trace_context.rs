@@ -0,0 +1,186 @@
+use super::http_log::HttpMessageLog;
+use crate::error::RpxyError;
+use http::{HeaderMap, HeaderName, HeaderValue, Request, StatusCode};
+use opentelemetry::{
+  Context, KeyValue, global,
+  propagation::{Extractor, Injector},
+  trace::{SpanKind, SpanRef, Status, TraceContextExt, Tracer},
+};
+
+/// Name of the tracer creating spans of rpxy
+const TRACER_NAME: &str = "rpxy";
+
+/// Extractor of trace context like `traceparent` and `tracestate` from request headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+  fn get(&self, key: &str) -> Option<&str> {
+    self.0.get(key).and_then(|v| v.to_str().ok())
+  }
+  fn keys(&self) -> Vec<&str> {
+    self.0.keys().map(|k| k.as_str()).collect()
+  }
+}
+
+/// Injector of trace context into request headers forwarded to upstream, where empty values like `tracestate` are not sent
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+  fn set(&mut self, key: &str, value: String) {
+    let Ok(name) = HeaderName::from_bytes(key.as_bytes()) else {
+      return;
+    };
+    match HeaderValue::from_str(&value) {
+      Ok(value) if !value.is_empty() => self.0.insert(name, value),
+      _ => self.0.remove(name),
+    };
+  }
+}
+
+/// Start a server span for the incoming request, continuing the trace given by `traceparent` and `tracestate` if any.
+/// Spans are recorded and exported only if the tracer provider and the propagator are globally installed by the caller of rpxy-lib.
+pub(super) fn start_server_span<B>(req: &Request<B>) -> Context {
+  let parent_cx = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(req.headers())));
+  let tracer = global::tracer(TRACER_NAME);
+  let span = tracer
+    .span_builder(req.method().to_string())
+    .with_kind(SpanKind::Server)
+    .with_attributes([
+      KeyValue::new("http.request.method", req.method().to_string()),
+      KeyValue::new("url.path", req.uri().path().to_string()),
+      KeyValue::new("network.protocol.version", format!("{:?}", req.version())),
+    ])
+    .start_with_context(&tracer, &parent_cx);
+  parent_cx.with_span(span)
+}
+
+/// End the server span with attributes of app, route, upstream, status and cache result
+pub(super) fn end_server_span(cx: &Context, app: &str, log_data: &HttpMessageLog) {
+  let span = cx.span();
+  if !log_data.route.is_empty() {
+    span.update_name(format!("{} {}", log_data.method, log_data.route));
+    span.set_attribute(KeyValue::new("http.route", log_data.route.clone()));
+  }
+  span.set_attribute(KeyValue::new("rpxy.app", app.to_string()));
+  if !log_data.upstream.is_empty() {
+    span.set_attribute(KeyValue::new("rpxy.upstream", log_data.upstream.clone()));
+  }
+  if !log_data.cache_status.is_empty() {
+    span.set_attribute(KeyValue::new("rpxy.cache", log_data.cache_status.clone()));
+  }
+  if !log_data.request_id.is_empty() {
+    span.set_attribute(KeyValue::new("rpxy.request_id", log_data.request_id.clone()));
+  }
+  if let Some(status) = log_data.status {
+    set_status(&span, status);
+  }
+  span.end();
+}
+
+/// Start a client span for the request forwarded to upstream as a child of the current server span,
+/// and inject its context into the request headers as `traceparent` and `tracestate`.
+pub(super) fn start_client_span<B>(req: &mut Request<B>) -> Context {
+  let cx = Context::current();
+  let tracer = global::tracer(TRACER_NAME);
+  let span = tracer
+    .span_builder(req.method().to_string())
+    .with_kind(SpanKind::Client)
+    .with_attributes([
+      KeyValue::new("http.request.method", req.method().to_string()),
+      KeyValue::new("url.full", req.uri().to_string()),
+      KeyValue::new(
+        "server.address",
+        req.uri().authority().map(|v| v.to_string()).unwrap_or_default(),
+      ),
+    ])
+    .start_with_context(&tracer, &cx);
+  let cx = cx.with_span(span);
+  global::get_text_map_propagator(|p| p.inject_context(&cx, &mut HeaderInjector(req.headers_mut())));
+  cx
+}
+
+/// End the client span with the response status and the cache result, or the error
+pub(super) fn end_client_span(cx: &Context, result: Result<(StatusCode, Option<&str>), &RpxyError>) {
+  let span = cx.span();
+  match result {
+    Ok((status, cache_status)) => {
+      if let Some(cache_status) = cache_status {
+        span.set_attribute(KeyValue::new("rpxy.cache", cache_status.to_string()));
+      }
+      set_status(&span, status);
+    }
+    Err(e) => span.set_status(Status::error(e.to_string())),
+  }
+  span.end();
+}
+
+/// Set the response status code, which makes the span status error if 5xx
+fn set_status(span: &SpanRef<'_>, status: StatusCode) {
+  span.set_attribute(KeyValue::new("http.response.status_code", status.as_u16() as i64));
+  if status.is_server_error() {
+    span.set_status(Status::error(status.to_string()));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opentelemetry::trace::FutureExt;
+  use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{InMemorySpanExporter, SdkTracerProvider},
+  };
+
+  #[tokio::test]
+  async fn trace_context_is_propagated() {
+    // In-memory exporter is used as a stand-in of OTLP collector
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+    global::set_tracer_provider(provider.clone());
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let incoming_trace_id = "0af7651916cd43dd8448eb211c80319c";
+    let incoming_span_id = "b7ad6b7169203331";
+    let req = Request::builder()
+      .uri("/api/v1")
+      .header("traceparent", format!("00-{incoming_trace_id}-{incoming_span_id}-01"))
+      .header("tracestate", "vendor=value")
+      .body(http_body_util::Empty::<bytes::Bytes>::new())
+      .unwrap();
+    let server_cx = start_server_span(&req);
+
+    let mut upstream_req = Request::builder().uri("http://backend:8080/api/v1").body(()).unwrap();
+    let client_cx = async { start_client_span(&mut upstream_req) }
+      .with_context(server_cx.clone())
+      .await;
+    end_client_span(&client_cx, Ok((StatusCode::OK, Some("miss"))));
+
+    let mut log_data = HttpMessageLog::from(&req);
+    log_data.route = "/api".to_string();
+    log_data.status = Some(StatusCode::OK);
+    end_server_span(&server_cx, "app1", &log_data);
+    provider.force_flush().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    assert_eq!(spans.len(), 2);
+    let (client, server) = (&spans[0], &spans[1]);
+    assert_eq!(server.span_kind, SpanKind::Server);
+    assert_eq!(server.name, "GET /api");
+    assert_eq!(server.span_context.trace_id().to_string(), incoming_trace_id);
+    assert_eq!(server.parent_span_id.to_string(), incoming_span_id);
+    assert_eq!(server.span_context.trace_state().header(), "vendor=value");
+    assert!(server.attributes.contains(&KeyValue::new("rpxy.app", "app1")));
+    assert!(server.attributes.contains(&KeyValue::new("http.route", "/api")));
+    assert_eq!(client.span_kind, SpanKind::Client);
+    assert_eq!(client.parent_span_id, server.span_context.span_id());
+    assert!(client.attributes.contains(&KeyValue::new("rpxy.cache", "miss")));
+
+    // Context of the client span is propagated to upstream
+    let traceparent = upstream_req.headers().get("traceparent").unwrap().to_str().unwrap();
+    assert_eq!(
+      traceparent,
+      format!("00-{incoming_trace_id}-{}-01", client.span_context.span_id())
+    );
+    assert_eq!(upstream_req.headers().get("tracestate").unwrap(), "vendor=value");
+  }
+}
@@ -1,6 +1,7 @@
 use super::canonical_address::ToCanonical;
 use crate::{
   backend::{UpstreamCandidates, UpstreamOption},
+  constants::UNIX_SOCKET_SCHEME,
   log::*,
 };
 use anyhow::{anyhow, ensure, Result};
@@ -81,6 +82,11 @@ pub(super) fn set_sticky_cookie_lb_context(headers: &mut HeaderMap, context_from
 
 /// overwrite HOST value with upstream hostname (like 192.168.xx.x seen from rpxy)
 fn override_host_header(headers: &mut HeaderMap, upstream_base_uri: &Uri) -> Result<()> {
+  // unix domain socket has no hostname, whose uri host is the encoded path
+  if upstream_base_uri.scheme_str() == Some(UNIX_SOCKET_SCHEME) {
+    headers.insert(header::HOST, HeaderValue::from_static("localhost"));
+    return Ok(());
+  }
   let mut upstream_host = upstream_base_uri
     .host()
     .ok_or_else(|| anyhow!("No hostname is given"))?
@@ -1,5 +1,6 @@
 use crate::{
   backend::{Upstream, UpstreamCandidates, UpstreamOption},
+  globals::UpstreamProtocol,
   log::*,
 };
 use anyhow::{anyhow, ensure, Result};
@@ -59,6 +60,23 @@ pub(super) fn update_request_line<B>(
   upstream_chosen: &Upstream,
   upstream_candidates: &UpstreamCandidates,
 ) -> anyhow::Result<()> {
+  // Version is fixed if the protocol is resolved to other than auto
+  match upstream_chosen.protocol {
+    UpstreamProtocol::Http1 => {
+      *req.version_mut() = Version::HTTP_11;
+      return Ok(());
+    }
+    UpstreamProtocol::H2 | UpstreamProtocol::H2c => {
+      *req.version_mut() = Version::HTTP_2;
+      return Ok(());
+    }
+    UpstreamProtocol::H3 => {
+      *req.version_mut() = Version::HTTP_3;
+      return Ok(());
+    }
+    UpstreamProtocol::Auto => (),
+  }
+
   // If request is grpc, HTTP/2 is required
   if req
     .headers()
@@ -78,7 +96,7 @@ pub(super) fn update_request_line<B>(
     *req.version_mut() = Version::HTTP_11;
   } else if req.version() == Version::HTTP_3 {
     // HTTP/3 is always https
-    debug!("HTTP/3 is currently unsupported for request to upstream.");
+    debug!("HTTP/3 to upstream requires h3 protocol or force_http3_upstream option.");
     *req.version_mut() = Version::HTTP_2;
   }
 
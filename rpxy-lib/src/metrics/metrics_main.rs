@@ -0,0 +1,430 @@
+use crate::error::RpxyError;
+use prometheus_client::{
+  encoding::{EncodeLabelSet, text::encode},
+  metrics::{
+    counter::Counter,
+    family::Family,
+    gauge::Gauge,
+    histogram::{Histogram, exponential_buckets},
+  },
+  registry::Registry,
+};
+use std::{sync::Arc, time::Duration};
+
+/* ---------------------------------------------- */
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+  app: String,
+  route: String,
+  status: u16,
+  protocol: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AppLabels {
+  app: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct UpstreamLabels {
+  app: String,
+  upstream: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct UpstreamErrorLabels {
+  app: String,
+  upstream: String,
+  kind: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TransportLabels {
+  transport: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct HandshakeFailureLabels {
+  transport: &'static str,
+  reason: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ReasonLabels {
+  reason: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TierLabels {
+  tier: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AcmeEventLabels {
+  domain: String,
+  event: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ResultLabels {
+  result: &'static str,
+}
+
+/// Latency histogram with buckets from 1ms to about 33s
+fn latency_histogram() -> Histogram {
+  Histogram::new(exponential_buckets(0.001, 2.0, 16))
+}
+
+/* ---------------------------------------------- */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Transport of connections from clients
+pub(crate) enum Transport {
+  /// Cleartext TCP
+  Tcp,
+  /// TLS over TCP
+  Tls,
+  /// QUIC
+  Quic,
+}
+
+impl Transport {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Transport::Tcp => "tcp",
+      Transport::Tls => "tls",
+      Transport::Quic => "quic",
+    }
+  }
+}
+
+/// Guard of an active connection, which decrements the gauge of active connections when dropped
+pub(crate) struct ConnectionGuard(Gauge);
+
+impl Drop for ConnectionGuard {
+  fn drop(&mut self) {
+    self.0.dec();
+  }
+}
+
+/* ---------------------------------------------- */
+/// Metrics of rpxy exposed in Prometheus text format.
+/// This is shared among proxy services and kept over the configuration reloads by cloning.
+#[derive(Clone)]
+pub struct Metrics {
+  inner: Arc<MetricsInner>,
+}
+
+struct MetricsInner {
+  registry: Registry,
+  requests: Family<RequestLabels, Counter>,
+  request_duration: Family<AppLabels, Histogram, fn() -> Histogram>,
+  upstream_duration: Family<UpstreamLabels, Histogram, fn() -> Histogram>,
+  upstream_errors: Family<UpstreamErrorLabels, Counter>,
+  active_connections: Family<TransportLabels, Gauge>,
+  tls_handshake_failures: Family<HandshakeFailureLabels, Counter>,
+  cache_hits: Counter,
+  cache_misses: Counter,
+  cache_evictions: Family<ReasonLabels, Counter>,
+  cache_stored_bytes: Counter,
+  cache_size_bytes: Family<TierLabels, Gauge>,
+  acme_events: Family<AcmeEventLabels, Counter>,
+  config_reloads: Family<ResultLabels, Counter>,
+}
+
+impl Default for Metrics {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl std::fmt::Debug for Metrics {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Metrics").finish_non_exhaustive()
+  }
+}
+
+impl Metrics {
+  /// Create metrics with all metric families registered
+  pub fn new() -> Self {
+    let mut inner = MetricsInner {
+      registry: Registry::with_prefix("rpxy"),
+      requests: Family::default(),
+      request_duration: Family::new_with_constructor(latency_histogram),
+      upstream_duration: Family::new_with_constructor(latency_histogram),
+      upstream_errors: Family::default(),
+      active_connections: Family::default(),
+      tls_handshake_failures: Family::default(),
+      cache_hits: Counter::default(),
+      cache_misses: Counter::default(),
+      cache_evictions: Family::default(),
+      cache_stored_bytes: Counter::default(),
+      cache_size_bytes: Family::default(),
+      acme_events: Family::default(),
+      config_reloads: Family::default(),
+    };
+    let registry = &mut inner.registry;
+    registry.register(
+      "requests",
+      "Number of requests by app, route, status and protocol",
+      inner.requests.clone(),
+    );
+    registry.register(
+      "request_duration_seconds",
+      "Time to respond to requests from clients",
+      inner.request_duration.clone(),
+    );
+    registry.register(
+      "upstream_duration_seconds",
+      "Time to receive response headers from upstreams",
+      inner.upstream_duration.clone(),
+    );
+    registry.register(
+      "upstream_errors",
+      "Number of failed requests to upstreams by kind",
+      inner.upstream_errors.clone(),
+    );
+    registry.register(
+      "active_connections",
+      "Number of active connections from clients by transport",
+      inner.active_connections.clone(),
+    );
+    registry.register(
+      "tls_handshake_failures",
+      "Number of failed TLS handshakes by reason",
+      inner.tls_handshake_failures.clone(),
+    );
+    registry.register("cache_hits", "Number of cache hits", inner.cache_hits.clone());
+    registry.register("cache_misses", "Number of cache misses", inner.cache_misses.clone());
+    registry.register(
+      "cache_evictions",
+      "Number of evicted cache entries by reason",
+      inner.cache_evictions.clone(),
+    );
+    registry.register(
+      "cache_stored_bytes",
+      "Bytes of response bodies stored in the cache",
+      inner.cache_stored_bytes.clone(),
+    );
+    registry.register(
+      "cache_size_bytes",
+      "Bytes of response bodies currently held in the cache by tier",
+      inner.cache_size_bytes.clone(),
+    );
+    registry.register(
+      "acme_events",
+      "Number of ACME certificate management events by domain",
+      inner.acme_events.clone(),
+    );
+    registry.register(
+      "config_reloads",
+      "Number of configuration reloads by result",
+      inner.config_reloads.clone(),
+    );
+    Self { inner: Arc::new(inner) }
+  }
+
+  /// Encode all metrics in Prometheus (OpenMetrics) text format
+  pub fn encode(&self) -> String {
+    let mut buf = String::new();
+    // Writing to String never fails
+    let _ = encode(&mut buf, &self.inner.registry);
+    buf
+  }
+
+  /// Record an ACME event for the domain, like "deployed_new_cert" or "error"
+  pub fn record_acme_event(&self, domain: &str, event: &str) {
+    let labels = AcmeEventLabels {
+      domain: domain.to_string(),
+      event: event.to_string(),
+    };
+    self.inner.acme_events.get_or_create(&labels).inc();
+  }
+
+  /// Record the result of a configuration reload
+  pub fn record_config_reload(&self, success: bool) {
+    let result = if success { "success" } else { "failure" };
+    self.inner.config_reloads.get_or_create(&ResultLabels { result }).inc();
+  }
+
+  /// Record a request served to a client, where the route is the matched path prefix if any.
+  /// The status is recorded as 0 if no response is returned, e.g., the connection is reset by fault injection.
+  pub(crate) fn observe_request(
+    &self,
+    app: &str,
+    route: &str,
+    status: Option<http::StatusCode>,
+    version: http::Version,
+    elapsed: Duration,
+  ) {
+    let protocol = match version {
+      http::Version::HTTP_09 => "HTTP/0.9",
+      http::Version::HTTP_10 => "HTTP/1.0",
+      http::Version::HTTP_11 => "HTTP/1.1",
+      http::Version::HTTP_2 => "HTTP/2",
+      http::Version::HTTP_3 => "HTTP/3",
+      _ => "unknown",
+    };
+    let labels = RequestLabels {
+      app: app.to_string(),
+      route: route.to_string(),
+      status: status.map_or(0, |v| v.as_u16()),
+      protocol,
+    };
+    self.inner.requests.get_or_create(&labels).inc();
+    let labels = AppLabels { app: app.to_string() };
+    self
+      .inner
+      .request_duration
+      .get_or_create(&labels)
+      .observe(elapsed.as_secs_f64());
+  }
+
+  /// Record the time to receive response headers from the upstream
+  pub(crate) fn observe_upstream(&self, app: &str, upstream: &str, elapsed: Duration) {
+    let labels = UpstreamLabels {
+      app: app.to_string(),
+      upstream: upstream.to_string(),
+    };
+    self
+      .inner
+      .upstream_duration
+      .get_or_create(&labels)
+      .observe(elapsed.as_secs_f64());
+  }
+
+  /// Record a failed request to the upstream
+  pub(crate) fn upstream_error(&self, app: &str, upstream: &str, error: &RpxyError) {
+    let kind = match error {
+      RpxyError::FailedToConnectUpstream(_) => "connect",
+      RpxyError::FailedToFetchFromUpstream(_) => "request",
+      RpxyError::UpstreamTimeout(_) => "timeout",
+      #[cfg(feature = "cache")]
+      RpxyError::CacheError(_) => "cache",
+      _ => "other",
+    };
+    let labels = UpstreamErrorLabels {
+      app: app.to_string(),
+      upstream: upstream.to_string(),
+      kind,
+    };
+    self.inner.upstream_errors.get_or_create(&labels).inc();
+  }
+
+  /// Count up active connections, which is counted down when the returned guard is dropped
+  pub(crate) fn connection_opened(&self, transport: Transport) -> ConnectionGuard {
+    let labels = TransportLabels {
+      transport: transport.as_str(),
+    };
+    let gauge = self.inner.active_connections.get_or_create(&labels).clone();
+    gauge.inc();
+    ConnectionGuard(gauge)
+  }
+
+  /// Get the number of active connections by transport
+  pub(crate) fn active_connections(&self) -> Vec<(&'static str, i64)> {
+    [Transport::Tcp, Transport::Tls, Transport::Quic]
+      .iter()
+      .map(|transport| {
+        let labels = TransportLabels {
+          transport: transport.as_str(),
+        };
+        (transport.as_str(), self.inner.active_connections.get_or_create(&labels).get())
+      })
+      .collect()
+  }
+
+  /// Record a failed TLS handshake, where `None` means timeout
+  pub(crate) fn tls_handshake_failed(&self, transport: Transport, error: Option<&RpxyError>) {
+    let reason = match error {
+      None => "timeout",
+      Some(RpxyError::NoServerNameInClientHello) => "no_sni",
+      Some(RpxyError::NoTlsServingApp(_)) => "no_serving_app",
+      #[cfg(feature = "acme")]
+      Some(RpxyError::NoAcmeServerConfig) => "no_acme_config",
+      Some(_) => "handshake_error",
+    };
+    let labels = HandshakeFailureLabels {
+      transport: transport.as_str(),
+      reason,
+    };
+    self.inner.tls_handshake_failures.get_or_create(&labels).inc();
+  }
+
+  #[cfg(feature = "cache")]
+  /// Record a cache hit
+  pub(crate) fn cache_hit(&self) {
+    self.inner.cache_hits.inc();
+  }
+
+  #[cfg(feature = "cache")]
+  /// Record a cache miss
+  pub(crate) fn cache_miss(&self) {
+    self.inner.cache_misses.inc();
+  }
+
+  #[cfg(feature = "cache")]
+  /// Get the numbers of cache hits and misses
+  pub(crate) fn cache_hits_misses(&self) -> (u64, u64) {
+    (self.inner.cache_hits.get(), self.inner.cache_misses.get())
+  }
+
+  #[cfg(feature = "cache")]
+  /// Record an eviction of a cache entry, like "capacity", "stale" or "corrupted"
+  pub(crate) fn cache_evicted(&self, reason: &'static str) {
+    self.inner.cache_evictions.get_or_create(&ReasonLabels { reason }).inc();
+  }
+
+  #[cfg(feature = "cache")]
+  /// Record bytes of a response body stored in the cache
+  pub(crate) fn cache_stored(&self, bytes: usize) {
+    self.inner.cache_stored_bytes.inc_by(bytes as u64);
+  }
+
+  #[cfg(feature = "cache")]
+  /// Set bytes of response bodies currently held in the cache on memory and in files
+  pub(crate) fn cache_size(&self, on_memory: usize, file: usize) {
+    for (tier, size) in [("on_memory", on_memory), ("file", file)] {
+      self
+        .inner
+        .cache_size_bytes
+        .get_or_create(&TierLabels { tier })
+        .set(size as i64);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encode_works() {
+    let metrics = Metrics::new();
+    metrics.observe_request(
+      "app1",
+      "/api",
+      Some(http::StatusCode::OK),
+      http::Version::HTTP_11,
+      Duration::from_millis(3),
+    );
+    metrics.observe_request("app1", "/api", None, http::Version::HTTP_2, Duration::from_millis(1));
+    metrics.upstream_error(
+      "app1",
+      "backend:8080",
+      &RpxyError::FailedToConnectUpstream("refused".to_string()),
+    );
+    metrics.record_config_reload(false);
+    {
+      let _guard = metrics.connection_opened(Transport::Tls);
+      assert!(metrics.encode().contains("rpxy_active_connections{transport=\"tls\"} 1"));
+    }
+    let text = metrics.encode();
+    assert!(text.contains("rpxy_active_connections{transport=\"tls\"} 0"));
+    assert!(text.contains("rpxy_requests_total{app=\"app1\",route=\"/api\",status=\"200\",protocol=\"HTTP/1.1\"} 1"));
+    assert!(text.contains("rpxy_requests_total{app=\"app1\",route=\"/api\",status=\"0\",protocol=\"HTTP/2\"} 1"));
+    assert!(text.contains("rpxy_request_duration_seconds_count{app=\"app1\"} 2"));
+    assert!(text.contains("rpxy_upstream_errors_total{app=\"app1\",upstream=\"backend:8080\",kind=\"connect\"} 1"));
+    assert!(text.contains("rpxy_config_reloads_total{result=\"failure\"} 1"));
+    assert!(text.contains("rpxy_cache_hits_total 0"));
+  }
+}
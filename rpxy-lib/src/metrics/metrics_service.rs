@@ -0,0 +1,67 @@
+use super::metrics_main::Metrics;
+use crate::{
+  constants::ACCEPT_ERROR_BACKOFF_MSEC,
+  error::*,
+  globals::MetricsConfig,
+  hyper_ext::{
+    body::{BoxBody, empty, full},
+    rt::LocalExecutor,
+  },
+  log::*,
+  proxy::bind_tcp_socket,
+};
+use bytes::Bytes;
+use http::{Method, Request, Response, StatusCode, header};
+use hyper::{body::Incoming, service::service_fn};
+use hyper_util::{rt::TokioIo, server::conn::auto::Builder as ConnectionBuilder};
+use std::{convert::Infallible, time::Duration};
+
+/// Content type of the OpenMetrics text format, which is also accepted by Prometheus
+const CONTENT_TYPE_OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Serve metrics in Prometheus text format on the separate listener given by the config.
+/// This is independent of proxy services, and hence kept running over the configuration reloads.
+pub async fn metrics_service(config: &MetricsConfig, metrics: Metrics, runtime_handle: tokio::runtime::Handle) -> RpxyResult<()> {
+  let tcp_socket = bind_tcp_socket(&config.listen_socket)?;
+  let tcp_listener = tcp_socket.listen(crate::constants::TCP_LISTEN_BACKLOG)?;
+  info!("Start metrics service on {}{}", config.listen_socket, config.path);
+
+  let connection_builder = ConnectionBuilder::new(LocalExecutor::new(runtime_handle.clone()));
+  loop {
+    let (stream, client_addr) = match tcp_listener.accept().await {
+      Ok(v) => v,
+      Err(e) => {
+        // errors like running out of file descriptors are transient, and the service keeps running
+        warn!("Failed to accept a connection to metrics service: {e}");
+        tokio::time::sleep(Duration::from_millis(ACCEPT_ERROR_BACKOFF_MSEC)).await;
+        continue;
+      }
+    };
+    let connection_builder = connection_builder.clone();
+    let metrics = metrics.clone();
+    let path = config.path.clone();
+    runtime_handle.spawn(async move {
+      let service = service_fn(move |req: Request<Incoming>| {
+        let res = metrics_response(&req, &path, &metrics);
+        async move { Ok::<_, Infallible>(res) }
+      });
+      if let Err(e) = connection_builder.serve_connection(TokioIo::new(stream), service).await {
+        debug!("Failed to serve metrics to {client_addr}: {e}");
+      }
+    });
+  }
+}
+
+/// Build response to the metrics request
+fn metrics_response<B>(req: &Request<B>, path: &str, metrics: &Metrics) -> Response<BoxBody> {
+  let (status, body) = match (req.method(), req.uri().path()) {
+    (&Method::GET, p) if p == path => (StatusCode::OK, full(Bytes::from(metrics.encode()))),
+    (_, p) if p == path => (StatusCode::METHOD_NOT_ALLOWED, empty()),
+    _ => (StatusCode::NOT_FOUND, empty()),
+  };
+  let mut builder = Response::builder().status(status);
+  if status == StatusCode::OK {
+    builder = builder.header(header::CONTENT_TYPE, CONTENT_TYPE_OPENMETRICS);
+  }
+  builder.body(body).unwrap()
+}
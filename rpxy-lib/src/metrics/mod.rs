@@ -0,0 +1,7 @@
+mod metrics_main;
+mod metrics_service;
+
+pub use metrics_main::Metrics;
+#[allow(unused)]
+pub(crate) use metrics_main::{ConnectionGuard, Transport};
+pub use metrics_service::metrics_service;
@@ -79,6 +79,17 @@ impl PathName {
   pub fn starts_with(&self, needle: &Self) -> bool {
     self.inner.starts_with(&needle.inner)
   }
+  /// Check if the path is the route itself or its sub-path, e.g., "/path" and "/path/sub" for route "/path"
+  pub fn is_under_route(&self, route: &Self) -> bool {
+    if !self.starts_with(route) {
+      return false;
+    }
+    route.len() == 1 // route = '/', i.e., default
+      || match self.get(route.len()) {
+        None => true, // exact case
+        Some(p) => p == &b'/', // sub-path case
+      }
+  }
 }
 
 /// Trait to express names in ascii-lowercased bytes
@@ -152,6 +163,16 @@ mod tests {
     assert!(!s.starts_with(&incorrect));
   }
 
+  #[test]
+  fn is_under_route_works() {
+    let route = "/path".to_path_name();
+    assert!("/path".to_path_name().is_under_route(&route));
+    assert!("/path/sub".to_path_name().is_under_route(&route));
+    assert!(!"/pathsub".to_path_name().is_under_route(&route));
+    assert!(!"/other".to_path_name().is_under_route(&route));
+    assert!("/any".to_path_name().is_under_route(&"/".to_path_name()));
+  }
+
   #[test]
   fn as_ref_works() {
     let s = "OK_str".to_path_name();
@@ -1,3 +1,4 @@
+mod proxy_context;
 mod proxy_main;
 mod socket;
 
@@ -20,7 +21,12 @@ use std::sync::Arc;
 /// SNI to ServerConfig map type
 pub type SniServerCryptoMap = std::collections::HashMap<ServerName, Arc<ServerConfig>, ahash::RandomState>;
 
-pub(crate) use proxy_main::Proxy;
+pub use proxy_context::ConfigUpdater;
+pub(crate) use {
+  proxy_context::{ProxyContext, ProxyContextSwap},
+  proxy_main::Proxy,
+  socket::bind_tcp_socket,
+};
 
 /// build connection builder shared with proxy instances
 pub(crate) fn connection_builder(globals: &Arc<Globals>) -> Arc<ConnectionBuilder<LocalExecutor>> {
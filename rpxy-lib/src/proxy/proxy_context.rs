@@ -0,0 +1,106 @@
+use crate::{RpxyOptions, error::*, globals::Globals, hyper_ext::rt::LocalExecutor, log::*, message_handler::HttpMessageHandler};
+use arc_swap::ArcSwap;
+use hyper_util::{client::legacy::connect::Connect, server::conn::auto::Builder as ConnectionBuilder};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// Context of proxy services built from a configuration.
+/// Each connection holds the context loaded at its acceptance, and hence it is served on the configuration it started with.
+pub(crate) struct ProxyContext<T>
+where
+  T: Send + Sync + Connect + Clone + 'static,
+{
+  /// global context of the configuration
+  pub globals: Arc<Globals>,
+  /// hyper connection builder serving http request
+  pub connection_builder: Arc<ConnectionBuilder<LocalExecutor>>,
+  /// message handler serving incoming http request
+  pub message_handler: Arc<HttpMessageHandler<T>>,
+}
+
+/// Context of proxy services swapped atomically at the configuration reload while listeners keep accepting,
+/// where listeners are notified of the swap to take the certificates of the new configuration.
+pub(crate) struct ProxyContextSwap<T>
+where
+  T: Send + Sync + Connect + Clone + 'static,
+{
+  current: ArcSwap<ProxyContext<T>>,
+  notifier: watch::Sender<()>,
+}
+
+impl<T> ProxyContextSwap<T>
+where
+  T: Send + Sync + Connect + Clone + 'static,
+{
+  pub(crate) fn new(context: ProxyContext<T>) -> Self {
+    Self {
+      current: ArcSwap::from_pointee(context),
+      notifier: watch::Sender::new(()),
+    }
+  }
+
+  /// Get the current context
+  pub(crate) fn load(&self) -> Arc<ProxyContext<T>> {
+    self.current.load_full()
+  }
+
+  /// Swap the current context with the new one, and notify listeners
+  pub(crate) fn store(&self, context: ProxyContext<T>) {
+    self.current.store(Arc::new(context));
+    self.notifier.send_replace(());
+  }
+
+  /// Subscribe the notification of the swap
+  pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+    self.notifier.subscribe()
+  }
+}
+
+/* ---------------------------------------------- */
+/// Request to update the configuration with the sender of its result
+pub(crate) type ConfigUpdateRequest = (RpxyOptions, oneshot::Sender<RpxyResult<()>>);
+
+/// Handle to update the configuration of the running proxy services in place, i.e., without re-binding listen sockets.
+/// This is kept over the configuration reloads by cloning, where the proxy service registers itself at every start.
+#[derive(Clone, Default)]
+pub struct ConfigUpdater {
+  inner: Arc<Mutex<Option<mpsc::UnboundedSender<ConfigUpdateRequest>>>>,
+}
+
+impl std::fmt::Debug for ConfigUpdater {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ConfigUpdater").finish_non_exhaustive()
+  }
+}
+
+impl ConfigUpdater {
+  /// Register the newly started proxy service, returning the receiver of update requests
+  pub(crate) fn register(&self) -> mpsc::UnboundedReceiver<ConfigUpdateRequest> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    if let Ok(mut lock) = self.inner.lock() {
+      *lock = Some(tx);
+    }
+    rx
+  }
+
+  /// Update the configuration of the running proxy services.
+  /// Routes, handler options and certificates are swapped atomically while in-flight connections are kept on the previous ones.
+  /// This fails if listen sockets need to be re-bound (see [`crate::ProxyConfig::requires_rebind`]),
+  /// or the new configuration is invalid, where the previous one is kept in use.
+  pub async fn update(&self, opts: RpxyOptions) -> RpxyResult<()> {
+    let (res_tx, res_rx) = oneshot::channel();
+    let sent = self
+      .inner
+      .lock()
+      .ok()
+      .and_then(|lock| lock.as_ref().map(|tx| tx.send((opts, res_tx)).is_ok()));
+    if sent != Some(true) {
+      return Err(RpxyError::NoRunningProxyService);
+    }
+    let res = res_rx.await.map_err(|_| RpxyError::NoRunningProxyService)?;
+    if res.is_ok() {
+      info!("Configuration is updated without re-binding listen sockets");
+    }
+    res
+  }
+}
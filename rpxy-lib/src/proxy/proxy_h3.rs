@@ -1,15 +1,17 @@
-use super::proxy_main::Proxy;
+use super::{ProxyContext, proxy_main::Proxy};
 use crate::{
   error::*,
   hyper_ext::body::{IncomingLike, RequestBody},
   log::*,
+  message_handler::TlsInfo,
+  metrics::Transport,
   name_exp::ServerName,
 };
 use bytes::{Buf, Bytes};
 use http::{Request, Response};
 use http_body_util::BodyExt;
 use hyper_util::client::legacy::connect::Connect;
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
 #[cfg(feature = "http3-quinn")]
 use h3::{quic::BidiStream, quic::Connection as ConnectionQuic, quic::OpenStreams, server::RequestStream};
@@ -22,6 +24,7 @@ where
 {
   pub(super) async fn h3_serve_connection<C>(
     &self,
+    context: Arc<ProxyContext<T>>,
     quic_connection: C,
     tls_server_name: ServerName,
     client_addr: SocketAddr,
@@ -32,6 +35,7 @@ where
     <<C as OpenStreams<Bytes>>::BidiStream as BidiStream<Bytes>>::RecvStream: Send,
     <<C as OpenStreams<Bytes>>::BidiStream as BidiStream<Bytes>>::SendStream: Send,
   {
+    let _connection_guard = self.globals.metrics.connection_opened(Transport::Quic);
     let mut h3_conn = h3::server::Connection::<_, Bytes>::new(quic_connection).await?;
     debug!(
       "QUIC/HTTP3 connection established from {:?} {}",
@@ -41,9 +45,21 @@ where
 
     // TODO: Is here enough to fetch server_name from NewConnection?
     // to avoid deep nested call from listener_service_h3
+    let mut shutting_down = false;
     loop {
+      let accepted = tokio::select! {
+        accepted = h3_conn.accept() => Some(accepted),
+        _ = self.shutdown_token.cancelled(), if !shutting_down => None,
+      };
+      let Some(accepted) = accepted else {
+        // send GOAWAY, then serve in-flight requests until the connection is closed
+        debug!("HTTP/3 connection is gracefully shutting down");
+        shutting_down = true;
+        h3_conn.shutdown(0).await?;
+        continue;
+      };
       // this routine follows hyperium/h3 examples https://github.com/hyperium/h3/blob/master/examples/server.rs
-      match h3_conn.accept().await {
+      match accepted {
         Ok(None) => {
           break;
         }
@@ -57,8 +73,8 @@ where
         Ok(Some((req, stream))) => {
           // We consider the connection count separately from the stream count.
           // Max clients for h1/h2 = max 'stream' for h3.
-          let request_count = self.globals.request_count.clone();
-          if request_count.increment() > self.globals.proxy_config.max_clients {
+          let request_count = context.globals.request_count.clone();
+          if request_count.increment() > context.globals.proxy_config.max_clients {
             request_count.decrement();
             h3_conn.shutdown(0).await?;
             break;
@@ -66,10 +82,12 @@ where
           debug!("Request incoming: current # {}", request_count.current());
 
           let self_inner = self.clone();
+          let context_inner = context.clone();
           let tls_server_name_inner = tls_server_name.clone();
           self.globals.runtime_handle.spawn(async move {
-            let fut = self_inner.h3_serve_stream(req, stream, client_addr, tls_server_name_inner);
-            if let Some(connection_handling_timeout) = self_inner.globals.proxy_config.connection_handling_timeout {
+            let connection_handling_timeout = context_inner.globals.proxy_config.connection_handling_timeout;
+            let fut = self_inner.h3_serve_stream(&context_inner, req, stream, client_addr, tls_server_name_inner);
+            if let Some(connection_handling_timeout) = connection_handling_timeout {
               if let Err(e) = tokio::time::timeout(connection_handling_timeout, fut).await {
                 warn!("HTTP/3 error on serve stream: {}", e);
               };
@@ -92,6 +110,7 @@ where
   /// Either<Incoming, IncomingLike> as body.
   async fn h3_serve_stream<S>(
     &self,
+    context: &ProxyContext<T>,
     req: Request<()>,
     stream: RequestStream<S, Bytes>,
     client_addr: SocketAddr,
@@ -110,7 +129,7 @@ where
 
     // Buffering and sending body through channel for protocol conversion like h3 -> h2/http1.1
     // The underling buffering, i.e., buffer given by the API recv_data.await?, is handled by quinn.
-    let max_body_size = self.globals.proxy_config.h3_request_max_body_size;
+    let max_body_size = context.globals.proxy_config.h3_request_max_body_size;
     self.globals.runtime_handle.spawn(async move {
       let mut sender = body_sender;
       let mut size = 0usize;
@@ -138,14 +157,19 @@ where
     });
 
     let new_req: Request<RequestBody> = Request::from_parts(req_parts, RequestBody::IncomingLike(req_body));
-    let res = self
+    let res = context
       .message_handler
       .handle_request(
         new_req,
         client_addr,
         self.listening_on,
         self.tls_enabled,
-        Some(tls_server_name),
+        Some(TlsInfo {
+          server_name: tls_server_name,
+          // QUIC always uses TLS 1.3, and the negotiated cipher suite is not exposed here
+          version: "TLSv1_3",
+          cipher: "",
+        }),
       )
       .await?;
 
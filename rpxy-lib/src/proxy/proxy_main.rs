@@ -1,25 +1,24 @@
-use super::socket::bind_tcp_socket;
+use super::{ProxyContext, ProxyContextSwap, socket::bind_tcp_socket};
 use crate::{
   constants::TLS_HANDSHAKE_TIMEOUT_SEC,
   error::*,
   globals::Globals,
-  hyper_ext::{
-    body::{RequestBody, ResponseBody},
-    rt::LocalExecutor,
-  },
+  hyper_ext::body::{RequestBody, ResponseBody},
   log::*,
-  message_handler::HttpMessageHandler,
+  message_handler::{HttpMessageHandler, TlsInfo},
+  metrics::Transport,
   name_exp::ServerName,
 };
 use futures::{select, FutureExt};
+use hot_reload::ReloaderReceiver;
 use http::{Request, Response};
 use hyper::{
   body::Incoming,
   rt::{Read, Write},
   service::service_fn,
 };
-use hyper_util::{client::legacy::connect::Connect, rt::TokioIo, server::conn::auto::Builder as ConnectionBuilder};
-use rpxy_certs::ServerCrypto;
+use hyper_util::{client::legacy::connect::Connect, rt::TokioIo};
+use rpxy_certs::{ServerCrypto, ServerCryptoBase};
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
@@ -32,7 +31,7 @@ async fn serve_request<T>(
   client_addr: SocketAddr,
   listen_addr: SocketAddr,
   tls_enabled: bool,
-  tls_server_name: Option<ServerName>,
+  tls_info: Option<TlsInfo>,
 ) -> RpxyResult<Response<ResponseBody>>
 where
   T: Send + Sync + Connect + Clone,
@@ -43,53 +42,68 @@ where
       client_addr,
       listen_addr,
       tls_enabled,
-      tls_server_name,
+      tls_info,
     )
     .await
 }
 
+/// Build the map of SNI to server config from the certificates given by the reloader
+fn sni_server_crypto_map(server_crypto_rx: &ReloaderReceiver<ServerCryptoBase>) -> Option<Arc<super::SniServerCryptoMap>> {
+  let server_crypto_base = server_crypto_rx.borrow().clone()?;
+  let server_config: Arc<ServerCrypto> = (&server_crypto_base).try_into().ok()?;
+  let map = server_config
+    .individual_config_map
+    .iter()
+    .map(|(k, v)| (ServerName::from(k.as_slice()), v.clone()))
+    .collect::<std::collections::HashMap<_, _, ahash::RandomState>>();
+  Some(Arc::new(map))
+}
+
 #[derive(Clone)]
 /// Proxy main object responsible to serve requests received from clients at the given socket address.
-pub(crate) struct Proxy<T, E = LocalExecutor>
+pub(crate) struct Proxy<T>
 where
   T: Send + Sync + Connect + Clone + 'static,
 {
-  /// global context shared among async tasks
+  /// global context at the start of the listener, giving parameters kept until the socket is re-bound
   pub globals: Arc<Globals>,
   /// listen socket address
   pub listening_on: SocketAddr,
   /// whether TLS is enabled or not
   pub tls_enabled: bool,
-  /// hyper connection builder serving http request
-  pub connection_builder: Arc<ConnectionBuilder<E>>,
-  /// message handler serving incoming http request
-  pub message_handler: Arc<HttpMessageHandler<T>>,
+  /// context of the current configuration, swapped at the configuration reload
+  pub context: Arc<ProxyContextSwap<T>>,
+  /// token notifying the shutdown, where established connections are gracefully closed after in-flight requests
+  pub shutdown_token: CancellationToken,
 }
 
 impl<T> Proxy<T>
 where
   T: Send + Sync + Connect + Clone + 'static,
 {
-  /// Serves requests from clients
-  fn serve_connection<I>(&self, stream: I, peer_addr: SocketAddr, tls_server_name: Option<ServerName>)
+  /// Serves requests from clients on the given context
+  fn serve_connection<I>(&self, context: Arc<ProxyContext<T>>, stream: I, peer_addr: SocketAddr, tls_info: Option<TlsInfo>)
   where
     I: Read + Write + Send + Unpin + 'static,
   {
-    let request_count = self.globals.request_count.clone();
-    if request_count.increment() > self.globals.proxy_config.max_clients {
+    let request_count = context.globals.request_count.clone();
+    if request_count.increment() > context.globals.proxy_config.max_clients {
       request_count.decrement();
       return;
     }
     debug!("Request incoming: current # {}", request_count.current());
+    let transport = if self.tls_enabled { Transport::Tls } else { Transport::Tcp };
+    let connection_guard = self.globals.metrics.connection_opened(transport);
 
-    let server_clone = self.connection_builder.clone();
-    let message_handler_clone = self.message_handler.clone();
+    let server_clone = context.connection_builder.clone();
+    let message_handler_clone = context.message_handler.clone();
     let tls_enabled = self.tls_enabled;
     let listening_on = self.listening_on;
-    let handling_timeout = self.globals.proxy_config.connection_handling_timeout;
+    let handling_timeout = context.globals.proxy_config.connection_handling_timeout;
+    let shutdown_token = self.shutdown_token.clone();
 
     self.globals.runtime_handle.clone().spawn(async move {
-      let fut = server_clone.serve_connection_with_upgrades(
+      let connection = server_clone.serve_connection_with_upgrades(
         stream,
         service_fn(move |req: Request<Incoming>| {
           serve_request(
@@ -98,10 +112,21 @@ where
             peer_addr,
             listening_on,
             tls_enabled,
-            tls_server_name.clone(),
+            tls_info.clone(),
           )
         }),
       );
+      let fut = async move {
+        let mut connection = std::pin::pin!(connection);
+        tokio::select! {
+          res = connection.as_mut() => res,
+          _ = shutdown_token.cancelled() => {
+            // close idle keep-alive connections for http/1.1 and send GOAWAY for http/2, then wait for in-flight requests
+            connection.as_mut().graceful_shutdown();
+            connection.await
+          }
+        }
+      };
 
       if let Some(handling_timeout) = handling_timeout {
         timeout(handling_timeout, fut).await.ok();
@@ -109,6 +134,7 @@ where
         fut.await.ok();
       }
 
+      drop(connection_guard);
       request_count.decrement();
       debug!("Request processed: current # {}", request_count.current());
     });
@@ -121,7 +147,7 @@ where
       let tcp_listener = tcp_socket.listen(self.globals.proxy_config.tcp_listen_backlog)?;
       info!("Start TCP proxy serving with HTTP request for configured host names");
       while let Ok((stream, client_addr)) = tcp_listener.accept().await {
-        self.serve_connection(TokioIo::new(stream), client_addr, None);
+        self.serve_connection(self.context.load(), TokioIo::new(stream), client_addr, None);
       }
       Ok(()) as RpxyResult<()>
     };
@@ -182,7 +208,8 @@ where
 
   // TCP Listener Service, i.e., http/2 and http/1.1
   async fn tls_listener_service(&self) -> RpxyResult<()> {
-    let Some(mut server_crypto_rx) = self.globals.cert_reloader_rx.clone() else {
+    let mut context_rx = self.context.subscribe();
+    let Some(mut server_crypto_rx) = self.context.load().globals.cert_reloader_rx.clone() else {
       return Err(RpxyError::NoCertificateReloader);
     };
     let tcp_socket = bind_tcp_socket(&self.listening_on)?;
@@ -191,9 +218,6 @@ where
 
     let mut server_crypto_map: Option<Arc<super::SniServerCryptoMap>> = None;
     loop {
-      #[cfg(feature = "acme")]
-      let server_configs_acme_challenge = self.globals.server_configs_acme_challenge.clone();
-
       select! {
         tcp_cnx = tcp_listener.accept().fuse() => {
           if tcp_cnx.is_err() || server_crypto_map.is_none() {
@@ -202,6 +226,9 @@ where
           let (raw_stream, client_addr) = tcp_cnx.unwrap();
           let sc_map_inner = server_crypto_map.clone();
           let self_inner = self.clone();
+          let context = self.context.load();
+          #[cfg(feature = "acme")]
+          let server_configs_acme_challenge = context.globals.server_configs_acme_challenge.clone();
 
           // spawns async handshake to avoid blocking thread by sequential handshake.
           let handshake_fut = async move {
@@ -254,13 +281,19 @@ where
                 return Err(RpxyError::FailedToTlsHandshake(e.to_string()));
               }
             };
+            let (_, tls_conn) = stream.inner().get_ref();
+            let tls_info = server_name.map(|server_name| TlsInfo {
+              server_name,
+              version: tls_conn.protocol_version().and_then(|v| v.as_str()).unwrap_or_default(),
+              cipher: tls_conn.negotiated_cipher_suite().and_then(|v| v.suite().as_str()).unwrap_or_default(),
+            });
             #[cfg(feature = "acme")]
             {
-              Ok((stream, client_addr, server_name, is_handshake_acme))
+              Ok((stream, client_addr, tls_info, is_handshake_acme))
             }
             #[cfg(not(feature="acme"))]
             {
-              Ok((stream, client_addr, server_name))
+              Ok((stream, client_addr, tls_info))
             }
           };
 
@@ -271,22 +304,24 @@ where
               handshake_fut
             ).await else {
               error!("Timeout to handshake TLS");
+              self_inner.globals.metrics.tls_handshake_failed(Transport::Tls, None);
               return;
             };
             /* ------------------ */
             #[cfg(feature = "acme")]
             {
               match v {
-                Ok((mut stream, client_addr, server_name, is_handshake_acme)) => {
+                Ok((mut stream, client_addr, tls_info, is_handshake_acme)) => {
                   if is_handshake_acme {
                     debug!("Shutdown TLS connection after ACME TLS ALPN challenge");
                     use tokio::io::AsyncWriteExt;
                     stream.inner_mut().shutdown().await.ok();
                   }
-                  self_inner.serve_connection(stream, client_addr, server_name);
+                  self_inner.serve_connection(context, stream, client_addr, tls_info);
                 }
                 Err(e) => {
                   error!("{}", e);
+                  self_inner.globals.metrics.tls_handshake_failed(Transport::Tls, Some(&e));
                 }
               }
             }
@@ -294,32 +329,43 @@ where
             #[cfg(not(feature = "acme"))]
             {
               match v {
-                Ok((stream, client_addr, server_name)) => {
-                  self_inner.serve_connection(stream, client_addr, server_name);
+                Ok((stream, client_addr, tls_info)) => {
+                  self_inner.serve_connection(context, stream, client_addr, tls_info);
                 }
                 Err(e) => {
                   error!("{}", e);
+                  self_inner.globals.metrics.tls_handshake_failed(Transport::Tls, Some(&e));
                 }
               }
             }
             /* ------------------ */
           });
         }
-        _ = server_crypto_rx.changed().fuse() => {
+        res = server_crypto_rx.changed().fuse() => {
+          if res.is_err() && context_rx.has_changed().unwrap_or_default() {
+            // the reloader of the previous configuration is closed, then that of the new one is taken
+            continue;
+          }
           if server_crypto_rx.borrow().is_none() {
             error!("Reloader is broken");
             break;
           }
-          let server_crypto_base = server_crypto_rx.borrow().clone().unwrap();
-          let Some(server_config): Option<Arc<ServerCrypto>> = (&server_crypto_base).try_into().ok() else {
+          let Some(map) = sni_server_crypto_map(&server_crypto_rx) else {
             error!("Failed to update server crypto");
             break;
           };
-          let map = server_config.individual_config_map.clone().iter().map(|(k,v)| {
-            let server_name = ServerName::from(k.as_slice());
-            (server_name, v.clone())
-          }).collect::<std::collections::HashMap<_,_,ahash::RandomState>>();
-          server_crypto_map = Some(Arc::new(map));
+          server_crypto_map = Some(map);
+        }
+        _ = context_rx.changed().fuse() => {
+          let Some(new_server_crypto_rx) = self.context.load().globals.cert_reloader_rx.clone() else {
+            error!("No certificate reloader in the updated configuration");
+            break;
+          };
+          server_crypto_rx = new_server_crypto_rx;
+          // certificates already loaded by the reloader of the new configuration are applied immediately
+          if let Some(map) = sni_server_crypto_map(&server_crypto_rx) {
+            server_crypto_map = Some(map);
+          }
         }
       }
     }
@@ -1,20 +1,36 @@
 use super::{proxy_main::Proxy, socket::bind_udp_socket};
-use crate::{error::*, log::*, name_exp::ByteName};
+use crate::{error::*, log::*, metrics::Transport, name_exp::ByteName};
+use hot_reload::ReloaderReceiver;
 use hyper_util::client::legacy::connect::Connect;
 use quinn::{
   Endpoint, TransportConfig,
   crypto::rustls::{HandshakeData, QuicServerConfig},
 };
-use rpxy_certs::ServerCrypto;
+use rpxy_certs::{ServerCrypto, ServerCryptoBase};
 use rustls::ServerConfig;
 use std::sync::Arc;
 
+/// Build the server config of quinn from the certificates given by the reloader
+fn quinn_server_config(
+  server_crypto_rx: &ReloaderReceiver<ServerCryptoBase>,
+) -> Option<(Arc<ServerCrypto>, quinn::ServerConfig)> {
+  let cert_keys_map = server_crypto_rx.borrow().clone()?;
+  let server_crypto: Arc<ServerCrypto> = (&cert_keys_map).try_into().ok()?;
+  let rustls_server_config = server_crypto.aggregated_config_no_client_auth.clone();
+  let quinn_server_config_crypto = QuicServerConfig::try_from(rustls_server_config).ok()?;
+  Some((
+    server_crypto,
+    quinn::ServerConfig::with_crypto(Arc::new(quinn_server_config_crypto)),
+  ))
+}
+
 impl<T> Proxy<T>
 where
   T: Send + Sync + Connect + Clone + 'static,
 {
   pub(super) async fn h3_listener_service(&self) -> RpxyResult<()> {
-    let Some(mut server_crypto_rx) = self.globals.cert_reloader_rx.clone() else {
+    let mut context_rx = self.context.subscribe();
+    let Some(mut server_crypto_rx) = self.context.load().globals.cert_reloader_rx.clone() else {
       return Err(RpxyError::NoCertificateReloader);
     };
     info!("Start UDP proxy serving with HTTP/3 request for configured host names [quinn]");
@@ -69,6 +85,7 @@ where
           };
           let Some(new_server_name) = hsd_downcast.server_name else {
             warn!("HTTP/3 no SNI is given");
+            self.globals.metrics.tls_handshake_failed(Transport::Quic, Some(&RpxyError::NoServerNameInClientHello));
             continue;
           };
           debug!(
@@ -78,6 +95,7 @@ where
           // TODO: server_nameをここで出してどんどん深く投げていくのは効率が悪い。connecting -> connectionsの後でいいのでは？
           // TODO: 通常のTLSと同じenumか何かにまとめたい
           let self_clone = self.clone();
+          let context = self.context.load();
           self.globals.runtime_handle.spawn(async move {
             let client_addr = incoming.remote_address();
             let quic_connection = match incoming.await {
@@ -87,34 +105,45 @@ where
               },
               Err(e) => {
                 warn!("QUIC accepting connection failed: {:?}", e);
-                return Err(RpxyError::QuinnConnectionFailed(e));
+                let e = RpxyError::QuinnConnectionFailed(e);
+                self_clone.globals.metrics.tls_handshake_failed(Transport::Quic, Some(&e));
+                return Err(e);
               }
             };
             // Timeout is based on underlying quic
-            if let Err(e) = self_clone.h3_serve_connection(quic_connection, new_server_name.to_server_name(), client_addr).await {
+            if let Err(e) = self_clone.h3_serve_connection(context, quic_connection, new_server_name.to_server_name(), client_addr).await {
               warn!("QUIC or HTTP/3 connection failed: {}", e);
             };
             Ok(())
           });
         }
-        _ = server_crypto_rx.changed() => {
+        res = server_crypto_rx.changed() => {
+          if res.is_err() && context_rx.has_changed().unwrap_or_default() {
+            // the reloader of the previous configuration is closed, then that of the new one is taken
+            continue;
+          }
           if server_crypto_rx.borrow().is_none() {
             error!("Reloader is broken");
             break;
           }
-          let cert_keys_map = server_crypto_rx.borrow().clone().unwrap();
-
-          server_crypto = (&cert_keys_map).try_into().ok();
-          let Some(inner) = server_crypto.clone() else {
+          let Some((new_server_crypto, server_config_h3)) = quinn_server_config(&server_crypto_rx) else {
             error!("Failed to update server crypto for h3");
             break;
           };
-          let rustls_server_config = inner.aggregated_config_no_client_auth.clone();
-          let Ok(quinn_server_config_crypto) = QuicServerConfig::try_from(rustls_server_config) else {
-            error!("Failed to update server crypto for h3");
+          server_crypto = Some(new_server_crypto);
+          endpoint.set_server_config(Some(server_config_h3));
+        }
+        _ = context_rx.changed() => {
+          let Some(new_server_crypto_rx) = self.context.load().globals.cert_reloader_rx.clone() else {
+            error!("No certificate reloader in the updated configuration");
             break;
           };
-          endpoint.set_server_config(Some(quinn::ServerConfig::with_crypto(Arc::new(quinn_server_config_crypto))));
+          server_crypto_rx = new_server_crypto_rx;
+          // certificates already loaded by the reloader of the new configuration are applied immediately
+          if let Some((new_server_crypto, server_config_h3)) = quinn_server_config(&server_crypto_rx) {
+            server_crypto = Some(new_server_crypto);
+            endpoint.set_server_config(Some(server_config_h3));
+          }
         }
         else => break
       }
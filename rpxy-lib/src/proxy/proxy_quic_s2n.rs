@@ -1,5 +1,5 @@
 use super::proxy_main::Proxy;
-use crate::{error::*, log::*, name_exp::ByteName};
+use crate::{error::*, log::*, metrics::Transport, name_exp::ByteName};
 use anyhow::anyhow;
 use hot_reload::ReloaderReceiver;
 use hyper_util::client::legacy::connect::Connect;
@@ -13,7 +13,8 @@ where
 {
   /// Start UDP proxy serving with HTTP/3 request for configured host names
   pub(super) async fn h3_listener_service(&self) -> RpxyResult<()> {
-    let Some(mut server_crypto_rx) = self.globals.cert_reloader_rx.clone() else {
+    let mut context_rx = self.context.subscribe();
+    let Some(mut server_crypto_rx) = self.context.load().globals.cert_reloader_rx.clone() else {
       return Err(RpxyError::NoCertificateReloader);
     };
     info!("Start UDP proxy serving with HTTP/3 request for configured host names [s2n-quic]");
@@ -34,7 +35,11 @@ where
             break;
           }
         }
-        _ = server_crypto_rx.changed() => {
+        res = server_crypto_rx.changed() => {
+          if res.is_err() && context_rx.has_changed().unwrap_or_default() {
+            // the reloader of the previous configuration is closed, then that of the new one is taken
+            continue;
+          }
           server_crypto = match self.receive_server_crypto(server_crypto_rx.clone()) {
             Ok(sc) => Some(sc),
             Err(e) => {
@@ -43,6 +48,17 @@ where
             }
           };
         }
+        _ = context_rx.changed() => {
+          let Some(new_server_crypto_rx) = self.context.load().globals.cert_reloader_rx.clone() else {
+            error!("No certificate reloader in the updated configuration");
+            break;
+          };
+          server_crypto_rx = new_server_crypto_rx;
+          // certificates already loaded by the reloader of the new configuration are applied immediately
+          if let Ok(sc) = self.receive_server_crypto(server_crypto_rx.clone()) {
+            server_crypto = Some(sc);
+          }
+        }
         else => break
       }
     }
@@ -113,17 +129,22 @@ where
       trace!("New QUIC connection established");
       let Ok(Some(new_server_name)) = new_conn.server_name() else {
         warn!("HTTP/3 no SNI is given");
+        self
+          .globals
+          .metrics
+          .tls_handshake_failed(Transport::Quic, Some(&RpxyError::NoServerNameInClientHello));
         continue;
       };
       debug!("HTTP/3 connection incoming (SNI {:?})", new_server_name);
       let self_clone = self.clone();
+      let context = self.context.load();
 
       self.globals.runtime_handle.spawn(async move {
         let client_addr = new_conn.remote_addr()?;
         let quic_connection = s2n_quic_h3::Connection::new(new_conn);
         // Timeout is based on underlying quic
         if let Err(e) = self_clone
-          .h3_serve_connection(quic_connection, new_server_name.to_server_name(), client_addr)
+          .h3_serve_connection(context, quic_connection, new_server_name.to_server_name(), client_addr)
           .await
         {
           warn!("QUIC or HTTP/3 connection failed: {}", e);
@@ -8,7 +8,7 @@ use tokio::net::TcpSocket;
 
 /// Bind TCP socket to the given `SocketAddr`, and returns the TCP socket with `SO_REUSEADDR` and `SO_REUSEPORT` options.
 /// This option is required to re-bind the socket address when the proxy instance is reconstructed.
-pub(super) fn bind_tcp_socket(listening_on: &SocketAddr) -> RpxyResult<TcpSocket> {
+pub(crate) fn bind_tcp_socket(listening_on: &SocketAddr) -> RpxyResult<TcpSocket> {
   let tcp_socket = if listening_on.is_ipv6() {
     TcpSocket::new_v6()
   } else {